@@ -0,0 +1,51 @@
+use crate::diagnostics::{print_diagnostic, print_diagnostics, MessageFormat};
+use clap::ValueEnum;
+use rustre_core::codegen::{c, lower_program, rust};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Target {
+    /// `<node>.h` and `<node>.c` files
+    C,
+    /// A `<node>.rs` module
+    Rust,
+}
+
+/// Compiles a node, writing the generated files in the output directory
+pub fn build(file: PathBuf, node: &str, output: &Path, target: Target) -> Result<(), u8> {
+    let db = rustre_core::driver();
+    rustre_core::add_source_file(&db, file);
+    print_diagnostics(&db, false, MessageFormat::Human)?;
+
+    let Some(main) = Option::clone(&rustre_core::name_resolution::find_node(&db, node.into()))
+    else {
+        println!("Unknown node : {node}");
+        return Err(1);
+    };
+
+    let program = match lower_program(&db, main) {
+        Ok(program) => program,
+        Err(diagnostic) => {
+            print_diagnostic(&diagnostic);
+            return Err(1);
+        }
+    };
+
+    let files = match target {
+        Target::C => {
+            let code = c::generate(&program, node);
+            vec![("h", code.header), ("c", code.source)]
+        }
+        Target::Rust => vec![("rs", rust::generate(&program))],
+    };
+
+    for (extension, contents) in files {
+        let path = output.join(format!("{node}.{extension}"));
+        if let Err(err) = std::fs::write(&path, contents) {
+            eprintln!("Cannot write {} : {err}", path.display());
+            return Err(1);
+        }
+    }
+
+    Ok(())
+}
@@ -1,9 +1,21 @@
 use ariadne::{Color, FnCache, Label, Report, ReportKind};
-use rustre_core::diagnostics::{Diagnostic, Level};
+use clap::ValueEnum;
+use rustre_core::diagnostics::{Diagnostic, Level, Span};
+use serde_json::{json, Value};
 use std::fmt::{Debug, Display, Formatter};
 use std::path::Path;
 use yeter::Database;
 
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MessageFormat {
+    /// Reports rendered with the source code, on stderr
+    Human,
+    /// One JSON object per diagnostic and per line, on stdout
+    Json,
+    /// A single SARIF log, on stdout
+    Sarif,
+}
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 struct Path2<'a>(&'a Path);
 
@@ -13,44 +25,33 @@ impl<'a> Display for Path2<'a> {
     }
 }
 
-pub fn print_diagnostics(db: &Database, deny_warnings: bool) -> Result<(), u8> {
+pub fn print_diagnostics(
+    db: &Database,
+    deny_warnings: bool,
+    format: MessageFormat,
+) -> Result<(), u8> {
     rustre_core::check(db);
 
     let effects = db.effect::<Diagnostic>();
 
     let mut errors = 0usize;
     let mut warnings = 0usize;
-    for diagnostic in effects {
-        let kind = match diagnostic.level {
-            Level::Debug => ReportKind::Custom("Debug", Color::Blue),
-            Level::Info => ReportKind::Advice,
-            Level::Warning => {
-                warnings += 1;
-                ReportKind::Warning
-            }
-            Level::Error => {
-                errors += 1;
-                ReportKind::Error
-            }
-        };
-
-        let cache = FnCache::new(|path: &Path2| {
-            std::fs::read_to_string(path.0).map_err(|b| Box::new(b) as Box<dyn Debug>)
-        });
-
-        let (path, offset) = diagnostic.file_context().unwrap();
-        let mut report = Report::build(kind, Path2(path), offset).with_message(&diagnostic.message);
+    for diagnostic in &effects {
+        match diagnostic.level {
+            Level::Warning => warnings += 1,
+            Level::Error => errors += 1,
+            Level::Debug | Level::Info => (),
+        }
 
-        for (idx, (span, message)) in diagnostic.attachments.iter().enumerate() {
-            let span = (Path2(span.file.as_path()), span.start..span.end);
-            report = report.with_label(
-                Label::new(span)
-                    .with_message(message)
-                    .with_order(idx as i32),
-            );
+        match format {
+            MessageFormat::Human => print_diagnostic(diagnostic),
+            MessageFormat::Json => println!("{}", to_json(db, diagnostic)),
+            MessageFormat::Sarif => (),
         }
+    }
 
-        report.finish().eprint(cache).unwrap();
+    if let MessageFormat::Sarif = format {
+        println!("{}", to_sarif(db, &effects));
     }
 
     if errors > 0 || (deny_warnings && warnings > 0) {
@@ -59,3 +60,255 @@ pub fn print_diagnostics(db: &Database, deny_warnings: bool) -> Result<(), u8> {
         Ok(())
     }
 }
+
+/// Prints a single diagnostic, that isn't necessarily an effect of a query
+pub fn print_diagnostic(diagnostic: &Diagnostic) {
+    let kind = match diagnostic.level {
+        Level::Debug => ReportKind::Custom("Debug", Color::Blue),
+        Level::Info => ReportKind::Advice,
+        Level::Warning => ReportKind::Warning,
+        Level::Error => ReportKind::Error,
+    };
+
+    let cache = FnCache::new(|path: &Path2| {
+        std::fs::read_to_string(path.0).map_err(|b| Box::new(b) as Box<dyn Debug>)
+    });
+
+    // Some diagnostics (such as unreadable input files) aren't attached to any source code
+    let (path, offset) = diagnostic.file_context().unwrap_or((Path::new(""), 0));
+    let mut report = Report::build(kind, Path2(path), offset).with_message(&diagnostic.message);
+
+    for (idx, (span, message)) in diagnostic.attachments.iter().enumerate() {
+        let span = (Path2(span.file.as_path()), span.start..span.end);
+        report = report.with_label(
+            Label::new(span)
+                .with_message(message)
+                .with_order(idx as i32),
+        );
+    }
+
+    report.finish().eprint(cache).unwrap();
+}
+
+/// Returns the text of a file of the program, or reads it if it isn't loaded
+fn source_text(db: &Database, path: &Path) -> Option<String> {
+    let files = rustre_core::files(db);
+    let files = files.as_ref().as_deref().unwrap_or_default();
+    match files.iter().find(|file| file.path == path) {
+        Some(file) => Some(file.text.clone()),
+        None => std::fs::read_to_string(path).ok(),
+    }
+}
+
+/// Converts a byte offset to a line and a column, both starting at 1 (columns count characters)
+///
+/// Offsets that are past the end of the text or inside a character are moved back to the start of
+/// the character.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before.matches('\n').count() + 1;
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn level_name(level: &Level) -> &'static str {
+    match level {
+        Level::Debug => "debug",
+        Level::Info => "info",
+        Level::Warning => "warning",
+        Level::Error => "error",
+    }
+}
+
+fn span_to_json(text: Option<&str>, span: &Span) -> Value {
+    let position = |offset| {
+        let (line, column) = line_column(text?, offset);
+        Some(json!({ "line": line, "column": column }))
+    };
+
+    json!({
+        "file": span.file.display().to_string(),
+        "byte_start": span.start,
+        "byte_end": span.end,
+        "start": position(span.start),
+        "end": position(span.end),
+    })
+}
+
+/// Converts a diagnostic to a JSON object, with the positions of each of its attachments
+fn to_json(db: &Database, diagnostic: &Diagnostic) -> Value {
+    let attachments = diagnostic.attachments.iter().map(|(span, label)| {
+        let text = source_text(db, &span.file);
+        let mut attachment = span_to_json(text.as_deref(), span);
+        attachment["label"] = label.as_str().into();
+        attachment
+    });
+
+    json!({
+        "level": level_name(&diagnostic.level),
+        "message": diagnostic.message,
+        "attachments": attachments.collect::<Vec<_>>(),
+    })
+}
+
+/// Builds a [SARIF](https://sarifweb.azurewebsites.net/) log of a list of diagnostics
+///
+/// The first attachment of a diagnostic is its location, the other ones are related locations.
+/// Columns count Unicode code points, as they do in the JSON format.
+fn to_sarif(db: &Database, diagnostics: &[Diagnostic]) -> Value {
+    let location = |(span, label): &(Span, String)| {
+        let text = source_text(db, &span.file);
+        let mut region = json!({ "byteOffset": span.start, "byteLength": span.end - span.start });
+        if let Some(text) = text {
+            let (start_line, start_column) = line_column(&text, span.start);
+            let (end_line, end_column) = line_column(&text, span.end);
+            region["startLine"] = start_line.into();
+            region["startColumn"] = start_column.into();
+            region["endLine"] = end_line.into();
+            region["endColumn"] = end_column.into();
+        }
+
+        json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": span.file.display().to_string() },
+                "region": region,
+            },
+            "message": { "text": label },
+        })
+    };
+
+    let results = diagnostics.iter().map(|diagnostic| {
+        let level = match diagnostic.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Debug | Level::Info => "note",
+        };
+        let (first, related) = match diagnostic.attachments.split_first() {
+            Some((first, related)) => (Some(first), related),
+            None => (None, &[][..]),
+        };
+
+        json!({
+            "level": level,
+            "message": { "text": diagnostic.message },
+            "locations": first.map(location).into_iter().collect::<Vec<_>>(),
+            "relatedLocations": related.iter().map(location).collect::<Vec<_>>(),
+        })
+    });
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "rustre",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": results.collect::<Vec<_>>(),
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn diagnostic() -> (Database, Diagnostic) {
+        let db = rustre_core::driver();
+        let text = "node n(x : int) returns (y : int);\nlet (* é *) y = z; tel\n";
+        rustre_core::set_source_contents(&db, PathBuf::from("test.lus"), text.into());
+
+        let span = |start, end| Span {
+            file: PathBuf::from("test.lus"),
+            revision: 0,
+            start,
+            end,
+        };
+        let diagnostic = Diagnostic::new(Level::Error, "cannot resolve z")
+            .with_attachment(span(52, 53), "not found")
+            .with_attachment(span(7, 8), "did you mean x?");
+        (db, diagnostic)
+    }
+
+    #[test]
+    fn line_columns() {
+        let text = "é\nab";
+        assert_eq!(line_column(text, 0), (1, 1));
+        assert_eq!(line_column(text, 1), (1, 1));
+        assert_eq!(line_column(text, 2), (1, 2));
+        assert_eq!(line_column(text, 4), (2, 2));
+        assert_eq!(line_column(text, 100), (2, 3));
+    }
+
+    #[test]
+    fn json_format() {
+        let (db, diagnostic) = diagnostic();
+        assert_eq!(
+            to_json(&db, &diagnostic),
+            json!({
+                "level": "error",
+                "message": "cannot resolve z",
+                "attachments": [
+                    {
+                        "file": "test.lus",
+                        "byte_start": 52,
+                        "byte_end": 53,
+                        "start": { "line": 2, "column": 17 },
+                        "end": { "line": 2, "column": 18 },
+                        "label": "not found",
+                    },
+                    {
+                        "file": "test.lus",
+                        "byte_start": 7,
+                        "byte_end": 8,
+                        "start": { "line": 1, "column": 8 },
+                        "end": { "line": 1, "column": 9 },
+                        "label": "did you mean x?",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn sarif_format() {
+        let (db, diagnostic) = diagnostic();
+        let location = |start: usize, line, column, label| {
+            json!({
+                "physicalLocation": {
+                    "artifactLocation": { "uri": "test.lus" },
+                    "region": {
+                        "byteOffset": start,
+                        "byteLength": 1,
+                        "startLine": line,
+                        "startColumn": column,
+                        "endLine": line,
+                        "endColumn": column + 1,
+                    },
+                },
+                "message": { "text": label },
+            })
+        };
+
+        let log = to_sarif(&db, &[diagnostic]);
+        assert_eq!(log["runs"][0]["columnKind"], "unicodeCodePoints");
+        assert_eq!(
+            log["runs"][0]["results"],
+            json!([{
+                "level": "error",
+                "message": { "text": "cannot resolve z" },
+                "locations": [location(52, 2, 17, "not found")],
+                "relatedLocations": [location(7, 1, 8, "did you mean x?")],
+            }])
+        );
+    }
+}
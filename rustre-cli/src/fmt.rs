@@ -0,0 +1,47 @@
+use crate::diagnostics::print_diagnostic;
+use rustre_core::format::format_file;
+use rustre_core::SourceFile;
+use std::path::PathBuf;
+
+/// Formats files in place, or only lists the ones that are not formatted if `check` is set
+pub fn fmt(files: &[PathBuf], check: bool) -> Result<(), u8> {
+    let mut failed = false;
+
+    for path in files {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("Cannot read {} : {err}", path.display());
+                failed = true;
+                continue;
+            }
+        };
+
+        let formatted = match format_file(&SourceFile::new(path.clone(), text.clone())) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                diagnostics.iter().for_each(print_diagnostic);
+                failed = true;
+                continue;
+            }
+        };
+
+        if formatted == text {
+            continue;
+        }
+
+        if check {
+            println!("{} is not formatted", path.display());
+            failed = true;
+        } else if let Err(err) = std::fs::write(path, formatted) {
+            eprintln!("Cannot write {} : {err}", path.display());
+            failed = true;
+        }
+    }
+
+    if failed {
+        Err(1)
+    } else {
+        Ok(())
+    }
+}
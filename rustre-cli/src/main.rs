@@ -1,10 +1,15 @@
+mod build;
 mod diagnostics;
+mod fmt;
+mod simulate;
 
 use std::path::PathBuf;
 
-use crate::diagnostics::print_diagnostics;
+use crate::diagnostics::{print_diagnostics, MessageFormat};
 use clap::{Parser, Subcommand};
+use petgraph::dot::{Config, Dot};
 use rowan::NodeOrToken;
+use rustre_core::dataflow::DataflowVertex;
 use rustre_parser::{ast::AstNode, lexer::Token, SyntaxNode, SyntaxToken};
 
 #[derive(Parser)]
@@ -38,11 +43,57 @@ enum Commands {
         #[clap(long, short = 'W')]
         /// If set, rustre will return a non-zero status code when it encounters a warning
         deny_warnings: bool,
+
+        /// How diagnostics are printed
+        #[clap(long, value_enum, default_value_t = MessageFormat::Human)]
+        message_format: MessageFormat,
+    },
+
+    /// Run a node, reading one line of input values per cycle from stdin
+    Simulate {
+        file: PathBuf,
+
+        /// Node to run
+        #[clap(long, short)]
+        node: String,
+
+        /// Number of cycles to run, instead of running until the end of the input
+        #[clap(long, short)]
+        cycles: Option<usize>,
+
+        /// Print the values of the local variables at each cycle
+        #[clap(long)]
+        trace: bool,
+
+        /// Read and write RIF streams, and check the outputs they may contain
+        #[clap(long)]
+        rif: bool,
     },
 
-    // Compiles file
+    /// Compile a main node to C or Rust
     Build {
         file: Option<String>,
+
+        /// Node to compile, along with the nodes it calls
+        #[clap(long, short)]
+        node: String,
+
+        /// Directory in which the generated files are written
+        #[clap(long, short, default_value = ".")]
+        output: PathBuf,
+
+        /// Language of the generated code
+        #[clap(long, short, value_enum, default_value_t = build::Target::C)]
+        target: build::Target,
+    },
+
+    /// Format Lustre files in place
+    Fmt {
+        files: Vec<PathBuf>,
+
+        /// Don't write the files, but fail if some of them are not formatted
+        #[clap(long)]
+        check: bool,
     },
 }
 
@@ -82,27 +133,75 @@ fn main() -> Result<(), u8> {
                 }
             }
         }
-        Commands::Dot { file: _, node: _ } => {
-            todo!()
+        Commands::Dot { file, node } => {
+            let db = rustre_core::driver();
+            rustre_core::add_source_file(&db, file.clone());
+            // The graph is still emitted for incorrect programs, but the status reports the errors
+            let checked = print_diagnostics(&db, false, MessageFormat::Human);
+            let Some(node) =
+                Option::clone(&rustre_core::name_resolution::find_node(&db, node.clone()))
+            else {
+                eprintln!("Unknown node : {node}");
+                std::process::exit(1);
+            };
+
+            let graph = rustre_core::dataflow::dataflow_graph(&db, node);
+            let dot = Dot::with_attr_getters(
+                &*graph,
+                &[Config::EdgeNoLabel, Config::NodeNoLabel],
+                &|_, edge| format!("label={:?}", edge.weight()),
+                &|_, (_, vertex)| {
+                    let shape = match vertex {
+                        DataflowVertex::Input(_) | DataflowVertex::Output(_) => "ellipse",
+                        DataflowVertex::Local(_) => "plaintext",
+                        DataflowVertex::Constant(_) => "note",
+                        DataflowVertex::Operator(_) => "circle",
+                        DataflowVertex::Memory(_) => "doublecircle",
+                        DataflowVertex::Call {
+                            stateful: false, ..
+                        } => "box",
+                        DataflowVertex::Call { stateful: true, .. } => "box3d",
+                    };
+                    let style = match vertex {
+                        DataflowVertex::Input(_) => ", style=filled, fillcolor=lightblue",
+                        DataflowVertex::Output(_) => ", style=filled, fillcolor=lightgreen",
+                        _ => "",
+                    };
+                    format!("label={:?}, shape={shape}{style}", vertex.to_string())
+                },
+            );
+            println!("{dot:?}");
+            checked
         }
         Commands::Check {
             file,
             deny_warnings,
+            message_format,
         } => {
             let db = rustre_core::driver();
             rustre_core::add_source_file(&db, file.clone());
-            print_diagnostics(&db, *deny_warnings)
+            print_diagnostics(&db, *deny_warnings, *message_format)
         }
-        Commands::Build { file } => match file {
-            Some(_filename) => {
-                println!("Ça construit un compilateur ou quoi ?? (PAS ENCORE PRÊT)");
-                Ok(())
-            }
+        Commands::Simulate {
+            file,
+            node,
+            cycles,
+            trace,
+            rif,
+        } => simulate::simulate(file.clone(), node, *cycles, *trace, *rif),
+        Commands::Build {
+            file,
+            node,
+            output,
+            target,
+        } => match file {
+            Some(filename) => build::build(PathBuf::from(filename), node, output, *target),
             None => {
                 println!("Missing argument : file");
                 Err(1)
             }
         },
+        Commands::Fmt { files, check } => fmt::fmt(files, *check),
     }
 }
 
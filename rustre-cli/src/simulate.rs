@@ -0,0 +1,207 @@
+use crate::diagnostics::{print_diagnostic, print_diagnostics, MessageFormat};
+use rustre_core::interpreter::{NodeInstance, Value};
+use rustre_core::rif::{
+    format_value, outputs_match, parse_value, split_values, RifItem, RifReader, RifWriter,
+};
+use rustre_core::TypedSignature;
+use rustre_parser::ast::AstToken;
+use std::io::BufRead;
+use std::path::PathBuf;
+
+/// Parses a line of input values, in the plain (non-RIF) protocol
+///
+/// Arrays and structures are written as literals (`[1, 2, 3]`, `{x = 1; y = 2}`).
+fn parse_line(line: &str, sig: &TypedSignature) -> Result<RifItem, String> {
+    let values = split_values(line);
+    if values.len() != sig.params.len() {
+        return Err(format!(
+            "Expected {} input values, got {}",
+            sig.params.len(),
+            values.len()
+        ));
+    }
+
+    let mut inputs = Vec::with_capacity(values.len());
+    for (text, (name, ty)) in values.into_iter().zip(&sig.params) {
+        let Some(value) = parse_value(text, ty) else {
+            return Err(format!("Invalid value for input {} : {text}", name.text()));
+        };
+        inputs.push(value);
+    }
+
+    Ok(RifItem::Inputs(inputs))
+}
+
+/// Runs a node, reading the values of its inputs from stdin (one line per cycle) and printing the
+/// values of its outputs on stdout
+///
+/// The program is checked first, and isn't run if it has errors.
+///
+/// In RIF mode, the input is a RIF stream, and a RIF trace is printed. When the input contains the
+/// outputs expected for each cycle (as in a trace produced by `lus2lic`), they are compared with
+/// the actual ones.
+pub fn simulate(
+    file: PathBuf,
+    node: &str,
+    cycles: Option<usize>,
+    trace: bool,
+    rif: bool,
+) -> Result<(), u8> {
+    let db = rustre_core::driver();
+    rustre_core::add_source_file(&db, file);
+    print_diagnostics(&db, false, MessageFormat::Human)?;
+
+    let Some(node) = Option::clone(&rustre_core::name_resolution::find_node(&db, node.into()))
+    else {
+        eprintln!("Unknown node : {node}");
+        return Err(1);
+    };
+
+    let sig = rustre_core::get_typed_signature(&db, node.clone());
+    let mut instance = NodeInstance::new(&db, node);
+    let stdin = std::io::stdin().lock();
+
+    // Nodes without inputs don't need stdin when the number of cycles is known
+    let items: Box<dyn Iterator<Item = Result<RifItem, String>>> =
+        if sig.params.is_empty() && cycles.is_some() {
+            Box::new(std::iter::repeat_with(|| Ok(RifItem::Inputs(vec![]))))
+        } else if rif {
+            Box::new(RifReader::new(stdin, &sig).map(|item| item.map_err(|e| e.to_string())))
+        } else {
+            Box::new(stdin.lines().filter_map(|line| match line {
+                Ok(line) if line.starts_with('#') => None,
+                Ok(line) => Some(parse_line(&line, &sig)),
+                Err(err) => Some(Err(format!("Cannot read inputs : {err}"))),
+            }))
+        };
+
+    let mut writer = match rif {
+        true => Some(RifWriter::new(std::io::stdout(), &sig).map_err(|_| 1u8)?),
+        false => None,
+    };
+
+    let mut cycle = 0;
+    let mut last_outputs: Vec<Value> = vec![];
+    let mut mismatches = 0;
+    for item in items {
+        let inputs = match item {
+            Ok(RifItem::Inputs(inputs)) => inputs,
+            Ok(RifItem::Outputs(expected)) => {
+                if !outputs_match(&expected, &last_outputs) {
+                    let format = |values: &[Value]| {
+                        values
+                            .iter()
+                            .map(format_value)
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    };
+                    eprintln!(
+                        "Cycle {cycle} : expected {}, got {}",
+                        format(&expected),
+                        format(&last_outputs)
+                    );
+                    mismatches += 1;
+                }
+                continue;
+            }
+            Ok(RifItem::Reset) => {
+                instance.reset();
+                if let Some(writer) = &mut writer {
+                    writer.write_reset().map_err(|_| 1u8)?;
+                }
+                continue;
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                return Err(1);
+            }
+        };
+
+        if cycles.is_some_and(|cycles| cycle >= cycles) {
+            break;
+        }
+
+        let inputs = inputs.into_iter().map(Some).collect::<Vec<_>>();
+        let outputs = match instance.step(inputs.clone()) {
+            Ok(outputs) => outputs,
+            Err(diagnostic) => {
+                print_diagnostic(&diagnostic);
+                return Err(1);
+            }
+        };
+
+        let locals = instance
+            .locals()
+            .map(|(name, value)| format!("{name}={}", format_value(&value)))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        match &mut writer {
+            Some(writer) => {
+                writer.write_step(&inputs, &outputs).map_err(|_| 1u8)?;
+                if trace {
+                    writer.write_comment(&locals).map_err(|_| 1u8)?;
+                }
+            }
+            None => {
+                if trace {
+                    println!("# {locals}");
+                }
+                let outputs = outputs.iter().map(format_value).collect::<Vec<_>>();
+                println!("{}", outputs.join(" "));
+            }
+        }
+
+        last_outputs = outputs;
+        cycle += 1;
+
+        // RIF streams may still contain the expected outputs of the last cycle
+        if !rif && cycles.is_some_and(|cycles| cycle >= cycles) {
+            break;
+        }
+    }
+
+    if mismatches > 0 {
+        eprintln!("{mismatches} cycles did not match the expected outputs");
+        return Err(1);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustre_core::types::ConstValue;
+
+    #[test]
+    fn array_inputs() {
+        let mut db = rustre_core::driver();
+        rustre_core::add_source_contents(
+            &mut db,
+            "node n(a : int^3; b : bool) returns (s : int);
+             let
+                 s = if b then a[0] + a[1] + a[2] else 0;
+             tel"
+            .into(),
+        );
+        let node = rustre_core::name_resolution::find_node(&db, "n".into());
+        let node = Option::clone(&node).unwrap();
+        let sig = rustre_core::get_typed_signature(&db, node.clone());
+
+        let Ok(RifItem::Inputs(inputs)) = parse_line("[1, 2,3] t", &sig) else {
+            panic!("the inputs should be valid");
+        };
+        let ints = [1, 2, 3].map(ConstValue::Integer).to_vec();
+        assert_eq!(
+            inputs,
+            vec![ConstValue::Array(ints), ConstValue::Boolean(true)]
+        );
+
+        let mut instance = NodeInstance::new(&db, node);
+        let outputs = instance.step(inputs.into_iter().map(Some).collect());
+        assert_eq!(outputs.ok(), Some(vec![Some(ConstValue::Integer(6))]));
+
+        assert!(parse_line("[1, 2] t", &sig).is_err());
+    }
+}
@@ -0,0 +1,323 @@
+//! Causality analysis
+//!
+//! Within a cycle, an equation can only be computed once all the variables it instantaneously
+//! depends on are known. Variables that are only read through a delay (`pre`, or the second
+//! operand of `fby`) don't count, as their previous values are already known.
+
+use crate::clocks::clock_condition;
+use crate::dataflow::left_item_name;
+use crate::diagnostics::{Diagnostic, Level, Span};
+use petgraph::algo::{tarjan_scc, toposort};
+use petgraph::graph::DiGraph;
+use petgraph::visit::EdgeRef;
+use rustre_parser::ast::{
+    AstNode, AstToken, ClockExpressionNode, EqualsEquationNode, ExpressionNode, Ident,
+    LeftItemNode, NodeNode,
+};
+use std::collections::HashMap;
+use yeter::Database;
+
+/// Graph of the equations of a node
+///
+/// There is an edge from an equation to another one when the second one instantaneously depends
+/// on a variable defined by the first one. Edges are weighted with the name of that variable. A
+/// variable can be defined part by part by several equations, which all come before its uses.
+pub type DependencyGraph = DiGraph<EqualsEquationNode, String>;
+
+/// Collects the variables an expression depends on during the same cycle
+pub fn instant_dependencies(expr: &ExpressionNode, deps: &mut Vec<Ident>) {
+    match expr {
+        ExpressionNode::PreExpressionNode(_) => (),
+        ExpressionNode::FbyExpressionNode(e) => {
+            if let Some(first) = e.left() {
+                instant_dependencies(&first, deps);
+            }
+        }
+        ExpressionNode::IdentExpressionNode(e) => deps.extend(e.id_node().and_then(|i| i.ident())),
+        ExpressionNode::WhenExpressionNode(e) => {
+            if let Some(operand) = e.left() {
+                instant_dependencies(&operand, deps);
+            }
+
+            let clock = e.syntax().children().find_map(ClockExpressionNode::cast);
+            deps.extend(clock.as_ref().and_then(clock_condition).map(|(var, _)| var));
+        }
+        ExpressionNode::FieldAccessExpressionNode(e) => {
+            if let Some(record) = e.left() {
+                instant_dependencies(&record, deps);
+            }
+        }
+        ExpressionNode::CallByNameExpressionNode(e) => {
+            deps.extend(e.base().and_then(|b| b.ident()));
+            for param in e.all_call_by_name_param_node() {
+                if let Some(value) = param.expression_node() {
+                    instant_dependencies(&value, deps);
+                }
+            }
+        }
+        ExpressionNode::MergeExpressionNode(e) => {
+            deps.extend(e.id_node().and_then(|i| i.ident()));
+            for case in e.all_merge_case_node() {
+                if let Some(branch) = case.expression_node() {
+                    instant_dependencies(&branch, deps);
+                }
+            }
+        }
+        ExpressionNode::CallByPosExpressionNode(e) => {
+            for arg in e.args().skip(1) {
+                instant_dependencies(&arg, deps);
+            }
+        }
+        _ => {
+            for child in expr.syntax().children().filter_map(ExpressionNode::cast) {
+                instant_dependencies(&child, deps);
+            }
+        }
+    }
+}
+
+/// Collects the variables the indices of a left item depend on, as in `t[i] = 0`
+fn left_dependencies(left: &LeftItemNode, deps: &mut Vec<Ident>) {
+    match left {
+        LeftItemNode::IdNode(_) => (),
+        LeftItemNode::LeftFieldAccessNode(access) => {
+            if let Some(parent) = access.left_item_node() {
+                left_dependencies(&parent, deps);
+            }
+        }
+        LeftItemNode::LeftTableAccessNode(access) => {
+            if let Some(parent) = access.left_item_node() {
+                left_dependencies(&parent, deps);
+            }
+            if let Some(index) = access.index() {
+                instant_dependencies(&index, deps);
+            }
+        }
+    }
+}
+
+/// **Query:** Builds the instantaneous dependency graph of the equations of a node
+#[yeter::query]
+pub fn dependency_graph(_db: &Database, node: NodeNode) -> DependencyGraph {
+    let mut graph = DependencyGraph::new();
+    let mut definitions = HashMap::<_, Vec<_>>::new();
+
+    let equations = node
+        .body_node()
+        .into_iter()
+        .flat_map(|b| b.all_equals_equation_node());
+    for equation in equations {
+        let lefts = equation
+            .left_node()
+            .into_iter()
+            .flat_map(|l| l.all_left_item_node());
+        let idx = graph.add_node(equation.clone());
+        for name in lefts.filter_map(|item| left_item_name(&item)) {
+            definitions.entry(name).or_default().push(idx);
+        }
+    }
+
+    for idx in graph.node_indices() {
+        let mut deps = vec![];
+        if let Some(expr) = graph[idx].expression_node() {
+            instant_dependencies(&expr, &mut deps);
+        }
+        let lefts = graph[idx].left_node().into_iter();
+        for left in lefts.flat_map(|l| l.all_left_item_node()) {
+            left_dependencies(&left, &mut deps);
+        }
+
+        for dep in deps {
+            for &def in definitions.get(dep.text()).into_iter().flatten() {
+                graph.add_edge(def, idx, dep.text().to_owned());
+            }
+        }
+    }
+
+    graph
+}
+
+fn defined_names(equation: &EqualsEquationNode) -> String {
+    let names = equation
+        .left_node()
+        .into_iter()
+        .flat_map(|l| l.all_left_item_node())
+        .filter_map(|item| left_item_name(&item))
+        .collect::<Vec<_>>();
+    names.join(", ")
+}
+
+/// Builds a diagnostic for each causality loop in a graph
+fn causality_loops(db: &Database, graph: &DependencyGraph) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    for mut component in tarjan_scc(graph) {
+        let is_loop = component.len() > 1
+            || graph
+                .edges(component[0])
+                .any(|edge| edge.target() == component[0]);
+        if !is_loop {
+            continue;
+        }
+
+        component.sort();
+        let mut diagnostic = Diagnostic::new(Level::Error, "causality loop");
+        for &idx in &component {
+            let mut deps = graph
+                .edges_directed(idx, petgraph::Direction::Incoming)
+                .filter(|edge| component.contains(&edge.source()))
+                .map(|edge| format!("`{}`", edge.weight()))
+                .collect::<Vec<_>>();
+            deps.sort();
+            deps.dedup();
+
+            let equation = &graph[idx];
+            diagnostic = diagnostic.with_attachment(
+                Span::of_node(db, equation.syntax()),
+                format!(
+                    "`{}` depends on {} during the same cycle",
+                    defined_names(equation),
+                    deps.join(", ")
+                ),
+            );
+        }
+
+        diagnostics.push(diagnostic);
+    }
+
+    diagnostics
+}
+
+/// **Query:** Reports equations that depend on themselves within a single cycle
+#[yeter::query]
+pub fn check_causality(db: &Database, node: NodeNode) {
+    let graph = dependency_graph(db, node);
+    for diagnostic in causality_loops(db, &graph) {
+        diagnostic.emit(db);
+    }
+}
+
+/// Orders the equations of a node so that each one comes after the ones it depends on
+pub fn schedule(db: &Database, node: &NodeNode) -> Result<Vec<EqualsEquationNode>, Diagnostic> {
+    let graph = dependency_graph(db, node.clone());
+    match toposort(&*graph, None) {
+        Ok(order) => Ok(order.into_iter().map(|idx| graph[idx].clone()).collect()),
+        Err(_) => Err(causality_loops(db, &graph).remove(0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::name_resolution::find_node;
+
+    #[test]
+    fn detect_loops() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node n(i : int) returns (x : int);
+             var y, z, w : int;
+             let
+                 x = y + 1;
+                 y = if i > 0 then x else 0;
+                 z = 0 -> pre w;
+                 w = z + i;
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let loops = causality_loops(&db, &dependency_graph(&db, node));
+
+        assert_eq!(loops.len(), 1);
+        let messages = loops[0]
+            .attachments
+            .iter()
+            .map(|(_, message)| message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                "`x` depends on `y` during the same cycle",
+                "`y` depends on `x` during the same cycle",
+            ]
+        );
+    }
+
+    #[test]
+    fn schedule_equations() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node n(x : int) returns (y : int);
+             var a, b : int;
+             let
+                 y = b + 1;
+                 b = a * 2;
+                 a = x -> pre y;
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let order = schedule(&db, &node)
+            .unwrap()
+            .iter()
+            .map(defined_names)
+            .collect::<Vec<_>>();
+
+        assert_eq!(order, ["a", "b", "y"]);
+    }
+
+    #[test]
+    fn schedule_partial_definitions() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type point = { x : int; y : int };
+
+             node n(x : int) returns (y : int);
+             var p : point;
+             let
+                 y = p.x + p.y;
+                 p.x = x;
+                 p.y = x;
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let order = schedule(&db, &node)
+            .unwrap()
+            .iter()
+            .map(defined_names)
+            .collect::<Vec<_>>();
+
+        assert_eq!(order.last().unwrap(), "y");
+    }
+
+    #[test]
+    fn schedule_dynamic_indices() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node n(x : int) returns (t : int^2);
+             var i : int;
+             let
+                 i = x mod 2;
+                 t[i] = x;
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let order = schedule(&db, &node)
+            .unwrap()
+            .iter()
+            .map(defined_names)
+            .collect::<Vec<_>>();
+
+        assert_eq!(order, ["i", "t"]);
+    }
+}
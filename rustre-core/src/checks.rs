@@ -2,8 +2,16 @@
 //!
 //! All of them should be directly or indirectly called by [`rustre_core::check`][crate::check()]
 
+use crate::dataflow::left_item_name;
+use crate::eval::{eval_const_node, eval_slice_bounds, slice_indices};
+use crate::name_resolution::{find_package, find_package_instance};
+use crate::types::{is_recursive_type_decl, type_of_ast_type, ConstValue, Type};
 use crate::{Diagnostic, Level, Span};
-use rustre_parser::ast::{AstNode, AstToken, NodeNode, NodeProfileNode, ParamsNode};
+use rustre_parser::ast::{
+    AstNode, AstToken, LeftItemNode, ModelDeclNode, NodeNode, NodeProfileNode, OneTypeDeclNode,
+    PackageDeclBody, PackageDeclNode, ParamsNode, ProvidesListNode, UsesNode,
+};
+use std::collections::{HashMap, HashSet};
 use yeter::Database;
 
 /// Checks that the number of params and return params is strictly greater than 0
@@ -42,3 +50,542 @@ pub fn check_arity(db: &Database, node: NodeNode) {
             .emit(db)
     }
 }
+
+/// Checks that each output and local variable of a node is defined exactly once, and that inputs
+/// are never defined
+///
+/// Variables defined by parts (such as `t[0] = ...; t[1] = ...;`) are only considered defined twice
+/// when two of the definitions overlap, and partly defined when some element or field is never
+/// defined. Parts selected by non-constant indices are assumed not to overlap, and to cover the
+/// whole array. Equations may only define outputs and local variables.
+#[yeter::query]
+pub fn check_definitions(db: &Database, node: NodeNode) {
+    let Some(body) = node.body_node() else {
+        return;
+    };
+
+    let mut definitions = HashMap::<String, Vec<LeftItemNode>>::new();
+    for equation in body.all_equals_equation_node() {
+        let lefts = equation
+            .left_node()
+            .into_iter()
+            .flat_map(|l| l.all_left_item_node());
+        for left in lefts {
+            if let Some(name) = left_item_name(&left) {
+                definitions.entry(name).or_default().push(left);
+            }
+        }
+    }
+
+    // Evaluating indices runs queries, which would take the effects emitted before them: paths are
+    // computed before emitting any diagnostic
+    let paths = definitions
+        .iter()
+        .map(|(name, lefts)| {
+            let paths = lefts.iter().map(|left| left_path(db, left, &node));
+            (name.clone(), paths.collect::<Vec<_>>())
+        })
+        .collect::<HashMap<_, _>>();
+
+    let sig = crate::get_signature(db, node.clone());
+    let locals = node
+        .all_var_decl_node()
+        .flat_map(|v| v.all_typed_ids_node())
+        .collect::<Vec<_>>();
+    // Types are computed by queries too
+    let defined = sig
+        .return_params
+        .iter()
+        .chain(&locals)
+        .flat_map(|ids| {
+            let ty = ids
+                .type_node()
+                .map(|t| Type::clone(&type_of_ast_type(db, Some(node.clone()), t)))
+                .unwrap_or_default();
+            ids.all_ident().zip(std::iter::repeat(ty))
+        })
+        .collect::<Vec<_>>();
+
+    let inputs = sig
+        .params
+        .iter()
+        .flat_map(|p| p.all_ident())
+        .collect::<Vec<_>>();
+    let declared = inputs
+        .iter()
+        .cloned()
+        .chain(defined.iter().map(|(ident, _)| ident.clone()))
+        .map(|ident| ident.text().to_owned())
+        .collect::<HashSet<_>>();
+    for input in inputs {
+        for left in definitions.get(input.text()).into_iter().flatten() {
+            Diagnostic::new(
+                Level::Error,
+                format!("cannot assign to input {:?}", input.text()),
+            )
+            .with_attachment(Span::of_node(db, left.syntax()), "assigned here")
+            .with_attachment(
+                Span::of_token(db, input.syntax()),
+                "declared as an input here",
+            )
+            .emit(db);
+        }
+    }
+
+    for (ident, ty) in defined {
+        let lefts = definitions
+            .get(ident.text())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let paths = paths
+            .get(ident.text())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let overlapping = lefts
+            .iter()
+            .zip(paths)
+            .enumerate()
+            .filter(|(idx, (_, path))| {
+                let others = paths.iter().enumerate().filter(|(other, _)| other != idx);
+                others.into_iter().any(|(_, other)| overlaps(path, other))
+            })
+            .map(|(_, (left, _))| left)
+            .collect::<Vec<_>>();
+
+        if lefts.is_empty() {
+            Diagnostic::new(Level::Error, format!("{:?} is never defined", ident.text()))
+                .with_attachment(
+                    Span::of_token(db, ident.syntax()),
+                    "hint: add an equation for this variable",
+                )
+                .emit(db);
+        } else if !overlapping.is_empty() {
+            let mut diagnostic =
+                Diagnostic::new(Level::Error, format!("{:?} is defined twice", ident.text()));
+            for (idx, left) in overlapping.iter().enumerate() {
+                let message = match idx {
+                    0 => "first defined here",
+                    _ => "defined again here",
+                };
+                diagnostic = diagnostic.with_attachment(Span::of_node(db, left.syntax()), message);
+            }
+            diagnostic.emit(db);
+        } else if let Some(missing) =
+            missing_part(&ty, &paths.iter().map(Vec::as_slice).collect::<Vec<_>>())
+        {
+            let mut diagnostic = Diagnostic::new(
+                Level::Error,
+                format!("{:?} is only partly defined", ident.text()),
+            )
+            .with_attachment(
+                Span::of_token(db, ident.syntax()),
+                format!("hint: add an equation for {}{missing}", ident.text()),
+            );
+            for left in lefts {
+                diagnostic =
+                    diagnostic.with_attachment(Span::of_node(db, left.syntax()), "defined here");
+            }
+            diagnostic.emit(db);
+        }
+    }
+
+    for (name, lefts) in &definitions {
+        if declared.contains(name) {
+            continue;
+        }
+
+        for left in lefts {
+            Diagnostic::new(
+                Level::Error,
+                format!("{name:?} is not an output or a local variable"),
+            )
+            .with_attachment(Span::of_node(db, left.syntax()), "assigned here")
+            .emit(db);
+        }
+    }
+}
+
+/// Step of the path from a variable to the part of it that is defined by a left item
+enum LeftPart {
+    Field(String),
+    /// Defined indices of an array, or `None` if they are not constant
+    Indices(Option<Vec<i32>>),
+}
+
+/// Returns the path from a variable to the part of it defined by a left item, which is empty when
+/// the whole variable is defined
+fn left_path(db: &Database, left: &LeftItemNode, node: &NodeNode) -> Vec<LeftPart> {
+    match left {
+        LeftItemNode::IdNode(_) => vec![],
+        LeftItemNode::LeftFieldAccessNode(access) => {
+            let mut path = access
+                .left_item_node()
+                .map(|left| left_path(db, &left, node))
+                .unwrap_or_default();
+            let field = access.field().and_then(|f| f.ident());
+            match field {
+                Some(field) => path.push(LeftPart::Field(field.text().to_owned())),
+                None => path.push(LeftPart::Indices(None)),
+            }
+            path
+        }
+        LeftItemNode::LeftTableAccessNode(access) => {
+            let parent = access.left_item_node();
+            let mut path = parent
+                .as_ref()
+                .map(|left| left_path(db, left, node))
+                .unwrap_or_default();
+            let mut indices = match (access.select_node(), access.index()) {
+                (Some(select), _) => eval_slice_bounds(db, &select, Some(node.clone()))
+                    .and_then(|(first, last, step)| slice_indices(first, last, step)),
+                (None, Some(index)) => match *eval_const_node(db, index, Some(node.clone())) {
+                    Some(ConstValue::Integer(i)) => Some(vec![i]),
+                    _ => None,
+                },
+                (None, None) => None,
+            };
+
+            // Indices in a slice (as in `t[1 .. 3][0]`) are indices in the array it is a slice of
+            let is_slice = matches!(
+                &parent,
+                Some(LeftItemNode::LeftTableAccessNode(parent)) if parent.select_node().is_some()
+            );
+            if is_slice {
+                let slice = match path.pop() {
+                    Some(LeftPart::Indices(slice)) => slice,
+                    _ => None,
+                };
+                indices = slice.zip(indices).and_then(|(slice, indices)| {
+                    indices
+                        .into_iter()
+                        .map(|i| slice.get(usize::try_from(i).ok()?).copied())
+                        .collect()
+                });
+            }
+            path.push(LeftPart::Indices(indices));
+            path
+        }
+    }
+}
+
+/// Returns a part of a variable of type `ty` that none of the paths define, like `[2]` or `.x`
+///
+/// Parts selected by non-constant indices are assumed to define the whole array.
+fn missing_part(ty: &Type, paths: &[&[LeftPart]]) -> Option<String> {
+    if paths.iter().any(|path| path.is_empty()) {
+        return None;
+    }
+
+    // The rest of the paths that start with a given part
+    let rests = |matches: &dyn Fn(&LeftPart) -> bool| {
+        paths
+            .iter()
+            .filter(|path| matches(&path[0]))
+            .map(|path| &path[1..])
+            .collect::<Vec<_>>()
+    };
+
+    match ty {
+        Type::Array { elem, size } => {
+            if paths
+                .iter()
+                .any(|path| matches!(path[0], LeftPart::Indices(None)))
+            {
+                return None;
+            }
+
+            (0..*size as i32).find_map(|idx| {
+                let rests = rests(&|part| match part {
+                    LeftPart::Indices(Some(indices)) => indices.contains(&idx),
+                    _ => false,
+                });
+                match rests.as_slice() {
+                    [] => Some(format!("[{idx}]")),
+                    _ => missing_part(elem, &rests).map(|missing| format!("[{idx}]{missing}")),
+                }
+            })
+        }
+        Type::Struct { fields, .. } => fields.iter().find_map(|(field, ty)| {
+            let rests = rests(&|part| matches!(part, LeftPart::Field(f) if f == field));
+            match rests.as_slice() {
+                [] => Some(format!(".{field}")),
+                _ => missing_part(ty, &rests).map(|missing| format!(".{field}{missing}")),
+            }
+        }),
+        // Parts of other types are reported by the type checker
+        _ => None,
+    }
+}
+
+/// Tells whether two parts of a variable may be the same, which is the case when one contains the
+/// other
+fn overlaps(a: &[LeftPart], b: &[LeftPart]) -> bool {
+    a.iter().zip(b).all(|parts| match parts {
+        (LeftPart::Field(a), LeftPart::Field(b)) => a == b,
+        (LeftPart::Indices(Some(a)), LeftPart::Indices(Some(b))) => a.iter().any(|i| b.contains(i)),
+        _ => false,
+    })
+}
+
+/// Checks that a type declaration doesn't refer to itself
+#[yeter::query]
+pub fn check_type_decl(db: &Database, decl: OneTypeDeclNode) {
+    if !is_recursive_type_decl(db, &decl) {
+        return;
+    }
+
+    let name = decl
+        .ident()
+        .map(|i| i.text().to_owned())
+        .unwrap_or_default();
+    Diagnostic::new(Level::Error, format!("type {name:?} is recursive"))
+        .with_attachment(
+            Span::of_node(db, decl.syntax()),
+            "hint: values of this type would be infinite",
+        )
+        .emit(db);
+}
+
+/// Checks that the packages used by a package exist, and that it declares everything it provides
+#[yeter::query]
+pub fn check_package(db: &Database, package: PackageDeclNode) {
+    check_package_contents(
+        db,
+        package.uses_node(),
+        package.provides_list_node(),
+        package.package_decl_body(),
+    );
+}
+
+/// Checks that the packages used by a model exist, and that it declares everything it provides
+#[yeter::query]
+pub fn check_model(db: &Database, model: ModelDeclNode) {
+    check_package_contents(
+        db,
+        model.uses_node(),
+        model.provides_list_node(),
+        model.package_decl_body(),
+    );
+}
+
+fn check_package_contents(
+    db: &Database,
+    uses: Option<UsesNode>,
+    provides: Option<ProvidesListNode>,
+    body: Option<PackageDeclBody>,
+) {
+    let uses = uses.into_iter().flat_map(|u| u.all_id_node());
+    for used in uses {
+        let Some(name) = used.name() else {
+            continue;
+        };
+
+        let name_text = String::from(name.text());
+        if find_package(db, name_text.clone()).is_none()
+            && find_package_instance(db, name_text).is_none()
+        {
+            Diagnostic::new(Level::Error, format!("unknown package {:?}", name.text()))
+                .with_attachment(
+                    Span::of_node(db, used.syntax()),
+                    "not found in this program",
+                )
+                .emit(db);
+        }
+    }
+
+    let (Some(provides), Some(body)) = (provides, body) else {
+        return;
+    };
+
+    let consts = body
+        .all_constant_decl_node()
+        .flat_map(|c| c.all_one_constant_decl_node())
+        .flat_map(|c| c.all_id_node())
+        .filter_map(|id| id.name());
+    let types = body
+        .all_type_decl_node()
+        .flat_map(|t| t.all_one_type_decl_node())
+        .filter_map(|t| t.ident());
+    let nodes = body
+        .all_node_node()
+        .filter_map(|n| n.id_node())
+        .chain(
+            body.all_external_node_decl_node()
+                .filter_map(|n| n.id_node()),
+        )
+        .filter_map(|id| id.name());
+    let declared = consts
+        .chain(types)
+        .chain(nodes)
+        .map(|name| name.text().to_owned())
+        .collect::<HashSet<_>>();
+
+    for item in provides.all_provides_node() {
+        let Some(name) = item.name() else {
+            continue;
+        };
+
+        if !declared.contains(name.text()) {
+            Diagnostic::new(
+                Level::Error,
+                format!("{:?} is provided but never declared", name.text()),
+            )
+            .with_attachment(
+                Span::of_node(db, item.syntax()),
+                "hint: declare it in the body of the package",
+            )
+            .emit(db);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::Diagnostic;
+    use std::path::Path;
+
+    #[test]
+    fn adder_defines_s_twice() {
+        let driver = crate::driver();
+        crate::add_source_file(&driver, Path::new("../tests/adder.lus").to_owned());
+        crate::check(&driver);
+
+        let diagnostics = driver.effect::<Diagnostic>();
+        let twice = diagnostics
+            .iter()
+            .find(|d| d.message == "\"s\" is defined twice")
+            .unwrap();
+        assert_eq!(twice.attachments.len(), 2);
+    }
+
+    #[test]
+    fn undefined_and_inputs() {
+        let mut driver = crate::driver();
+        crate::add_source_contents(
+            &mut driver,
+            "node n(x : int) returns (y, z : int);
+             var l : int;
+             let
+                 y = x;
+                 x = 1;
+             tel"
+            .into(),
+        );
+        crate::check(&driver);
+
+        let messages = driver
+            .effect::<Diagnostic>()
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert!(messages.contains(&"cannot assign to input \"x\"".to_owned()));
+        assert!(messages.contains(&"\"z\" is never defined".to_owned()));
+        assert!(messages.contains(&"\"l\" is never defined".to_owned()));
+    }
+
+    #[test]
+    fn parts_defined_twice() {
+        let mut driver = crate::driver();
+        crate::add_source_contents(
+            &mut driver,
+            "type S = { x : int; y : int };
+
+             node n(a, b : int) returns (t : int^3; s : S; u : int^3; w : int^3);
+             let
+                 t[0] = a;
+                 t[0] = b;
+                 t[1 .. 2] = [a, b];
+                 s.x = a;
+                 s.x = b;
+                 s.y = a;
+                 u[0 .. 1] = [a, b];
+                 u[1 .. 2] = [a, b];
+                 w[0 .. 2][1] = a;
+                 w[0 .. 2][2] = a;
+                 w[2] = b;
+                 w[0] = b;
+             tel"
+            .into(),
+        );
+        crate::check(&driver);
+
+        let twice = |name: &str| {
+            driver
+                .effect::<Diagnostic>()
+                .into_iter()
+                .find(|d| d.message == format!("{name:?} is defined twice"))
+                .map(|d| d.attachments.len())
+        };
+        assert_eq!(twice("t"), Some(2));
+        assert_eq!(twice("s"), Some(2));
+        assert_eq!(twice("u"), Some(2));
+        assert_eq!(twice("w"), Some(2));
+    }
+
+    #[test]
+    fn partly_defined_and_unknown() {
+        let mut driver = crate::driver();
+        crate::add_source_contents(
+            &mut driver,
+            "type S = { x : int; y : int };
+
+             node n(a : int) returns (t : int^3; s : S; u : int^2; v : S^2);
+             let
+                 t[0] = a;
+                 s.x = a;
+                 u[a] = a;
+                 v[0].x = a;
+                 v[1].x = a;
+                 v[0].y = a;
+                 q = a;
+             tel"
+            .into(),
+        );
+        crate::check(&driver);
+
+        let diagnostics = driver.effect::<Diagnostic>();
+        let hint = |name: &str| {
+            diagnostics
+                .iter()
+                .find(|d| d.message == format!("{name:?} is only partly defined"))
+                .map(|d| d.attachments[0].1.clone())
+        };
+        assert_eq!(hint("t").as_deref(), Some("hint: add an equation for t[1]"));
+        assert_eq!(hint("s").as_deref(), Some("hint: add an equation for s.y"));
+        assert_eq!(hint("u"), None);
+        assert_eq!(
+            hint("v").as_deref(),
+            Some("hint: add an equation for v[1].y")
+        );
+
+        let messages = diagnostics.iter().map(|d| &d.message).collect::<Vec<_>>();
+        assert!(messages.contains(&&"\"q\" is not an output or a local variable".to_owned()));
+    }
+
+    #[test]
+    fn package_declarations() {
+        let mut driver = crate::driver();
+        crate::add_source_contents(
+            &mut driver,
+            "package P
+               uses Q;
+               provides
+                 const k : int;
+                 type t;
+             body
+               type t = int;
+             end"
+            .into(),
+        );
+        crate::check(&driver);
+
+        let messages = driver
+            .effect::<Diagnostic>()
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert!(messages.contains(&"unknown package \"Q\"".to_owned()));
+        assert!(messages.contains(&"\"k\" is provided but never declared".to_owned()));
+        assert!(!messages.contains(&"\"t\" is provided but never declared".to_owned()));
+    }
+}
@@ -0,0 +1,732 @@
+//! Clock calculus
+//!
+//! Each flow in a Lustre program has a clock, that tells on which cycles it has a value. Inputs
+//! are on the base clock of the node (unless they are declared with a clock, as in
+//! `x : int when c`), and `when` and `current` respectively create and remove sub-clocks.
+//!
+//! Operators can only combine flows that are on the same clock. Constants are on any clock.
+//! `merge` combines flows on complementary sub-clocks into a flow on their parent clock.
+
+use crate::dataflow::left_item_name;
+use crate::diagnostics::{Diagnostic, Level, Span};
+use crate::iterators::{iterated_node_name, resolve_iterator};
+use crate::name_resolution::{resolve_extern_node, resolve_node};
+use rustre_parser::ast::{
+    AstNode, AstToken, CallByPosExpressionNode, ClockExpressionNode, ExpressionNode, IdNode, Ident,
+    MergeCaseNode, NodeNode, TypedIdsNode, VarDeclNode,
+};
+use rustre_parser::SyntaxNode;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use yeter::Database;
+
+/// Value of a clock variable on the cycles of a sub-clock
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ClockCase {
+    Bool(bool),
+    /// Constructor of an enumerated type
+    Constructor(String),
+}
+
+impl Display for ClockCase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClockCase::Bool(b) => write!(f, "{b}"),
+            ClockCase::Constructor(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Hash, PartialEq)]
+pub enum Clock {
+    /// Clock of the node itself
+    Base,
+    /// Cycles of the parent clock where a variable has a given value
+    On {
+        parent: Box<Clock>,
+        var: String,
+        case: ClockCase,
+    },
+}
+
+impl Display for Clock {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Clock::Base => write!(f, "base"),
+            Clock::On {
+                parent,
+                var,
+                case: ClockCase::Bool(true),
+            } => write!(f, "{parent} on {var}"),
+            Clock::On {
+                parent,
+                var,
+                case: ClockCase::Bool(false),
+            } => write!(f, "{parent} on not {var}"),
+            Clock::On {
+                parent,
+                var,
+                case: ClockCase::Constructor(name),
+            } => write!(f, "{parent} on {name}({var})"),
+        }
+    }
+}
+
+/// Returns the variable of a clock expression, and the value it has on the cycles of this clock
+///
+/// `c` and `not c` stand for `true` and `false`, and `Ctor(c)` for a constructor of an enumerated
+/// type.
+pub fn clock_condition(clock: &ClockExpressionNode) -> Option<(Ident, ClockCase)> {
+    let mut ids = clock.syntax().children().filter_map(IdNode::cast);
+    let first = ids.next()?;
+    Some(match ids.next() {
+        Some(var) => {
+            let ctor = first.name()?.text().to_owned();
+            (var.ident()?, ClockCase::Constructor(ctor))
+        }
+        None => (first.ident()?, ClockCase::Bool(clock.not().is_none())),
+    })
+}
+
+/// Returns the value of the clock variable a branch of a `merge` is selected for
+pub fn merge_case(case: &MergeCaseNode) -> Option<ClockCase> {
+    if case.is_true() {
+        Some(ClockCase::Bool(true))
+    } else if case.is_false() {
+        Some(ClockCase::Bool(false))
+    } else {
+        let ctor = case.id_node()?.name()?;
+        Some(ClockCase::Constructor(ctor.text().to_owned()))
+    }
+}
+
+/// Returns the clock with which variables are declared, if any
+fn declared_clock(ids: &TypedIdsNode) -> Option<ClockExpressionNode> {
+    let decl = ids.syntax().parent().and_then(VarDeclNode::cast)?;
+    decl.clock_expression_node()
+}
+
+/// Returns the clocks of the inputs, outputs and local variables of a node
+///
+/// Variables whose declared clock is invalid (because it refers to an unknown variable, or because
+/// clocks depend on each other) are on the base clock.
+pub fn declared_clocks(db: &Database, node: &NodeNode) -> HashMap<String, Clock> {
+    let sig = crate::get_signature(db, node.clone());
+    let locals = node
+        .all_var_decl_node()
+        .flat_map(|v| v.all_typed_ids_node())
+        .collect::<Vec<_>>();
+
+    let mut declarations = HashMap::new();
+    for ids in sig.params.iter().chain(&sig.return_params).chain(&locals) {
+        let clock = declared_clock(ids);
+        for ident in ids.all_ident() {
+            declarations.insert(ident.text().to_owned(), clock.clone());
+        }
+    }
+
+    fn resolve(
+        name: &str,
+        declarations: &HashMap<String, Option<ClockExpressionNode>>,
+        clocks: &mut HashMap<String, Clock>,
+        depth: usize,
+    ) -> Clock {
+        if let Some(clock) = clocks.get(name) {
+            return clock.clone();
+        }
+
+        let clock = declarations.get(name).cloned().flatten();
+        let clock = match clock.as_ref().and_then(clock_condition) {
+            Some((var, case))
+                if depth < declarations.len() && declarations.contains_key(var.text()) =>
+            {
+                Clock::On {
+                    parent: Box::new(resolve(var.text(), declarations, clocks, depth + 1)),
+                    var: var.text().to_owned(),
+                    case,
+                }
+            }
+            _ => Clock::Base,
+        };
+
+        clocks.insert(name.to_owned(), clock.clone());
+        clock
+    }
+
+    let mut clocks = HashMap::new();
+    for name in declarations.keys() {
+        resolve(name, &declarations, &mut clocks, 0);
+    }
+    clocks
+}
+
+/// Instantiates a clock declared in the signature of a node, for an instance that runs on `base`
+///
+/// `vars` gives the actual variables for the formal parameters of the node. Returns `None` if the
+/// clock depends on a parameter that is not given a variable.
+fn instantiate(clock: &Clock, base: &Clock, vars: &HashMap<String, String>) -> Option<Clock> {
+    match clock {
+        Clock::Base => Some(base.clone()),
+        Clock::On { parent, var, case } => Some(Clock::On {
+            parent: Box::new(instantiate(parent, base, vars)?),
+            var: vars.get(var)?.clone(),
+            case: case.clone(),
+        }),
+    }
+}
+
+/// Returns the clock on which an instance runs, given the actual clock of an argument that is
+/// declared on the `formal` clock
+fn instance_base(actual: &Clock, formal: &Clock) -> Option<Clock> {
+    match (actual, formal) {
+        (_, Clock::Base) => Some(actual.clone()),
+        (Clock::On { parent, .. }, Clock::On { parent: formal, .. }) => {
+            instance_base(parent, formal)
+        }
+        (Clock::Base, Clock::On { .. }) => None,
+    }
+}
+
+pub(crate) struct ClockChecker<'db> {
+    db: &'db Database,
+    clocks: HashMap<String, Clock>,
+    /// Only infers clocks, without reporting mismatches
+    quiet: bool,
+    /// Mismatches that have been found, only emitted once the whole node is checked: Yéter could
+    /// attach the effects of a query to the other queries it calls afterwards
+    diagnostics: RefCell<Vec<Diagnostic>>,
+}
+
+impl<'db> ClockChecker<'db> {
+    /// Creates a checker that infers the clocks of the expressions of a node without reporting
+    /// errors, for programs that are already known to be well-clocked
+    pub(crate) fn inference(db: &'db Database, node: &NodeNode) -> Self {
+        ClockChecker {
+            db,
+            clocks: declared_clocks(db, node),
+            quiet: true,
+            diagnostics: Default::default(),
+        }
+    }
+
+    /// Returns the declared clock of a variable
+    pub(crate) fn variable(&self, name: &str) -> Option<&Clock> {
+        self.clocks.get(name)
+    }
+
+    fn report(&self, diagnostic: Diagnostic) {
+        if !self.quiet {
+            self.diagnostics.borrow_mut().push(diagnostic);
+        }
+    }
+
+    /// Returns the number of values returned by a node
+    fn outputs(&self, name: IdNode) -> Option<usize> {
+        if let Some(node) = Option::clone(&resolve_node(self.db, name.clone())) {
+            let sig = crate::get_signature(self.db, node);
+            return Some(
+                sig.return_params
+                    .iter()
+                    .map(|p| p.all_ident().count())
+                    .sum(),
+            );
+        }
+
+        let ext = Option::clone(&resolve_extern_node(self.db, name))?;
+        Some(
+            crate::get_extern_signature(self.db, ext)
+                .return_params
+                .len(),
+        )
+    }
+
+    /// Checks that two flows are on the same clock, and returns this clock
+    ///
+    /// `None` stands for flows that can be on any clock.
+    fn unify(
+        &self,
+        (left, left_node): (Option<Clock>, &SyntaxNode),
+        (right, right_node): (Option<Clock>, &SyntaxNode),
+    ) -> Option<Clock> {
+        match (left, right) {
+            (Some(left), Some(right)) if left != right => {
+                self.report(
+                    Diagnostic::new(Level::Error, "clock mismatch")
+                        .with_attachment(
+                            Span::of_node(self.db, left_node),
+                            format!("this is on clock {left}"),
+                        )
+                        .with_attachment(
+                            Span::of_node(self.db, right_node),
+                            format!("while this is on clock {right}"),
+                        ),
+                );
+
+                // Don't report errors for the expressions using this one
+                None
+            }
+            (left, right) => left.or(right),
+        }
+    }
+
+    /// Unifies the clocks of a list of expressions, that must all be on the same clock
+    fn unify_all(&self, exprs: impl IntoIterator<Item = ExpressionNode>) -> Option<Clock> {
+        let mut unified: Option<(Option<Clock>, ExpressionNode)> = None;
+        for expr in exprs {
+            for clock in self.expr(&expr) {
+                unified = Some(match unified {
+                    None => (clock, expr.clone()),
+                    Some((previous, node)) => {
+                        let clock = self.unify((previous, node.syntax()), (clock, expr.syntax()));
+                        (clock, node)
+                    }
+                });
+            }
+        }
+
+        unified.and_then(|(clock, _)| clock)
+    }
+
+    /// Returns the clock on which a node call runs, or `None` if it can be on any clock
+    pub(crate) fn call_clock(&self, call: &CallByPosExpressionNode) -> Option<Clock> {
+        self.call(call).0
+    }
+
+    /// Returns the clock on which a node call runs, and the clocks of its results
+    ///
+    /// The clocks declared in the signature of the callee are relative to the clock on which it
+    /// runs, and refer to its parameters: they are instantiated with the actual arguments.
+    fn call(&self, e: &CallByPosExpressionNode) -> (Option<Clock>, Vec<Option<Clock>>) {
+        let name = e.node_ref().and_then(|r| r.id_node());
+
+        // Iterators return as many arrays as the node they iterate returns values
+        let is_iterator = name
+            .as_ref()
+            .is_some_and(|n| resolve_iterator(self.db, n).is_some());
+        let callee = name
+            .clone()
+            .filter(|_| !is_iterator)
+            .and_then(|n| Option::clone(&resolve_node(self.db, n)));
+        let Some(callee) = callee else {
+            let clock = self.unify_all(e.args().skip(1));
+            let callee = if is_iterator {
+                e.static_args_node()
+                    .and_then(|a| a.all_static_arg_node().next())
+                    .and_then(|a| iterated_node_name(&a))
+            } else {
+                name
+            };
+            let outputs = callee.and_then(|c| self.outputs(c)).unwrap_or(1);
+            return (clock.clone(), vec![clock; outputs]);
+        };
+
+        let sig = crate::get_signature(self.db, callee.clone());
+        let names = |ids: &[TypedIdsNode]| {
+            ids.iter()
+                .flat_map(|group| group.all_ident())
+                .map(|i| i.text().to_owned())
+                .collect::<Vec<_>>()
+        };
+        let declared = declared_clocks(self.db, &callee);
+        let formal = |param: &String| declared.get(param).cloned().unwrap_or(Clock::Base);
+
+        // Each value of the arguments, with its clock and the variable it is, if any
+        let mut args = vec![];
+        for arg in e.args().skip(1) {
+            let var = match &arg {
+                ExpressionNode::IdentExpressionNode(i) => i.id_node().and_then(|i| i.ident()),
+                _ => None,
+            };
+            for clock in self.expr(&arg) {
+                args.push((arg.clone(), clock, var.clone()));
+            }
+        }
+
+        let params = names(&sig.params);
+        let vars = params
+            .iter()
+            .zip(&args)
+            .filter_map(|(param, (_, _, var))| {
+                Some((param.clone(), var.as_ref()?.text().to_owned()))
+            })
+            .collect::<HashMap<_, _>>();
+        let base = params
+            .iter()
+            .zip(&args)
+            .find_map(|(param, (_, clock, _))| instance_base(clock.as_ref()?, &formal(param)));
+
+        for (param, (arg, clock, _)) in params.iter().zip(&args) {
+            let (Some(base), Some(clock)) = (&base, clock) else {
+                continue;
+            };
+            let Some(expected) = instantiate(&formal(param), base, &vars) else {
+                continue;
+            };
+
+            if clock != &expected {
+                let callee = e.node_ref().map(|r| r.syntax().clone());
+                let callee = callee.unwrap_or_else(|| e.syntax().clone());
+                self.report(
+                    Diagnostic::new(Level::Error, "clock mismatch")
+                        .with_attachment(
+                            Span::of_node(self.db, arg.syntax()),
+                            format!("this is on clock {clock}"),
+                        )
+                        .with_attachment(
+                            Span::of_node(self.db, &callee),
+                            format!("while this node expects it on clock {expected}"),
+                        ),
+                );
+            }
+        }
+
+        let outputs = names(&sig.return_params)
+            .iter()
+            .map(|param| {
+                let base = base.as_ref()?;
+                instantiate(&formal(param), base, &vars)
+            })
+            .collect();
+        (base, outputs)
+    }
+
+    fn operands(&self, expr: &ExpressionNode) -> Vec<ExpressionNode> {
+        expr.syntax()
+            .children()
+            .filter_map(ExpressionNode::cast)
+            .collect()
+    }
+
+    /// Returns the clock of each value of an expression
+    pub(crate) fn expr(&self, expr: &ExpressionNode) -> Vec<Option<Clock>> {
+        let clock = match expr {
+            ExpressionNode::ConstantNode(_) => None,
+            ExpressionNode::IdentExpressionNode(e) => e
+                .id_node()
+                .and_then(|i| i.ident())
+                .and_then(|i| self.clocks.get(i.text()).cloned()),
+            ExpressionNode::WhenExpressionNode(e) => {
+                let operand = e.left().and_then(|e| self.expr(&e).pop().flatten());
+                let condition = e.syntax().children().find_map(ClockExpressionNode::cast);
+                let Some((var, case)) = condition.as_ref().and_then(clock_condition) else {
+                    return vec![operand];
+                };
+                let Some(parent) = self.clocks.get(var.text()).cloned() else {
+                    return vec![operand];
+                };
+
+                if let (Some(operand), Some(left)) = (&operand, e.left()) {
+                    if operand != &parent {
+                        self.report(
+                            Diagnostic::new(Level::Error, "clock mismatch")
+                                .with_attachment(
+                                    Span::of_node(self.db, left.syntax()),
+                                    format!("this is on clock {operand}"),
+                                )
+                                .with_attachment(
+                                    Span::of_token(self.db, var.syntax()),
+                                    format!("while this condition is on clock {parent}"),
+                                ),
+                        );
+                    }
+                }
+
+                Some(Clock::On {
+                    parent: Box::new(parent),
+                    var: var.text().to_owned(),
+                    case,
+                })
+            }
+            ExpressionNode::MergeExpressionNode(e) => {
+                let Some(var) = e.id_node().and_then(|i| i.ident()) else {
+                    return vec![None];
+                };
+                let Some(parent) = self.clocks.get(var.text()).cloned() else {
+                    return vec![None];
+                };
+
+                // Each branch must be on the sub-clock for which it is selected
+                let mut width = 1;
+                for case in e.all_merge_case_node() {
+                    let (Some(value), Some(body)) = (merge_case(&case), case.expression_node())
+                    else {
+                        continue;
+                    };
+
+                    let expected = Clock::On {
+                        parent: Box::new(parent.clone()),
+                        var: var.text().to_owned(),
+                        case: value,
+                    };
+                    let clocks = self.expr(&body);
+                    width = clocks.len();
+                    if let Some(clock) = clocks.into_iter().flatten().find(|c| c != &expected) {
+                        self.report(
+                            Diagnostic::new(Level::Error, "clock mismatch")
+                                .with_attachment(
+                                    Span::of_node(self.db, body.syntax()),
+                                    format!("this is on clock {clock}"),
+                                )
+                                .with_attachment(
+                                    Span::of_node(self.db, case.syntax()),
+                                    format!("while this branch is on clock {expected}"),
+                                ),
+                        );
+                    }
+                }
+
+                return vec![Some(parent); width];
+            }
+            ExpressionNode::CurrentExpressionNode(e) => {
+                let operand = e.operand();
+                match operand.as_ref().map(|o| (o, self.expr(o).pop().flatten())) {
+                    Some((_, Some(Clock::On { parent, .. }))) => Some(*parent),
+                    Some((operand, Some(Clock::Base))) => {
+                        self.report(
+                            Diagnostic::new(Level::Error, "`current` of a flow on the base clock")
+                                .with_attachment(
+                                    Span::of_node(self.db, operand.syntax()),
+                                    "this is already on the base clock",
+                                ),
+                        );
+                        Some(Clock::Base)
+                    }
+                    _ => None,
+                }
+            }
+            ExpressionNode::ParExpressionNode(_) => {
+                return self
+                    .operands(expr)
+                    .iter()
+                    .flat_map(|e| self.expr(e))
+                    .collect()
+            }
+            ExpressionNode::IfExpressionNode(e) => {
+                let (Some(cond), Some(then), Some(otherwise)) =
+                    (e.cond(), e.if_body(), e.else_body())
+                else {
+                    return vec![self.unify_all(self.operands(expr))];
+                };
+
+                let cond_clock = self.expr(&cond).pop().flatten();
+                let clocks = self.expr(&then).into_iter().zip(self.expr(&otherwise));
+                return clocks
+                    .map(|(then_clock, otherwise_clock)| {
+                        let clock = self.unify(
+                            (then_clock, then.syntax()),
+                            (otherwise_clock, otherwise.syntax()),
+                        );
+                        self.unify((cond_clock.clone(), cond.syntax()), (clock, then.syntax()))
+                    })
+                    .collect();
+            }
+            ExpressionNode::WithExpressionNode(e) => {
+                return e.with_body().map(|b| self.expr(&b)).unwrap_or_default()
+            }
+            ExpressionNode::HatExpressionNode(e) => {
+                e.left().and_then(|l| self.expr(&l).pop().flatten())
+            }
+            ExpressionNode::CallByPosExpressionNode(e) => return self.call(e).1,
+            ExpressionNode::FbyExpressionNode(_) | ExpressionNode::ArrowExpressionNode(_) => {
+                let operands = self.operands(expr);
+                let (Some(left), Some(right)) = (operands.first(), operands.get(1)) else {
+                    return vec![self.unify_all(operands)];
+                };
+
+                // Both operands may be tuples
+                let clocks = self.expr(left).into_iter().zip(self.expr(right));
+                return clocks
+                    .map(|(l, r)| self.unify((l, left.syntax()), (r, right.syntax())))
+                    .collect();
+            }
+            ExpressionNode::PreExpressionNode(e) => {
+                return e.operand().map(|o| self.expr(&o)).unwrap_or_default()
+            }
+            ExpressionNode::FieldAccessExpressionNode(e) => {
+                e.left().and_then(|l| self.expr(&l).pop().flatten())
+            }
+            ExpressionNode::CallByNameExpressionNode(e) => {
+                let values = self.unify_all(
+                    e.all_call_by_name_param_node()
+                        .filter_map(|p| p.expression_node()),
+                );
+
+                // The updated structure is on the same clock as the new values of its fields
+                match e.base() {
+                    Some(base) => {
+                        let clock = base
+                            .ident()
+                            .and_then(|i| self.clocks.get(i.text()).cloned());
+                        self.unify((clock, base.syntax()), (values, e.syntax()))
+                    }
+                    None => values,
+                }
+            }
+            _ => self.unify_all(self.operands(expr)),
+        };
+
+        vec![clock]
+    }
+}
+
+/// **Query:** Checks that the flows combined in the equations of a node are on the same clock
+#[yeter::query]
+pub fn check_clocks(db: &Database, node: NodeNode) {
+    let Some(body) = node.body_node() else {
+        return;
+    };
+
+    let checker = ClockChecker {
+        db,
+        clocks: declared_clocks(db, &node),
+        quiet: false,
+        diagnostics: Default::default(),
+    };
+
+    for equation in body.all_equals_equation_node() {
+        let Some(expr) = equation.expression_node() else {
+            continue;
+        };
+
+        let lefts = equation
+            .left_node()
+            .into_iter()
+            .flat_map(|l| l.all_left_item_node());
+        for (left, clock) in lefts.zip(checker.expr(&expr)) {
+            let declared = left_item_name(&left).and_then(|n| checker.clocks.get(&n).cloned());
+            if let (Some(declared), Some(clock)) = (declared, clock) {
+                if declared != clock {
+                    checker.report(
+                        Diagnostic::new(Level::Error, "clock mismatch")
+                            .with_attachment(
+                                Span::of_node(db, left.syntax()),
+                                format!("this is declared on clock {declared}"),
+                            )
+                            .with_attachment(
+                                Span::of_node(db, expr.syntax()),
+                                format!("but this is on clock {clock}"),
+                            ),
+                    );
+                }
+            }
+        }
+    }
+
+    for diagnostic in checker.diagnostics.take() {
+        diagnostic.emit(db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::Diagnostic;
+
+    fn clock_errors(source: &str) -> Vec<String> {
+        let mut driver = crate::driver();
+        crate::add_source_contents(&mut driver, source.into());
+        crate::check(&driver);
+
+        driver
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| d.message.contains("clock") || d.message.contains("current"))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    #[test]
+    fn sub_clocks() {
+        let errors = clock_errors(
+            "node n(c : bool; x : int when c) returns (y : int);
+             var z : int when c; w : int when not c;
+             let
+                 z = x + 1;
+                 w = 0 when not c;
+                 y = current z;
+             tel",
+        );
+
+        assert!(errors.is_empty(), "{errors:?}");
+    }
+
+    #[test]
+    fn mismatches() {
+        let errors = clock_errors(
+            "node n(c : bool; x : int) returns (y, z, v : int);
+             var a : int when c;
+             let
+                 a = x when c;
+                 y = a + x;
+                 z = current x;
+                 v = x when c;
+             tel",
+        );
+
+        assert_eq!(
+            errors,
+            [
+                "this is on clock base on c",
+                "while this is on clock base",
+                "this is already on the base clock",
+                "this is declared on clock base",
+                "but this is on clock base on c",
+            ]
+        );
+    }
+
+    #[test]
+    fn merges() {
+        let errors = clock_errors(
+            "type color = enum { Red, Green };
+
+             node n(c : bool; col : color; x : int) returns (y, z, w : int);
+             var a : int when c; r : int when Red(col);
+             let
+                 a = x when c;
+                 r = x when Red(col);
+                 y = merge c (true -> a) (false -> 0);
+                 z = merge col (Red -> r) (Green -> x when Green(col));
+                 w = merge c (true -> x when not c) (false -> x when not c);
+             tel",
+        );
+
+        assert_eq!(
+            errors,
+            [
+                "this is on clock base on not c",
+                "while this branch is on clock base on c",
+            ]
+        );
+    }
+
+    #[test]
+    fn node_calls() {
+        let errors = clock_errors(
+            "node f(c : bool; x : int when c) returns (y : int when c);
+             let
+                 y = x;
+             tel
+
+             node n(c : bool; x : int) returns (y, z : int);
+             var a, b : int when c;
+             let
+                 a = f(c, x when c);
+                 b = f(c, x);
+                 y = current(f(c, a));
+                 z = f(c, a);
+             tel",
+        );
+
+        assert_eq!(
+            errors,
+            [
+                "this is on clock base",
+                "while this node expects it on clock base on c",
+                "this is declared on clock base",
+                "but this is on clock base on c",
+            ]
+        );
+    }
+}
@@ -0,0 +1,707 @@
+//! C backend
+//!
+//! The generated code only depends on the C99 standard library. Each node `N` is compiled to:
+//!
+//! ```c
+//! typedef struct { ... } N_state;
+//! void N_reset(N_state *self);
+//! void N_step(N_state *self, /* inputs */ int32_t x, /* outputs */ int32_t *y);
+//! ```
+//!
+//! `N_reset` must be called before the first cycle. Arrays are wrapped in structures so that they
+//! can be passed and assigned by value. Arrays and structures are compared with the `<type>_eq`
+//! functions declared next to them.
+//!
+//! Extern nodes are declared the same way, with an empty state, but their `N_reset` and `N_step`
+//! functions are not defined: they must be implemented next to the generated code.
+
+use super::{program_types, Access, BinaryOp, Expr, Memory, NodeCode, Statement, UnaryOp};
+use crate::types::{ConstValue, Type};
+use std::fmt::Write;
+
+/// Generated header and source files
+pub struct CProgram {
+    pub header: String,
+    pub source: String,
+}
+
+const KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "continue", "default", "do", "double", "enum", "extern",
+    "float", "for", "goto", "inline", "long", "register", "restrict", "return", "self", "short",
+    "signed", "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void",
+    "volatile", "while",
+];
+
+/// Renames variables that would clash with C keywords
+fn var_name(name: &str) -> String {
+    if KEYWORDS.contains(&name) {
+        format!("{name}_")
+    } else {
+        name.to_owned()
+    }
+}
+
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Boolean => "bool".into(),
+        Type::Integer => "int32_t".into(),
+        Type::Real => "float".into(),
+        Type::Array { elem, size } => format!("array_{}_{size}", type_suffix(elem)),
+        Type::Struct { name, .. } | Type::Enum { name, .. } => format!("{name}_t"),
+        Type::Unknown | Type::Function { .. } | Type::Tuple(_) | Type::Abstract(_) => "void".into(),
+    }
+}
+
+fn type_suffix(ty: &Type) -> String {
+    match ty {
+        Type::Boolean => "bool".into(),
+        Type::Integer => "int".into(),
+        Type::Real => "real".into(),
+        ty => type_name(ty),
+    }
+}
+
+/// Value used to initialize variables and memories before they are first assigned
+fn default_value(ty: &Type) -> String {
+    match ty {
+        Type::Boolean => "false".into(),
+        Type::Integer => "0".into(),
+        Type::Real => "0.0f".into(),
+        Type::Array { .. } | Type::Struct { .. } => format!("(({}){{0}})", type_name(ty)),
+        Type::Enum { constructors, .. } => constructor(ty, &constructors[0]),
+        Type::Unknown | Type::Function { .. } | Type::Tuple(_) | Type::Abstract(_) => "0".into(),
+    }
+}
+
+/// Name of a constructor, prefixed with the name of its type as constructors share a single
+/// namespace in C
+fn constructor(ty: &Type, ctor: &str) -> String {
+    match ty {
+        Type::Enum { name, .. } => format!("{name}_{ctor}"),
+        _ => unreachable!("constructors always have enumerated types"),
+    }
+}
+
+/// Compares two values, that can't be compared with `==` if they are arrays or structures
+fn equal(ty: &Type, left: &str, right: &str) -> String {
+    match ty {
+        Type::Array { .. } | Type::Struct { .. } => {
+            format!("{}_eq({left}, {right})", type_name(ty))
+        }
+        _ => format!("({left} == {right})"),
+    }
+}
+
+fn constant(value: &ConstValue) -> String {
+    match value {
+        ConstValue::Boolean(b) => b.to_string(),
+        ConstValue::Integer(i) if *i < 0 => format!("({i})"),
+        ConstValue::Integer(i) => i.to_string(),
+        ConstValue::Real(r) if *r < 0.0 => format!("({r:?}f)"),
+        ConstValue::Real(r) => format!("{r:?}f"),
+        ConstValue::Array(values) => {
+            let ty = type_name(&super::type_of_const(value));
+            let values = values.iter().map(constant).collect::<Vec<_>>();
+            format!("(({ty}){{{{{}}}}})", values.join(", "))
+        }
+        ConstValue::Enum(_) => unreachable!("constructors are converted to expressions"),
+        ConstValue::Struct(_) => unreachable!("structures are converted to expressions"),
+    }
+}
+
+fn expr(e: &Expr) -> String {
+    match e {
+        Expr::Const(value) => constant(value),
+        Expr::Var(name) => var_name(name),
+        Expr::Memory(name) | Expr::First(name) => format!("self->{name}"),
+        Expr::Unary(op, ty, operand) => {
+            let operand = expr(operand);
+            match op {
+                UnaryOp::Not => format!("(!{operand})"),
+                UnaryOp::Neg if *ty == Type::Integer => {
+                    format!("((int32_t)(0u - (uint32_t){operand}))")
+                }
+                UnaryOp::Neg => format!("(-{operand})"),
+                UnaryOp::ToInt => format!("((int32_t){operand})"),
+                UnaryOp::ToReal => format!("((float){operand})"),
+            }
+        }
+        Expr::Binary(op, ty, left, right) => {
+            let (left, right) = (expr(left), expr(right));
+            match op {
+                BinaryOp::Eq => return equal(ty, &left, &right),
+                BinaryOp::Neq if matches!(ty, Type::Array { .. } | Type::Struct { .. }) => {
+                    return format!("(!{})", equal(ty, &left, &right))
+                }
+                _ => (),
+            }
+
+            // Signed overflows are undefined behaviors in C, while integers wrap around in Lustre
+            if *ty == Type::Integer {
+                let wrapping = match op {
+                    BinaryOp::Add => Some("+"),
+                    BinaryOp::Sub => Some("-"),
+                    BinaryOp::Mul => Some("*"),
+                    _ => None,
+                };
+                if let Some(op) = wrapping {
+                    return format!("((int32_t)((uint32_t){left} {op} (uint32_t){right}))");
+                }
+            }
+
+            let op = match op {
+                BinaryOp::And => "&&",
+                BinaryOp::Or => "||",
+                BinaryOp::Xor | BinaryOp::Neq => "!=",
+                BinaryOp::Impl => return format!("(!{left} || {right})"),
+                BinaryOp::Eq => unreachable!("equality is handled above"),
+                BinaryOp::Lt => "<",
+                BinaryOp::Lte => "<=",
+                BinaryOp::Gt => ">",
+                BinaryOp::Gte => ">=",
+                BinaryOp::Add => "+",
+                BinaryOp::Sub => "-",
+                BinaryOp::Mul => "*",
+                BinaryOp::Div if *ty == Type::Integer => {
+                    return format!("rustre_div_int({left}, {right})")
+                }
+                BinaryOp::Div => "/",
+                BinaryOp::Mod if *ty == Type::Real => return format!("fmodf({left}, {right})"),
+                BinaryOp::Mod => return format!("rustre_mod_int({left}, {right})"),
+                BinaryOp::Pow if *ty == Type::Real => return format!("powf({left}, {right})"),
+                BinaryOp::Pow => return format!("rustre_pow_int({left}, {right})"),
+            };
+            format!("({left} {op} {right})")
+        }
+        Expr::If(cond, then, otherwise) => {
+            format!("({} ? {} : {})", expr(cond), expr(then), expr(otherwise))
+        }
+        Expr::Repeat(value, ty) => {
+            let Type::Array { size, .. } = ty else {
+                unreachable!("repeated values are always arrays");
+            };
+            let values = vec![expr(value); *size];
+            format!("(({}){{{{{}}}}})", type_name(ty), values.join(", "))
+        }
+        Expr::CountTrue(operands) if operands.is_empty() => "0".into(),
+        Expr::CountTrue(operands) => {
+            let operands = operands.iter().map(expr).collect::<Vec<_>>();
+            format!("({})", operands.join(" + "))
+        }
+        Expr::Array(ty, elements) => {
+            let elements = elements.iter().map(expr).collect::<Vec<_>>();
+            format!("(({}){{{{{}}}}})", type_name(ty), elements.join(", "))
+        }
+        Expr::Index(array, i, size) => format!("{}.a[{}]", expr(array), index(i, *size)),
+        Expr::CheckedIndex(i, size) => format!("rustre_index({}, {size})", expr(i)),
+        Expr::Constructor(ty, ctor) => constructor(ty, ctor),
+        Expr::Struct(ty, values) => {
+            let Type::Struct { fields, .. } = ty else {
+                unreachable!("structure values always have structure types");
+            };
+            let values = fields
+                .iter()
+                .zip(values)
+                .map(|((field, _), value)| format!(".{} = {}", var_name(field), expr(value)))
+                .collect::<Vec<_>>();
+            format!("(({}){{{}}})", type_name(ty), values.join(", "))
+        }
+        Expr::Field(value, field) => format!("{}.{}", expr(value), var_name(field)),
+    }
+}
+
+/// Writes an index into an array of a given size, checked unless it is constant
+fn index(i: &Expr, size: usize) -> String {
+    match i {
+        Expr::Const(_) => expr(i),
+        _ => format!("rustre_index({}, {size})", expr(i)),
+    }
+}
+
+/// Writes the path to a part of a variable
+fn path(path: &[Access]) -> String {
+    path.iter()
+        .map(|access| match access {
+            Access::Field(field) => format!(".{}", var_name(field)),
+            Access::Index(i, size) => format!(".a[{}]", index(i, *size)),
+        })
+        .collect()
+}
+
+fn signature(node: &NodeCode) -> String {
+    let mut params = vec![format!("{}_state *self", node.name)];
+    for input in &node.inputs {
+        params.push(format!(
+            "{} {}",
+            type_name(&input.ty),
+            var_name(&input.name)
+        ));
+    }
+    for output in &node.outputs {
+        params.push(format!("{} *{}_out", type_name(&output.ty), output.name));
+    }
+
+    format!("void {}_step({})", node.name, params.join(", "))
+}
+
+/// Writes the declaration of an array, structure or enumerated type, and the function comparing
+/// its values if `==` can't
+fn type_decl(h: &mut String, ty: &Type) -> std::fmt::Result {
+    let name = type_name(ty);
+    writeln!(h)?;
+    match ty {
+        Type::Enum { constructors, .. } => {
+            let constructors = constructors
+                .iter()
+                .map(|ctor| constructor(ty, ctor))
+                .collect::<Vec<_>>();
+            writeln!(h, "typedef enum {{ {} }} {name};", constructors.join(", "))
+        }
+        Type::Array { elem, size } => {
+            writeln!(
+                h,
+                "typedef struct {{ {} a[{size}]; }} {name};",
+                type_name(elem)
+            )?;
+            writeln!(h)?;
+            writeln!(h, "static inline bool {name}_eq({name} a, {name} b) {{")?;
+            writeln!(h, "    for (int i = 0; i < {size}; i++) {{")?;
+            let elements = equal(elem, "a.a[i]", "b.a[i]");
+            writeln!(h, "        if (!{elements}) return false;")?;
+            writeln!(h, "    }}")?;
+            writeln!(h, "    return true;")?;
+            writeln!(h, "}}")
+        }
+        Type::Struct { fields, .. } => {
+            writeln!(h, "typedef struct {{")?;
+            for (field, ty) in fields {
+                writeln!(h, "    {} {};", type_name(ty), var_name(field))?;
+            }
+            writeln!(h, "}} {name};")?;
+            writeln!(h)?;
+            writeln!(h, "static inline bool {name}_eq({name} a, {name} b) {{")?;
+            let fields = fields
+                .iter()
+                .map(|(field, ty)| {
+                    let field = var_name(field);
+                    equal(ty, &format!("a.{field}"), &format!("b.{field}"))
+                })
+                .collect::<Vec<_>>();
+            match fields.is_empty() {
+                true => writeln!(h, "    return true;")?,
+                false => writeln!(h, "    return {};", fields.join(" && "))?,
+            }
+            writeln!(h, "}}")
+        }
+        _ => unreachable!("only arrays, structures and enumerated types are declared"),
+    }
+}
+
+fn header(program: &[NodeCode], guard: &str) -> String {
+    let mut h = String::new();
+    writeln!(h, "#ifndef {guard}").unwrap();
+    writeln!(h, "#define {guard}").unwrap();
+    writeln!(h).unwrap();
+    writeln!(h, "#include <stdbool.h>").unwrap();
+    writeln!(h, "#include <stdint.h>").unwrap();
+
+    for ty in program_types(program) {
+        type_decl(&mut h, &ty).unwrap();
+    }
+
+    for node in program {
+        writeln!(h).unwrap();
+        if node.external {
+            writeln!(
+                h,
+                "/* Extern node, implemented outside of the generated code */"
+            )
+            .unwrap();
+        }
+        writeln!(h, "typedef struct {{").unwrap();
+        for memory in &node.memories {
+            match memory {
+                Memory::Value(var) => writeln!(h, "    {} {};", type_name(&var.ty), var.name),
+                Memory::First(name) => writeln!(h, "    bool {name};"),
+                Memory::Instance { name, node } => writeln!(h, "    {node}_state {name};"),
+            }
+            .unwrap();
+        }
+        if node.memories.is_empty() {
+            writeln!(h, "    char _unused;").unwrap();
+        }
+        writeln!(h, "}} {}_state;", node.name).unwrap();
+        writeln!(h).unwrap();
+        writeln!(h, "void {}_reset({}_state *self);", node.name, node.name).unwrap();
+        writeln!(h, "{};", signature(node)).unwrap();
+    }
+
+    writeln!(h).unwrap();
+    writeln!(h, "#endif").unwrap();
+    h
+}
+
+/// Integer operations that wrap around instead of overflowing, as in the interpreter
+///
+/// Products are computed on unsigned integers, and `INT32_MIN / -1` is handled separately.
+const INTEGER_HELPERS: &str = "\
+static inline int32_t rustre_pow_int(int32_t x, int32_t n) {
+    uint32_t result = 1, base = (uint32_t)x, exp = (uint32_t)n;
+    for (; exp > 0; exp >>= 1) {
+        if (exp & 1) result *= base;
+        base *= base;
+    }
+    return (int32_t)result;
+}
+
+static inline int32_t rustre_div_int(int32_t x, int32_t y) {
+    return y == -1 ? (int32_t)(0u - (uint32_t)x) : x / y;
+}
+
+static inline int32_t rustre_mod_int(int32_t x, int32_t y) {
+    return y == -1 ? 0 : x % y;
+}
+
+static inline int32_t rustre_index(int32_t i, int32_t size) {
+    if (i < 0 || i >= size) {
+        fprintf(stderr, \"index out of bounds: the length is %d but the index is %d\\n\", size, i);
+        abort();
+    }
+    return i;
+}
+";
+
+/// Writes a statement, indented by `depth` levels
+fn statement(c: &mut String, s: &Statement, depth: usize) -> std::fmt::Result {
+    let indent = "    ".repeat(depth);
+    match s {
+        Statement::Assign { var, value } => {
+            writeln!(c, "{indent}{} = {};", var_name(var), expr(value))
+        }
+        Statement::AssignPart {
+            var,
+            path: parts,
+            value,
+        } => {
+            let (var, parts) = (var_name(var), path(parts));
+            writeln!(c, "{indent}{var}{parts} = {};", expr(value))
+        }
+        Statement::Step {
+            instance,
+            node,
+            args,
+            results,
+        } => {
+            let mut args = args.iter().map(expr).collect::<Vec<_>>();
+            args.extend(results.iter().map(|r| format!("&{}", var_name(r))));
+            let args = args.join(", ");
+            writeln!(c, "{indent}{node}_step(&self->{instance}, {args});")
+        }
+        Statement::Store { memory, value } => {
+            writeln!(c, "{indent}self->{memory} = {};", expr(value))
+        }
+        Statement::ClearFirst(flag) => writeln!(c, "{indent}self->{flag} = false;"),
+        Statement::If { cond, then } => {
+            writeln!(c, "{indent}if ({}) {{", expr(cond))?;
+            for nested in then {
+                statement(c, nested, depth + 1)?;
+            }
+            writeln!(c, "{indent}}}")
+        }
+    }
+}
+
+fn source(program: &[NodeCode], header_name: &str) -> String {
+    let mut c = String::new();
+    writeln!(c, "#include \"{header_name}\"").unwrap();
+    writeln!(c, "#include <math.h>").unwrap();
+    writeln!(c, "#include <stdio.h>").unwrap();
+    writeln!(c, "#include <stdlib.h>").unwrap();
+    writeln!(c).unwrap();
+    c.push_str(INTEGER_HELPERS);
+
+    for node in program.iter().filter(|node| !node.external) {
+        writeln!(c).unwrap();
+        writeln!(c, "void {}_reset({}_state *self) {{", node.name, node.name).unwrap();
+        for memory in &node.memories {
+            match memory {
+                Memory::Value(var) => {
+                    let value = default_value(&var.ty);
+                    writeln!(c, "    self->{} = {value};", var.name).unwrap()
+                }
+                Memory::First(name) => writeln!(c, "    self->{name} = true;").unwrap(),
+                Memory::Instance { name, node } => {
+                    writeln!(c, "    {node}_reset(&self->{name});").unwrap()
+                }
+            }
+        }
+        writeln!(c, "}}").unwrap();
+
+        writeln!(c).unwrap();
+        writeln!(c, "{} {{", signature(node)).unwrap();
+        for var in node.outputs.iter().chain(&node.locals) {
+            let (ty, name) = (type_name(&var.ty), var_name(&var.name));
+            writeln!(c, "    {ty} {name} = {};", default_value(&var.ty)).unwrap();
+        }
+
+        for s in &node.step {
+            statement(&mut c, s, 1).unwrap();
+        }
+
+        for output in &node.outputs {
+            let name = &output.name;
+            writeln!(c, "    *{name}_out = {};", var_name(name)).unwrap();
+        }
+        writeln!(c, "}}").unwrap();
+    }
+
+    c
+}
+
+/// Generates C code for a lowered program
+///
+/// `name` is used to name the header file (`<name>.h`) that the source file includes.
+pub fn generate(program: &[NodeCode], name: &str) -> CProgram {
+    let guard = format!("RUSTRE_{}_H", name.to_uppercase());
+    CProgram {
+        header: header(program, &guard),
+        source: source(program, &format!("{name}.h")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::lower_program;
+    use crate::name_resolution::find_node;
+
+    #[test]
+    fn counter() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node counter(reset : bool) returns (n : int);
+             let
+                 n = if reset then 0 else (0 -> pre n) + 1;
+             tel
+
+             node main(x : bool) returns (a, b : int);
+             let
+                 a = counter(x);
+                 b = counter(not x);
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let program = lower_program(&db, main).unwrap();
+        let code = generate(&program, "main");
+
+        assert!(code
+            .header
+            .contains("void counter_reset(counter_state *self);"));
+        assert!(code
+            .header
+            .contains("void main_step(main_state *self, bool x, int32_t *a_out, int32_t *b_out);"));
+        assert!(code.header.find("} counter_state;") < code.header.find("} main_state;"));
+
+        assert!(code.source.contains("counter_reset(&self->"));
+        assert!(code.source.contains("(self->_f1 ? 0 : self->_m0)"));
+        assert!(code.source.contains("self->_m0 = "));
+    }
+
+    #[test]
+    fn wrapping_integers() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "function main(x, y : int) returns (a, b, c : int);
+             let
+                 a = x * y + 1;
+                 b = -x;
+                 c = x ** y;
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let code = generate(&lower_program(&db, main).unwrap(), "main");
+
+        assert!(code.source.contains(
+            "a = ((int32_t)((uint32_t)((int32_t)((uint32_t)x * (uint32_t)y)) + (uint32_t)1));"
+        ));
+        assert!(code.source.contains("b = ((int32_t)(0u - (uint32_t)x));"));
+        assert!(code.source.contains("c = rustre_pow_int(x, y);"));
+        assert!(code.source.contains("uint32_t result = 1"));
+    }
+
+    #[test]
+    fn array_equality() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type row = int^2;
+
+             node main(x : int) returns (same : bool);
+             var t : row^3;
+             let
+                 t = (x ^ 2) ^ 3;
+                 same = t <> (t -> pre t);
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let code = generate(&lower_program(&db, main).unwrap(), "main");
+
+        assert!(code
+            .header
+            .contains("if (!array_int_2_eq(a.a[i], b.a[i])) return false;"));
+        assert!(code
+            .source
+            .contains("array_array_int_2_3 t = ((array_array_int_2_3){0});"));
+        assert!(code
+            .source
+            .contains("same = (!array_array_int_2_3_eq(t, (self->_f2 ? t : self->_m1)));"));
+    }
+
+    #[test]
+    fn sub_clocks() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node main(c : bool; x : int) returns (y : int);
+             var s : int when not c;
+             let
+                 s = x when not c;
+                 y = current s;
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let code = generate(&lower_program(&db, main).unwrap(), "main");
+
+        assert!(code.source.contains(
+            "    if ((!c)) {
+        s = x;
+        self->_m1 = s;
+    }
+    y = self->_m1;"
+        ));
+    }
+
+    #[test]
+    fn structures() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type point = { x : int; y : int };
+
+             node main(x : int) returns (same : bool);
+             var p, q : point;
+             let
+                 p = point { y = x; x = 0 };
+                 q.x = p.y;
+                 q.y = 0;
+                 same = p = q;
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let code = generate(&lower_program(&db, main).unwrap(), "main");
+
+        assert!(code
+            .header
+            .contains("typedef struct {\n    int32_t x;\n    int32_t y;\n} point_t;"));
+        assert!(code.header.contains("return (a.x == b.x) && (a.y == b.y);"));
+        assert!(code.source.contains("p = ((point_t){.x = 0, .y = x});"));
+        assert!(code.source.contains("q.x = p.y;"));
+        assert!(code.source.contains("same = point_t_eq(p, q);"));
+    }
+
+    #[test]
+    fn enumerations() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type mode = enum { Idle, Run };
+
+             node main(x : int) returns (m : mode; y : int);
+             let
+                 m = if x > 0 then Run else Idle;
+                 y = merge m (Idle -> 0 when Idle(m)) (Run -> x when Run(m));
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let code = generate(&lower_program(&db, main).unwrap(), "main");
+
+        assert!(code
+            .header
+            .contains("typedef enum { mode_Idle, mode_Run } mode_t;"));
+        assert!(code.source.contains("mode_t m = mode_Idle;"));
+        assert!(code
+            .source
+            .contains("m = ((x > 0) ? mode_Run : mode_Idle);"));
+        assert!(code.source.contains("y = ((m == mode_Idle) ? 0 : x);"));
+    }
+
+    #[test]
+    fn array_accesses() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node main(i : int; t : int^3) returns (x : int; u : int^4);
+             let
+                 x = t[i] + t[1];
+                 u[0] = x;
+                 u[1 .. 3] = [i, 2] | t[0 .. 0];
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let code = generate(&lower_program(&db, main).unwrap(), "main");
+
+        assert!(code
+            .source
+            .contains("    u.a[1] = i;\n    u.a[2] = 2;\n    u.a[3] = t.a[0];"));
+        assert!(code
+            .source
+            .contains("t.a[rustre_index(i, 3)] + (uint32_t)t.a[1]"));
+        assert!(code.source.contains("u.a[0] = x;"));
+
+        // Out of bounds indices stop the program, as in the interpreter
+        assert!(code.source.contains("if (i < 0 || i >= size) {"));
+        assert!(code.source.contains("abort();"));
+    }
+
+    #[test]
+    fn extern_nodes() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "extern function sin(x : real) returns (y : real);
+
+             node main(a : real^2) returns (b : real^2);
+             let
+                 b = map<<sin, 2>>(a);
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let code = generate(&lower_program(&db, main).unwrap(), "main");
+
+        assert!(code
+            .header
+            .contains("void sin_step(sin_state *self, float x, float *y_out);"));
+        assert!(!code.source.contains("void sin_step("));
+        assert!(code.source.contains("sin_step(&self->_i1, a.a[0], &_t0);"));
+        assert!(code.source.contains("sin_step(&self->_i3, a.a[1], &_t2);"));
+        assert!(code.source.contains("b = ((array_real_2){{_t0, _t2}});"));
+    }
+}
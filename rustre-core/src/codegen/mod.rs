@@ -0,0 +1,2129 @@
+//! Code generation
+//!
+//! Nodes are first lowered to a small imperative representation ([NodeCode]): equations are
+//! scheduled according to their [dependencies][crate::causality], tuples are split into scalar
+//! values, and the state of each node (as described in [node_state][crate::node_state]) is made
+//! explicit. Backends then only have to print this representation in their target language.
+//!
+//! # State
+//!
+//! Each node has a state, made of [Memory] slots, that backends should represent as a structure.
+//! Every call site has its own instance of the called node in the state of the caller, even when
+//! the called node is stateless, so that all nodes can be called the same way.
+//!
+//! Backends must generate two functions for each node:
+//!
+//!   * `reset`, that sets all the [first-cycle flags][Memory::First] to `true`, the other
+//!     memories to default values, and resets the sub-instances recursively.
+//!   * `step`, that runs a cycle by executing [NodeCode::step], taking the inputs as arguments and
+//!     returning the outputs.
+//!
+//! Default values (`false`, `0`, `0.0`, the first constructor of enumerated types, and arrays and
+//! structures of them) are also given to the outputs and local variables at the beginning of each
+//! cycle, so that reading a memory or a variable before it is assigned is never undefined.
+//!
+//! # Instances and iterators
+//!
+//! Generic nodes are monomorphized: each [instance][crate::generics::Instance] of a generic node,
+//! made by an alias (`node m = n<<int, 4>>;`) or at a call site (`n<<int, 4>>(x)`), is lowered
+//! separately with its static arguments substituted. Calls to iterators (`map<<f, 4>>(a)`) are
+//! expanded to the applications of their node to each element, with an instance of the node for
+//! each of them.
+//!
+//! Extern nodes are lowered to [external][NodeCode::external] nodes, that have no memories nor
+//! statements: backends only declare them, and their `reset` and `step` functions have to be
+//! implemented by the user.
+//!
+//! # Clocks
+//!
+//! Flows on a sub-clock are only computed on the cycles of this clock: their assignments, the
+//! steps of the nodes called on them and the updates of their memories are [guarded][Statement::If]
+//! by the [clock][crate::clocks] they are on. The other cycles leave them untouched, so that
+//! `current` can read their last value. Outputs that are on a sub-clock have their default value
+//! on the cycles where they are absent.
+
+pub mod c;
+pub mod rust;
+
+use crate::causality::schedule;
+use crate::clocks::{clock_condition, merge_case, Clock, ClockCase, ClockChecker};
+use crate::diagnostics::{Diagnostic, Level, Span};
+use crate::eval::slice_indices;
+use crate::generics::{instance_of, resolve_callee, Callee, Instance};
+use crate::iterators::{iterated_node_name, resolve_iterator, ArrayIterator};
+use crate::name_resolution::resolve_type_decl;
+use crate::types::{type_check_expression, ConstValue, Type};
+use rustre_parser::ast::{
+    AstNode, AstToken, BinaryExpression, CallByNameExpressionNode, CallByPosExpressionNode,
+    ClockExpressionNode, EqualsEquationNode, ExpressionNode, ExternalNodeDeclNode, IdNode,
+    LeftItemNode, MergeExpressionNode, NodeNode, PredefOp, SelectNode, StaticArgNode,
+    UnaryExpression,
+};
+use std::collections::{HashMap, HashSet};
+use yeter::Database;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Memory {
+    /// Last value of the operand of a `pre`, of the second operand of a `fby`, or last present
+    /// value of the operand of a `current`
+    Value(Variable),
+    /// Flag that is set until the end of the first cycle (for `->` and `fby`)
+    First(String),
+    /// Instance of another node
+    Instance { name: String, node: String },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    ToInt,
+    ToReal,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    And,
+    Or,
+    Xor,
+    Impl,
+    Eq,
+    Neq,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+}
+
+/// Side-effect free expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Const(ConstValue),
+    /// Input, output or local variable (including temporaries)
+    Var(String),
+    /// Value stored in a [Memory::Value]
+    Memory(String),
+    /// Value of a [Memory::First] flag
+    First(String),
+    /// Unary operation, the type being the one of the operand
+    Unary(UnaryOp, Type, Box<Expr>),
+    /// Binary operation, the type being the one of the operands
+    Binary(BinaryOp, Type, Box<Expr>, Box<Expr>),
+    If(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// Array made of copies of the same value
+    Repeat(Box<Expr>, Type),
+    /// Array, with its type and the values of its elements
+    Array(Type, Vec<Expr>),
+    /// Element of an array of a given size
+    ///
+    /// Going out of the bounds of the array is not detected statically: indices that aren't
+    /// constant are checked, and the program stops with an error when they are out of bounds, as
+    /// the interpreter does.
+    Index(Box<Expr>, Box<Expr>, usize),
+    /// Index in an array of a given size, checked like the ones of [Expr::Index]
+    CheckedIndex(Box<Expr>, usize),
+    /// Number of `true` values among boolean expressions, as an integer
+    CountTrue(Vec<Expr>),
+    /// Constructor of an enumerated type
+    Constructor(Type, String),
+    /// Structure, with the values of its fields in declaration order
+    Struct(Type, Vec<Expr>),
+    /// Field of a structure
+    Field(Box<Expr>, String),
+}
+
+/// Step of the path from a variable to one of its parts
+#[derive(Clone, Debug, PartialEq)]
+pub enum Access {
+    Field(String),
+    /// Element of an array of a given size, checked like the ones of [Expr::Index]
+    Index(Expr, usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    Assign {
+        var: String,
+        value: Expr,
+    },
+    /// Assigns a part of a variable, whose other parts are assigned by other statements
+    AssignPart {
+        var: String,
+        path: Vec<Access>,
+        value: Expr,
+    },
+    /// Runs a cycle of a [Memory::Instance], and stores its outputs in variables
+    Step {
+        instance: String,
+        node: String,
+        args: Vec<Expr>,
+        results: Vec<String>,
+    },
+    /// Persists a value in a [Memory::Value] for the next cycle
+    Store {
+        memory: String,
+        value: Expr,
+    },
+    /// Marks the end of the first cycle for a [Memory::First] flag
+    ClearFirst(String),
+    /// Statements that are only executed when a condition holds, as on the cycles of a sub-clock
+    If {
+        cond: Expr,
+        then: Vec<Statement>,
+    },
+}
+
+/// Lowered node
+#[derive(Clone, Debug)]
+pub struct NodeCode {
+    pub name: String,
+    pub inputs: Vec<Variable>,
+    pub outputs: Vec<Variable>,
+    /// Local variables of the node, and temporaries introduced while lowering it
+    pub locals: Vec<Variable>,
+    pub memories: Vec<Memory>,
+    pub step: Vec<Statement>,
+    /// Extern node, whose `reset` and `step` functions are implemented outside of the generated
+    /// code
+    pub external: bool,
+}
+
+impl NodeCode {
+    /// Returns the type of an input, output or local variable
+    pub fn type_of(&self, var: &str) -> Option<&Type> {
+        self.inputs
+            .iter()
+            .chain(&self.outputs)
+            .chain(&self.locals)
+            .chain(self.memories.iter().filter_map(|m| match m {
+                Memory::Value(v) => Some(v),
+                _ => None,
+            }))
+            .find(|v| v.name == var)
+            .map(|v| &v.ty)
+    }
+}
+
+fn unsupported(db: &Database, node: &impl AstNode, what: &str) -> Diagnostic {
+    Diagnostic::new(Level::Error, "unsupported construct").with_attachment(
+        Span::of_node(db, node.syntax()),
+        format!("{what} can't be compiled yet"),
+    )
+}
+
+fn incomplete(db: &Database, node: &impl AstNode) -> Diagnostic {
+    Diagnostic::new(Level::Error, "incomplete expression").with_attachment(
+        Span::of_node(db, node.syntax()),
+        "some operands are missing",
+    )
+}
+
+/// Adds a statement that only runs when a condition holds, in the same block as the previous one if
+/// it has the same condition
+fn push_guarded(statements: &mut Vec<Statement>, cond: Option<Expr>, statement: Statement) {
+    let Some(cond) = cond else {
+        statements.push(statement);
+        return;
+    };
+
+    match statements.last_mut() {
+        Some(Statement::If { cond: last, then }) if *last == cond => then.push(statement),
+        _ => statements.push(Statement::If {
+            cond,
+            then: vec![statement],
+        }),
+    }
+}
+
+/// Part of a variable defined by a left item
+struct Target {
+    /// Condition for this part to be the one that is defined, when it depends on a dynamic index
+    cond: Option<Expr>,
+    path: Vec<Access>,
+    /// Element of the defined value that this part receives, when the left item is a slice
+    element: Option<usize>,
+}
+
+struct Lowering<'db> {
+    db: &'db Database,
+    instance: Instance,
+    variables: HashMap<String, Type>,
+    locals: Vec<Variable>,
+    memories: Vec<Memory>,
+    step: Vec<Statement>,
+    /// Operands whose values have to be stored in memories at the end of the cycle, with the
+    /// context they appear in
+    deferred: Vec<(ExpressionNode, Vec<String>, Clock)>,
+    /// First-cycle flags, with the clock of the cycles that clear them
+    flags: Vec<(String, Clock)>,
+    callees: Vec<Callee>,
+    clocks: ClockChecker<'db>,
+    /// Clock of the expression being lowered, for the values that can be on any clock (such as
+    /// constants)
+    context: Clock,
+}
+
+impl<'db> Lowering<'db> {
+    fn fresh(&self, prefix: &str) -> String {
+        format!("_{prefix}{}", self.locals.len() + self.memories.len())
+    }
+
+    fn temporary(&mut self, ty: Type) -> String {
+        let name = self.fresh("t");
+        self.locals.push(Variable {
+            name: name.clone(),
+            ty,
+        });
+        name
+    }
+
+    /// Returns a value that can be used several times without computing it again, storing it in a
+    /// temporary on the cycles of a clock if needed
+    fn shared(&mut self, value: Expr, ty: Type, clock: &Clock) -> Expr {
+        match value {
+            value if is_cheap(&value) => value,
+            value => {
+                let var = self.temporary(ty);
+                let assign = Statement::Assign {
+                    var: var.clone(),
+                    value,
+                };
+                self.push_on(clock, assign);
+                Expr::Var(var)
+            }
+        }
+    }
+
+    /// Returns the indices selected by a slice
+    fn slice(&self, select: &SelectNode) -> Result<Vec<i32>, Diagnostic> {
+        self.instance
+            .slice_bounds(self.db, select)
+            .and_then(|(first, last, step)| slice_indices(first, last, step))
+            .ok_or_else(|| {
+                Diagnostic::new(Level::Error, "invalid slice").with_attachment(
+                    Span::of_node(self.db, select.syntax()),
+                    "the bounds and the step must be valid constants",
+                )
+            })
+    }
+
+    /// Lowers an array operand that is used several times, and returns its size
+    fn array(
+        &mut self,
+        operand: Option<ExpressionNode>,
+        parent: &impl AstNode,
+    ) -> Result<(Expr, usize), Diagnostic> {
+        let Some(operand) = operand else {
+            return Err(incomplete(self.db, parent));
+        };
+
+        let value = self.operand(Some(operand.clone()), parent)?;
+        let ty = self.value_type(&operand, &value)?;
+        let Type::Array { size, .. } = ty else {
+            return Err(incomplete(self.db, parent));
+        };
+        let clock = self
+            .clocks_of(&operand)
+            .pop()
+            .unwrap_or_else(|| self.context.clone());
+        Ok((self.shared(value, ty, &clock), size))
+    }
+
+    /// Returns the clock of each value of an expression
+    fn clocks_of(&self, expr: &ExpressionNode) -> Vec<Clock> {
+        self.clocks
+            .expr(expr)
+            .into_iter()
+            .map(|clock| clock.unwrap_or_else(|| self.context.clone()))
+            .collect()
+    }
+
+    /// Returns the clock of the variable of a clock expression
+    fn clock_of_condition(&self, clock: &ClockExpressionNode) -> Option<Clock> {
+        let (var, _) = clock_condition(clock)?;
+        self.clocks.variable(var.text()).cloned()
+    }
+
+    /// Returns the condition that holds when a clock variable has a given value
+    fn case_condition(&self, var: &str, case: &ClockCase) -> Expr {
+        let value = Box::new(Expr::Var(var.to_owned()));
+        match case {
+            ClockCase::Bool(true) => *value,
+            ClockCase::Bool(false) => Expr::Unary(UnaryOp::Not, Type::Boolean, value),
+            ClockCase::Constructor(ctor) => {
+                let ty = self.variables.get(var).cloned().unwrap_or_default();
+                let ctor = Box::new(Expr::Constructor(ty.clone(), ctor.clone()));
+                Expr::Binary(BinaryOp::Eq, ty, value, ctor)
+            }
+        }
+    }
+
+    /// Returns the condition that holds on the cycles of a clock, or `None` for the base clock
+    fn condition(&self, clock: &Clock) -> Option<Expr> {
+        let Clock::On { parent, var, case } = clock else {
+            return None;
+        };
+
+        let cond = self.case_condition(var, case);
+        Some(match self.condition(parent) {
+            Some(parent) => Expr::Binary(
+                BinaryOp::And,
+                Type::Boolean,
+                Box::new(parent),
+                Box::new(cond),
+            ),
+            None => cond,
+        })
+    }
+
+    /// Adds a statement to the step, that only runs on the cycles of a clock
+    fn push_on(&mut self, clock: &Clock, statement: Statement) {
+        let cond = self.condition(clock);
+        push_guarded(&mut self.step, cond, statement);
+    }
+
+    /// Lowers an expression in the context of another clock
+    fn expr_on(&mut self, clock: Clock, expr: &ExpressionNode) -> Result<Vec<Expr>, Diagnostic> {
+        let context = std::mem::replace(&mut self.context, clock);
+        let values = self.expr(expr);
+        self.context = context;
+        values
+    }
+
+    fn type_of(&self, expr: &ExpressionNode) -> Result<Vec<Type>, Diagnostic> {
+        // Variables are typed by their declarations, resolved for the instance being lowered
+        let var = match expr {
+            ExpressionNode::IdentExpressionNode(e) => e.id_node().and_then(|i| i.ident()),
+            _ => None,
+        };
+        if let Some(ty) = var.and_then(|var| self.variables.get(var.text())) {
+            return Ok(vec![ty.clone()]);
+        }
+
+        let node = Some(self.instance.node.clone());
+        let ty = type_check_expression(self.db, expr, &node, None);
+        match self.instance.statics.substitute(&ty) {
+            Type::Tuple(types) => Ok(types),
+            Type::Unknown => Err(unknown_type(self.db, expr)),
+            ty => Ok(vec![ty]),
+        }
+    }
+
+    /// Returns the type of an expression, if it is fully known before lowering it
+    fn known_type(&self, expr: &ExpressionNode) -> Option<Type> {
+        let mut types = self.type_of(expr).ok()?;
+        match types.len() {
+            1 => Some(types.remove(0)).filter(is_known),
+            _ => None,
+        }
+    }
+
+    /// Returns the type of an expression, given its lowered value
+    ///
+    /// In generic nodes, the types of expressions may depend on static arguments (as the sizes of
+    /// arrays): they are then found from the types of the values they are made of.
+    fn value_type(&self, expr: &ExpressionNode, value: &Expr) -> Result<Type, Diagnostic> {
+        if let Some(ty) = self.known_type(expr) {
+            return Ok(ty);
+        }
+
+        Some(self.type_of_value(value))
+            .filter(is_known)
+            .ok_or_else(|| unknown_type(self.db, expr))
+    }
+
+    /// Returns the type of a lowered expression
+    fn type_of_value(&self, value: &Expr) -> Type {
+        match value {
+            Expr::Const(value) => type_of_const(value),
+            Expr::Var(var) => self
+                .variables
+                .get(var)
+                .or_else(|| self.locals.iter().find(|v| v.name == *var).map(|v| &v.ty))
+                .cloned()
+                .unwrap_or_default(),
+            Expr::Memory(memory) => self
+                .memories
+                .iter()
+                .find_map(|m| match m {
+                    Memory::Value(v) if v.name == *memory => Some(v.ty.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default(),
+            Expr::First(_) | Expr::Unary(UnaryOp::Not, ..) => Type::Boolean,
+            Expr::Unary(UnaryOp::Neg, ty, _) => ty.clone(),
+            Expr::Unary(UnaryOp::ToInt, ..) | Expr::CountTrue(_) | Expr::CheckedIndex(..) => {
+                Type::Integer
+            }
+            Expr::Unary(UnaryOp::ToReal, ..) => Type::Real,
+            Expr::Binary(op, ty, ..) => match op {
+                BinaryOp::Add
+                | BinaryOp::Sub
+                | BinaryOp::Mul
+                | BinaryOp::Div
+                | BinaryOp::Mod
+                | BinaryOp::Pow => ty.clone(),
+                _ => Type::Boolean,
+            },
+            Expr::If(_, then, _) => self.type_of_value(then),
+            Expr::Repeat(_, ty)
+            | Expr::Array(ty, _)
+            | Expr::Constructor(ty, _)
+            | Expr::Struct(ty, _) => ty.clone(),
+            Expr::Index(array, ..) => match self.type_of_value(array) {
+                Type::Array { elem, .. } => *elem,
+                _ => Type::Unknown,
+            },
+            Expr::Field(value, field) => self
+                .type_of_value(value)
+                .field(field)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Returns the type of an array of lowered values
+    fn array_type(&self, elements: &[Expr]) -> Type {
+        let elem = elements.first().map(|e| self.type_of_value(e));
+        Type::Array {
+            elem: Box::new(elem.unwrap_or_default()),
+            size: elements.len(),
+        }
+    }
+
+    /// Builds an array out of lowered elements, with the type of an expression if it is known
+    fn array_of(&self, expr: &ExpressionNode, elements: Vec<Expr>) -> Expr {
+        let ty = self
+            .known_type(expr)
+            .unwrap_or_else(|| self.array_type(&elements));
+        Expr::Array(ty, elements)
+    }
+
+    fn constant(&self, expr: &ExpressionNode) -> Result<Expr, Diagnostic> {
+        let value = self.const_value(expr)?;
+        let ty = self
+            .known_type(expr)
+            .unwrap_or_else(|| type_of_const(&value));
+        Ok(constant_expr(value, &ty))
+    }
+
+    fn const_value(&self, expr: &ExpressionNode) -> Result<ConstValue, Diagnostic> {
+        match self.instance.eval(self.db, expr.clone()) {
+            Some(value) => Ok(value),
+            None => Err(
+                Diagnostic::new(Level::Error, "unknown value").with_attachment(
+                    Span::of_node(self.db, expr.syntax()),
+                    "this is neither a variable nor a constant",
+                ),
+            ),
+        }
+    }
+
+    fn operand(
+        &mut self,
+        operand: Option<ExpressionNode>,
+        parent: &impl AstNode,
+    ) -> Result<Expr, Diagnostic> {
+        let Some(operand) = operand else {
+            return Err(incomplete(self.db, parent));
+        };
+
+        let mut values = self.expr(&operand)?;
+        if values.len() != 1 {
+            return Err(unsupported(self.db, &operand, "tuples used as operands"));
+        }
+        Ok(values.remove(0))
+    }
+
+    fn unary<E>(&mut self, e: &E, op: UnaryOp) -> Result<Vec<Expr>, Diagnostic>
+    where
+        E: UnaryExpression + AstNode,
+    {
+        let Some(operand) = e.operand() else {
+            return Err(incomplete(self.db, e));
+        };
+
+        let value = self.operand(Some(operand.clone()), e)?;
+        let ty = self.value_type(&operand, &value)?;
+        Ok(vec![Expr::Unary(op, ty, Box::new(value))])
+    }
+
+    fn binary<E>(&mut self, e: &E, op: BinaryOp) -> Result<Vec<Expr>, Diagnostic>
+    where
+        E: BinaryExpression + AstNode,
+    {
+        let Some(left) = e.left() else {
+            return Err(incomplete(self.db, e));
+        };
+
+        let value = self.operand(Some(left.clone()), e)?;
+        let ty = self.value_type(&left, &value)?;
+        let right = self.operand(e.right(), e)?;
+        Ok(vec![Expr::Binary(op, ty, Box::new(value), Box::new(right))])
+    }
+
+    /// Allocates memories for the values of an expression, that are stored at the end of the cycle
+    ///
+    /// Types that depend on static arguments are only known once the operands are lowered.
+    fn memories_for(&mut self, operand: &ExpressionNode) -> Result<Vec<String>, Diagnostic> {
+        let types = match self.type_of(operand) {
+            Ok(types) => types,
+            Err(_) => vec![Type::Unknown; self.clocks.expr(operand).len()],
+        };
+
+        let mut names = vec![];
+        for ty in types {
+            let name = self.fresh("m");
+            self.memories.push(Memory::Value(Variable {
+                name: name.clone(),
+                ty,
+            }));
+            names.push(name);
+        }
+
+        let context = self.context.clone();
+        self.deferred
+            .push((operand.clone(), names.clone(), context));
+        Ok(names)
+    }
+
+    /// Allocates a first-cycle flag for an `->` or a `fby`, that is cleared on the cycles of the
+    /// clock of this expression
+    fn first_flag(&mut self, expr: &ExpressionNode) -> String {
+        let clock = self
+            .clocks_of(expr)
+            .pop()
+            .unwrap_or_else(|| self.context.clone());
+        let name = self.fresh("f");
+        self.memories.push(Memory::First(name.clone()));
+        self.flags.push((name.clone(), clock));
+        name
+    }
+
+    fn call(
+        &mut self,
+        expr: &ExpressionNode,
+        call: &CallByPosExpressionNode,
+    ) -> Result<Vec<Expr>, Diagnostic> {
+        let name = call.node_ref().and_then(|n| n.id_node());
+        let iterator = name.as_ref().and_then(|n| resolve_iterator(self.db, n));
+
+        // Nodes run on the clock of their arguments that are on their own base clock
+        let clock = self.clocks.call_clock(call);
+        let clock = clock.unwrap_or_else(|| self.context.clone());
+        if let Some(iterator) = iterator {
+            return self.iterate(expr, call, iterator, clock);
+        }
+
+        let callee = name.and_then(|name| {
+            resolve_callee(self.db, &name, call.static_args_node(), &self.instance)
+        });
+        let mut args = vec![];
+        for arg in call.args().skip(1) {
+            args.extend(self.expr(&arg)?);
+        }
+        self.step(expr, callee, args, clock)
+    }
+
+    /// Runs a cycle of an instance of a node on the cycles of a clock, and returns its outputs
+    fn step(
+        &mut self,
+        expr: &ExpressionNode,
+        callee: Option<Callee>,
+        args: Vec<Expr>,
+        clock: Clock,
+    ) -> Result<Vec<Expr>, Diagnostic> {
+        let Some(callee) = callee else {
+            return Err(
+                Diagnostic::new(Level::Error, "cannot compile call").with_attachment(
+                    Span::of_node(self.db, expr.syntax()),
+                    "this node is unknown, or generic and not given static arguments",
+                ),
+            );
+        };
+
+        let sig = callee.signature(self.db);
+        let results = sig
+            .return_params
+            .iter()
+            .map(|(_, ty)| self.temporary(ty.clone()))
+            .collect::<Vec<_>>();
+
+        let node = callee.name();
+        let instance = self.fresh("i");
+        self.memories.push(Memory::Instance {
+            name: instance.clone(),
+            node: node.clone(),
+        });
+        if !self.callees.iter().any(|c| c.name() == node) {
+            self.callees.push(callee);
+        }
+
+        let step = Statement::Step {
+            instance,
+            node,
+            args,
+            results: results.clone(),
+        };
+        self.push_on(&clock, step);
+        Ok(results.into_iter().map(Expr::Var).collect())
+    }
+
+    /// Expands a call to an iterator to the applications of its node to the elements of arrays
+    fn iterate(
+        &mut self,
+        expr: &ExpressionNode,
+        call: &CallByPosExpressionNode,
+        iterator: ArrayIterator,
+        clock: Clock,
+    ) -> Result<Vec<Expr>, Diagnostic> {
+        let statics = call
+            .static_args_node()
+            .map(|args| args.all_static_arg_node().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let Some(node_arg) = statics.first() else {
+            return Err(incomplete(self.db, call));
+        };
+
+        // Sizes, and the bounds of `boolred`, are constant integers
+        let const_args = match iterator {
+            ArrayIterator::BoolRed => &statics[..],
+            _ => &statics[1..],
+        };
+        let mut consts = vec![];
+        for arg in const_args {
+            let value = arg.expression_node().map(|e| self.const_value(&e));
+            match value.transpose()? {
+                Some(ConstValue::Integer(i)) if i >= 0 => consts.push(i as usize),
+                _ => return Err(incomplete(self.db, arg)),
+            }
+        }
+        let Some(&size) = consts.last() else {
+            return Err(incomplete(self.db, call));
+        };
+        let mut operands = call.args().skip(1);
+
+        if iterator == ArrayIterator::BoolRed {
+            let (array, _) = self.array(operands.next(), call)?;
+            let elements = (0..size).map(|k| element(&array, k, size)).collect();
+            let count = self.shared(Expr::CountTrue(elements), Type::Integer, &clock);
+            let at_least = Expr::Binary(
+                BinaryOp::Lte,
+                Type::Integer,
+                Box::new(int(consts[0])),
+                Box::new(count.clone()),
+            );
+            let at_most = Expr::Binary(
+                BinaryOp::Lte,
+                Type::Integer,
+                Box::new(count),
+                Box::new(int(consts[1])),
+            );
+            let holds = Expr::Binary(
+                BinaryOp::And,
+                Type::Boolean,
+                Box::new(at_least),
+                Box::new(at_most),
+            );
+            return Ok(vec![holds]);
+        }
+
+        if size == 0 {
+            return Err(unsupported(self.db, call, "iterations over empty arrays"));
+        }
+
+        let op = node_arg.predef_op();
+        let callee = match op {
+            Some(_) => None,
+            None => {
+                let args = node_arg
+                    .effective_node_node()
+                    .and_then(|e| e.static_args_node());
+                iterated_node_name(node_arg)
+                    .and_then(|name| resolve_callee(self.db, &name, args, &self.instance))
+            }
+        };
+
+        let mut acc = match iterator.accumulates() {
+            true => Some(self.operand(operands.next(), call)?),
+            false => None,
+        };
+        let mut arrays = vec![];
+        for operand in operands {
+            arrays.push(self.array(Some(operand), call)?.0);
+        }
+
+        // Each application has its own instance of the node
+        let mut outputs: Vec<Vec<Expr>> = vec![];
+        for k in 0..size {
+            let mut inputs = acc.take().into_iter().collect::<Vec<_>>();
+            inputs.extend(arrays.iter().map(|array| element(array, k, size)));
+            let mut results = match &op {
+                Some(op) => vec![self.predef_op(op, inputs, node_arg)?],
+                None => self.step(expr, callee.clone(), inputs, clock.clone())?,
+            };
+
+            if iterator.accumulates() {
+                let next = results.remove(0);
+                let ty = self.type_of_value(&next);
+                acc = Some(self.shared(next, ty, &clock));
+            }
+            outputs.resize(results.len(), vec![]);
+            for (output, result) in outputs.iter_mut().zip(results) {
+                output.push(result);
+            }
+        }
+
+        let arrays = outputs
+            .into_iter()
+            .map(|elements| Expr::Array(self.array_type(&elements), elements));
+        Ok(acc.into_iter().chain(arrays).collect())
+    }
+
+    /// Applies a predefined operator given to an iterator (as `+` in `red<<+, 4>>`)
+    fn predef_op(
+        &self,
+        op: &PredefOp,
+        inputs: Vec<Expr>,
+        arg: &StaticArgNode,
+    ) -> Result<Expr, Diagnostic> {
+        let binary = if op.and().is_some() {
+            Some(BinaryOp::And)
+        } else if op.or().is_some() {
+            Some(BinaryOp::Or)
+        } else if op.xor().is_some() {
+            Some(BinaryOp::Xor)
+        } else if op.r#impl().is_some() {
+            Some(BinaryOp::Impl)
+        } else if op.equal().is_some() {
+            Some(BinaryOp::Eq)
+        } else if op.neq().is_some() {
+            Some(BinaryOp::Neq)
+        } else if op.lt().is_some() {
+            Some(BinaryOp::Lt)
+        } else if op.lte().is_some() {
+            Some(BinaryOp::Lte)
+        } else if op.gt().is_some() {
+            Some(BinaryOp::Gt)
+        } else if op.gte().is_some() {
+            Some(BinaryOp::Gte)
+        } else if op.plus().is_some() {
+            Some(BinaryOp::Add)
+        } else if op.minus().is_some() {
+            Some(BinaryOp::Sub)
+        } else if op.star().is_some() {
+            Some(BinaryOp::Mul)
+        } else if op.slash().is_some() || op.div().is_some() {
+            Some(BinaryOp::Div)
+        } else if op.r#mod().is_some() {
+            Some(BinaryOp::Mod)
+        } else {
+            None
+        };
+
+        let ty = inputs.first().map(|i| self.type_of_value(i));
+        let mut inputs = inputs.into_iter().map(Box::new);
+        let mut operand = || inputs.next().ok_or_else(|| incomplete(self.db, arg));
+        if let Some(binary) = binary {
+            let (left, right) = (operand()?, operand()?);
+            return Ok(Expr::Binary(binary, ty.unwrap_or_default(), left, right));
+        }
+
+        if op.not().is_some() {
+            Ok(Expr::Unary(UnaryOp::Not, Type::Boolean, operand()?))
+        } else if op.r#if().is_some() {
+            Ok(Expr::If(operand()?, operand()?, operand()?))
+        } else {
+            Err(unsupported(
+                self.db,
+                arg,
+                "temporal operators given to iterators",
+            ))
+        }
+    }
+
+    fn expr(&mut self, expr: &ExpressionNode) -> Result<Vec<Expr>, Diagnostic> {
+        let value = match expr {
+            ExpressionNode::ConstantNode(_) => self.constant(expr)?,
+            ExpressionNode::IdentExpressionNode(e) => {
+                let Some(ident) = e.id_node().and_then(|i| i.ident()) else {
+                    return Err(incomplete(self.db, e));
+                };
+
+                if self.variables.contains_key(ident.text()) {
+                    Expr::Var(ident.text().into())
+                } else {
+                    self.constant(expr)?
+                }
+            }
+            ExpressionNode::NotExpressionNode(e) => return self.unary(e, UnaryOp::Not),
+            ExpressionNode::NegExpressionNode(e) => return self.unary(e, UnaryOp::Neg),
+            ExpressionNode::IntExpressionNode(e) => return self.unary(e, UnaryOp::ToInt),
+            ExpressionNode::RealExpressionNode(e) => return self.unary(e, UnaryOp::ToReal),
+            ExpressionNode::PreExpressionNode(e) => {
+                let Some(operand) = e.operand() else {
+                    return Err(incomplete(self.db, e));
+                };
+
+                let memories = self.memories_for(&operand)?;
+                return Ok(memories.into_iter().map(Expr::Memory).collect());
+            }
+            ExpressionNode::FbyExpressionNode(e) => {
+                let (Some(first), Some(then)) = (e.left(), e.right()) else {
+                    return Err(incomplete(self.db, e));
+                };
+
+                let first = self.expr(&first)?;
+                let memories = self.memories_for(&then)?;
+                let flag = self.first_flag(expr);
+                return Ok(first
+                    .into_iter()
+                    .zip(memories)
+                    .map(|(first, memory)| {
+                        let first_cycle = Box::new(Expr::First(flag.clone()));
+                        Expr::If(first_cycle, Box::new(first), Box::new(Expr::Memory(memory)))
+                    })
+                    .collect());
+            }
+            ExpressionNode::ArrowExpressionNode(e) => {
+                let (Some(first), Some(then)) = (e.left(), e.right()) else {
+                    return Err(incomplete(self.db, e));
+                };
+
+                let first = self.expr(&first)?;
+                let then = self.expr(&then)?;
+                let flag = self.first_flag(expr);
+                return Ok(first
+                    .into_iter()
+                    .zip(then)
+                    .map(|(first, then)| {
+                        let first_cycle = Box::new(Expr::First(flag.clone()));
+                        Expr::If(first_cycle, Box::new(first), Box::new(then))
+                    })
+                    .collect());
+            }
+            ExpressionNode::CurrentExpressionNode(e) => {
+                let Some(operand) = e.operand() else {
+                    return Err(incomplete(self.db, e));
+                };
+
+                // The last present values are kept in memories, that are updated right away
+                let values = self.expr(&operand)?;
+                let clocks = self.clocks_of(&operand);
+                let types = self
+                    .type_of(&operand)
+                    .ok()
+                    .filter(|types| types.iter().all(is_known))
+                    .unwrap_or_else(|| values.iter().map(|v| self.type_of_value(v)).collect());
+                let mut memories = vec![];
+                for ((value, clock), ty) in values.into_iter().zip(clocks).zip(types) {
+                    let memory = self.fresh("m");
+                    self.memories.push(Memory::Value(Variable {
+                        name: memory.clone(),
+                        ty,
+                    }));
+
+                    let store = Statement::Store {
+                        memory: memory.clone(),
+                        value,
+                    };
+                    self.push_on(&clock, store);
+                    memories.push(Expr::Memory(memory));
+                }
+                return Ok(memories);
+            }
+            ExpressionNode::WhenExpressionNode(e) => {
+                let Some(operand) = e.left() else {
+                    return Err(incomplete(self.db, e));
+                };
+
+                // Sampling doesn't change values, only the cycles on which they are used
+                let clock = e.syntax().children().find_map(ClockExpressionNode::cast);
+                return match clock.and_then(|c| self.clock_of_condition(&c)) {
+                    Some(parent) => self.expr_on(parent, &operand),
+                    None => self.expr(&operand),
+                };
+            }
+            ExpressionNode::MergeExpressionNode(e) => return self.merge(e),
+            ExpressionNode::FieldAccessExpressionNode(e) => {
+                let Some(field) = e.field() else {
+                    return Err(incomplete(self.db, e));
+                };
+
+                let value = self.operand(e.left(), e)?;
+                Expr::Field(Box::new(value), field.text().to_owned())
+            }
+            ExpressionNode::CallByNameExpressionNode(e) => return self.call_by_name(expr, e),
+            ExpressionNode::ArrayAccessExpressionNode(e) => {
+                let Some(select) = e.select_node() else {
+                    let (array, size) = self.array(e.array(), e)?;
+                    let index = self.operand(e.index(), e)?;
+                    return Ok(vec![Expr::Index(Box::new(array), Box::new(index), size)]);
+                };
+
+                // Slices are expanded to the elements they select
+                let (array, size) = self.array(e.array(), e)?;
+                let elements = self
+                    .slice(&select)?
+                    .into_iter()
+                    .map(|i| element(&array, i as usize, size))
+                    .collect::<Vec<_>>();
+                self.array_of(expr, elements)
+            }
+            ExpressionNode::ArrayLiteralExpressionNode(e) => {
+                let mut elements = vec![];
+                for element in e.elements() {
+                    elements.push(self.operand(Some(element), e)?);
+                }
+                self.array_of(expr, elements)
+            }
+            ExpressionNode::ConcatExpressionNode(e) => {
+                let (left, left_size) = self.array(e.left(), e)?;
+                let (right, right_size) = self.array(e.right(), e)?;
+                let left = (0..left_size).map(|i| element(&left, i, left_size));
+                let right = (0..right_size).map(|i| element(&right, i, right_size));
+                self.array_of(expr, left.chain(right).collect())
+            }
+            ExpressionNode::AndExpressionNode(e) => return self.binary(e, BinaryOp::And),
+            ExpressionNode::OrExpressionNode(e) => return self.binary(e, BinaryOp::Or),
+            ExpressionNode::XorExpressionNode(e) => return self.binary(e, BinaryOp::Xor),
+            ExpressionNode::ImplExpressionNode(e) => return self.binary(e, BinaryOp::Impl),
+            ExpressionNode::EqExpressionNode(e) => return self.binary(e, BinaryOp::Eq),
+            ExpressionNode::NeqExpressionNode(e) => return self.binary(e, BinaryOp::Neq),
+            ExpressionNode::LtExpressionNode(e) => return self.binary(e, BinaryOp::Lt),
+            ExpressionNode::LteExpressionNode(e) => return self.binary(e, BinaryOp::Lte),
+            ExpressionNode::GtExpressionNode(e) => return self.binary(e, BinaryOp::Gt),
+            ExpressionNode::GteExpressionNode(e) => return self.binary(e, BinaryOp::Gte),
+            ExpressionNode::AddExpressionNode(e) => return self.binary(e, BinaryOp::Add),
+            ExpressionNode::SubExpressionNode(e) => return self.binary(e, BinaryOp::Sub),
+            ExpressionNode::MulExpressionNode(e) => return self.binary(e, BinaryOp::Mul),
+            ExpressionNode::DivExpressionNode(e) => return self.binary(e, BinaryOp::Div),
+            ExpressionNode::ModExpressionNode(e) => return self.binary(e, BinaryOp::Mod),
+            ExpressionNode::PowerExpressionNode(e) => return self.binary(e, BinaryOp::Pow),
+            ExpressionNode::IfExpressionNode(e) => {
+                let cond = self.operand(e.cond(), e)?;
+                let (Some(then), Some(otherwise)) = (e.if_body(), e.else_body()) else {
+                    return Err(incomplete(self.db, e));
+                };
+
+                let then = self.expr(&then)?;
+                let otherwise = self.expr(&otherwise)?;
+                return Ok(then
+                    .into_iter()
+                    .zip(otherwise)
+                    .map(|(then, otherwise)| {
+                        Expr::If(Box::new(cond.clone()), Box::new(then), Box::new(otherwise))
+                    })
+                    .collect());
+            }
+            ExpressionNode::WithExpressionNode(e) => {
+                let branch = match e.cond().map(|cond| self.const_value(&cond)).transpose()? {
+                    Some(ConstValue::Boolean(true)) => e.with_body(),
+                    Some(ConstValue::Boolean(false)) => e.else_body(),
+                    _ => return Err(incomplete(self.db, e)),
+                };
+
+                return match branch {
+                    Some(branch) => self.expr(&branch),
+                    None => Err(incomplete(self.db, e)),
+                };
+            }
+            ExpressionNode::DieseExpressionNode(e) => {
+                let operands = self.list(e)?;
+                let count = Box::new(Expr::CountTrue(operands));
+                let one = Box::new(Expr::Const(ConstValue::Integer(1)));
+                Expr::Binary(BinaryOp::Lte, Type::Integer, count, one)
+            }
+            ExpressionNode::NorExpressionNode(e) => {
+                let operands = self.list(e)?;
+                let count = Box::new(Expr::CountTrue(operands));
+                let zero = Box::new(Expr::Const(ConstValue::Integer(0)));
+                Expr::Binary(BinaryOp::Eq, Type::Integer, count, zero)
+            }
+            ExpressionNode::ParExpressionNode(e) => {
+                let mut values = vec![];
+                for operand in e.syntax().children().filter_map(ExpressionNode::cast) {
+                    values.extend(self.expr(&operand)?);
+                }
+                return Ok(values);
+            }
+            ExpressionNode::HatExpressionNode(e) => {
+                let value = self.operand(e.left(), e)?;
+                let ty = match (self.known_type(expr), e.right()) {
+                    (Some(ty), _) => ty,
+                    (None, Some(size)) => match self.const_value(&size)? {
+                        ConstValue::Integer(size) if size >= 0 => Type::Array {
+                            elem: Box::new(self.type_of_value(&value)),
+                            size: size as usize,
+                        },
+                        _ => return Err(unknown_type(self.db, expr)),
+                    },
+                    (None, None) => return Err(incomplete(self.db, e)),
+                };
+                Expr::Repeat(Box::new(value), ty)
+            }
+            ExpressionNode::CallByPosExpressionNode(e) => return self.call(expr, e),
+        };
+
+        Ok(vec![value])
+    }
+
+    /// Lowers a structure literal (`T { a = 1; b = 2 }`), the update of a structure
+    /// (`T { s with a = 1 }`), or a call with named arguments (`N { x = 1; y = 2 }`)
+    fn call_by_name(
+        &mut self,
+        expr: &ExpressionNode,
+        call: &CallByNameExpressionNode,
+    ) -> Result<Vec<Expr>, Diagnostic> {
+        let Some(name) = call.name() else {
+            return Err(incomplete(self.db, call));
+        };
+        let value_of = |name: &str| {
+            call.all_call_by_name_param_node()
+                .find(|p| {
+                    let ident = p.id_node().and_then(|i| i.ident());
+                    ident.is_some_and(|i| i.text() == name)
+                })
+                .and_then(|p| p.expression_node())
+        };
+
+        if resolve_type_decl(self.db, name.clone()).is_none() {
+            let callee = resolve_callee(self.db, &name, None, &self.instance);
+            let params = callee
+                .as_ref()
+                .map(|c| c.signature(self.db).params)
+                .unwrap_or_default();
+            let mut args = vec![];
+            for (param, _) in params {
+                match value_of(param.text()) {
+                    Some(value) => args.extend(self.expr(&value)?),
+                    None => return Err(incomplete(self.db, call)),
+                }
+            }
+            let clock = self
+                .clocks_of(expr)
+                .pop()
+                .unwrap_or_else(|| self.context.clone());
+            return self.step(expr, callee, args, clock);
+        }
+
+        let ty = self.type_of(expr)?.remove(0);
+        let Type::Struct { fields, .. } = &ty else {
+            return Err(incomplete(self.db, call));
+        };
+
+        // Fields that are not given keep the value they have in the updated structure
+        let base = match call.base() {
+            Some(base) => Some(self.base(&base, &ty)?),
+            None => None,
+        };
+        let mut values = vec![];
+        for (k, (field, _)) in fields.iter().enumerate() {
+            let value = match (value_of(field), &base) {
+                (Some(value), _) => self.operand(Some(value), call)?,
+                (None, Some(Expr::Struct(_, values))) => values[k].clone(),
+                (None, Some(base)) => Expr::Field(Box::new(base.clone()), field.clone()),
+                (None, None) => return Err(incomplete(self.db, call)),
+            };
+            values.push(value);
+        }
+
+        Ok(vec![Expr::Struct(ty, values)])
+    }
+
+    /// Lowers the structure that is updated by a structure literal, a variable or a constant
+    fn base(&self, base: &IdNode, ty: &Type) -> Result<Expr, Diagnostic> {
+        let Some(ident) = base.ident() else {
+            return Err(incomplete(self.db, base));
+        };
+        if self.variables.contains_key(ident.text()) {
+            return Ok(Expr::Var(ident.text().to_owned()));
+        }
+
+        match self.instance.eval_ident(self.db, base) {
+            Some(value) => Ok(constant_expr(value, ty)),
+            None => Err(
+                Diagnostic::new(Level::Error, "unknown value").with_attachment(
+                    Span::of_node(self.db, base.syntax()),
+                    "this is neither a variable nor a constant",
+                ),
+            ),
+        }
+    }
+
+    /// Lowers a `merge` to conditional expressions, the last branch being selected when the
+    /// conditions of the other ones don't hold
+    fn merge(&mut self, merge: &MergeExpressionNode) -> Result<Vec<Expr>, Diagnostic> {
+        let Some(var) = merge.id_node().and_then(|i| i.ident()) else {
+            return Err(incomplete(self.db, merge));
+        };
+        let var = var.text().to_owned();
+        let parent = self.clocks.variable(&var).cloned().unwrap_or(Clock::Base);
+
+        let mut branches = vec![];
+        for case in merge.all_merge_case_node() {
+            let (Some(value), Some(body)) = (merge_case(&case), case.expression_node()) else {
+                return Err(incomplete(self.db, &case));
+            };
+
+            // Branches are computed on the cycles where they are selected
+            let clock = Clock::On {
+                parent: Box::new(parent.clone()),
+                var: var.clone(),
+                case: value.clone(),
+            };
+            let values = self.expr_on(clock, &body)?;
+            branches.push((self.case_condition(&var, &value), values));
+        }
+
+        let Some((_, mut merged)) = branches.pop() else {
+            return Err(incomplete(self.db, merge));
+        };
+        for (cond, values) in branches.into_iter().rev() {
+            merged = values
+                .into_iter()
+                .zip(merged)
+                .map(|(then, otherwise)| {
+                    Expr::If(Box::new(cond.clone()), Box::new(then), Box::new(otherwise))
+                })
+                .collect();
+        }
+        Ok(merged)
+    }
+
+    fn list(&mut self, e: &impl AstNode) -> Result<Vec<Expr>, Diagnostic> {
+        let mut operands = vec![];
+        for operand in e.syntax().children().filter_map(ExpressionNode::cast) {
+            operands.extend(self.expr(&operand)?);
+        }
+        Ok(operands)
+    }
+
+    fn equation(&mut self, equation: &EqualsEquationNode) -> Result<(), Diagnostic> {
+        let Some(expr) = equation.expression_node() else {
+            return Err(incomplete(self.db, equation));
+        };
+
+        let lefts = equation
+            .left_node()
+            .into_iter()
+            .flat_map(|l| l.all_left_item_node())
+            .collect::<Vec<_>>();
+
+        // Variables are assigned on the cycles of the clock they are declared on
+        let clock_of = |left: &LeftItemNode| {
+            let name = crate::dataflow::left_item_name(left)?;
+            self.clocks.variable(&name).cloned()
+        };
+        self.context = lefts.first().and_then(clock_of).unwrap_or(Clock::Base);
+        let clocks = lefts.iter().map(clock_of).collect::<Vec<_>>();
+
+        let values = self.expr(&expr)?;
+        for ((left, value), clock) in lefts.into_iter().zip(values).zip(clocks) {
+            let clock = clock.unwrap_or(Clock::Base);
+            let (var, ty, targets) = self.left(&left)?;
+
+            // Slices receive the elements of their value one by one
+            let value = match ty {
+                Type::Array { size, .. } if targets.iter().any(|t| t.element.is_some()) => {
+                    (self.shared(value, ty, &clock), size)
+                }
+                _ => (value, 0),
+            };
+            for target in targets {
+                let value = match target.element {
+                    Some(i) => element(&value.0, i, value.1),
+                    None => value.0.clone(),
+                };
+                let assign = match target.path.is_empty() {
+                    true => Statement::Assign {
+                        var: var.clone(),
+                        value,
+                    },
+                    false => Statement::AssignPart {
+                        var: var.clone(),
+                        path: target.path,
+                        value,
+                    },
+                };
+                let assign = match target.cond {
+                    Some(cond) => Statement::If {
+                        cond,
+                        then: vec![assign],
+                    },
+                    None => assign,
+                };
+                self.push_on(&clock, assign);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the variable that a left item defines, the type of the left item, and the parts of
+    /// the variable that it defines
+    fn left(&mut self, left: &LeftItemNode) -> Result<(String, Type, Vec<Target>), Diagnostic> {
+        match left {
+            LeftItemNode::IdNode(id) => {
+                let Some(ident) = id.ident() else {
+                    return Err(incomplete(self.db, id));
+                };
+
+                let var = ident.text().to_owned();
+                let ty = self.variables.get(&var).cloned().unwrap_or_default();
+                let target = Target {
+                    cond: None,
+                    path: vec![],
+                    element: None,
+                };
+                Ok((var, ty, vec![target]))
+            }
+            LeftItemNode::LeftFieldAccessNode(access) => {
+                let field = access.field().and_then(|f| f.ident());
+                let (Some(parent), Some(field)) = (access.left_item_node(), field) else {
+                    return Err(incomplete(self.db, access));
+                };
+
+                let (var, ty, mut targets) = self.left(&parent)?;
+                let Type::Struct { fields, .. } = ty else {
+                    return Err(incomplete(self.db, access));
+                };
+                let ty = fields
+                    .into_iter()
+                    .find_map(|(f, ty)| (f == field.text()).then_some(ty))
+                    .unwrap_or_default();
+                for target in &mut targets {
+                    target.path.push(Access::Field(field.text().to_owned()));
+                }
+                Ok((var, ty, targets))
+            }
+            LeftItemNode::LeftTableAccessNode(access) => {
+                let Some(parent) = access.left_item_node() else {
+                    return Err(incomplete(self.db, access));
+                };
+
+                let (var, ty, targets) = self.left(&parent)?;
+                let Type::Array { elem, size } = ty else {
+                    return Err(incomplete(self.db, access));
+                };
+                let sliced = targets.iter().any(|t| t.element.is_some());
+
+                if let Some(select) = access.select_node() {
+                    let indices = self.slice(&select)?;
+                    let ty = Type::Array {
+                        elem,
+                        size: indices.len(),
+                    };
+                    let targets = indices
+                        .into_iter()
+                        .enumerate()
+                        .flat_map(|(k, i)| {
+                            let i = i as usize;
+                            let parts = targets.iter().filter(move |t| match sliced {
+                                true => t.element == Some(i),
+                                false => true,
+                            });
+                            parts.map(move |t| {
+                                let mut path = t.path.clone();
+                                if !sliced {
+                                    path.push(Access::Index(int(i), size));
+                                }
+                                Target {
+                                    cond: t.cond.clone(),
+                                    path,
+                                    element: Some(k),
+                                }
+                            })
+                        })
+                        .collect();
+                    return Ok((var, ty, targets));
+                }
+
+                let index = self.operand(access.index(), access)?;
+                if !sliced {
+                    let targets = targets
+                        .into_iter()
+                        .map(|mut t| {
+                            t.path.push(Access::Index(index.clone(), size));
+                            t
+                        })
+                        .collect();
+                    return Ok((var, *elem, targets));
+                }
+
+                // Only the part selected by the index is defined, which may not be known statically
+                let targets = targets
+                    .into_iter()
+                    .filter_map(|t| {
+                        let i = t.element?;
+                        let cond = match selects(&index, i) {
+                            Some(true) => None,
+                            Some(false) => return None,
+                            None => Some(index_condition(&index, i, size)),
+                        };
+                        let cond = match (t.cond, cond) {
+                            (Some(l), Some(r)) => Some(Expr::Binary(
+                                BinaryOp::And,
+                                Type::Boolean,
+                                Box::new(l),
+                                Box::new(r),
+                            )),
+                            (l, r) => l.or(r),
+                        };
+                        Some(Target {
+                            cond,
+                            path: t.path,
+                            element: None,
+                        })
+                    })
+                    .collect();
+                Ok((var, *elem, targets))
+            }
+        }
+    }
+
+    /// Generates the statements that persist memories at the end of a cycle
+    fn end_of_cycle(&mut self) -> Result<(), Diagnostic> {
+        // All the values are computed before anything is stored, because they may depend on the
+        // previous values of other memories. Lowering operands may defer other ones.
+        let mut stores = vec![];
+        while !self.deferred.is_empty() {
+            for (operand, memories, context) in std::mem::take(&mut self.deferred) {
+                self.context = context;
+                let values = self.expr(&operand)?;
+                let clocks = self.clocks_of(&operand);
+                for ((memory, value), clock) in memories.into_iter().zip(values).zip(clocks) {
+                    let value_type = self.type_of_value(&value);
+                    let ty = match self.memories.iter_mut().find_map(|m| match m {
+                        Memory::Value(v) if v.name == memory => Some(&mut v.ty),
+                        _ => None,
+                    }) {
+                        // Types that depend on static arguments are known once lowered
+                        Some(ty) if !is_known(ty) => {
+                            *ty = value_type;
+                            ty.clone()
+                        }
+                        Some(ty) => ty.clone(),
+                        None => unreachable!("memory {memory} was not allocated"),
+                    };
+
+                    // Memories are only updated on the cycles where their operands are present
+                    let temporary = self.temporary(ty);
+                    let assign = Statement::Assign {
+                        var: temporary.clone(),
+                        value,
+                    };
+                    self.push_on(&clock, assign);
+                    let store = Statement::Store {
+                        memory,
+                        value: Expr::Var(temporary),
+                    };
+                    push_guarded(&mut stores, self.condition(&clock), store);
+                }
+            }
+        }
+
+        self.step.extend(stores);
+
+        for (flag, clock) in std::mem::take(&mut self.flags) {
+            self.push_on(&clock, Statement::ClearFirst(flag));
+        }
+
+        Ok(())
+    }
+}
+
+/// Lowers an instance of a node, and returns it along with the nodes it calls
+fn lower_node(db: &Database, instance: &Instance) -> Result<(NodeCode, Vec<Callee>), Diagnostic> {
+    let node = &instance.node;
+    let sig = instance.signature(db);
+    let variables = |params: &[(rustre_parser::ast::Ident, Type)]| {
+        params
+            .iter()
+            .map(|(ident, ty)| Variable {
+                name: ident.text().into(),
+                ty: ty.clone(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let inputs = variables(&sig.params);
+    let outputs = variables(&sig.return_params);
+
+    let mut locals = vec![];
+    for group in node
+        .all_var_decl_node()
+        .flat_map(|v| v.all_typed_ids_node())
+    {
+        let ty = group
+            .type_node()
+            .map(|t| instance.type_of(db, t))
+            .unwrap_or_default();
+
+        for ident in group.all_ident() {
+            locals.push(Variable {
+                name: ident.text().into(),
+                ty: ty.clone(),
+            });
+        }
+    }
+
+    let mut lowering = Lowering {
+        db,
+        instance: instance.clone(),
+        variables: inputs
+            .iter()
+            .chain(&outputs)
+            .chain(&locals)
+            .map(|v| (v.name.clone(), v.ty.clone()))
+            .collect(),
+        locals,
+        memories: vec![],
+        step: vec![],
+        deferred: vec![],
+        flags: vec![],
+        callees: vec![],
+        clocks: ClockChecker::inference(db, node),
+        context: Clock::Base,
+    };
+
+    for equation in schedule(db, node)? {
+        lowering.equation(&equation)?;
+    }
+    lowering.end_of_cycle()?;
+
+    let code = NodeCode {
+        name: instance.name.clone(),
+        inputs,
+        outputs,
+        locals: lowering.locals,
+        memories: lowering.memories,
+        step: lowering.step,
+        external: false,
+    };
+    Ok((code, lowering.callees))
+}
+
+/// Declares an extern node, that is implemented outside of the generated code
+fn lower_extern_node(db: &Database, ext: &ExternalNodeDeclNode) -> NodeCode {
+    let callee = Callee::Extern(ext.clone());
+    let sig = callee.signature(db);
+    let variables = |params: &[(rustre_parser::ast::Ident, Type)]| {
+        params
+            .iter()
+            .map(|(ident, ty)| Variable {
+                name: ident.text().into(),
+                ty: ty.clone(),
+            })
+            .collect::<Vec<_>>()
+    };
+
+    NodeCode {
+        name: callee.name(),
+        inputs: variables(&sig.params),
+        outputs: variables(&sig.return_params),
+        locals: vec![],
+        memories: vec![],
+        step: vec![],
+        external: true,
+    }
+}
+
+/// Lowers a main node and all the nodes it depends on
+///
+/// Nodes are returned in dependency order: each node comes after the ones it calls. The main node
+/// can be an alias of an instance of a generic node.
+pub fn lower_program(db: &Database, main: NodeNode) -> Result<Vec<NodeCode>, Diagnostic> {
+    fn visit(
+        db: &Database,
+        callee: Callee,
+        visited: &mut HashSet<String>,
+        program: &mut Vec<NodeCode>,
+    ) -> Result<(), Diagnostic> {
+        if !visited.insert(callee.name()) {
+            return Ok(());
+        }
+
+        let code = match callee {
+            Callee::Node(instance) => {
+                let (code, callees) = lower_node(db, &instance)?;
+                for callee in callees {
+                    visit(db, callee, visited, program)?;
+                }
+                code
+            }
+            Callee::Extern(ext) => lower_extern_node(db, &ext),
+        };
+
+        program.push(code);
+        Ok(())
+    }
+
+    let Some(instance) = instance_of(db, &main, None, None) else {
+        let name = main.id_node().map(|id| Span::of_node(db, id.syntax()));
+        let diagnostic = Diagnostic::new(Level::Error, "cannot compile node");
+        return Err(match name {
+            Some(span) => {
+                diagnostic.with_attachment(span, "this node has no body, or needs static arguments")
+            }
+            None => diagnostic,
+        });
+    };
+
+    let mut program = vec![];
+    visit(
+        db,
+        Callee::Node(instance),
+        &mut HashSet::new(),
+        &mut program,
+    )?;
+    Ok(program)
+}
+
+fn int(i: usize) -> Expr {
+    Expr::Const(ConstValue::Integer(i as i32))
+}
+
+fn unknown_type(db: &Database, expr: &ExpressionNode) -> Diagnostic {
+    Diagnostic::new(Level::Error, "unknown type").with_attachment(
+        Span::of_node(db, expr.syntax()),
+        "the type of this expression can't be inferred",
+    )
+}
+
+/// Returns whether a type is fully known, once the static arguments of generic nodes are
+/// substituted
+fn is_known(ty: &Type) -> bool {
+    match ty {
+        Type::Boolean | Type::Integer | Type::Real | Type::Struct { .. } | Type::Enum { .. } => {
+            true
+        }
+        Type::Array { elem, .. } => is_known(elem),
+        Type::Unknown | Type::Function { .. } | Type::Tuple(_) | Type::Abstract(_) => false,
+    }
+}
+
+/// Returns whether an expression only reads values, so that it costs nothing to compute it again
+fn is_cheap(expr: &Expr) -> bool {
+    match expr {
+        Expr::Const(_) | Expr::Var(_) | Expr::Memory(_) => true,
+        Expr::Index(array, index, _) => is_cheap(array) && is_cheap(index),
+        Expr::Array(_, elements) => elements.iter().all(is_cheap),
+        _ => false,
+    }
+}
+
+/// Returns an element of an array, at a constant index
+fn element(array: &Expr, i: usize, size: usize) -> Expr {
+    match array {
+        Expr::Array(_, elements) => elements[i].clone(),
+        _ => Expr::Index(Box::new(array.clone()), Box::new(int(i)), size),
+    }
+}
+
+/// Returns whether a constant index selects the `i`-th element of an array
+fn selects(index: &Expr, i: usize) -> Option<bool> {
+    let Expr::Const(ConstValue::Integer(index)) = index else {
+        return None;
+    };
+    Some(*index == i as i32)
+}
+
+/// Returns the condition for an index to select the `i`-th element of an array, the program
+/// stopping when it is out of the bounds of the array
+fn index_condition(index: &Expr, i: usize, size: usize) -> Expr {
+    let index = Box::new(Expr::CheckedIndex(Box::new(index.clone()), size));
+    Expr::Binary(BinaryOp::Eq, Type::Integer, index, Box::new(int(i)))
+}
+
+/// Converts a constant of a given type to an expression
+///
+/// Constructors and structures are converted to [Expr::Constructor] and [Expr::Struct], as backends
+/// need their types to write them, and so are the arrays containing them.
+fn constant_expr(value: ConstValue, ty: &Type) -> Expr {
+    match (value, ty) {
+        (ConstValue::Enum(ctor), Type::Enum { .. }) => Expr::Constructor(ty.clone(), ctor),
+        (ConstValue::Array(values), Type::Array { elem, .. }) => {
+            let elements = values
+                .iter()
+                .map(|value| constant_expr(value.clone(), elem))
+                .collect::<Vec<_>>();
+            match elements.iter().all(|e| matches!(e, Expr::Const(_))) {
+                true => Expr::Const(ConstValue::Array(values)),
+                false => Expr::Array(ty.clone(), elements),
+            }
+        }
+        (ConstValue::Struct(values), Type::Struct { fields, .. }) => {
+            let values = values
+                .into_iter()
+                .zip(fields)
+                .map(|((_, value), (_, ty))| constant_expr(value, ty))
+                .collect();
+            Expr::Struct(ty.clone(), values)
+        }
+        (value, _) => Expr::Const(value),
+    }
+}
+
+/// Returns the array, structure and enumerated types used by a program
+///
+/// Each type comes after the types it contains, so that they can be declared in this order.
+pub fn program_types(program: &[NodeCode]) -> Vec<Type> {
+    let mut types = vec![];
+    for node in program {
+        let memories = node.memories.iter().filter_map(|m| match m {
+            Memory::Value(var) => Some(var),
+            _ => None,
+        });
+        let variables = node.inputs.iter().chain(&node.outputs).chain(&node.locals);
+        for var in variables.chain(memories) {
+            collect_type(&var.ty, &mut types);
+        }
+        for statement in &node.step {
+            collect_statement_types(statement, &mut types);
+        }
+    }
+    types
+}
+
+fn collect_type(ty: &Type, types: &mut Vec<Type>) {
+    match ty {
+        Type::Array { elem, .. } => collect_type(elem, types),
+        Type::Struct { fields, .. } => {
+            for (_, field) in fields {
+                collect_type(field, types);
+            }
+        }
+        Type::Enum { .. } => (),
+        _ => return,
+    }
+
+    if !types.contains(ty) {
+        types.push(ty.clone());
+    }
+}
+
+fn collect_expr_types(expr: &Expr, types: &mut Vec<Type>) {
+    match expr {
+        Expr::Const(value) => collect_type(&type_of_const(value), types),
+        Expr::Var(_) | Expr::Memory(_) | Expr::First(_) => (),
+        Expr::Constructor(ty, _) => collect_type(ty, types),
+        Expr::Unary(_, ty, e) => {
+            collect_type(ty, types);
+            collect_expr_types(e, types);
+        }
+        Expr::Binary(_, ty, l, r) => {
+            collect_type(ty, types);
+            collect_expr_types(l, types);
+            collect_expr_types(r, types);
+        }
+        Expr::If(c, t, e) => {
+            collect_expr_types(c, types);
+            collect_expr_types(t, types);
+            collect_expr_types(e, types);
+        }
+        Expr::Repeat(e, ty) => {
+            collect_expr_types(e, types);
+            collect_type(ty, types);
+        }
+        Expr::Array(ty, elements) => {
+            for e in elements {
+                collect_expr_types(e, types);
+            }
+            collect_type(ty, types);
+        }
+        Expr::Index(array, index, _) => {
+            collect_expr_types(array, types);
+            collect_expr_types(index, types);
+        }
+        Expr::CheckedIndex(index, _) => collect_expr_types(index, types),
+        Expr::CountTrue(operands) => {
+            for e in operands {
+                collect_expr_types(e, types);
+            }
+        }
+        Expr::Struct(ty, values) => {
+            for e in values {
+                collect_expr_types(e, types);
+            }
+            collect_type(ty, types);
+        }
+        Expr::Field(e, _) => collect_expr_types(e, types),
+    }
+}
+
+fn collect_statement_types(statement: &Statement, types: &mut Vec<Type>) {
+    match statement {
+        Statement::Assign { value, .. } | Statement::Store { value, .. } => {
+            collect_expr_types(value, types)
+        }
+        Statement::AssignPart { path, value, .. } => {
+            for access in path {
+                if let Access::Index(index, _) = access {
+                    collect_expr_types(index, types);
+                }
+            }
+            collect_expr_types(value, types);
+        }
+        Statement::Step { args, .. } => {
+            for arg in args {
+                collect_expr_types(arg, types);
+            }
+        }
+        Statement::ClearFirst(_) => (),
+        Statement::If { cond, then } => {
+            collect_expr_types(cond, types);
+            for statement in then {
+                collect_statement_types(statement, types);
+            }
+        }
+    }
+}
+
+/// Returns the type of a constant value
+pub fn type_of_const(value: &ConstValue) -> Type {
+    match value {
+        ConstValue::Boolean(_) => Type::Boolean,
+        ConstValue::Integer(_) => Type::Integer,
+        ConstValue::Real(_) => Type::Real,
+        ConstValue::Array(values) => Type::Array {
+            elem: Box::new(values.first().map(type_of_const).unwrap_or_default()),
+            size: values.len(),
+        },
+        ConstValue::Enum(_) | ConstValue::Struct(_) => Type::Unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::name_resolution::find_node;
+
+    #[test]
+    fn lower_memories() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node n(x : int) returns (y : int);
+             let
+                 y = 0 -> pre (pre x);
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let program = lower_program(&db, node).unwrap();
+        let code = &program[0];
+
+        let values = code
+            .memories
+            .iter()
+            .filter(|m| matches!(m, Memory::Value(_)));
+        assert_eq!(values.count(), 2);
+
+        // Memories are only stored once all the values to store are computed
+        let first_store = code
+            .step
+            .iter()
+            .position(|s| matches!(s, Statement::Store { .. }))
+            .unwrap();
+        assert!(code.step[first_store..]
+            .iter()
+            .all(|s| matches!(s, Statement::Store { .. } | Statement::ClearFirst(_))));
+    }
+
+    #[test]
+    fn lower_clocks() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node n(c : bool; x : int) returns (y : int);
+             var s : int when c;
+             let
+                 s = (x when c) + (0 -> pre s);
+                 y = current s;
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let code = &lower_program(&db, node).unwrap()[0];
+
+        // Everything but the assignment of the output is done on the cycles where `c` is true
+        let c = Expr::Var("c".into());
+        let (guarded, unguarded): (Vec<_>, Vec<_>) = code
+            .step
+            .iter()
+            .partition(|s| matches!(s, Statement::If { cond, .. } if *cond == c));
+        let output = Statement::Assign {
+            var: "y".into(),
+            value: Expr::Memory("_m3".into()),
+        };
+        assert_eq!(unguarded, [&output]);
+
+        let guarded = guarded
+            .into_iter()
+            .flat_map(|s| match s {
+                Statement::If { then, .. } => then.clone(),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert!(matches!(&guarded[0], Statement::Assign { var, .. } if var == "s"));
+        assert!(matches!(&guarded[1], Statement::Store { value: Expr::Var(s), .. } if s == "s"));
+        assert!(matches!(guarded.last(), Some(Statement::ClearFirst(_))));
+    }
+
+    #[test]
+    fn lower_merge() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node n(c : bool; x : int) returns (y : int);
+             let
+                 y = merge c (false -> 0 when not c) (true -> x when c);
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let code = &lower_program(&db, node).unwrap()[0];
+
+        let not_c = Expr::Unary(UnaryOp::Not, Type::Boolean, Box::new(Expr::Var("c".into())));
+        let merged = Expr::If(
+            Box::new(not_c),
+            Box::new(Expr::Const(ConstValue::Integer(0))),
+            Box::new(Expr::Var("x".into())),
+        );
+        assert_eq!(
+            code.step,
+            [Statement::Assign {
+                var: "y".into(),
+                value: merged,
+            }]
+        );
+    }
+
+    #[test]
+    fn lower_dynamic_index_in_slice() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node n(i : int) returns (t : int^3);
+             let
+                 t[0] = 0;
+                 t[1 .. 2][i] = 1;
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let code = &lower_program(&db, node).unwrap()[0];
+
+        // The index is checked against the size of the slice
+        let i = || Box::new(Expr::CheckedIndex(Box::new(Expr::Var("i".into())), 2));
+        let assign = |index| Statement::AssignPart {
+            var: "t".into(),
+            path: vec![Access::Index(int(index), 3)],
+            value: int(1),
+        };
+        assert!(code.step.contains(&Statement::If {
+            cond: Expr::Binary(BinaryOp::Eq, Type::Integer, i(), Box::new(int(0))),
+            then: vec![assign(1)],
+        }));
+        assert!(code.step.contains(&Statement::If {
+            cond: Expr::Binary(BinaryOp::Eq, Type::Integer, i(), Box::new(int(1))),
+            then: vec![assign(2)],
+        }));
+    }
+
+    #[test]
+    fn lower_calls_by_name() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type point = { x : int; y : int };
+
+             function sub(a, b : int) returns (c : int);
+             let
+                 c = a - b;
+             tel
+
+             node n(p : point) returns (q : point);
+             let
+                 q = point { p with y = sub { b = p.y; a = 0 } };
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let program = lower_program(&db, node).unwrap();
+        let code = program.iter().find(|code| code.name == "n").unwrap();
+
+        let y = Expr::Field(Box::new(Expr::Var("p".into())), "y".into());
+        assert!(matches!(
+            &code.step[0],
+            Statement::Step { args, .. } if *args == [int(0), y]
+        ));
+        let Statement::Assign {
+            value: Expr::Struct(_, values),
+            ..
+        } = &code.step[1]
+        else {
+            panic!("expected a structure, found {:?}", code.step[1]);
+        };
+        assert_eq!(
+            values[0],
+            Expr::Field(Box::new(Expr::Var("p".into())), "x".into())
+        );
+    }
+
+    #[test]
+    fn lower_generic_instances() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node id<<type t>>(x : t) returns (y : t);
+             let
+                 y = x;
+             tel
+
+             node repeat<<const n : int>>(x : int) returns (y : int^n);
+             let
+                 y = x ^ n;
+             tel
+
+             node id_int = id<<int>>;
+
+             node main(i : int; r : real) returns (j : int; s : real; b : int^3);
+             let
+                 j = id_int(i);
+                 s = id<<real>>(r);
+                 b = repeat<<3>>(i);
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+        let errors = db.effect::<Diagnostic>();
+        assert!(!errors.iter().any(|d| matches!(d.level, Level::Error)));
+
+        let node = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let program = lower_program(&db, node).unwrap();
+        let mut names = program.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["id_int", "id_real", "main", "repeat_3"]);
+        let code = |name: &str| program.iter().find(|c| c.name == name).unwrap();
+
+        let array = Type::Array {
+            elem: Box::new(Type::Integer),
+            size: 3,
+        };
+        assert_eq!(code("id_real").outputs[0].ty, Type::Real);
+        assert_eq!(code("repeat_3").outputs[0].ty, array);
+        assert_eq!(
+            code("repeat_3").step,
+            [Statement::Assign {
+                var: "y".into(),
+                value: Expr::Repeat(Box::new(Expr::Var("x".into())), array),
+            }]
+        );
+    }
+
+    #[test]
+    fn lower_aliases_of_included_files() {
+        let db = crate::driver();
+        crate::add_source_file(&db, "../tests/access.lus".into());
+
+        for (alias, elem) in [
+            ("access", Type::Integer),
+            ("quick_access_real8", Type::Real),
+        ] {
+            let node = Option::clone(&find_node(&db, alias.into())).unwrap();
+            let program = lower_program(&db, node).unwrap();
+            assert_eq!(program.len(), 1);
+            assert_eq!(program[0].name, alias);
+            assert_eq!(
+                program[0].inputs[0].ty,
+                Type::Array {
+                    elem: Box::new(elem.clone()),
+                    size: 8,
+                }
+            );
+            assert_eq!(program[0].outputs[0].ty, elem);
+        }
+    }
+
+    #[test]
+    fn lower_iterators() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "extern function incr(x : int) returns (y : int);
+
+             node main(a : int^3) returns (b : int^3; total : int; some : bool);
+             let
+                 b = map<<incr, 3>>(a);
+                 total = red<<+, 3>>(0, a);
+                 some = boolred<<1, 3, 3>>(map<<>; 3>>(a, 0^3));
+             tel"
+            .into(),
+        );
+
+        let node = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let program = lower_program(&db, node).unwrap();
+        assert!(program[0].external);
+        assert_eq!(program[0].name, "incr");
+        let code = &program[1];
+
+        // Each element is given to its own instance of the iterated node
+        let steps = code
+            .step
+            .iter()
+            .filter_map(|s| match s {
+                Statement::Step { node, args, .. } => Some((node.as_str(), args.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let a = |i| element(&Expr::Var("a".into()), i, 3);
+        assert_eq!(
+            steps,
+            [
+                ("incr", vec![a(0)]),
+                ("incr", vec![a(1)]),
+                ("incr", vec![a(2)])
+            ]
+        );
+        let instances = code
+            .memories
+            .iter()
+            .filter(|m| matches!(m, Memory::Instance { .. }));
+        assert_eq!(instances.count(), 3);
+
+        // The accumulator is passed from an application to the next one
+        let sums = code
+            .step
+            .iter()
+            .filter_map(|s| match s {
+                Statement::Assign {
+                    value: Expr::Binary(BinaryOp::Add, _, acc, element),
+                    ..
+                } => Some((*acc.clone(), *element.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sums.len(), 3);
+        assert_eq!(sums[0], (int(0), a(0)));
+        assert!(matches!(&sums[1], (Expr::Var(_), e) if *e == a(1)));
+    }
+}
@@ -0,0 +1,637 @@
+//! Rust backend
+//!
+//! Each node `n` is compiled to a structure holding its memories:
+//!
+//! ```ignore
+//! pub struct N { ... }
+//!
+//! impl N {
+//!     pub fn new() -> Self;
+//!     pub fn reset(&mut self);
+//!     pub fn step(&mut self, x: i32) -> (i32, bool);
+//! }
+//! ```
+//!
+//! Nodes with a single output return it directly instead of a tuple. Reals are represented as
+//! `f32`, as they are in [ConstValue]. Structure and enumerated types are declared in a `types`
+//! module, so that their names can't collide with the names of nodes.
+//!
+//! Extern nodes are imported from an `externs` module next to the generated one, that must define
+//! them with the same interface as the generated nodes, deriving `Clone` and `Debug`.
+
+use super::{program_types, Access, BinaryOp, Expr, Memory, NodeCode, Statement, UnaryOp};
+use crate::types::{ConstValue, Type};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Escapes a variable name, as it could be a Rust keyword
+///
+/// The few keywords that can't be raw identifiers are prefixed with an underscore instead, which
+/// Lustre identifiers never start with.
+fn var_name(name: &str) -> String {
+    match name {
+        "crate" | "self" | "Self" | "super" => format!("_{name}"),
+        _ if name.starts_with('_') => name.to_owned(),
+        _ => format!("r#{name}"),
+    }
+}
+
+/// Converts a node name to upper camel case
+fn camel_case(node: &str) -> String {
+    node.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// Returns the name of the structure of each node
+///
+/// Node names are converted to upper camel case, unless several nodes would get the same name this
+/// way (as `sum_up` and `sumUp`): these nodes keep their original names. As converting a name twice
+/// gives the same name, a kept name can't be the converted name of another node.
+fn struct_names(program: &[NodeCode]) -> HashMap<String, String> {
+    let camel_cased = program
+        .iter()
+        .map(|n| (n.name.clone(), camel_case(&n.name)))
+        .collect::<Vec<_>>();
+
+    camel_cased
+        .iter()
+        .map(|(name, camel)| {
+            let collides = camel_cased
+                .iter()
+                .any(|(other, other_camel)| other != name && other_camel == camel);
+            match collides {
+                true => (name.clone(), var_name(name)),
+                false if camel == "Self" => (name.clone(), var_name(camel)),
+                false => (name.clone(), camel.clone()),
+            }
+        })
+        .collect()
+}
+
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Boolean => "bool".into(),
+        Type::Integer => "i32".into(),
+        Type::Real => "f32".into(),
+        Type::Array { elem, size } => format!("[{}; {size}]", type_name(elem)),
+        Type::Tuple(types) => tuple(types.iter().map(type_name)),
+        Type::Struct { name, .. } | Type::Enum { name, .. } => {
+            format!("types::{}", var_name(name))
+        }
+        Type::Unknown | Type::Function { .. } | Type::Abstract(_) => "()".into(),
+    }
+}
+
+/// Value used to initialize variables and memories before they are first assigned
+fn default_value(ty: &Type) -> String {
+    match ty {
+        Type::Boolean => "false".into(),
+        Type::Integer => "0".into(),
+        Type::Real => "0.0".into(),
+        Type::Array { elem, size } => format!("[{}; {size}]", default_value(elem)),
+        Type::Tuple(types) => tuple(types.iter().map(default_value)),
+        Type::Struct { fields, .. } => {
+            let fields = fields
+                .iter()
+                .map(|(field, ty)| format!("{}: {}", var_name(field), default_value(ty)))
+                .collect::<Vec<_>>();
+            format!("{} {{ {} }}", type_name(ty), fields.join(", "))
+        }
+        Type::Enum { constructors, .. } => {
+            format!("{}::{}", type_name(ty), var_name(&constructors[0]))
+        }
+        Type::Unknown | Type::Function { .. } | Type::Abstract(_) => "()".into(),
+    }
+}
+
+/// Formats values as a tuple, unless there is exactly one of them
+fn tuple(values: impl Iterator<Item = String>) -> String {
+    let values = values.collect::<Vec<_>>();
+    match values.as_slice() {
+        [value] => value.clone(),
+        values => format!("({})", values.join(", ")),
+    }
+}
+
+fn constant(value: &ConstValue) -> String {
+    match value {
+        ConstValue::Boolean(b) => b.to_string(),
+        ConstValue::Integer(i) if *i < 0 => format!("({i})"),
+        ConstValue::Integer(i) => i.to_string(),
+        ConstValue::Real(r) if *r < 0.0 => format!("({r:?}f32)"),
+        ConstValue::Real(r) => format!("{r:?}f32"),
+        ConstValue::Array(values) => {
+            let values = values.iter().map(constant).collect::<Vec<_>>();
+            format!("[{}]", values.join(", "))
+        }
+        ConstValue::Enum(_) => unreachable!("constructors are converted to expressions"),
+        ConstValue::Struct(_) => unreachable!("structures are converted to expressions"),
+    }
+}
+
+fn expr(e: &Expr) -> String {
+    match e {
+        Expr::Const(value) => constant(value),
+        Expr::Var(name) => var_name(name),
+        Expr::Memory(name) | Expr::First(name) => format!("self.{name}"),
+        Expr::Unary(op, ty, operand) => {
+            let operand = expr(operand);
+            match op {
+                UnaryOp::Not => format!("(!{operand})"),
+                UnaryOp::Neg if *ty == Type::Integer => format!("i32::wrapping_neg({operand})"),
+                UnaryOp::Neg => format!("(-{operand})"),
+                UnaryOp::ToInt => format!("({operand} as i32)"),
+                UnaryOp::ToReal => format!("({operand} as f32)"),
+            }
+        }
+        Expr::Binary(op, ty, left, right) => {
+            let (left, right) = (expr(left), expr(right));
+            // Integers wrap around in Lustre, instead of panicking on overflows
+            if *ty == Type::Integer {
+                let wrapping = match op {
+                    BinaryOp::Add => Some("wrapping_add"),
+                    BinaryOp::Sub => Some("wrapping_sub"),
+                    BinaryOp::Mul => Some("wrapping_mul"),
+                    BinaryOp::Div => Some("wrapping_div"),
+                    BinaryOp::Mod => Some("wrapping_rem"),
+                    BinaryOp::Pow => return format!("i32::wrapping_pow({left}, {right} as u32)"),
+                    _ => None,
+                };
+                if let Some(method) = wrapping {
+                    return format!("i32::{method}({left}, {right})");
+                }
+            }
+
+            let op = match op {
+                BinaryOp::And => "&&",
+                BinaryOp::Or => "||",
+                BinaryOp::Xor => "^",
+                BinaryOp::Impl => return format!("(!{left} || {right})"),
+                BinaryOp::Eq => "==",
+                BinaryOp::Neq => "!=",
+                BinaryOp::Lt => "<",
+                BinaryOp::Lte => "<=",
+                BinaryOp::Gt => ">",
+                BinaryOp::Gte => ">=",
+                BinaryOp::Add => "+",
+                BinaryOp::Sub => "-",
+                BinaryOp::Mul => "*",
+                BinaryOp::Div => "/",
+                BinaryOp::Mod => "%",
+                BinaryOp::Pow => return format!("f32::powf({left}, {right})"),
+            };
+            format!("({left} {op} {right})")
+        }
+        Expr::If(cond, then, otherwise) => {
+            let (cond, then, otherwise) = (expr(cond), expr(then), expr(otherwise));
+            format!("(if {cond} {{ {then} }} else {{ {otherwise} }})")
+        }
+        Expr::Repeat(value, ty) => {
+            let Type::Array { size, .. } = ty else {
+                unreachable!("repeated values are always arrays");
+            };
+            format!("[{}; {size}]", expr(value))
+        }
+        Expr::CountTrue(operands) if operands.is_empty() => "0".into(),
+        Expr::CountTrue(operands) => {
+            let operands = operands
+                .iter()
+                .map(|e| format!("({} as i32)", expr(e)))
+                .collect::<Vec<_>>();
+            format!("({})", operands.join(" + "))
+        }
+        Expr::Array(_, elements) => {
+            let elements = elements.iter().map(expr).collect::<Vec<_>>();
+            format!("[{}]", elements.join(", "))
+        }
+        Expr::Index(array, i, size) => format!("{}[{}]", expr(array), index(i, *size)),
+        Expr::CheckedIndex(i, size) => format!("rustre_index({}, {size})", expr(i)),
+        Expr::Constructor(ty, ctor) => format!("{}::{}", type_name(ty), var_name(ctor)),
+        Expr::Struct(ty, values) => {
+            let Type::Struct { fields, .. } = ty else {
+                unreachable!("structure values always have structure types");
+            };
+            let values = fields
+                .iter()
+                .zip(values)
+                .map(|((field, _), value)| format!("{}: {}", var_name(field), expr(value)))
+                .collect::<Vec<_>>();
+            format!("{} {{ {} }}", type_name(ty), values.join(", "))
+        }
+        Expr::Field(value, field) => format!("{}.{}", expr(value), var_name(field)),
+    }
+}
+
+/// Writes an index into an array of a given size, checked unless it is constant
+fn index(i: &Expr, size: usize) -> String {
+    match i {
+        Expr::Const(_) => expr(i),
+        _ => format!("rustre_index({}, {size}) as usize", expr(i)),
+    }
+}
+
+/// Stops the program when an index is out of the bounds of an array
+const INDEX_HELPER: &str = "\
+fn rustre_index(i: i32, size: i32) -> i32 {
+    if i < 0 || i >= size {
+        panic!(\"index out of bounds: the length is {size} but the index is {i}\");
+    }
+    i
+}
+";
+
+/// Writes the path to a part of a variable
+fn path(path: &[Access]) -> String {
+    path.iter()
+        .map(|access| match access {
+            Access::Field(field) => format!(".{}", var_name(field)),
+            Access::Index(i, size) => format!("[{}]", index(i, *size)),
+        })
+        .collect()
+}
+
+/// Writes a statement, indented by `depth` levels
+fn statement(code: &mut String, s: &Statement, depth: usize) -> std::fmt::Result {
+    let indent = "    ".repeat(depth);
+    match s {
+        Statement::Assign { var, value } => {
+            writeln!(code, "{indent}{} = {};", var_name(var), expr(value))
+        }
+        Statement::AssignPart {
+            var,
+            path: parts,
+            value,
+        } => {
+            let (var, parts) = (var_name(var), path(parts));
+            writeln!(code, "{indent}{var}{parts} = {};", expr(value))
+        }
+        Statement::Step {
+            instance,
+            args,
+            results,
+            ..
+        } => {
+            let args = args.iter().map(expr).collect::<Vec<_>>().join(", ");
+            let call = format!("self.{instance}.step({args})");
+            match results.is_empty() {
+                true => writeln!(code, "{indent}{call};"),
+                false => {
+                    let results = tuple(results.iter().map(|r| var_name(r)));
+                    writeln!(code, "{indent}{results} = {call};")
+                }
+            }
+        }
+        Statement::Store { memory, value } => {
+            writeln!(code, "{indent}self.{memory} = {};", expr(value))
+        }
+        Statement::ClearFirst(flag) => writeln!(code, "{indent}self.{flag} = false;"),
+        Statement::If { cond, then } => {
+            writeln!(code, "{indent}if {} {{", expr(cond))?;
+            for nested in then {
+                statement(code, nested, depth + 1)?;
+            }
+            writeln!(code, "{indent}}}")
+        }
+    }
+}
+
+/// Writes the `types` module, declaring the structure and enumerated types of a program
+fn types(code: &mut String, program: &[NodeCode]) -> std::fmt::Result {
+    let types = program_types(program);
+    if !types.iter().any(|ty| !ty.is_array()) {
+        return Ok(());
+    }
+
+    writeln!(code)?;
+    writeln!(code, "pub mod types {{")?;
+    writeln!(code, "    use super::types;")?;
+    for ty in &types {
+        match ty {
+            Type::Struct { name, fields } => {
+                writeln!(code)?;
+                writeln!(code, "    #[derive(Clone, Copy, Debug, PartialEq)]")?;
+                writeln!(code, "    pub struct {} {{", var_name(name))?;
+                for (field, ty) in fields {
+                    writeln!(code, "        pub {}: {},", var_name(field), type_name(ty))?;
+                }
+                writeln!(code, "    }}")?;
+            }
+            Type::Enum { name, constructors } => {
+                writeln!(code)?;
+                writeln!(code, "    #[derive(Clone, Copy, Debug, PartialEq, Eq)]")?;
+                writeln!(code, "    pub enum {} {{", var_name(name))?;
+                for ctor in constructors {
+                    writeln!(code, "        {},", var_name(ctor))?;
+                }
+                writeln!(code, "    }}")?;
+            }
+            _ => (),
+        }
+    }
+    writeln!(code, "}}")
+}
+
+fn node(code: &mut String, node: &NodeCode, names: &HashMap<String, String>) -> std::fmt::Result {
+    let name = &names[&node.name];
+
+    writeln!(code, "#[derive(Clone, Debug)]")?;
+    writeln!(code, "pub struct {name} {{")?;
+    for memory in &node.memories {
+        match memory {
+            Memory::Value(var) => writeln!(code, "    {}: {},", var.name, type_name(&var.ty))?,
+            Memory::First(flag) => writeln!(code, "    {flag}: bool,")?,
+            Memory::Instance { name, node } => writeln!(code, "    {name}: {},", names[node])?,
+        }
+    }
+    writeln!(code, "}}")?;
+    writeln!(code)?;
+
+    writeln!(code, "impl {name} {{")?;
+    writeln!(code, "    pub fn new() -> Self {{")?;
+    writeln!(code, "        let mut node = Self {{")?;
+    for memory in &node.memories {
+        match memory {
+            Memory::Value(var) => writeln!(
+                code,
+                "            {}: {},",
+                var.name,
+                default_value(&var.ty)
+            )?,
+            Memory::First(flag) => writeln!(code, "            {flag}: true,")?,
+            Memory::Instance { name, node } => {
+                writeln!(code, "            {name}: {}::new(),", names[node])?
+            }
+        }
+    }
+    writeln!(code, "        }};")?;
+    writeln!(code, "        node.reset();")?;
+    writeln!(code, "        node")?;
+    writeln!(code, "    }}")?;
+    writeln!(code)?;
+
+    writeln!(code, "    pub fn reset(&mut self) {{")?;
+    for memory in &node.memories {
+        match memory {
+            Memory::Value(var) => writeln!(
+                code,
+                "        self.{} = {};",
+                var.name,
+                default_value(&var.ty)
+            )?,
+            Memory::First(flag) => writeln!(code, "        self.{flag} = true;")?,
+            Memory::Instance { name, .. } => writeln!(code, "        self.{name}.reset();")?,
+        }
+    }
+    writeln!(code, "    }}")?;
+    writeln!(code)?;
+
+    let inputs = node
+        .inputs
+        .iter()
+        .map(|i| format!(", {}: {}", var_name(&i.name), type_name(&i.ty)))
+        .collect::<String>();
+    let outputs = node.outputs.iter().map(|o| type_name(&o.ty));
+    writeln!(
+        code,
+        "    pub fn step(&mut self{inputs}) -> {} {{",
+        tuple(outputs)
+    )?;
+    for var in node.outputs.iter().chain(&node.locals) {
+        writeln!(
+            code,
+            "        let mut {}: {} = {};",
+            var_name(&var.name),
+            type_name(&var.ty),
+            default_value(&var.ty)
+        )?;
+    }
+
+    for s in &node.step {
+        statement(code, s, 2)?;
+    }
+
+    let outputs = node.outputs.iter().map(|o| var_name(&o.name));
+    writeln!(code, "        {}", tuple(outputs))?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")?;
+
+    writeln!(code)?;
+    writeln!(code, "impl Default for {name} {{")?;
+    writeln!(code, "    fn default() -> Self {{")?;
+    writeln!(code, "        Self::new()")?;
+    writeln!(code, "    }}")?;
+    writeln!(code, "}}")
+}
+
+/// Generates a Rust module for a lowered program
+pub fn generate(program: &[NodeCode]) -> String {
+    let mut code = String::new();
+    writeln!(code, "// Generated by rustre, do not edit").unwrap();
+    writeln!(code).unwrap();
+    writeln!(
+        code,
+        "#![allow(unused_parens, unused_imports, unused_variables, unused_mut, unused_assignments, non_snake_case, non_camel_case_types, dead_code, clippy::all)]"
+    )
+    .unwrap();
+    writeln!(code).unwrap();
+    code.push_str(INDEX_HELPER);
+
+    types(&mut code, program).unwrap();
+
+    let names = struct_names(program);
+    let externs = program.iter().filter(|n| n.external).collect::<Vec<_>>();
+    if !externs.is_empty() {
+        let externs = externs.iter().map(|n| names[&n.name].as_str());
+        let externs = externs.collect::<Vec<_>>().join(", ");
+        writeln!(code).unwrap();
+        writeln!(code, "use super::externs::{{{externs}}};").unwrap();
+    }
+
+    for n in program.iter().filter(|n| !n.external) {
+        writeln!(code).unwrap();
+        node(&mut code, n, &names).unwrap();
+    }
+
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen::lower_program;
+    use crate::name_resolution::find_node;
+
+    #[test]
+    fn node_structures() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node sum_up(x : int) returns (s : int; tab : int^2);
+             let
+                 s = x + (0 fby s);
+                 tab = s ^ 2;
+             tel
+
+             node main(x : int) returns (s : int);
+             var t : int^2;
+             let
+                 s, t = sum_up(x);
+             tel"
+            .into(),
+        );
+
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        let program = lower_program(&db, main).unwrap();
+        let code = generate(&program);
+
+        assert!(code.contains("pub struct SumUp {"));
+        assert!(code.contains("pub fn step(&mut self, r#x: i32) -> (i32, [i32; 2]) {"));
+        assert!(code.contains("pub fn step(&mut self, r#x: i32) -> i32 {"));
+        assert!(code.contains("_i3: SumUp::new(),"));
+        assert!(code.contains("(_t1, _t2) = self._i3.step(r#x);"));
+    }
+
+    fn generate_main(source: &str) -> String {
+        let mut db = crate::driver();
+        crate::add_source_contents(&mut db, source.into());
+        let main = Option::clone(&find_node(&db, "main".into())).unwrap();
+        generate(&lower_program(&db, main).unwrap())
+    }
+
+    #[test]
+    fn keywords_as_names() {
+        let code = generate_main(
+            "node main(fn, self : int) returns (match : int);
+             let
+                 match = fn + self;
+             tel",
+        );
+
+        assert!(code.contains("pub fn step(&mut self, r#fn: i32, _self: i32) -> i32 {"));
+        assert!(code.contains("r#match = i32::wrapping_add(r#fn, _self);"));
+    }
+
+    #[test]
+    fn colliding_node_names() {
+        let code = generate_main(
+            "node sum_up(x : int) returns (s : int);
+             let
+                 s = x;
+             tel
+
+             node sumUp(x : int) returns (s : int);
+             let
+                 s = x;
+             tel
+
+             node main(x : int) returns (s : int);
+             let
+                 s = sum_up(x) + sumUp(x);
+             tel",
+        );
+
+        assert!(code.contains("pub struct r#sum_up {"));
+        assert!(code.contains("pub struct r#sumUp {"));
+        assert!(code.contains("pub struct Main {"));
+    }
+
+    #[test]
+    fn wrapping_integers() {
+        let code = generate_main(
+            "node main(x : int; y : real) returns (a, b : int; c : real);
+             let
+                 a = -x * x - 1;
+                 b = x ** 2 / x mod 3;
+                 c = y * y ** y;
+             tel",
+        );
+
+        assert!(
+            code.contains("i32::wrapping_sub(i32::wrapping_mul(i32::wrapping_neg(r#x), r#x), 1)")
+        );
+        assert!(code.contains("i32::wrapping_pow(r#x, 2 as u32)"));
+        assert!(code.contains("i32::wrapping_rem(i32::wrapping_div("));
+        assert!(code.contains("(r#y * f32::powf(r#y, r#y))"));
+    }
+
+    #[test]
+    fn structures() {
+        let code = generate_main(
+            "type point = { x : int; y : int };
+
+             node main(x : int) returns (p : point);
+             let
+                 p.x = x;
+                 p.y = point { y = x; x = 0 }.x;
+             tel",
+        );
+
+        assert!(code.contains("pub mod types {"));
+        assert!(code.contains("pub struct r#point {\n        pub r#x: i32,\n        pub r#y: i32,"));
+        assert!(code.contains("pub fn step(&mut self, r#x: i32) -> types::r#point {"));
+        assert!(code.contains("let mut r#p: types::r#point = types::r#point { r#x: 0, r#y: 0 };"));
+        assert!(code.contains("r#p.r#y = types::r#point { r#x: 0, r#y: r#x }.r#x;"));
+    }
+
+    #[test]
+    fn enumerations() {
+        let code = generate_main(
+            "type mode = enum { Idle, Run };
+
+             node main(x : int) returns (m : mode; y : int);
+             let
+                 m = if x > 0 then Run else Idle;
+                 y = merge m (Idle -> 0 when Idle(m)) (Run -> x when Run(m));
+             tel",
+        );
+
+        assert!(code.contains("pub enum r#mode {\n        r#Idle,\n        r#Run,\n    }"));
+        assert!(code.contains("let mut r#m: types::r#mode = types::r#mode::r#Idle;"));
+        assert!(code.contains("(r#m == types::r#mode::r#Idle)"));
+    }
+
+    #[test]
+    fn array_accesses() {
+        let code = generate_main(
+            "node main(i : int; t : int^3) returns (x : int; u : int^4);
+             let
+                 x = t[i] + t[1];
+                 u[0] = x;
+                 u[1 .. 3] = [i, 2] | t[0 .. 0];
+             tel",
+        );
+
+        assert!(code.contains("r#u[3] = r#t[0];"));
+        assert!(code.contains("i32::wrapping_add(r#t[rustre_index(r#i, 3) as usize], r#t[1])"));
+        assert!(code.contains("r#u[0] = r#x;"));
+
+        // Out of bounds indices stop the program, as in the interpreter
+        assert!(code.contains(
+            "panic!(\"index out of bounds: the length is {size} but the index is {i}\");"
+        ));
+    }
+
+    #[test]
+    fn extern_nodes() {
+        let code = generate_main(
+            "extern function sin(x : real) returns (y : real);
+
+             node main(x : real) returns (y : real);
+             let
+                 y = sin(x);
+             tel",
+        );
+
+        assert!(code.contains("use super::externs::{Sin};"));
+        assert!(!code.contains("pub struct Sin"));
+        assert!(code.contains("_i1: Sin::new(),"));
+    }
+}
@@ -0,0 +1,382 @@
+//! Dataflow graphs of nodes
+//!
+//! Lustre programs are, at their core, descriptions of flows of data going through operators. This
+//! module provides the [dataflow_graph][dataflow_graph()] query that builds such a graph for a
+//! single node, which can then be displayed (for instance with `rustre dot`) to document or review
+//! it.
+//!
+//! # Structure of the graph
+//!
+//! Each vertex is either a variable of the node (input, output or local), a literal or global
+//! constant, an operator, a memory (`pre`, `fby` or `->`) or a call to another node. Edges go from
+//! a value to the operator, variable or call that consumes it. When the order of the operands
+//! matters, edges are labeled with the position or the name of the operand.
+
+use crate::clocks::{clock_condition, merge_case};
+use crate::node_state::stateful_expr_of_node;
+use petgraph::graph::{DiGraph, NodeIndex};
+use rustre_parser::ast::{
+    AstNode, AstToken, BinaryExpression, ExpressionNode, LeftItemNode, NodeNode, UnaryExpression,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use yeter::Database;
+
+pub type DataflowGraph = DiGraph<DataflowVertex, String>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataflowVertex {
+    Input(String),
+    Output(String),
+    Local(String),
+    /// Literal or reference to a global constant
+    Constant(String),
+    /// Stateless operator, such as `+` or `if`
+    Operator(&'static str),
+    /// Temporal operator that needs memory (`pre`, `fby` or `->`)
+    Memory(&'static str),
+    /// Call site of another node
+    Call {
+        node: String,
+        /// `true` if the called node has internal state, meaning the call site is an _instance_
+        stateful: bool,
+    },
+}
+
+impl Display for DataflowVertex {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Input(name) | Self::Output(name) | Self::Local(name) => write!(f, "{name}"),
+            Self::Constant(value) => write!(f, "{value}"),
+            Self::Operator(op) | Self::Memory(op) => write!(f, "{op}"),
+            Self::Call { node, .. } => write!(f, "{node}(...)"),
+        }
+    }
+}
+
+struct GraphBuilder<'db> {
+    db: &'db Database,
+    graph: DataflowGraph,
+    variables: HashMap<String, NodeIndex>,
+    constants: HashMap<String, NodeIndex>,
+    stateful: HashSet<ExpressionNode>,
+}
+
+impl<'db> GraphBuilder<'db> {
+    fn variable(&mut self, name: &str) -> NodeIndex {
+        if let Some(index) = self.variables.get(name) {
+            return *index;
+        }
+
+        // Not a variable of the node, it must be a global constant
+        *self.constants.entry(name.to_owned()).or_insert_with(|| {
+            self.graph
+                .add_node(DataflowVertex::Constant(name.to_owned()))
+        })
+    }
+
+    fn vertex(
+        &mut self,
+        vertex: DataflowVertex,
+        operands: impl IntoIterator<Item = (Option<ExpressionNode>, String)>,
+    ) -> NodeIndex {
+        let index = self.graph.add_node(vertex);
+        for (operand, label) in operands {
+            if let Some(operand) = operand.and_then(|o| self.expr(&o)) {
+                self.graph.add_edge(operand, index, label);
+            }
+        }
+        index
+    }
+
+    fn unary(&mut self, vertex: DataflowVertex, e: &impl UnaryExpression) -> NodeIndex {
+        self.vertex(vertex, [(e.operand(), String::new())])
+    }
+
+    fn binary(&mut self, vertex: DataflowVertex, e: &impl BinaryExpression) -> NodeIndex {
+        self.vertex(vertex, [(e.left(), "0".into()), (e.right(), "1".into())])
+    }
+
+    fn list(&mut self, vertex: DataflowVertex, e: &impl AstNode) -> NodeIndex {
+        let operands = e
+            .syntax()
+            .children()
+            .filter_map(ExpressionNode::cast)
+            .enumerate()
+            .map(|(idx, o)| (Some(o), idx.to_string()))
+            .collect::<Vec<_>>();
+        self.vertex(vertex, operands)
+    }
+
+    /// Adds the vertices of an expression to the graph, and returns the one holding its value
+    fn expr(&mut self, e: &ExpressionNode) -> Option<NodeIndex> {
+        use DataflowVertex::{Memory, Operator};
+
+        Some(match e {
+            ExpressionNode::ConstantNode(c) => {
+                let value = c.syntax().text().to_string();
+                self.graph
+                    .add_node(DataflowVertex::Constant(value.trim().into()))
+            }
+            ExpressionNode::IdentExpressionNode(i) => {
+                let ident = i.id_node()?.ident()?;
+                self.variable(ident.text())
+            }
+            ExpressionNode::NotExpressionNode(e) => self.unary(Operator("not"), e),
+            ExpressionNode::NegExpressionNode(e) => self.unary(Operator("-"), e),
+            ExpressionNode::PreExpressionNode(e) => self.unary(Memory("pre"), e),
+            ExpressionNode::CurrentExpressionNode(e) => self.unary(Operator("current"), e),
+            ExpressionNode::IntExpressionNode(e) => self.unary(Operator("int"), e),
+            ExpressionNode::RealExpressionNode(e) => self.unary(Operator("real"), e),
+            ExpressionNode::WhenExpressionNode(e) => {
+                let index = self.vertex(Operator("when"), [(e.left(), String::new())]);
+                let clock = e
+                    .syntax()
+                    .children()
+                    .find_map(rustre_parser::ast::ClockExpressionNode::cast)
+                    .and_then(|c| clock_condition(&c));
+                if let Some((clock, _)) = clock {
+                    let clock = self.variable(clock.text());
+                    self.graph.add_edge(clock, index, "clock".into());
+                }
+                index
+            }
+            ExpressionNode::FieldAccessExpressionNode(e) => {
+                let field = e.field().map(|f| f.text().to_owned());
+                self.vertex(Operator("."), [(e.left(), field.unwrap_or_default())])
+            }
+            ExpressionNode::CallByNameExpressionNode(e) => {
+                let fields = e
+                    .all_call_by_name_param_node()
+                    .map(|param| {
+                        let field = param.id_node().and_then(|i| i.ident());
+                        let label = field.map(|f| f.text().to_owned());
+                        (param.expression_node(), label.unwrap_or_default())
+                    })
+                    .collect::<Vec<_>>();
+                self.vertex(Operator("struct"), fields)
+            }
+            ExpressionNode::MergeExpressionNode(e) => {
+                let branches = e
+                    .all_merge_case_node()
+                    .map(|case| {
+                        let label = merge_case(&case).map(|c| c.to_string());
+                        (case.expression_node(), label.unwrap_or_default())
+                    })
+                    .collect::<Vec<_>>();
+                let index = self.vertex(Operator("merge"), branches);
+                if let Some(clock) = e.id_node().and_then(|i| i.ident()) {
+                    let clock = self.variable(clock.text());
+                    self.graph.add_edge(clock, index, "clock".into());
+                }
+                index
+            }
+            ExpressionNode::FbyExpressionNode(e) => self.binary(Memory("fby"), e),
+            ExpressionNode::ArrowExpressionNode(e) => self.binary(Memory("->"), e),
+            ExpressionNode::AndExpressionNode(e) => self.binary(Operator("and"), e),
+            ExpressionNode::OrExpressionNode(e) => self.binary(Operator("or"), e),
+            ExpressionNode::XorExpressionNode(e) => self.binary(Operator("xor"), e),
+            ExpressionNode::ImplExpressionNode(e) => self.binary(Operator("=>"), e),
+            ExpressionNode::EqExpressionNode(e) => self.binary(Operator("="), e),
+            ExpressionNode::NeqExpressionNode(e) => self.binary(Operator("<>"), e),
+            ExpressionNode::LtExpressionNode(e) => self.binary(Operator("<"), e),
+            ExpressionNode::LteExpressionNode(e) => self.binary(Operator("<="), e),
+            ExpressionNode::GtExpressionNode(e) => self.binary(Operator(">"), e),
+            ExpressionNode::GteExpressionNode(e) => self.binary(Operator(">="), e),
+            ExpressionNode::DivExpressionNode(e) => self.binary(Operator("/"), e),
+            ExpressionNode::ModExpressionNode(e) => self.binary(Operator("mod"), e),
+            ExpressionNode::SubExpressionNode(e) => self.binary(Operator("-"), e),
+            ExpressionNode::AddExpressionNode(e) => self.binary(Operator("+"), e),
+            ExpressionNode::MulExpressionNode(e) => self.binary(Operator("*"), e),
+            ExpressionNode::PowerExpressionNode(e) => self.binary(Operator("**"), e),
+            ExpressionNode::HatExpressionNode(e) => self.binary(Operator("^"), e),
+            ExpressionNode::ConcatExpressionNode(e) => self.binary(Operator("|"), e),
+            ExpressionNode::ArrayAccessExpressionNode(e) => self.vertex(
+                Operator("[]"),
+                [(e.array(), String::new()), (e.index(), "index".into())],
+            ),
+            ExpressionNode::ArrayLiteralExpressionNode(e) => self.list(Operator("array"), e),
+            ExpressionNode::IfExpressionNode(e) => self.vertex(
+                Operator("if"),
+                [
+                    (e.cond(), "cond".into()),
+                    (e.if_body(), "then".into()),
+                    (e.else_body(), "else".into()),
+                ],
+            ),
+            ExpressionNode::WithExpressionNode(e) => self.vertex(
+                Operator("with"),
+                [
+                    (e.cond(), "cond".into()),
+                    (e.with_body(), "then".into()),
+                    (e.else_body(), "else".into()),
+                ],
+            ),
+            ExpressionNode::DieseExpressionNode(e) => self.list(Operator("#"), e),
+            ExpressionNode::NorExpressionNode(e) => self.list(Operator("nor"), e),
+            ExpressionNode::ParExpressionNode(e) => {
+                let mut operands = e.syntax().children().filter_map(ExpressionNode::cast);
+                match (operands.next(), operands.next()) {
+                    (Some(single), None) => self.expr(&single)?,
+                    _ => self.list(Operator("tuple"), e),
+                }
+            }
+            ExpressionNode::CallByPosExpressionNode(call) => {
+                let id_node = call.node_ref()?.id_node()?;
+                let name = id_node.name()?;
+                let callee = crate::name_resolution::resolve_node(self.db, id_node.clone());
+                let params = match Option::clone(&callee) {
+                    Some(n) => crate::get_typed_signature(self.db, n).params.clone(),
+                    None => crate::name_resolution::resolve_extern_node(self.db, id_node)
+                        .as_ref()
+                        .clone()
+                        .map(|ext| crate::get_extern_signature(self.db, ext).params.clone())
+                        .unwrap_or_default(),
+                };
+
+                let operands = call
+                    .args()
+                    .skip(1)
+                    .enumerate()
+                    .map(|(idx, arg)| {
+                        let label = params
+                            .get(idx)
+                            .map(|(ident, _)| ident.text().to_owned())
+                            .unwrap_or_else(|| idx.to_string());
+                        (Some(arg), label)
+                    })
+                    .collect::<Vec<_>>();
+
+                let vertex = DataflowVertex::Call {
+                    node: name.text().into(),
+                    stateful: self.stateful.contains(e),
+                };
+                self.vertex(vertex, operands)
+            }
+        })
+    }
+}
+
+/// Returns the name of the variable that is (partially) defined by a left item
+pub(crate) fn left_item_name(item: &LeftItemNode) -> Option<String> {
+    match item {
+        LeftItemNode::IdNode(id) => Some(id.ident()?.text().into()),
+        LeftItemNode::LeftFieldAccessNode(access) => left_item_name(&access.left_item_node()?),
+        LeftItemNode::LeftTableAccessNode(access) => left_item_name(&access.left_item_node()?),
+    }
+}
+
+/// **Query:** Builds the dataflow graph of a node
+#[yeter::query]
+pub fn dataflow_graph(db: &Database, node: NodeNode) -> DataflowGraph {
+    let sig = crate::get_signature(db, node.clone());
+
+    let mut builder = GraphBuilder {
+        db,
+        graph: DataflowGraph::new(),
+        variables: HashMap::new(),
+        constants: HashMap::new(),
+        stateful: stateful_expr_of_node(db, node.clone())
+            .iter()
+            .cloned()
+            .collect(),
+    };
+
+    let locals = node
+        .all_var_decl_node()
+        .flat_map(|v| v.all_typed_ids_node())
+        .collect::<Vec<_>>();
+
+    let variables = [
+        (
+            &sig.params,
+            DataflowVertex::Input as fn(String) -> DataflowVertex,
+        ),
+        (&sig.return_params, DataflowVertex::Output),
+        (&locals, DataflowVertex::Local),
+    ];
+
+    for (ids, constructor) in variables {
+        for ident in ids.iter().flat_map(|group| group.all_ident()) {
+            let name = ident.text().to_owned();
+            let index = builder.graph.add_node(constructor(name.clone()));
+            builder.variables.insert(name, index);
+        }
+    }
+
+    for equation in node
+        .body_node()
+        .iter()
+        .flat_map(|b| b.all_equals_equation_node())
+    {
+        let Some(value) = equation.expression_node().and_then(|e| builder.expr(&e)) else {
+            continue;
+        };
+
+        let lefts = equation
+            .left_node()
+            .iter()
+            .flat_map(|l| l.all_left_item_node())
+            .filter_map(|item| left_item_name(&item))
+            .collect::<Vec<_>>();
+
+        let is_tuple = lefts.len() > 1;
+        for (idx, name) in lefts.iter().enumerate() {
+            let label = if is_tuple {
+                idx.to_string()
+            } else {
+                String::new()
+            };
+            let variable = builder.variable(name);
+            builder.graph.add_edge(value, variable, label);
+        }
+    }
+
+    for assertion in node
+        .body_node()
+        .iter()
+        .flat_map(|b| b.all_assert_equation_node())
+    {
+        let operand = assertion.expression_node();
+        builder.vertex(
+            DataflowVertex::Operator("assert"),
+            [(operand, String::new())],
+        );
+    }
+
+    builder.graph
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_graph() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node counter(x : int) returns (y : int);
+             var last : int;
+             let
+                 last = 0 -> pre y;
+                 y = last + x;
+             tel"
+            .into(),
+        );
+
+        let node = crate::name_resolution::find_node(&db, "counter".into());
+        let graph = dataflow_graph(&db, Option::clone(&node).unwrap());
+
+        let count = |v: &DataflowVertex| graph.node_weights().filter(|w| *w == v).count();
+        assert_eq!(count(&DataflowVertex::Input("x".into())), 1);
+        assert_eq!(count(&DataflowVertex::Output("y".into())), 1);
+        assert_eq!(count(&DataflowVertex::Local("last".into())), 1);
+        assert_eq!(count(&DataflowVertex::Memory("->")), 1);
+        assert_eq!(count(&DataflowVertex::Memory("pre")), 1);
+        assert_eq!(count(&DataflowVertex::Operator("+")), 1);
+
+        // 0 -> arrow, pre -> arrow, y -> pre, arrow -> last, last -> +, x -> +, + -> y
+        assert_eq!(graph.edge_count(), 7);
+    }
+}
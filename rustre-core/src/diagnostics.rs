@@ -1,11 +1,16 @@
 use std::fmt::{Debug, Formatter};
 use std::path::{Path, PathBuf};
 
+use crate::SourceFile;
+
+use rustre_parser::ast::AstNode;
 use rustre_parser::{SyntaxElement, SyntaxNode, SyntaxToken};
 
 #[derive(Clone)]
 pub struct Span {
     pub file: PathBuf,
+    /// [Revision][SourceFile::revision] of the file when the span was computed
+    pub revision: u64,
     pub start: usize,
     pub end: usize,
 }
@@ -16,24 +21,41 @@ impl Debug for Span {
     }
 }
 
+/// **Query**: Returns the file a syntax tree was parsed from
+///
+/// `root` must be the root node of a syntax tree returned by [`parse_file`][crate::parse_file()]
+/// for one of the loaded [files][crate::files()].
 #[yeter::query]
-pub fn file_for_root(db: &yeter::Database, _root: SyntaxNode) -> Option<PathBuf> {
+pub fn file_for_root(db: &yeter::Database, root: SyntaxNode) -> Option<SourceFile> {
     let files = crate::files(db);
     let files = files.as_ref().as_deref().unwrap_or_default();
 
-    // TODO: we assume only one file is loaded, that's stupid
-    files.get(0).map(|s| s.path.clone())
+    let exact = files
+        .iter()
+        .find(|file| crate::parse_file(db, (*file).clone()).syntax() == &root);
+    if let Some(file) = exact {
+        return Some(file.clone());
+    }
+
+    // The root may come from an outdated parse of the file if the parsing query was recomputed, so
+    // we fall back to comparing its text (the syntax tree is lossless). This is only done when a
+    // single file has this text, as the tree could belong to any of them otherwise.
+    let mut same_text = files
+        .iter()
+        .filter(|file| root.text() == file.text.as_str());
+    match (same_text.next(), same_text.next()) {
+        (Some(file), None) => Some(file.clone()),
+        _ => None,
+    }
 }
 
 /// Returns the length of the trivia preceding a node
 fn preceding_trivia_len(syntax: &SyntaxNode) -> usize {
     syntax
-        .children_with_tokens()
-        .map_while(|el| match el {
-            SyntaxElement::Token(t) if !t.kind().is_trivia() => None,
-            SyntaxElement::Token(t) => Some(t.text().len()),
-            SyntaxElement::Node(n) => Some(preceding_trivia_len(&n)),
-        })
+        .descendants_with_tokens()
+        .filter_map(SyntaxElement::into_token)
+        .take_while(|t| t.kind().is_trivia())
+        .map(|t| t.text().len())
         .sum()
 }
 
@@ -43,23 +65,33 @@ impl Span {
         let root = syntax_token.parent_ancestors().last().unwrap();
         let file = Option::clone(&file_for_root(db, root)).expect("AST not bound to a file");
         Span {
-            file,
+            revision: file.revision(),
+            file: file.path,
             start: range.start().into(),
             end: range.end().into(),
         }
     }
 
     pub fn of_node(db: &yeter::Database, syntax_node: &SyntaxNode) -> Self {
-        let to_skip = preceding_trivia_len(syntax_node);
-        let range = syntax_node.text_range();
         let root = syntax_node
             .ancestors()
             .last()
             .unwrap_or(syntax_node.clone());
 
         let file = Option::clone(&file_for_root(db, root)).expect("AST not bound to a file");
+        Self::in_file(&file, syntax_node)
+    }
+
+    /// Like [`Span::of_node`], but with an explicitly given file
+    ///
+    /// This is useful while files are still being loaded, when their syntax trees can't be bound to
+    /// them yet.
+    pub fn in_file(file: &SourceFile, syntax_node: &SyntaxNode) -> Self {
+        let to_skip = preceding_trivia_len(syntax_node);
+        let range = syntax_node.text_range();
         Span {
-            file,
+            file: file.path.clone(),
+            revision: file.revision(),
             start: to_skip + usize::from(range.start()),
             end: range.end().into(),
         }
@@ -70,6 +102,18 @@ impl Span {
         self.start = self.end;
         self
     }
+
+    /// Returns `true` if the file this span refers to has changed since it was computed
+    ///
+    /// The effects of queries that were computed on former versions of the program are kept in the
+    /// database, this tells which diagnostics are still relevant.
+    pub fn is_outdated(&self, db: &yeter::Database) -> bool {
+        let files = crate::files(db);
+        let files = files.as_ref().as_deref().unwrap_or_default();
+        !files
+            .iter()
+            .any(|f| f.path == self.file && f.revision() == self.revision)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -100,6 +144,13 @@ impl Diagnostic {
             .map(|(span, _)| (span.file.as_path(), span.start))
     }
 
+    /// Returns `true` if some attachments of this diagnostic are [outdated][Span::is_outdated]
+    pub fn is_outdated(&self, db: &yeter::Database) -> bool {
+        self.attachments
+            .iter()
+            .any(|(span, _)| span.is_outdated(db))
+    }
+
     pub fn emit(self, db: &yeter::Database) {
         db.do_effect(self);
     }
@@ -112,3 +163,54 @@ pub enum Level {
     Warning,
     Error,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Span;
+    use crate::name_resolution::find_node;
+    use rustre_parser::ast::AstNode;
+
+    const SOURCE: &str = "-- a comment before the node
+node n(a : int) returns (b : int);
+let
+  -- a comment inside of the node
+  b = a;
+tel
+";
+
+    #[test]
+    fn node_spans_skip_leading_trivia() {
+        let mut db = crate::driver();
+        crate::add_source_contents(&mut db, SOURCE.into());
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let span = Span::of_node(&db, node.syntax());
+        assert_eq!(span.start, SOURCE.find("node n").unwrap());
+        assert!(SOURCE[span.start..span.end].trim_end().ends_with("tel"));
+
+        // The trivia that follows the first token must not be skipped
+        let body = node.body_node().unwrap();
+        let span = Span::of_node(&db, body.syntax());
+        assert_eq!(span.start, SOURCE.find("let").unwrap());
+
+        let equation = body.all_equals_equation_node().next().unwrap();
+        let span = Span::of_node(&db, equation.syntax());
+        assert_eq!(&SOURCE[span.start..span.end], "b = a");
+    }
+
+    #[test]
+    fn spans_are_outdated_once_their_file_changes() {
+        let db = crate::driver();
+        crate::set_source_contents(&db, "a.lus".into(), SOURCE.into());
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let span = Span::of_node(&db, node.syntax());
+        assert!(!span.is_outdated(&db));
+
+        crate::set_source_contents(&db, "a.lus".into(), SOURCE.replace("b = a", "b = 2 * a"));
+        assert!(span.is_outdated(&db));
+
+        crate::set_source_contents(&db, "a.lus".into(), SOURCE.into());
+        assert!(!span.is_outdated(&db));
+    }
+}
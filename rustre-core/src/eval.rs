@@ -1,6 +1,7 @@
 use crate::{
+    generics::{self, StaticBindings},
     name_resolution::{self, NameResolveQuery},
-    types::ConstValue,
+    types::{type_of_type_decl, ConstValue, Type},
 };
 use rustre_parser::ast::*;
 use yeter::Database;
@@ -11,372 +12,597 @@ pub fn eval_const_node(
     node: ExpressionNode,
     in_node: Option<NodeNode>,
 ) -> Option<ConstValue> {
-    // TODO : Parse constant nodes values from string better
-    match node {
-        ExpressionNode::ConstantNode(node) => {
-            if node.r#true().is_some() {
-                return Some(ConstValue::Boolean(true));
-            }
-            if node.r#false().is_some() {
-                return Some(ConstValue::Boolean(false));
-            }
-            if node.i_const().is_some() {
-                return Some(ConstValue::Integer(
-                    node.i_const().unwrap().text().parse::<i32>().unwrap(),
-                ));
-            }
-            if node.r_const().is_some() {
-                return Some(ConstValue::Real(
-                    node.r_const().unwrap().text().parse::<f32>().unwrap(),
-                ));
-            }
+    Evaluator::new(db, in_node).eval(node)
+}
 
-            todo!()
-        }
-        ExpressionNode::IdentExpressionNode(node) => {
-            let ident = node.id_node().unwrap();
-            let node = name_resolution::resolve_const_expr_node(
-                db,
-                NameResolveQuery {
-                    ident: ident.ident().unwrap(),
-                    in_node: in_node.clone(),
-                },
-            );
-
-            if let Some(ref node) = node.as_ref() {
-                Option::clone(&eval_const_node(db, node.clone(), in_node))
-            } else {
-                None
-            }
+/// Evaluates a constant expression in an instance of a generic node, where static constants have
+/// the given values
+pub fn eval_with_statics(
+    db: &Database,
+    node: ExpressionNode,
+    in_node: Option<NodeNode>,
+    statics: &StaticBindings,
+) -> Option<ConstValue> {
+    let evaluator = Evaluator {
+        statics: Some(statics),
+        ..Evaluator::new(db, in_node)
+    };
+    evaluator.eval(node)
+}
+
+struct Evaluator<'a> {
+    db: &'a Database,
+    in_node: Option<NodeNode>,
+    statics: Option<&'a StaticBindings>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(db: &'a Database, in_node: Option<NodeNode>) -> Self {
+        Evaluator {
+            db,
+            in_node,
+            statics: None,
         }
-        ExpressionNode::NotExpressionNode(node) => {
-            let value = Option::clone(&eval_const_node(db, node.operand()?, in_node))?;
-            match value {
-                ConstValue::Boolean(value) => Some(ConstValue::Boolean(!value)),
-                _ => todo!(),
-            }
+    }
+
+    /// Evaluates a sub-expression, through the query when possible so that its value is cached
+    fn sub(&self, node: ExpressionNode) -> Option<ConstValue> {
+        match self.statics {
+            None => Option::clone(&eval_const_node(self.db, node, self.in_node.clone())),
+            Some(_) => self.eval(node),
         }
-        ExpressionNode::NegExpressionNode(node) => {
-            let value = Option::clone(&eval_const_node(db, node.operand()?, in_node))?;
-            match value {
-                ConstValue::Integer(value) => Some(ConstValue::Integer(-value)),
-                ConstValue::Real(value) => Some(ConstValue::Real(-value)),
-                _ => todo!(),
-            }
+    }
+
+    fn slice_bounds(&self, select: &SelectNode) -> Option<(i32, i32, Option<i32>)> {
+        let int = |expr: Option<ExpressionNode>| match self.sub(expr?) {
+            Some(ConstValue::Integer(i)) => Some(i),
+            _ => None,
+        };
+
+        let first = int(select.left())?;
+        let last = int(select.right())?;
+        let step = match select.step_node() {
+            Some(step) => Some(int(step.expression_node())?),
+            None => None,
+        };
+        Some((first, last, step))
+    }
+
+    /// Evaluates a constant, an enum constructor or a static constant from its name
+    fn ident(&self, ident: &IdNode) -> Option<ConstValue> {
+        let name = ident.name()?;
+        let statics = self.statics.filter(|_| ident.package().is_none());
+        if let Some(value) = statics.and_then(|s| s.consts.get(name.text())) {
+            return Some(value.clone());
         }
-        ExpressionNode::PreExpressionNode(_) => todo!(),
-        ExpressionNode::CurrentExpressionNode(_) => todo!(),
-        ExpressionNode::IntExpressionNode(node) => {
-            let value = Option::clone(&eval_const_node(db, node.operand()?, in_node))?;
-            match value {
-                ConstValue::Integer(value) => Some(ConstValue::Integer(value)),
-                ConstValue::Real(value) => Some(ConstValue::Integer(value as i32)),
-                _ => todo!(),
-            }
+
+        let node = name_resolution::resolve_const_expr_node(
+            self.db,
+            NameResolveQuery {
+                ident: name,
+                in_node: self.in_node.clone(),
+            },
+        );
+
+        // Constants of a model depend on the static arguments of the instance they are seen
+        // through
+        let instance = name_resolution::resolve_instance(self.db, ident.clone());
+        if let (Some(node), Some(instance)) = (node.as_ref(), Option::clone(&instance)) {
+            let bindings = generics::package_instance_bindings(self.db, instance);
+            let bindings = Option::clone(&bindings).unwrap_or_default();
+            return eval_with_statics(self.db, node.clone(), None, &bindings);
         }
-        ExpressionNode::RealExpressionNode(node) => {
-            let value = Option::clone(&eval_const_node(db, node.operand()?, in_node))?;
-            match value {
-                ConstValue::Integer(value) => Some(ConstValue::Real(value as f32)),
-                ConstValue::Real(value) => Some(ConstValue::Real(value)),
-                _ => todo!(),
-            }
+
+        if let Some(node) = node.as_ref() {
+            self.sub(node.clone())
+        } else if name_resolution::resolve_enum_constructor(self.db, ident.clone()).is_some() {
+            Some(ConstValue::Enum(ident.name()?.text().to_owned()))
+        } else {
+            None
         }
-        ExpressionNode::WhenExpressionNode(_) => todo!(),
-        ExpressionNode::FbyExpressionNode(_) => todo!(),
-        ExpressionNode::ArrowExpressionNode(_) => todo!(),
-        ExpressionNode::AndExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
-                    Some(ConstValue::Boolean(left && right))
+    }
+
+    /// Evaluates a structure literal (`T { a = 1; b = 2 }`), or the update of a constant
+    /// structure (`T { s with a = 1 }`)
+    fn struct_literal(&self, node: &CallByNameExpressionNode) -> Option<ConstValue> {
+        let decl = name_resolution::resolve_type_decl(self.db, node.name()?);
+        let ty = type_of_type_decl(self.db, self.in_node.clone(), Option::clone(&decl)?);
+        let Type::Struct { fields, .. } = &*ty else {
+            return None;
+        };
+
+        let base = match node.base() {
+            Some(base) => match self.ident(&base)? {
+                ConstValue::Struct(values) => values,
+                _ => return None,
+            },
+            None => vec![],
+        };
+        let params = node.all_call_by_name_param_node().collect::<Vec<_>>();
+
+        let values = fields
+            .iter()
+            .map(|(field, _)| {
+                let param = params.iter().find(|p| {
+                    p.id_node()
+                        .and_then(|i| i.ident())
+                        .is_some_and(|i| i.text() == field)
+                });
+                let value = match param {
+                    Some(param) => self.sub(param.expression_node()?)?,
+                    None => base.iter().find(|(f, _)| f == field)?.1.clone(),
+                };
+                Some((field.clone(), value))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(ConstValue::Struct(values))
+    }
+
+    fn eval(&self, node: ExpressionNode) -> Option<ConstValue> {
+        // TODO : Parse constant nodes values from string better
+        match node {
+            ExpressionNode::ConstantNode(node) => {
+                if node.r#true().is_some() {
+                    return Some(ConstValue::Boolean(true));
+                }
+                if node.r#false().is_some() {
+                    return Some(ConstValue::Boolean(false));
+                }
+                if node.i_const().is_some() {
+                    return Some(ConstValue::Integer(
+                        node.i_const().unwrap().text().parse::<i32>().unwrap(),
+                    ));
+                }
+                if node.r_const().is_some() {
+                    return Some(ConstValue::Real(
+                        node.r_const().unwrap().text().parse::<f32>().unwrap(),
+                    ));
                 }
-                _ => todo!(),
+
+                None
             }
-        }
-        ExpressionNode::OrExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
-                    Some(ConstValue::Boolean(left || right))
+            ExpressionNode::IdentExpressionNode(node) => self.ident(&node.id_node()?),
+            ExpressionNode::NotExpressionNode(node) => {
+                let value = self.sub(node.operand()?)?;
+                match value {
+                    ConstValue::Boolean(value) => Some(ConstValue::Boolean(!value)),
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::XorExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
-                    Some(ConstValue::Boolean(left ^ right))
+            ExpressionNode::NegExpressionNode(node) => {
+                let value = self.sub(node.operand()?)?;
+                match value {
+                    ConstValue::Integer(value) => Some(ConstValue::Integer(value.wrapping_neg())),
+                    ConstValue::Real(value) => Some(ConstValue::Real(-value)),
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::ImplExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
-                    Some(ConstValue::Boolean(!left || right))
+            ExpressionNode::PreExpressionNode(_) => None,
+            ExpressionNode::CurrentExpressionNode(_) => None,
+            ExpressionNode::IntExpressionNode(node) => {
+                let value = self.sub(node.operand()?)?;
+                match value {
+                    ConstValue::Integer(value) => Some(ConstValue::Integer(value)),
+                    ConstValue::Real(value) => Some(ConstValue::Integer(value as i32)),
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::EqExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
-                    Some(ConstValue::Boolean(left == right))
-                }
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left == right))
-                }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean(left == right))
+            ExpressionNode::RealExpressionNode(node) => {
+                let value = self.sub(node.operand()?)?;
+                match value {
+                    ConstValue::Integer(value) => Some(ConstValue::Real(value as f32)),
+                    ConstValue::Real(value) => Some(ConstValue::Real(value)),
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::NeqExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
-                    Some(ConstValue::Boolean(left != right))
-                }
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left != right))
+            ExpressionNode::WhenExpressionNode(_) => None,
+            ExpressionNode::FbyExpressionNode(_) => None,
+            ExpressionNode::ArrowExpressionNode(_) => None,
+            ExpressionNode::AndExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
+                        Some(ConstValue::Boolean(left && right))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean(left != right))
-                }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::LtExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left < right))
-                }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean(left < right))
-                }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean((left as f32) < right))
-                }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left < (right as f32)))
+            ExpressionNode::OrExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
+                        Some(ConstValue::Boolean(left || right))
+                    }
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::LteExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left <= right))
-                }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean(left <= right))
-                }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean((left as f32) <= right))
+            ExpressionNode::XorExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
+                        Some(ConstValue::Boolean(left ^ right))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left <= (right as f32)))
-                }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::GtExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left > right))
+            ExpressionNode::ImplExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
+                        Some(ConstValue::Boolean(!left || right))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean(left > right))
-                }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean((left as f32) > right))
-                }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left > (right as f32)))
-                }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::GteExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left >= right))
-                }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean(left >= right))
+            ExpressionNode::EqExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
+                        Some(ConstValue::Boolean(left == right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left == right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean(left == right))
+                    }
+                    (ConstValue::Enum(left), ConstValue::Enum(right)) => {
+                        Some(ConstValue::Boolean(left == right))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Boolean((left as f32) >= right))
-                }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Boolean(left >= (right as f32)))
-                }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::DivExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Integer(left / right))
-                }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left / right))
-                }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left as f32 / right))
+            ExpressionNode::NeqExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Boolean(left), ConstValue::Boolean(right)) => {
+                        Some(ConstValue::Boolean(left != right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left != right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean(left != right))
+                    }
+                    (ConstValue::Enum(left), ConstValue::Enum(right)) => {
+                        Some(ConstValue::Boolean(left != right))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Real(left / right as f32))
-                }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::ModExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Integer(left % right))
-                }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left % right))
-                }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left as f32 % right))
-                }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Real(left % right as f32))
+            ExpressionNode::LtExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left < right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean(left < right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean((left as f32) < right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left < (right as f32)))
+                    }
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::SubExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Integer(left - right))
-                }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left - right))
-                }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left as f32 - right))
-                }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Real(left - right as f32))
+            ExpressionNode::LteExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left <= right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean(left <= right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean((left as f32) <= right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left <= (right as f32)))
+                    }
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::AddExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Integer(left + right))
-                }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left + right))
-                }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left as f32 + right))
+            ExpressionNode::GtExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left > right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean(left > right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean((left as f32) > right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left > (right as f32)))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Real(left + right as f32))
+            }
+            ExpressionNode::GteExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left >= right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean(left >= right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Boolean((left as f32) >= right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Boolean(left >= (right as f32)))
+                    }
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::MulExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Integer(left * right))
+            ExpressionNode::DivExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        (right != 0).then(|| ConstValue::Integer(left.wrapping_div(right)))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left / right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left as f32 / right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Real(left / right as f32))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left * right))
+            }
+            ExpressionNode::ModExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        (right != 0).then(|| ConstValue::Integer(left.wrapping_rem(right)))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left % right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left as f32 % right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Real(left % right as f32))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left as f32 * right))
+            }
+            ExpressionNode::SubExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Integer(left.wrapping_sub(right)))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left - right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left as f32 - right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Real(left - right as f32))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Real(left * right as f32))
+            }
+            ExpressionNode::AddExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Integer(left.wrapping_add(right)))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left + right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left as f32 + right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Real(left + right as f32))
+                    }
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::PowerExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match (left, right) {
-                (ConstValue::Integer(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Integer(left.pow(right as u32)))
+            ExpressionNode::MulExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Integer(left.wrapping_mul(right)))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left * right))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left as f32 * right))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Real(left * right as f32))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real(left.powf(right)))
+            }
+            ExpressionNode::PowerExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Integer(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Integer(left.wrapping_pow(right as u32)))
+                    }
+                    (ConstValue::Real(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real(left.powf(right)))
+                    }
+                    (ConstValue::Integer(left), ConstValue::Real(right)) => {
+                        Some(ConstValue::Real((left as f32).powf(right)))
+                    }
+                    (ConstValue::Real(left), ConstValue::Integer(right)) => {
+                        Some(ConstValue::Real(left.powf(right as f32)))
+                    }
+                    _ => None,
                 }
-                (ConstValue::Integer(left), ConstValue::Real(right)) => {
-                    Some(ConstValue::Real((left as f32).powf(right)))
+            }
+            ExpressionNode::IfExpressionNode(node) => {
+                let cond = self.sub(node.cond()?)?;
+                match cond {
+                    ConstValue::Boolean(true) => self.sub(node.if_body()?),
+                    ConstValue::Boolean(false) => self.sub(node.else_body()?),
+                    _ => None,
                 }
-                (ConstValue::Real(left), ConstValue::Integer(right)) => {
-                    Some(ConstValue::Real(left.powf(right as f32)))
+            }
+            ExpressionNode::HatExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match right {
+                    ConstValue::Integer(i) => Some(ConstValue::Array(
+                        std::iter::repeat_n(left, i as usize).collect(),
+                    )),
+                    _ => None,
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::IfExpressionNode(node) => {
-            let cond = Option::clone(&eval_const_node(db, node.cond()?, in_node.clone()))?;
-            match cond {
-                ConstValue::Boolean(true) => {
-                    Option::clone(&eval_const_node(db, node.if_body()?, in_node))
+            ExpressionNode::WithExpressionNode(_) => None,
+            ExpressionNode::DieseExpressionNode(_) => None,
+            ExpressionNode::NorExpressionNode(_) => None,
+            ExpressionNode::ParExpressionNode(node) => {
+                let mut operands = node.syntax().children().filter_map(ExpressionNode::cast);
+                match (operands.next(), operands.next()) {
+                    (Some(single), None) => self.sub(single),
+                    _ => None,
                 }
-                ConstValue::Boolean(false) => {
-                    Option::clone(&eval_const_node(db, node.else_body()?, in_node))
+            }
+            ExpressionNode::CallByPosExpressionNode(_) => None,
+            ExpressionNode::MergeExpressionNode(_) => None,
+            ExpressionNode::FieldAccessExpressionNode(node) => {
+                let ConstValue::Struct(fields) = self.sub(node.left()?)? else {
+                    return None;
+                };
+                let field = node.field()?;
+                let (_, value) = fields.into_iter().find(|(f, _)| f == field.text())?;
+                Some(value)
+            }
+            ExpressionNode::CallByNameExpressionNode(node) => self.struct_literal(&node),
+            ExpressionNode::ArrayAccessExpressionNode(node) => {
+                let array = self.sub(node.array()?)?;
+                let ConstValue::Array(elements) = array else {
+                    return None;
+                };
+
+                if let Some(select) = node.select_node() {
+                    let (first, last, step) = self.slice_bounds(&select)?;
+                    let slice = slice_indices(first, last, step)?
+                        .into_iter()
+                        .map(|i| elements.get(usize::try_from(i).ok()?).cloned())
+                        .collect::<Option<_>>()?;
+                    Some(ConstValue::Array(slice))
+                } else {
+                    match self.sub(node.index()?)? {
+                        ConstValue::Integer(i) => elements.get(usize::try_from(i).ok()?).cloned(),
+                        _ => None,
+                    }
                 }
-                _ => todo!(),
             }
-        }
-        ExpressionNode::HatExpressionNode(node) => {
-            let left = Option::clone(&eval_const_node(db, node.left()?, in_node.clone()))?;
-            let right = Option::clone(&eval_const_node(db, node.right()?, in_node))?;
-            match right {
-                ConstValue::Integer(i) => Some(ConstValue::Array(
-                    std::iter::repeat(left).take(i as usize).collect(),
-                )),
-                _ => None,
+            ExpressionNode::ArrayLiteralExpressionNode(node) => {
+                let elements = node
+                    .elements()
+                    .map(|e| self.sub(e))
+                    .collect::<Option<_>>()?;
+                Some(ConstValue::Array(elements))
+            }
+            ExpressionNode::ConcatExpressionNode(node) => {
+                let left = self.sub(node.left()?)?;
+                let right = self.sub(node.right()?)?;
+                match (left, right) {
+                    (ConstValue::Array(mut left), ConstValue::Array(right)) => {
+                        left.extend(right);
+                        Some(ConstValue::Array(left))
+                    }
+                    _ => None,
+                }
             }
         }
-        ExpressionNode::WithExpressionNode(_) => todo!(),
-        ExpressionNode::DieseExpressionNode(_) => todo!(),
-        ExpressionNode::NorExpressionNode(_) => todo!(),
-        ExpressionNode::ParExpressionNode(_) => todo!(),
-        ExpressionNode::CallByPosExpressionNode(_) => todo!(),
     }
 }
 
+/// Evaluates a constant or an enum constructor from its name
+pub fn eval_ident(db: &Database, ident: &IdNode, in_node: Option<NodeNode>) -> Option<ConstValue> {
+    Evaluator::new(db, in_node).ident(ident)
+}
+
+/// Evaluates a constant or an enum constructor from its name, in an instance of a generic node
+pub fn eval_ident_with_statics(
+    db: &Database,
+    ident: &IdNode,
+    in_node: Option<NodeNode>,
+    statics: &StaticBindings,
+) -> Option<ConstValue> {
+    let evaluator = Evaluator {
+        statics: Some(statics),
+        ..Evaluator::new(db, in_node)
+    };
+    evaluator.ident(ident)
+}
+
+/// Evaluates the first index, the last index and the step of a slice, if they are constant
+pub fn eval_slice_bounds(
+    db: &Database,
+    select: &SelectNode,
+    in_node: Option<NodeNode>,
+) -> Option<(i32, i32, Option<i32>)> {
+    Evaluator::new(db, in_node).slice_bounds(select)
+}
+
+/// Evaluates the bounds of a slice in an instance of a generic node, see [eval_slice_bounds]
+pub fn eval_slice_bounds_with_statics(
+    db: &Database,
+    select: &SelectNode,
+    in_node: Option<NodeNode>,
+    statics: &StaticBindings,
+) -> Option<(i32, i32, Option<i32>)> {
+    let evaluator = Evaluator {
+        statics: Some(statics),
+        ..Evaluator::new(db, in_node)
+    };
+    evaluator.slice_bounds(select)
+}
+
+/// Returns the indices selected by a slice, in order
+///
+/// Without an explicit step, the slice goes from `first` to `last` one element at a time, in
+/// whichever direction is needed. `None` is returned if the step is zero or goes the wrong way.
+pub fn slice_indices(first: i32, last: i32, step: Option<i32>) -> Option<Vec<i32>> {
+    let step = step.unwrap_or(if first <= last { 1 } else { -1 });
+    if step == 0 || (last - first).signum() * step.signum() < 0 {
+        return None;
+    }
+
+    let count = (last - first) / step + 1;
+    Some((0..count).map(|i| first + i * step).collect())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::files;
@@ -394,7 +620,7 @@ mod tests {
                 .as_ref()
                 .as_ref()
                 .unwrap()
-                .get(0)
+                .first()
                 .unwrap()
                 .clone(),
         );
@@ -447,7 +673,7 @@ mod tests {
                 .as_ref()
                 .as_ref()
                 .unwrap()
-                .get(0)
+                .first()
                 .unwrap()
                 .clone(),
         );
@@ -478,7 +704,7 @@ mod tests {
                 .as_ref()
                 .as_ref()
                 .unwrap()
-                .get(0)
+                .first()
                 .unwrap()
                 .clone(),
         );
@@ -509,7 +735,7 @@ mod tests {
                 .as_ref()
                 .as_ref()
                 .unwrap()
-                .get(0)
+                .first()
                 .unwrap()
                 .clone(),
         );
@@ -526,4 +752,71 @@ mod tests {
         let value = value.as_ref().unwrap();
         assert_eq!(*value, crate::types::ConstValue::Integer(2));
     }
+
+    #[test]
+    fn struct_constants() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            String::from(
+                "type point = struct { x : int; y : int };
+                 const origin : point = point { x = 0; y = 0 };
+                 const right : point = point { origin with x = 4 };
+                 const n : int = right.x + right.y;",
+            ),
+        );
+        let node = crate::parse_file(
+            &db,
+            files(&db)
+                .as_ref()
+                .as_ref()
+                .unwrap()
+                .first()
+                .unwrap()
+                .clone(),
+        );
+        let exprs = node
+            .all_constant_decl_node()
+            .flat_map(|c| c.all_one_constant_decl_node())
+            .map(|c| c.expression_node().unwrap())
+            .collect::<Vec<_>>();
+
+        use crate::types::ConstValue::{Integer, Struct};
+        let right = Option::clone(&eval_const_node(&db, exprs[1].clone(), None));
+        assert_eq!(
+            right,
+            Some(Struct(vec![
+                ("x".into(), Integer(4)),
+                ("y".into(), Integer(0))
+            ]))
+        );
+        let n = Option::clone(&eval_const_node(&db, exprs[2].clone(), None));
+        assert_eq!(n, Some(Integer(4)));
+    }
+
+    #[test]
+    fn non_constant_call() {
+        let mut db = crate::driver();
+        crate::add_source_contents(&mut db, String::from("const x = f(1) + 1;"));
+        let node = crate::parse_file(
+            &db,
+            files(&db)
+                .as_ref()
+                .as_ref()
+                .unwrap()
+                .first()
+                .unwrap()
+                .clone(),
+        );
+        let expr = node
+            .all_constant_decl_node()
+            .next()
+            .unwrap()
+            .all_one_constant_decl_node()
+            .next()
+            .unwrap()
+            .expression_node()
+            .unwrap();
+        assert!(eval_const_node(&db, expr, None).is_none());
+    }
 }
@@ -0,0 +1,514 @@
+//! Canonical source formatting
+//!
+//! The formatter walks the tokens of the lossless syntax tree and decides, for each of them, how it
+//! is separated from the previous one: by nothing, by a space, or by a line break with the
+//! indentation of the block it is in. Comments are kept, as well as single blank lines and line
+//! breaks within expressions.
+//!
+//! Only files without syntax errors are formatted, the tree of a broken file may not reflect what
+//! its author meant.
+
+use crate::diagnostics::{Diagnostic, Level, Span};
+use crate::SourceFile;
+use rustre_parser::ast::AstNode;
+use rustre_parser::lexer::Token;
+use rustre_parser::{SyntaxElement, SyntaxNode, SyntaxToken};
+use std::collections::HashMap;
+
+const INDENT: &str = "    ";
+
+/// Formats a source file, or returns the syntax errors that prevent it from being formatted
+pub fn format_file(file: &SourceFile) -> Result<String, Vec<Diagnostic>> {
+    let (root, errors) = rustre_parser::parse(&file.text);
+    if !errors.is_empty() {
+        let revision = file.revision();
+        let diagnostics = errors.into_iter().map(|error| {
+            let span = Span {
+                file: file.path.clone(),
+                revision,
+                start: error.span.start,
+                end: error.span.end,
+            };
+            Diagnostic::new(Level::Error, "parsing error").with_attachment(span, error.msg)
+        });
+        return Err(diagnostics.collect());
+    }
+
+    let mut formatter = Formatter::new(root.syntax());
+    formatter.format(root.syntax());
+    Ok(formatter.out)
+}
+
+/// How a token is separated from the previous one
+enum Separator {
+    None,
+    Space,
+    Line { indent: usize, blank: bool },
+}
+
+struct Formatter {
+    out: String,
+    /// Width of the names declared in each `var` section, by [TypedIdsNode], to align their types
+    var_widths: HashMap<SyntaxNode, usize>,
+    /// Last token that was written, comments included
+    prev: Option<SyntaxToken>,
+    /// Number of line breaks in the source since `prev`
+    newlines: usize,
+}
+
+fn first_token(node: &SyntaxNode) -> Option<SyntaxToken> {
+    node.descendants_with_tokens()
+        .filter_map(|el| el.into_token())
+        .find(|t| !t.kind().is_trivia())
+}
+
+/// Returns `true` if no tokens but trivia follow a token in a node
+fn is_last_token(token: &SyntaxToken, node: &SyntaxNode) -> bool {
+    let mut next = std::iter::successors(token.next_token(), |t| t.next_token());
+    next.find(|t| !t.kind().is_trivia())
+        .is_none_or(|t| t.text_range().start() >= node.text_range().end())
+}
+
+fn prev_siblings(element: SyntaxElement) -> impl Iterator<Item = SyntaxElement> {
+    std::iter::successors(element.prev_sibling_or_token(), |s| {
+        s.prev_sibling_or_token()
+    })
+    .filter(|s| !s.kind().is_trivia())
+}
+
+fn next_siblings(element: SyntaxElement) -> impl Iterator<Item = SyntaxElement> {
+    std::iter::successors(element.next_sibling_or_token(), |s| {
+        s.next_sibling_or_token()
+    })
+    .filter(|s| !s.kind().is_trivia())
+}
+
+/// Lists the nodes that start with a given token, from the innermost one
+fn started_by(token: &SyntaxToken) -> impl Iterator<Item = SyntaxNode> + '_ {
+    token
+        .parent_ancestors()
+        .take_while(move |node| first_token(node).as_ref() == Some(token))
+}
+
+fn is_comment(token: &SyntaxToken) -> bool {
+    matches!(token.kind(), Token::Comment | Token::InlineComment)
+}
+
+/// Returns `true` for top-level declarations, that are separated by a blank line
+fn is_big_item(kind: Token) -> bool {
+    matches!(
+        kind,
+        Token::NodeNode | Token::PackageDeclNode | Token::ModelDeclNode
+    )
+}
+
+/// Returns `true` if a node is one of several declarations after a single `const` or `type`
+fn is_one_of_many_decls(node: &SyntaxNode) -> bool {
+    let kind = node.kind();
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+
+    matches!(
+        (parent.kind(), kind),
+        (Token::ConstantDeclNode, Token::OneConstantDeclNode)
+            | (Token::TypeDeclNode, Token::OneTypeDeclNode)
+    ) && parent.children().filter(|c| c.kind() == kind).count() > 1
+}
+
+/// Returns `true` if an element of a node is indented relatively to the node
+fn is_indented(parent: &SyntaxNode, child: &SyntaxElement) -> bool {
+    let kind = child.kind();
+    match parent.kind() {
+        Token::BodyNode => !matches!(kind, Token::Let | Token::Tel),
+        Token::PackageDeclBody => !matches!(kind, Token::Body | Token::End),
+        Token::UsesNode | Token::ProvidesListNode | Token::ProvidesNode => true,
+        Token::ModelDeclNode => matches!(kind, Token::Needs | Token::StaticParamNode),
+        Token::NodeNode => match child {
+            SyntaxElement::Node(_) => {
+                matches!(kind, Token::VarDeclNode | Token::OneConstantDeclNode)
+            }
+            // Comments between local declarations
+            SyntaxElement::Token(_) if matches!(kind, Token::Var | Token::Const) => false,
+            SyntaxElement::Token(_) => prev_siblings(child.clone())
+                .take_while(|s| s.kind() != Token::BodyNode)
+                .any(|s| matches!(s.kind(), Token::Var | Token::Const)),
+        },
+        _ => matches!(child, SyntaxElement::Node(n) if is_one_of_many_decls(n)),
+    }
+}
+
+/// Indentation of a line that would start at a token
+fn block_indent(token: &SyntaxToken) -> usize {
+    let mut child = SyntaxElement::Token(token.clone());
+    let mut indent = 0;
+
+    for ancestor in token.parent_ancestors() {
+        indent += is_indented(&ancestor, &child) as usize;
+        child = SyntaxElement::Node(ancestor);
+    }
+
+    indent
+}
+
+/// Returns `true` if a token must start a new line
+fn starts_line(token: &SyntaxToken) -> bool {
+    let parent = token.parent().map(|p| p.kind());
+    let keyword_line = matches!(
+        (parent, token.kind()),
+        (Some(Token::BodyNode), Token::Let | Token::Tel)
+            | (Some(Token::NodeNode), Token::Var | Token::Const)
+            | (Some(Token::PackageDeclBody), Token::Body | Token::End)
+            | (Some(Token::ProvidesListNode), Token::Provides)
+            | (Some(Token::ModelDeclNode), Token::Needs)
+    );
+
+    keyword_line
+        || started_by(token).any(|node| {
+            let parent = node.parent().map(|p| p.kind());
+            matches!(
+                (parent, node.kind()),
+                (Some(Token::Root | Token::PackageDeclBody), _)
+                    | (Some(Token::BodyNode), _)
+                    | (Some(_), Token::UsesNode | Token::ProvidesNode)
+                    | (
+                        Some(Token::NodeNode),
+                        Token::VarDeclNode | Token::OneConstantDeclNode
+                    )
+            ) || is_one_of_many_decls(&node)
+        })
+}
+
+/// Returns the top-level declaration (in a file or package) a token is part of
+fn item_of(token: &SyntaxToken) -> Option<SyntaxNode> {
+    token.parent_ancestors().find(|node| {
+        matches!(
+            node.parent().map(|p| p.kind()),
+            Some(Token::Root | Token::PackageDeclBody)
+        )
+    })
+}
+
+/// Returns `true` if two consecutive tokens on the same line are separated by a space
+fn spaced(prev: &SyntaxToken, token: &SyntaxToken) -> bool {
+    use Token::*;
+
+    let parent = token.parent().map(|p| p.kind());
+    let prev_parent = prev.parent().map(|p| p.kind());
+
+    let no_space_before = matches!(
+        token.kind(),
+        Comma
+            | Semicolon
+            | ClosePar
+            | CloseBracket
+            | CloseStaticPar
+            | OpenStaticPar
+            | DoubleColon
+            | CDots
+            | Dot
+            | Hat
+    );
+    let no_space_after = matches!(
+        prev.kind(),
+        OpenPar | OpenBracket | OpenStaticPar | DoubleColon | CDots | IConstAndCDots | Dot | Hat
+    );
+
+    // Calls, profiles and static arguments: `f(x)`, `node n(x : int)`, `f<<3>>(x)`, `#(a, b)`
+    let call_par = token.kind() == OpenPar
+        && matches!(prev.kind(), Ident | CloseStaticPar | Diese | Nor)
+        && parent != Some(MergeCaseNode);
+    let access = token.kind() == OpenBracket
+        && matches!(
+            parent,
+            Some(ArrayAccessExpressionNode | LeftTableAccessNode)
+        );
+    let negation = prev.kind() == Minus && prev_parent == Some(NegExpressionNode);
+
+    !(no_space_before || no_space_after || call_par || access || negation)
+}
+
+impl Formatter {
+    fn new(root: &SyntaxNode) -> Self {
+        let mut var_widths = HashMap::new();
+
+        for node in root.descendants().filter(|n| n.kind() == Token::NodeNode) {
+            let mut section = Vec::new();
+            for element in node.children_with_tokens() {
+                match element.kind() {
+                    Token::Var | Token::Const | Token::BodyNode => {
+                        align_section(&mut var_widths, &section);
+                        section.clear();
+                    }
+                    Token::VarDeclNode => {
+                        let ids = element.as_node().and_then(|decl| decl.first_child());
+                        section.extend(ids.filter(|ids| ids.kind() == Token::TypedIdsNode));
+                    }
+                    _ => (),
+                }
+            }
+            align_section(&mut var_widths, &section);
+        }
+
+        Formatter {
+            out: String::new(),
+            var_widths,
+            prev: None,
+            newlines: 0,
+        }
+    }
+
+    fn format(&mut self, root: &SyntaxNode) {
+        let tokens = root
+            .descendants_with_tokens()
+            .filter_map(|el| el.into_token());
+
+        for token in tokens {
+            match token.kind() {
+                Token::Space => self.newlines += token.text().matches('\n').count(),
+                _ if self.is_dropped(&token) => (),
+                _ => self.write(token),
+            }
+        }
+
+        let trimmed = self.out.trim_end().len();
+        self.out.truncate(trimmed);
+        self.out.push('\n');
+    }
+
+    fn write(&mut self, token: SyntaxToken) {
+        let separator = self.separator(&token);
+        match separator {
+            Separator::None => (),
+            Separator::Space => self.out.push(' '),
+            Separator::Line { indent, blank } => {
+                let trimmed = self.out.trim_end_matches(' ').len();
+                self.out.truncate(trimmed);
+                self.out.push('\n');
+                if blank {
+                    self.out.push('\n');
+                }
+                self.out.push_str(&INDENT.repeat(indent));
+            }
+        }
+
+        if let Some(width) = self.alignment(&token) {
+            let line = self.out.rsplit('\n').next().unwrap_or_default();
+            let names = line.trim_start().trim_end().chars().count();
+            let padding = width.saturating_sub(names);
+            self.out.push_str(&" ".repeat(padding));
+        }
+
+        self.out.push_str(token.text().trim_end());
+        if self.needs_semicolon_after(&token) {
+            self.out.push(';');
+        }
+
+        // Line comments include their line break
+        self.newlines = (token.kind() == Token::InlineComment) as usize;
+        self.prev = Some(token);
+    }
+
+    fn separator(&self, token: &SyntaxToken) -> Separator {
+        let Some(prev) = &self.prev else {
+            return Separator::None;
+        };
+
+        let indent = block_indent(token);
+        let blank = self.newlines > 1;
+
+        if is_comment(token) {
+            return match self.newlines {
+                0 => Separator::Space,
+                _ => Separator::Line { indent, blank },
+            };
+        }
+
+        if starts_line(token) {
+            // Nodes and packages are separated from other declarations by a blank line
+            let (item, prev_item) = (item_of(token), item_of(prev));
+            let starts_item = item
+                .as_ref()
+                .is_some_and(|i| first_token(i).as_ref() == Some(token));
+            let siblings = item.as_ref().and_then(|i| i.parent())
+                == prev_item.as_ref().and_then(|i| i.parent());
+            let big = [&item, &prev_item]
+                .into_iter()
+                .flatten()
+                .any(|i| is_big_item(i.kind()));
+
+            return Separator::Line {
+                indent,
+                blank: blank || (starts_item && siblings && big && !is_comment(prev)),
+            };
+        }
+
+        if self.newlines > 0 {
+            // Line breaks within a declaration or an expression are kept, and indented once more,
+            // unless the line would start with a closing delimiter or a separator
+            return match token.kind() {
+                Token::CloseBrace | Token::ClosePar | Token::CloseBracket => {
+                    Separator::Line { indent, blank }
+                }
+                Token::Comma | Token::Semicolon | Token::Dot
+                    if prev.kind() != Token::InlineComment =>
+                {
+                    Separator::None
+                }
+                _ => Separator::Line {
+                    indent: indent + 1,
+                    blank,
+                },
+            };
+        }
+
+        match spaced(prev, token) {
+            true => Separator::Space,
+            false => Separator::None,
+        }
+    }
+
+    /// Width of the names of the variables in the `var` section of the token, if it is the colon
+    /// before their type
+    fn alignment(&self, token: &SyntaxToken) -> Option<usize> {
+        let ids = token.parent().filter(|_| token.kind() == Token::Colon)?;
+        self.var_widths.get(&ids).copied()
+    }
+
+    /// Returns `true` for the optional `;` or `.` after `tel`, that are removed
+    fn is_dropped(&self, token: &SyntaxToken) -> bool {
+        let after_body = prev_siblings(token.clone().into())
+            .next()
+            .is_some_and(|s| s.kind() == Token::BodyNode);
+
+        matches!(token.kind(), Token::Semicolon | Token::Dot)
+            && token.parent().is_some_and(|p| p.kind() == Token::NodeNode)
+            && after_body
+    }
+
+    /// Returns `true` if a `;` is missing after a token: after the profile of a node that has a
+    /// body, and at the end of aliases and extern declarations
+    fn needs_semicolon_after(&self, token: &SyntaxToken) -> bool {
+        if token.kind() == Token::Semicolon {
+            return false;
+        }
+
+        let has_body = |n: &SyntaxNode| n.children().any(|c| c.kind() == Token::BodyNode);
+        token.parent_ancestors().any(|node| match node.kind() {
+            Token::NodeProfileNode => {
+                let in_definition = node
+                    .parent()
+                    .is_some_and(|p| p.kind() == Token::NodeNode && has_body(&p));
+                let next = next_siblings(node.clone().into()).next();
+                in_definition
+                    && is_last_token(token, &node)
+                    && next.is_some_and(|n| n.kind() != Token::Semicolon)
+            }
+            Token::NodeNode => !has_body(&node) && is_last_token(token, &node),
+            Token::ExternalNodeDeclNode => is_last_token(token, &node),
+            _ => false,
+        })
+    }
+}
+
+/// Computes the width of the names of a `var` section
+fn align_section(widths: &mut HashMap<SyntaxNode, usize>, section: &[SyntaxNode]) {
+    let width_of = |ids: &SyntaxNode| {
+        let tokens = ids
+            .children_with_tokens()
+            .filter_map(|el| el.into_token())
+            .take_while(|t| t.kind() != Token::Colon);
+        tokens
+            .map(|t| match t.kind() {
+                Token::Ident => t.text().len(),
+                Token::Comma => 2,
+                _ => 0,
+            })
+            .sum::<usize>()
+    };
+
+    let width = section.iter().map(width_of).max().unwrap_or_default();
+    for ids in section {
+        widths.insert(ids.clone(), width);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn format(text: &str) -> String {
+        let file = SourceFile::new(PathBuf::from("test.lus"), text.into());
+        format_file(&file).unwrap()
+    }
+
+    #[test]
+    fn node() {
+        let source = "-- Sums its inputs
+node sum_all (a : int) returns (res : int)
+var   x,y:int ; long_name : bool;
+let
+res=0->add(pre res,a);   -- running sum
+    x = - a ;y=a[ 2 ];
+  long_name = x<y;
+tel;
+function add(a, b : int) returns (res : int); let res = a + b; tel
+";
+        let expected = "-- Sums its inputs
+node sum_all(a : int) returns (res : int);
+var
+    x, y      : int;
+    long_name : bool;
+let
+    res = 0 -> add(pre res, a); -- running sum
+    x = -a;
+    y = a[2];
+    long_name = x < y;
+tel
+
+function add(a, b : int) returns (res : int);
+let
+    res = a + b;
+tel
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn declarations() {
+        let source = "type t = enum{A,B};const a:int^2=[1,2];b:bool=true;
+extern function f(x:real)returns(y:real)
+";
+        let expected = "type t = enum { A, B };
+const
+    a : int^2 = [1, 2];
+    b : bool = true;
+extern function f(x : real) returns (y : real);
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn idempotent() {
+        let dirs = ["../tests", "../demo"];
+        let files = dirs
+            .iter()
+            .flat_map(|dir| std::fs::read_dir(dir).unwrap())
+            .map(|entry| entry.unwrap().path());
+
+        for path in files {
+            let text = std::fs::read_to_string(&path).unwrap();
+            let Ok(formatted) = format_file(&SourceFile::new(path.clone(), text)) else {
+                continue;
+            };
+            assert_eq!(format(&formatted), formatted, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let file = SourceFile::new(PathBuf::from("test.lus"), "node n(".into());
+        assert!(format_file(&file).is_err());
+    }
+}
@@ -0,0 +1,726 @@
+//! Generic nodes
+//!
+//! Nodes can take static parameters (`node n<<type t; const size : int>>(...)`), that are given
+//! values when the node is instantiated, either by an alias (`node m = n<<int, 4>>;`) or directly
+//! when it is called (`n<<int, 4>>(x)`).
+//!
+//! The body of a generic node is checked once, with its static types left abstract. The signature
+//! of each instance is then computed by substituting the static arguments to the parameters.
+//!
+//! Models work the same way: their `needs` are static parameters of all their declarations, and
+//! are given values by their instances (`package P = M(t = int; n = 4)`).
+
+use crate::diagnostics::{Diagnostic, Level, Span};
+use crate::eval::{
+    eval_const_node, eval_ident, eval_ident_with_statics, eval_slice_bounds,
+    eval_slice_bounds_with_statics, eval_with_statics,
+};
+use crate::iterators::iterated_node_name;
+use crate::name_resolution::{
+    enclosing_model, instantiated_model, resolve_extern_node, resolve_node, resolve_static_param,
+    resolve_type_decl, NameResolveQuery,
+};
+use crate::types::{type_check_expression, type_of_ast_type, type_of_type_decl, type_with_statics};
+use crate::types::{ConstValue, Type};
+use crate::TypedSignature;
+use rustre_parser::ast::{
+    AstNode, AstToken, CallByPosExpressionNode, EffectiveNodeNode, ExpressionNode,
+    ExternalNodeDeclNode, IdNode, NamedStaticArgNode, NodeNode, PackageAliasNode, SelectNode,
+    StaticArgNode, StaticArgsNode, StaticParamNode, TypeNode, TypedIdsNode,
+};
+use rustre_parser::SyntaxNode;
+use std::collections::HashMap;
+use yeter::Database;
+
+/// Values of the static parameters of a generic node, in one of its instances
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StaticBindings {
+    pub types: HashMap<String, Type>,
+    pub consts: HashMap<String, ConstValue>,
+    pub nodes: HashMap<String, NodeNode>,
+}
+
+impl StaticBindings {
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty() && self.consts.is_empty() && self.nodes.is_empty()
+    }
+
+    /// Replaces the abstract types of a generic node by the types they are bound to
+    pub fn substitute(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Abstract(name) => self.types.get(name).cloned().unwrap_or_else(|| ty.clone()),
+            Type::Array { elem, size } => Type::Array {
+                elem: Box::new(self.substitute(elem)),
+                size: *size,
+            },
+            Type::Tuple(types) => Type::Tuple(types.iter().map(|t| self.substitute(t)).collect()),
+            Type::Function { args, ret } => Type::Function {
+                args: args.iter().map(|t| self.substitute(t)).collect(),
+                ret: ret.iter().map(|t| self.substitute(t)).collect(),
+            },
+            ty => ty.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StaticParamKind {
+    Type,
+    Const,
+    Node,
+}
+
+impl std::fmt::Display for StaticParamKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StaticParamKind::Type => write!(f, "type"),
+            StaticParamKind::Const => write!(f, "constant"),
+            StaticParamKind::Node => write!(f, "node"),
+        }
+    }
+}
+
+pub fn static_param_kind(param: &StaticParamNode) -> StaticParamKind {
+    if param.r#type().is_some() {
+        StaticParamKind::Type
+    } else if param.r#const().is_some() {
+        StaticParamKind::Const
+    } else {
+        StaticParamKind::Node
+    }
+}
+
+/// Returns the generic node instantiated with some static arguments
+pub fn instantiated_node(db: &Database, args: &StaticArgsNode) -> Option<NodeNode> {
+    let parent = args.syntax().parent()?;
+    let name = if let Some(effective) = EffectiveNodeNode::cast(parent.clone()) {
+        effective.id_node()
+    } else {
+        CallByPosExpressionNode::cast(parent)?.node_ref()?.id_node()
+    };
+
+    Option::clone(&resolve_node(db, name?))
+}
+
+/// Returns the node to which an alias (`node m = n<<int, 4>>;`) refers
+pub fn aliased_node(db: &Database, alias: &NodeNode) -> Option<NodeNode> {
+    let name = alias.effective_node_node()?.id_node()?;
+    let node = Option::clone(&resolve_node(db, name))?;
+
+    // A node aliasing itself would be resolved forever
+    (&node != alias).then_some(node)
+}
+
+/// Value given to a static parameter, either by position (`n<<int>>`) or by name (`M(t = int)`)
+struct StaticArg {
+    syntax: SyntaxNode,
+    type_node: Option<TypeNode>,
+    expression: Option<ExpressionNode>,
+    effective_node: Option<EffectiveNodeNode>,
+}
+
+impl From<&StaticArgNode> for StaticArg {
+    fn from(arg: &StaticArgNode) -> Self {
+        StaticArg {
+            syntax: arg.syntax().clone(),
+            type_node: arg.type_node(),
+            expression: arg.expression_node(),
+            effective_node: arg.effective_node_node(),
+        }
+    }
+}
+
+impl From<&NamedStaticArgNode> for StaticArg {
+    fn from(arg: &NamedStaticArgNode) -> Self {
+        StaticArg {
+            syntax: arg.syntax().clone(),
+            type_node: arg.type_node(),
+            expression: arg.expression_node(),
+            effective_node: arg.effective_node_node(),
+        }
+    }
+}
+
+/// Resolves a static argument that is expected to be a type
+fn static_arg_type(db: &Database, arg: &StaticArg, in_node: &Option<NodeNode>) -> Option<Type> {
+    if let Some(type_node) = &arg.type_node {
+        return Some(Type::clone(&type_of_ast_type(
+            db,
+            in_node.clone(),
+            type_node.clone(),
+        )));
+    }
+
+    let Some(ExpressionNode::IdentExpressionNode(ident)) = &arg.expression else {
+        return None;
+    };
+    let id = ident.id_node()?;
+    let name = id.name()?;
+
+    let query = NameResolveQuery {
+        ident: name.clone(),
+        in_node: in_node.clone(),
+    };
+    if let Some(param) = Option::clone(&resolve_static_param(db, query)) {
+        let kind = static_param_kind(&param);
+        return (kind == StaticParamKind::Type).then(|| Type::Abstract(name.text().into()));
+    }
+
+    let decl = Option::clone(&resolve_type_decl(db, id))?;
+    Some(Type::clone(&type_of_type_decl(db, in_node.clone(), decl)))
+}
+
+/// Resolves a static argument that is expected to be a node
+///
+/// Predefined operators (as in `map<<+, 4>>`) are accepted, but not bound to anything.
+fn static_arg_node(db: &Database, arg: &StaticArg) -> Result<Option<NodeNode>, ()> {
+    let name = match (&arg.effective_node, &arg.expression) {
+        (Some(effective), _) => effective.id_node(),
+        (None, Some(ExpressionNode::IdentExpressionNode(ident))) => ident.id_node(),
+        (None, Some(_)) => return Err(()),
+        (None, None) if arg.type_node.is_some() => return Err(()),
+        (None, None) => return Ok(None),
+    };
+
+    let name = name.ok_or(())?;
+    match Option::clone(&resolve_node(db, name)) {
+        Some(node) => Ok(Some(node)),
+        None => Err(()),
+    }
+}
+
+/// Binds a static parameter to its argument, reporting arguments of the wrong kind
+///
+/// `generic` is the node declaring the parameter (`None` for the `needs` of a model), and
+/// `in_node` the node where the argument is given.
+fn bind_static_arg(
+    db: &Database,
+    bindings: &mut StaticBindings,
+    param: &StaticParamNode,
+    arg: &StaticArg,
+    generic: &Option<NodeNode>,
+    in_node: &Option<NodeNode>,
+) {
+    let Some(param_name) = param.id_node().and_then(|i| i.ident()) else {
+        return;
+    };
+    let param_name = param_name.text().to_owned();
+    let kind = static_param_kind(param);
+    let span = Span::of_node(db, &arg.syntax);
+
+    let bound = match kind {
+        StaticParamKind::Type => static_arg_type(db, arg, in_node)
+            .map(|ty| bindings.types.insert(param_name.clone(), ty))
+            .is_some(),
+        StaticParamKind::Const => {
+            let expr = arg
+                .expression
+                .clone()
+                .filter(|_| arg.type_node.is_none() && arg.effective_node.is_none());
+            match expr {
+                Some(expr) => {
+                    let expected = param
+                        .type_node()
+                        .map(|t| type_with_statics(db, generic.clone(), t, bindings))
+                        .unwrap_or_default();
+                    let found = type_check_expression(db, &expr, in_node, Some(expected.clone()));
+                    if !expected.is_unknown() && !found.is_unknown() && expected != found {
+                        Diagnostic::new(Level::Error, "incorrect type")
+                            .with_attachment(
+                                span.clone(),
+                                format!("expected {expected}, found {found}"),
+                            )
+                            .emit(db);
+                    }
+
+                    match Option::clone(&eval_const_node(db, expr, in_node.clone())) {
+                        Some(value) => {
+                            bindings.consts.insert(param_name.clone(), value);
+                        }
+                        None => {
+                            Diagnostic::new(Level::Error, "invalid static argument")
+                                .with_attachment(span.clone(), "this is not a constant")
+                                .emit(db);
+                        }
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+        StaticParamKind::Node => match static_arg_node(db, arg) {
+            Ok(node) => {
+                bindings.nodes.extend(node.map(|n| (param_name.clone(), n)));
+                true
+            }
+            Err(()) => false,
+        },
+    };
+
+    if !bound {
+        Diagnostic::new(Level::Error, "invalid static argument")
+            .with_attachment(
+                span,
+                format!("expected a {kind} for the static parameter {param_name:?}"),
+            )
+            .emit(db);
+    }
+}
+
+/// **Query:** Binds the static parameters of a generic node to the arguments of one of its
+/// instances
+///
+/// Static arguments of the wrong kind, or in the wrong number, are reported.
+#[yeter::query]
+pub fn static_bindings(
+    db: &Database,
+    args: StaticArgsNode,
+    in_node: Option<NodeNode>,
+) -> Option<StaticBindings> {
+    let generic = instantiated_node(db, &args)?;
+    let name = generic.id_node()?.ident()?;
+
+    let params = generic
+        .static_params_node()
+        .map(|p| p.all_static_param_node().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let arg_nodes = args.all_static_arg_node().collect::<Vec<_>>();
+    if params.len() != arg_nodes.len() {
+        Diagnostic::new(Level::Error, "wrong number of static arguments")
+            .with_attachment(
+                Span::of_node(db, args.syntax()),
+                format!(
+                    "{:?} expects {} static arguments but {} were supplied",
+                    name.text(),
+                    params.len(),
+                    arg_nodes.len()
+                ),
+            )
+            .emit(db);
+        return None;
+    }
+
+    let mut bindings = StaticBindings::default();
+    let generic = Some(generic);
+    for (param, arg) in params.iter().zip(&arg_nodes) {
+        bind_static_arg(db, &mut bindings, param, &arg.into(), &generic, &in_node);
+    }
+
+    Some(bindings)
+}
+
+/// **Query:** Binds the `needs` of a model to the arguments of one of its instances
+/// (`package P = M(t = int; n = 4)`)
+///
+/// Missing arguments, and arguments that the model doesn't need, are reported.
+#[yeter::query]
+pub fn package_instance_bindings(
+    db: &Database,
+    instance: PackageAliasNode,
+) -> Option<StaticBindings> {
+    let model = instantiated_model(db, &instance)?;
+    let model_name = model.id_node()?.name()?;
+    let args = instance
+        .named_static_args_node()
+        .map(|a| a.all_named_static_arg_node().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let arg_name = |arg: &NamedStaticArgNode| arg.id_node().and_then(|id| id.name());
+
+    let mut bindings = StaticBindings::default();
+    let params = model.all_static_param_node().collect::<Vec<_>>();
+    for param in &params {
+        let Some(param_name) = param.id_node().and_then(|i| i.ident()) else {
+            continue;
+        };
+
+        match args
+            .iter()
+            .find(|arg| arg_name(arg).is_some_and(|n| n.text() == param_name.text()))
+        {
+            Some(arg) => bind_static_arg(db, &mut bindings, param, &arg.into(), &None, &None),
+            None => {
+                Diagnostic::new(Level::Error, "missing static argument")
+                    .with_attachment(
+                        Span::of_node(db, instance.syntax()),
+                        format!(
+                            "{:?} needs a {} {:?}",
+                            model_name.text(),
+                            static_param_kind(param),
+                            param_name.text()
+                        ),
+                    )
+                    .emit(db);
+            }
+        }
+    }
+
+    for arg in &args {
+        let Some(name) = arg_name(arg) else {
+            continue;
+        };
+        let is_needed = params.iter().any(|param| {
+            param
+                .id_node()
+                .and_then(|i| i.ident())
+                .is_some_and(|n| n.text() == name.text())
+        });
+        if !is_needed {
+            Diagnostic::new(Level::Error, "unknown static parameter")
+                .with_attachment(
+                    Span::of_node(db, arg.syntax()),
+                    format!(
+                        "{:?} has no static parameter {:?}",
+                        model_name.text(),
+                        name.text()
+                    ),
+                )
+                .emit(db);
+        }
+    }
+
+    Some(bindings)
+}
+
+/// Computes the signature of a node, with static arguments substituted to its static parameters
+fn substituted_signature(
+    db: &Database,
+    node: &NodeNode,
+    bindings: &StaticBindings,
+) -> TypedSignature {
+    let sig = crate::get_signature(db, node.clone());
+
+    let get_params = |params: &[TypedIdsNode]| {
+        params
+            .iter()
+            .flat_map(|group| {
+                let ty = group
+                    .type_node()
+                    .map(|t| type_with_statics(db, Some(node.clone()), t, bindings))
+                    .unwrap_or_default();
+                group.all_ident().zip(std::iter::repeat(ty))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    TypedSignature {
+        name: sig.name.clone(),
+        params: get_params(&sig.params),
+        return_params: get_params(&sig.return_params),
+    }
+}
+
+/// **Query:** Computes the signature of an instance of a generic node, by substituting its static
+/// arguments to its static parameters
+#[yeter::query]
+pub fn instance_signature(
+    db: &Database,
+    args: StaticArgsNode,
+    in_node: Option<NodeNode>,
+) -> Option<TypedSignature> {
+    let bindings = Option::clone(&static_bindings(db, args.clone(), in_node))?;
+    let generic = instantiated_node(db, &args)?;
+    Some(substituted_signature(db, &generic, &bindings))
+}
+
+/// Node with a body that runs when a node is called, with the values of its static parameters
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+    pub node: NodeNode,
+    pub statics: StaticBindings,
+    /// Name of the node, of the alias instantiating it (`node m = n<<int, 4>>;`), or a name made of
+    /// the static arguments for the instances made at call sites (`n<<int, 4>>(x)`)
+    pub name: String,
+}
+
+impl Instance {
+    /// Instance of a node without static parameters
+    pub fn of_node(node: NodeNode) -> Instance {
+        let name = node.id_node().and_then(|i| i.ident());
+        Instance {
+            name: name.map(|n| n.text().to_owned()).unwrap_or_default(),
+            node,
+            statics: StaticBindings::default(),
+        }
+    }
+
+    /// Evaluates a constant expression of the body of the node
+    pub fn eval(&self, db: &Database, expr: ExpressionNode) -> Option<ConstValue> {
+        let node = Some(self.node.clone());
+        match self.statics.is_empty() {
+            true => Option::clone(&eval_const_node(db, expr, node)),
+            false => eval_with_statics(db, expr, node, &self.statics),
+        }
+    }
+
+    /// Evaluates a constant, an enum constructor or a static constant from its name
+    pub fn eval_ident(&self, db: &Database, ident: &IdNode) -> Option<ConstValue> {
+        let node = Some(self.node.clone());
+        match self.statics.is_empty() {
+            true => eval_ident(db, ident, node),
+            false => eval_ident_with_statics(db, ident, node, &self.statics),
+        }
+    }
+
+    /// Evaluates the first index, the last index and the step of a slice of the node
+    pub fn slice_bounds(
+        &self,
+        db: &Database,
+        select: &SelectNode,
+    ) -> Option<(i32, i32, Option<i32>)> {
+        let node = Some(self.node.clone());
+        match self.statics.is_empty() {
+            true => eval_slice_bounds(db, select, node),
+            false => eval_slice_bounds_with_statics(db, select, node, &self.statics),
+        }
+    }
+
+    /// Resolves a type written in the node
+    pub fn type_of(&self, db: &Database, type_node: TypeNode) -> Type {
+        let node = Some(self.node.clone());
+        match self.statics.is_empty() {
+            true => Type::clone(&type_of_ast_type(db, node, type_node)),
+            false => type_with_statics(db, node, type_node, &self.statics),
+        }
+    }
+
+    /// Computes the signature of the node, with its static arguments substituted
+    pub fn signature(&self, db: &Database) -> TypedSignature {
+        match self.statics.is_empty() {
+            true => TypedSignature::clone(&crate::get_typed_signature(db, self.node.clone())),
+            false => substituted_signature(db, &self.node, &self.statics),
+        }
+    }
+}
+
+/// Returns the instance that runs when a node is called, with some static arguments
+///
+/// Aliases are replaced by the node they refer to. The static arguments may refer to the static
+/// parameters of `caller`, the instance in which they are given. `None` is returned for nodes
+/// without a body, and for generic nodes that are not given static arguments.
+pub fn instance_of(
+    db: &Database,
+    node: &NodeNode,
+    args: Option<StaticArgsNode>,
+    caller: Option<&Instance>,
+) -> Option<Instance> {
+    if let Some(target) = aliased_node(db, node) {
+        let args = node.effective_node_node()?.static_args_node();
+        let is_generic = args.is_some();
+        let instance = instance_of(db, &target, args, None)?;
+        return Some(match is_generic {
+            true => Instance {
+                name: Instance::of_node(node.clone()).name,
+                ..instance
+            },
+            false => instance,
+        });
+    }
+
+    node.body_node()?;
+    let Some(args) = args else {
+        return match node.static_params_node() {
+            Some(_) => None,
+            None => Some(Instance::of_node(node.clone())),
+        };
+    };
+
+    let statics = instance_bindings(db, &args, caller)?;
+    Some(Instance {
+        name: instance_name(node, &statics),
+        node: node.clone(),
+        statics,
+    })
+}
+
+/// Binds the static parameters of a generic node to arguments that may refer to the static
+/// parameters of the caller
+fn instance_bindings(
+    db: &Database,
+    args: &StaticArgsNode,
+    caller: Option<&Instance>,
+) -> Option<StaticBindings> {
+    let in_node = caller.map(|c| c.node.clone());
+    let mut bindings = Option::clone(&static_bindings(db, args.clone(), in_node))?;
+    let Some(caller) = caller.filter(|c| !c.statics.is_empty()) else {
+        return Some(bindings);
+    };
+
+    let generic = instantiated_node(db, args)?;
+    let params = generic
+        .static_params_node()
+        .into_iter()
+        .flat_map(|p| p.all_static_param_node());
+    for (param, arg) in params.zip(args.all_static_arg_node()) {
+        let Some(name) = param.id_node().and_then(|i| i.ident()) else {
+            continue;
+        };
+        let name = name.text().to_owned();
+        match static_param_kind(&param) {
+            StaticParamKind::Type => {
+                let ty = match arg.type_node() {
+                    Some(type_node) => Some(caller.type_of(db, type_node)),
+                    None => bindings
+                        .types
+                        .get(&name)
+                        .map(|t| caller.statics.substitute(t)),
+                };
+                bindings.types.extend(ty.map(|ty| (name, ty)));
+            }
+            StaticParamKind::Const => {
+                let value = arg.expression_node().and_then(|e| caller.eval(db, e));
+                bindings.consts.extend(value.map(|value| (name, value)));
+            }
+            StaticParamKind::Node => {
+                let bound = iterated_node_name(&arg)
+                    .and_then(|n| n.name())
+                    .and_then(|n| caller.statics.nodes.get(n.text()).cloned());
+                bindings.nodes.extend(bound.map(|node| (name, node)));
+            }
+        }
+    }
+
+    Some(bindings)
+}
+
+/// Name of an instance made at a call site, made of the name of the node and of its static
+/// arguments (`n_int_4` for `n<<int, 4>>`)
+fn instance_name(node: &NodeNode, statics: &StaticBindings) -> String {
+    fn constant(value: &ConstValue) -> String {
+        match value {
+            ConstValue::Boolean(b) => b.to_string(),
+            ConstValue::Integer(i) => i.to_string(),
+            ConstValue::Real(r) => format!("{r:?}"),
+            ConstValue::Enum(ctor) => ctor.clone(),
+            ConstValue::Array(values) => values.iter().map(constant).collect::<Vec<_>>().join("_"),
+            ConstValue::Struct(fields) => fields
+                .iter()
+                .map(|(_, value)| constant(value))
+                .collect::<Vec<_>>()
+                .join("_"),
+        }
+    }
+
+    let mut name = Instance::of_node(node.clone()).name;
+    let params = node
+        .static_params_node()
+        .into_iter()
+        .flat_map(|p| p.all_static_param_node());
+    for param in params {
+        let param_name = param.id_node().and_then(|i| i.ident());
+        let param_name = param_name.as_ref().map(|n| n.text()).unwrap_or_default();
+        let arg = match static_param_kind(&param) {
+            StaticParamKind::Type => statics.types.get(param_name).map(Type::to_string),
+            StaticParamKind::Const => statics.consts.get(param_name).map(constant),
+            StaticParamKind::Node => statics
+                .nodes
+                .get(param_name)
+                .map(|n| Instance::of_node(n.clone()).name),
+        };
+
+        // Names can only be made of letters, digits and underscores
+        name.push('_');
+        name.extend(arg.unwrap_or_default().chars().map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c,
+            '-' => 'm',
+            _ => '_',
+        }));
+    }
+    name
+}
+
+/// Node run by a call
+#[derive(Clone, Debug)]
+pub enum Callee {
+    Node(Instance),
+    Extern(ExternalNodeDeclNode),
+}
+
+impl Callee {
+    pub fn name(&self) -> String {
+        match self {
+            Callee::Node(instance) => instance.name.clone(),
+            Callee::Extern(ext) => ext
+                .id_node()
+                .and_then(|i| i.ident())
+                .map(|i| i.text().to_owned())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn signature(&self, db: &Database) -> TypedSignature {
+        match self {
+            Callee::Node(instance) => instance.signature(db),
+            Callee::Extern(ext) => {
+                TypedSignature::clone(&crate::get_extern_signature(db, ext.clone()))
+            }
+        }
+    }
+}
+
+/// Resolves the node that a name refers to in an instance, with its static arguments if it is
+/// given some (`n<<int, 4>>`)
+///
+/// Static node parameters of the instance are replaced by the node they are bound to.
+pub fn resolve_callee(
+    db: &Database,
+    name: &IdNode,
+    args: Option<StaticArgsNode>,
+    caller: &Instance,
+) -> Option<Callee> {
+    let param = name.name().filter(|_| name.package().is_none());
+    if let Some(node) = param.and_then(|p| caller.statics.nodes.get(p.text()).cloned()) {
+        return instance_of(db, &node, None, None).map(Callee::Node);
+    }
+
+    if let Some(node) = Option::clone(&resolve_node(db, name.clone())) {
+        return instance_of(db, &node, args, Some(caller)).map(Callee::Node);
+    }
+
+    let ext = Option::clone(&resolve_extern_node(db, name.clone()))?;
+    Some(Callee::Extern(ext))
+}
+
+/// **Query:** Computes the signature of a node of a model, as seen through one of its instances
+#[yeter::query]
+pub fn package_instance_signature(
+    db: &Database,
+    instance: PackageAliasNode,
+    node: NodeNode,
+) -> TypedSignature {
+    let bindings = Option::clone(&package_instance_bindings(db, instance)).unwrap_or_default();
+    substituted_signature(db, &node, &bindings)
+}
+
+/// **Query:** Checks an instance of a model
+///
+/// Its static arguments are bound, and the signatures of its nodes computed, so that invalid
+/// arguments are reported even if the instance is never used.
+#[yeter::query]
+pub fn check_package_instance(db: &Database, instance: PackageAliasNode) {
+    let Some(model) = instantiated_model(db, &instance) else {
+        if let Some(model) = instance.model() {
+            let name = model.syntax().text().to_string();
+            Diagnostic::new(Level::Error, format!("unknown model {:?}", name.trim()))
+                .with_attachment(
+                    Span::of_node(db, model.syntax()),
+                    "not found in this program",
+                )
+                .emit(db);
+        }
+        return;
+    };
+
+    package_instance_bindings(db, instance.clone());
+    for node in model
+        .package_decl_body()
+        .iter()
+        .flat_map(|b| b.all_node_node())
+    {
+        package_instance_signature(db, instance.clone(), node);
+    }
+}
+
+/// Returns `true` if a part of the program is declared in a model, where the `needs` are unknown
+pub fn in_model(syntax: &SyntaxNode) -> bool {
+    enclosing_model(syntax).is_some()
+}
@@ -0,0 +1,215 @@
+//! Initialization analysis
+//!
+//! On the first cycle, `pre x` evaluates to nil, as `x` has no previous value yet. It should thus
+//! always be guarded by an initializer: either by being the second operand of `->`, as in
+//! `0 -> pre x`, or by using `fby` instead.
+//!
+//! This analysis looks for the [stateful expressions][stateful_expr_of_node] that are `pre`
+//! operators, and finds out whether their values can be observed on the first cycle. Variables
+//! defined by such expressions may be nil on the first cycle too, and so may be the ones reading
+//! them (without a delay). Outputs that may be nil are reported.
+
+use crate::dataflow::left_item_name;
+use crate::diagnostics::{Diagnostic, Level, Span};
+use crate::node_state::stateful_expr_of_node;
+use rustre_parser::ast::expr_visitor::ExpressionWalker;
+use rustre_parser::ast::{
+    ArrowExpressionNode, AstNode, AstToken, EqualsEquationNode, ExpressionNode, FbyExpressionNode,
+    IdentExpressionNode, NodeNode, PreExpressionNode,
+};
+use rustre_parser::SyntaxNode;
+use std::collections::{HashMap, HashSet};
+use yeter::Database;
+
+/// Returns the equation in which an expression is, if its value on the first cycle may be observed
+/// through the value of this equation
+///
+/// The second operands of `->` and `fby`, and the operands of `pre` aren't observed on the first
+/// cycle.
+fn first_cycle_equation(expr: &SyntaxNode) -> Option<EqualsEquationNode> {
+    let mut child = expr.clone();
+    while let Some(parent) = child.parent() {
+        if let Some(equation) = EqualsEquationNode::cast(parent.clone()) {
+            return Some(equation);
+        }
+
+        let delayed = if let Some(arrow) = ArrowExpressionNode::cast(parent.clone()) {
+            arrow.right().is_some_and(|r| r.syntax() == &child)
+        } else if let Some(fby) = FbyExpressionNode::cast(parent.clone()) {
+            fby.right().is_some_and(|r| r.syntax() == &child)
+        } else {
+            PreExpressionNode::can_cast(parent.kind())
+        };
+
+        if delayed {
+            return None;
+        }
+        child = parent;
+    }
+
+    None
+}
+
+#[derive(Default)]
+struct IdentCollector {
+    idents: Vec<IdentExpressionNode>,
+}
+
+impl ExpressionWalker for IdentCollector {
+    fn walk_ident(&mut self, e: IdentExpressionNode) {
+        self.idents.push(e);
+    }
+}
+
+/// Reason why a variable may be nil on the first cycle
+enum Cause {
+    /// Unguarded `pre`
+    Pre(ExpressionNode),
+    /// Read of a variable that may be nil
+    Variable(IdentExpressionNode),
+}
+
+fn defined_names(equation: &EqualsEquationNode) -> Vec<String> {
+    equation
+        .left_node()
+        .into_iter()
+        .flat_map(|l| l.all_left_item_node())
+        .filter_map(|item| left_item_name(&item))
+        .collect()
+}
+
+/// **Query:** Reports outputs that may be nil on the first cycle
+#[yeter::query]
+pub fn check_initialization(db: &Database, node: NodeNode) {
+    let Some(body) = node.body_node() else {
+        return;
+    };
+
+    // Variables that may be nil, and the expressions that make them so
+    let mut causes = HashMap::<String, Vec<Cause>>::new();
+
+    for expr in stateful_expr_of_node(db, node.clone()).iter() {
+        if !matches!(expr, ExpressionNode::PreExpressionNode(_)) {
+            continue;
+        }
+
+        if let Some(equation) = first_cycle_equation(expr.syntax()) {
+            for name in defined_names(&equation) {
+                let cause = Cause::Pre(expr.clone());
+                causes.entry(name).or_default().push(cause);
+            }
+        }
+    }
+
+    // Variables read by each equation during the first cycle
+    let reads = body
+        .all_equals_equation_node()
+        .map(|equation| {
+            let mut collector = IdentCollector::default();
+            if let Some(expr) = equation.expression_node() {
+                collector.walk_expr(expr);
+            }
+
+            let idents = collector
+                .idents
+                .into_iter()
+                .filter(|i| first_cycle_equation(i.syntax()).as_ref() == Some(&equation))
+                .collect::<Vec<_>>();
+            (defined_names(&equation), idents)
+        })
+        .collect::<Vec<_>>();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (names, idents) in &reads {
+            for ident in idents {
+                let Some(name) = ident.id_node().and_then(|i| i.ident()) else {
+                    continue;
+                };
+                if !causes.contains_key(name.text()) {
+                    continue;
+                }
+
+                for defined in names {
+                    let defined_causes = causes.entry(defined.clone()).or_default();
+                    let new = defined_causes.iter().all(|c| match c {
+                        Cause::Variable(read) => read != ident,
+                        Cause::Pre(_) => true,
+                    });
+                    if new {
+                        defined_causes.push(Cause::Variable(ident.clone()));
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    let sig = crate::get_signature(db, node.clone());
+    let mut reported = HashSet::new();
+    for output in sig.return_params.iter().flat_map(|p| p.all_ident()) {
+        let Some(output_causes) = causes.get(output.text()) else {
+            continue;
+        };
+        if !reported.insert(output.text().to_owned()) {
+            continue;
+        }
+
+        let mut diagnostic = Diagnostic::new(
+            Level::Warning,
+            format!("output {:?} may be nil on the first cycle", output.text()),
+        )
+        .with_attachment(Span::of_token(db, output.syntax()), "declared here");
+
+        for cause in output_causes {
+            diagnostic = match cause {
+                Cause::Pre(expr) => diagnostic.with_attachment(
+                    Span::of_node(db, expr.syntax()),
+                    "this has no value on the first cycle (hint: add an initial value with `->`)",
+                ),
+                Cause::Variable(ident) => diagnostic.with_attachment(
+                    Span::of_node(db, ident.syntax()),
+                    "this may be nil on the first cycle",
+                ),
+            };
+        }
+
+        diagnostic.emit(db);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostics::Diagnostic;
+
+    fn warnings(source: &str) -> Vec<String> {
+        let mut driver = crate::driver();
+        crate::add_source_contents(&mut driver, source.into());
+        crate::check(&driver);
+
+        driver
+            .effect::<Diagnostic>()
+            .into_iter()
+            .map(|d| d.message)
+            .filter(|m| m.contains("nil"))
+            .collect()
+    }
+
+    #[test]
+    fn unguarded_pre() {
+        let warnings = warnings(
+            "node n(x : int) returns (a, b, c, d : int);
+             var l : int;
+             let
+                 a = 0 -> pre x;
+                 b = 0 fby x;
+                 l = pre x + 1;
+                 c = if x > 0 then l else 0;
+                 d = 0 -> l;
+             tel",
+        );
+
+        assert_eq!(warnings, ["output \"c\" may be nil on the first cycle"]);
+    }
+}
@@ -0,0 +1,1980 @@
+//! Reference interpreter
+//!
+//! This module runs Lustre nodes directly from their syntax tree, one cycle at a time. It is slow,
+//! but it follows the semantics of the language as closely as possible, which makes it useful to
+//! try programs out and to validate the output of the code generators.
+//!
+//! # Instances
+//!
+//! A [NodeInstance] holds the memory of one instance of a node: one slot for each of the stateful
+//! expressions returned by [stateful_expr_of_node][crate::node_state::stateful_expr_of_node()],
+//! and nested instances for each call site, created the first time it runs. Calls to generic
+//! nodes and aliases run an [Instance][crate::generics::Instance] of the node with the values of
+//! its static parameters, and array iterators have one nested instance per element.
+//!
+//! # Evaluation of a cycle
+//!
+//! Equations are evaluated on demand, in the order in which their variables are needed, which
+//! allows detecting causality loops when an equation ends up depending on itself. Variables defined
+//! part by part (`p.x = ...; t[i] = ...;`) start each cycle from a value made of zeros, as in the
+//! generated code, and each of their equations replaces a part of it. The operands of
+//! `pre` and the second operands of `fby` are only evaluated at the end of the cycle, once all
+//! the variables are known, and are then persisted for the next cycle.
+//!
+//! Values of the stream at a given cycle are represented as [Value]s, where `None` is Lustre's
+//! `nil` (e.g. the value of a `pre` during the first cycle) or the absence of value for
+//! expressions on a slower clock.
+//!
+//! # Clocks
+//!
+//! Stateful expressions only advance on the cycles of their [clock][crate::clocks]: a `pre`,
+//! `fby` or `->` on a sub-clock is absent on the other cycles and keeps its memory, and node
+//! instances are only run when their arguments are present. Only the selected branch of a `merge`
+//! is evaluated.
+
+use crate::clocks::{clock_condition, merge_case, Clock, ClockCase, ClockChecker};
+use crate::dataflow::left_item_name;
+use crate::diagnostics::{Diagnostic, Level, Span};
+use crate::eval::slice_indices;
+use crate::generics::{instance_of, resolve_callee, Callee, Instance};
+use crate::iterators::{iterated_node_name, resolve_iterator, ArrayIterator};
+use crate::name_resolution::resolve_type_decl;
+use crate::node_state::stateful_expr_of_node;
+use crate::types::{type_of_type_decl, ConstValue, Type};
+use rustre_parser::ast::{
+    AstNode, AstToken, BinaryExpression, CallByNameExpressionNode, CallByPosExpressionNode,
+    EqualsEquationNode, ExpressionNode, LeftItemNode, NodeNode, PredefOp, StaticArgNode,
+    UnaryExpression,
+};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use yeter::Database;
+
+/// Value of a stream at a given cycle, `None` standing for `nil`
+pub type Value = Option<ConstValue>;
+
+enum Memory<'db> {
+    /// Last value of the operand of a `pre` (empty during the first cycle)
+    Pre(Vec<Value>),
+    /// Last value of the second operand of a `fby` (`None` during the first cycle)
+    Fby(Option<Vec<Value>>),
+    /// `true` during the first cycle of a `->`
+    Arrow(bool),
+    /// Last present value of the operand of a `current`
+    Current(Vec<Value>),
+    /// Instances of the node run by a call, one for each application of an iterator
+    Instances(Vec<NodeInstance<'db>>),
+}
+
+/// State of an instance of a node
+pub struct NodeInstance<'db> {
+    db: &'db Database,
+    instance: Instance,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    locals: Vec<String>,
+    types: HashMap<String, Type>,
+    /// Equations defining each variable, that may define parts of it (`p.x = ...`, `t[i] = ...`)
+    definitions: HashMap<String, Vec<EqualsEquationNode>>,
+    memory: HashMap<ExpressionNode, Memory<'db>>,
+    clocks: ClockChecker<'db>,
+    /// Clock of the expression being evaluated, for the values that can be on any clock (such as
+    /// constants)
+    context: Clock,
+
+    /// Values of the variables during the current (or last) cycle
+    values: HashMap<String, Value>,
+    /// Variables with a part defined by `nil` during the current cycle, that are `nil` as a whole
+    nil_parts: HashSet<String>,
+    /// Equations that are already evaluated during the current cycle
+    evaluated: HashSet<EqualsEquationNode>,
+    /// Equations that are being evaluated, to detect causality loops
+    pending: HashSet<EqualsEquationNode>,
+    /// `pre`, `fby` and `->` expressions whose clock is active during the current cycle, with this
+    /// clock
+    ticked: HashMap<ExpressionNode, Clock>,
+}
+
+fn error(db: &Database, node: &impl AstNode, message: &str, label: &str) -> Diagnostic {
+    Diagnostic::new(Level::Error, message).with_attachment(Span::of_node(db, node.syntax()), label)
+}
+
+/// Allocates the memory of `pre`, `fby` and `->`, the instances of called nodes being created
+/// when they first run
+fn allocate_memory<'db>(
+    db: &'db Database,
+    node: &NodeNode,
+) -> HashMap<ExpressionNode, Memory<'db>> {
+    let mut memory = HashMap::new();
+
+    for expr in stateful_expr_of_node(db, node.clone()).iter() {
+        let slot = match expr {
+            ExpressionNode::PreExpressionNode(_) => Memory::Pre(vec![]),
+            ExpressionNode::FbyExpressionNode(_) => Memory::Fby(None),
+            ExpressionNode::ArrowExpressionNode(_) => Memory::Arrow(true),
+            _ => continue,
+        };
+
+        memory.insert(expr.clone(), slot);
+    }
+
+    memory
+}
+
+/// Applies an arithmetic operator, converting integers to reals if the operands are mixed
+fn numeric(
+    left: ConstValue,
+    right: ConstValue,
+    int: fn(i32, i32) -> i32,
+    real: fn(f32, f32) -> f32,
+) -> Option<ConstValue> {
+    match (left, right) {
+        (ConstValue::Integer(l), ConstValue::Integer(r)) => Some(ConstValue::Integer(int(l, r))),
+        (ConstValue::Real(l), ConstValue::Real(r)) => Some(ConstValue::Real(real(l, r))),
+        (ConstValue::Integer(l), ConstValue::Real(r)) => Some(ConstValue::Real(real(l as f32, r))),
+        (ConstValue::Real(l), ConstValue::Integer(r)) => Some(ConstValue::Real(real(l, r as f32))),
+        _ => None,
+    }
+}
+
+fn compare(left: ConstValue, right: ConstValue, f: fn(Ordering) -> bool) -> Option<ConstValue> {
+    let ordering = match (left, right) {
+        (ConstValue::Integer(l), ConstValue::Integer(r)) => l.cmp(&r),
+        (ConstValue::Real(l), ConstValue::Real(r)) => l.partial_cmp(&r)?,
+        (ConstValue::Integer(l), ConstValue::Real(r)) => (l as f32).partial_cmp(&r)?,
+        (ConstValue::Real(l), ConstValue::Integer(r)) => l.partial_cmp(&(r as f32))?,
+        _ => return None,
+    };
+
+    Some(ConstValue::Boolean(f(ordering)))
+}
+
+fn logic(left: ConstValue, right: ConstValue, f: fn(bool, bool) -> bool) -> Option<ConstValue> {
+    match (left, right) {
+        (ConstValue::Boolean(l), ConstValue::Boolean(r)) => Some(ConstValue::Boolean(f(l, r))),
+        _ => None,
+    }
+}
+
+fn equal(left: ConstValue, right: ConstValue) -> Option<bool> {
+    match (&left, &right) {
+        (ConstValue::Integer(_), ConstValue::Real(_))
+        | (ConstValue::Real(_), ConstValue::Integer(_)) => {
+            Some(compare(left, right, Ordering::is_eq)? == ConstValue::Boolean(true))
+        }
+        _ if std::mem::discriminant(&left) == std::mem::discriminant(&right) => Some(left == right),
+        _ => None,
+    }
+}
+
+fn is_zero(value: &Value) -> bool {
+    matches!(value, Some(ConstValue::Integer(0)))
+}
+
+/// Value given to variables defined part by part before their first part is defined
+fn zero(ty: &Type) -> Option<ConstValue> {
+    match ty {
+        Type::Boolean => Some(ConstValue::Boolean(false)),
+        Type::Integer => Some(ConstValue::Integer(0)),
+        Type::Real => Some(ConstValue::Real(0.0)),
+        Type::Enum { constructors, .. } => constructors.first().cloned().map(ConstValue::Enum),
+        Type::Array { elem, size } => Some(ConstValue::Array(vec![zero(elem)?; *size])),
+        Type::Struct { fields, .. } => fields
+            .iter()
+            .map(|(name, ty)| Some((name.clone(), zero(ty)?)))
+            .collect::<Option<_>>()
+            .map(ConstValue::Struct),
+        _ => None,
+    }
+}
+
+/// Part of a variable defined by an equation
+enum Part {
+    Field(String),
+    Index(i32),
+    Slice(Vec<i32>),
+}
+
+/// Parts to go through to reach a part of a variable, from the variable itself
+type Path = Vec<Part>;
+
+/// Replaces a part of a value, or returns the message and label of an error
+fn set_part(
+    value: &mut ConstValue,
+    path: &[Part],
+    part: ConstValue,
+) -> Result<(), (&'static str, String)> {
+    fn element(
+        elements: &mut [ConstValue],
+        i: i32,
+    ) -> Result<&mut ConstValue, (&'static str, String)> {
+        let len = elements.len();
+        match usize::try_from(i).ok().and_then(|i| elements.get_mut(i)) {
+            Some(element) => Ok(element),
+            None => Err((
+                "index out of bounds",
+                format!("the length is {len} but the index is {i}"),
+            )),
+        }
+    }
+
+    let Some((first, rest)) = path.split_first() else {
+        *value = part;
+        return Ok(());
+    };
+
+    let type_error = |label: &str| ("type error", label.to_owned());
+    match (first, value) {
+        (Part::Field(name), ConstValue::Struct(fields)) => {
+            let field = fields.iter_mut().find(|(f, _)| f == name);
+            let Some((_, field)) = field else {
+                return Err(type_error("unknown field"));
+            };
+            set_part(field, rest, part)
+        }
+        (Part::Index(i), ConstValue::Array(elements)) => {
+            set_part(element(elements, *i)?, rest, part)
+        }
+        (Part::Slice(indices), ConstValue::Array(elements)) => {
+            let ConstValue::Array(parts) = part else {
+                return Err(type_error("expected an array"));
+            };
+            if parts.len() != indices.len() {
+                return Err(type_error("the slice and the array have different sizes"));
+            }
+
+            for (i, part) in indices.iter().zip(parts) {
+                set_part(element(elements, *i)?, rest, part)?;
+            }
+            Ok(())
+        }
+        _ => Err(type_error("this part doesn't exist in the variable")),
+    }
+}
+
+/// Tells if a clock variable has a given value, `nil` never matching any, or returns `None` if the
+/// value can't be the one of a clock
+fn case_holds(value: &Value, case: &ClockCase) -> Option<bool> {
+    match (value, case) {
+        (Some(ConstValue::Boolean(b)), ClockCase::Bool(expected)) => Some(b == expected),
+        (Some(ConstValue::Enum(c)), ClockCase::Constructor(expected)) => Some(c == expected),
+        (None, _) => Some(false),
+        _ => None,
+    }
+}
+
+impl<'db> NodeInstance<'db> {
+    /// Creates a new instance of a node, in its initial state
+    ///
+    /// Aliases run the node they refer to.
+    pub fn new(db: &'db Database, node: NodeNode) -> Self {
+        let instance = instance_of(db, &node, None, None);
+        Self::of_instance(db, instance.unwrap_or_else(|| Instance::of_node(node)))
+    }
+
+    /// Creates a new instance of a node with the values of its static parameters
+    pub fn of_instance(db: &'db Database, instance: Instance) -> Self {
+        let node = instance.node.clone();
+        let sig = instance.signature(db);
+        let names = |params: &[(rustre_parser::ast::Ident, Type)]| {
+            params
+                .iter()
+                .map(|(i, _)| i.text().to_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let mut types = sig
+            .params
+            .iter()
+            .chain(&sig.return_params)
+            .map(|(i, ty)| (i.text().to_owned(), ty.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut locals = vec![];
+        for group in node
+            .all_var_decl_node()
+            .flat_map(|v| v.all_typed_ids_node())
+        {
+            let ty = group
+                .type_node()
+                .map(|t| instance.type_of(db, t))
+                .unwrap_or_default();
+            for ident in group.all_ident() {
+                locals.push(ident.text().to_owned());
+                types.insert(ident.text().to_owned(), ty.clone());
+            }
+        }
+
+        let mut definitions = HashMap::<_, Vec<_>>::new();
+        let equations = node
+            .body_node()
+            .into_iter()
+            .flat_map(|b| b.all_equals_equation_node());
+        for eq in equations {
+            let lefts = eq
+                .left_node()
+                .into_iter()
+                .flat_map(|l| l.all_left_item_node());
+            for name in lefts.filter_map(|item| left_item_name(&item)) {
+                definitions.entry(name).or_default().push(eq.clone());
+            }
+        }
+
+        NodeInstance {
+            db,
+            inputs: names(&sig.params),
+            outputs: names(&sig.return_params),
+            locals,
+            types,
+            definitions,
+            memory: allocate_memory(db, &node),
+            clocks: ClockChecker::inference(db, &node),
+            context: Clock::Base,
+            instance,
+            values: Default::default(),
+            nil_parts: Default::default(),
+            evaluated: Default::default(),
+            pending: Default::default(),
+            ticked: Default::default(),
+        }
+    }
+
+    pub fn node(&self) -> &NodeNode {
+        &self.instance.node
+    }
+
+    /// Returns the local variables of the node, with their values during the last cycle
+    pub fn locals(&self) -> impl Iterator<Item = (&str, Value)> + '_ {
+        self.locals
+            .iter()
+            .map(|name| (name.as_str(), self.values.get(name).cloned().flatten()))
+    }
+
+    /// Puts the instance (and its sub-instances) back in its initial state
+    pub fn reset(&mut self) {
+        self.memory = allocate_memory(self.db, &self.instance.node);
+        self.values.clear();
+        self.ticked.clear();
+    }
+
+    /// Runs one cycle of the node, and returns the values of its outputs
+    pub fn step(&mut self, inputs: Vec<Value>) -> Result<Vec<Value>, Diagnostic> {
+        if inputs.len() != self.inputs.len() {
+            let message = format!(
+                "expected {} input values, got {}",
+                self.inputs.len(),
+                inputs.len()
+            );
+            return Err(match self.instance.node.id_node() {
+                Some(id) => error(self.db, &id, &message, "while running this node"),
+                None => Diagnostic::new(Level::Error, message),
+            });
+        }
+
+        self.values = self.inputs.iter().cloned().zip(inputs).collect();
+        self.nil_parts.clear();
+        self.evaluated.clear();
+        self.pending.clear();
+        self.ticked.clear();
+        self.context = Clock::Base;
+
+        let body = self.instance.node.body_node();
+        for equation in body.iter().flat_map(|b| b.all_equals_equation_node()) {
+            self.equation(&equation)?;
+        }
+
+        for assertion in body.iter().flat_map(|b| b.all_assert_equation_node()) {
+            let Some(expr) = assertion.expression_node() else {
+                continue;
+            };
+
+            if let Some(ConstValue::Boolean(false)) = self.scalar(&expr)? {
+                return Err(error(
+                    self.db,
+                    &assertion,
+                    "assertion failed",
+                    "this assertion does not hold",
+                ));
+            }
+        }
+
+        self.update_memory()?;
+
+        let outputs = self
+            .outputs
+            .iter()
+            .map(|name| self.values.get(name).cloned().flatten())
+            .collect();
+        Ok(outputs)
+    }
+
+    /// Persists the operands of `pre` and `fby` at the end of a cycle, for the ones whose clock was
+    /// active
+    fn update_memory(&mut self) -> Result<(), Diagnostic> {
+        // All the operands are evaluated before modifying anything, as they may depend on other
+        // memory slots. They may themselves contain stateful expressions, that tick while they are
+        // evaluated.
+        let mut updates = Vec::with_capacity(self.ticked.len());
+        let mut done = HashSet::new();
+        loop {
+            let ticked = self
+                .ticked
+                .iter()
+                .filter(|(expr, _)| !done.contains(*expr))
+                .map(|(expr, clock)| (expr.clone(), clock.clone()))
+                .collect::<Vec<_>>();
+            if ticked.is_empty() {
+                break;
+            }
+
+            for (expr, clock) in ticked {
+                done.insert(expr.clone());
+                let operand = match &expr {
+                    ExpressionNode::PreExpressionNode(e) => e.operand(),
+                    ExpressionNode::FbyExpressionNode(e) => e.right(),
+                    _ => None,
+                };
+
+                let value = match operand {
+                    Some(operand) => Some(self.eval_on(clock, &operand)?),
+                    None => None,
+                };
+                updates.push((expr, value));
+            }
+        }
+
+        for (expr, value) in updates {
+            match (self.memory.get_mut(&expr), value) {
+                (Some(Memory::Pre(last)), Some(value)) => *last = value,
+                (Some(Memory::Fby(last)), Some(value)) => *last = Some(value),
+                (Some(Memory::Arrow(first)), _) => *first = false,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates an equation (if it wasn't already during this cycle)
+    fn equation(&mut self, equation: &EqualsEquationNode) -> Result<(), Diagnostic> {
+        if self.evaluated.contains(equation) {
+            return Ok(());
+        }
+
+        if !self.pending.insert(equation.clone()) {
+            return Err(error(
+                self.db,
+                equation,
+                "causality loop",
+                "this equation depends on itself during the same cycle",
+            ));
+        }
+
+        let lefts = equation
+            .left_node()
+            .into_iter()
+            .flat_map(|l| l.all_left_item_node())
+            .collect::<Vec<_>>();
+
+        // Constants are on the clock of the variables they define
+        let clock = lefts
+            .first()
+            .and_then(left_item_name)
+            .and_then(|name| self.clocks.variable(&name).cloned())
+            .unwrap_or(Clock::Base);
+        let values = match equation.expression_node() {
+            Some(expr) => self.eval_on(clock, &expr)?,
+            None => vec![],
+        };
+
+        for (idx, left) in lefts.into_iter().enumerate() {
+            let value = values.get(idx).cloned().flatten();
+            match &left {
+                LeftItemNode::IdNode(id) => {
+                    if let Some(ident) = id.ident() {
+                        self.values.insert(ident.text().to_owned(), value);
+                    }
+                }
+                _ => self.define_part(&left, value)?,
+            }
+        }
+
+        self.pending.remove(equation);
+        self.evaluated.insert(equation.clone());
+        Ok(())
+    }
+
+    /// Defines a part of a variable (`p.x`, `t[i]`, `t[0 .. 2]`), the other parts keeping their
+    /// value
+    fn define_part(&mut self, left: &LeftItemNode, value: Value) -> Result<(), Diagnostic> {
+        let Some((var, path)) = self.part(left)? else {
+            return Ok(());
+        };
+
+        // A variable with a `nil` part is `nil`, as are the variables defined with a `nil` index
+        let (Some(path), Some(value)) = (path, value) else {
+            self.values.insert(var.clone(), None);
+            self.nil_parts.insert(var);
+            return Ok(());
+        };
+        if self.nil_parts.contains(&var) {
+            return Ok(());
+        }
+
+        let current = self.values.get(&var).cloned().flatten();
+        let current = current.or_else(|| self.types.get(&var).and_then(zero));
+        let Some(mut current) = current else {
+            return Err(error(
+                self.db,
+                left,
+                "type error",
+                "the type of this variable is unknown",
+            ));
+        };
+
+        if let Err((message, label)) = set_part(&mut current, &path, value) {
+            return Err(error(self.db, left, message, &label));
+        }
+        self.values.insert(var, Some(current));
+        Ok(())
+    }
+
+    /// Returns the variable of which a left item is a part, and the path to this part, which is
+    /// `None` when an index is `nil`
+    fn part(&mut self, left: &LeftItemNode) -> Result<Option<(String, Option<Path>)>, Diagnostic> {
+        match left {
+            LeftItemNode::IdNode(id) => {
+                let name = id.ident().map(|i| i.text().to_owned());
+                Ok(name.map(|name| (name, Some(vec![]))))
+            }
+            LeftItemNode::LeftFieldAccessNode(access) => {
+                let field = access.field().and_then(|f| f.ident());
+                let (Some(parent), Some(field)) = (access.left_item_node(), field) else {
+                    return Err(error(
+                        self.db,
+                        access,
+                        "incomplete expression",
+                        "a part of the variable is missing",
+                    ));
+                };
+
+                let part = self.part(&parent)?;
+                Ok(part.map(|(var, path)| {
+                    let path = path.map(|mut path| {
+                        path.push(Part::Field(field.text().to_owned()));
+                        path
+                    });
+                    (var, path)
+                }))
+            }
+            LeftItemNode::LeftTableAccessNode(access) => {
+                let Some(parent) = access.left_item_node() else {
+                    return Err(error(
+                        self.db,
+                        access,
+                        "incomplete expression",
+                        "a part of the variable is missing",
+                    ));
+                };
+                let Some((var, path)) = self.part(&parent)? else {
+                    return Ok(None);
+                };
+
+                let indices = match access.select_node() {
+                    Some(select) => self
+                        .instance
+                        .slice_bounds(self.db, &select)
+                        .and_then(|(first, last, step)| slice_indices(first, last, step))
+                        .ok_or_else(|| {
+                            error(
+                                self.db,
+                                &select,
+                                "invalid slice",
+                                "the bounds and the step must be valid constants",
+                            )
+                        })?,
+                    None => match self.operand(access.index(), access)? {
+                        Some(ConstValue::Integer(i)) => vec![i],
+                        None => return Ok(Some((var, None))),
+                        Some(_) => {
+                            return Err(error(
+                                self.db,
+                                access,
+                                "type error",
+                                "expected an integer index",
+                            ))
+                        }
+                    },
+                };
+                let Some(mut path) = path else {
+                    return Ok(Some((var, None)));
+                };
+
+                // Indices in a slice are indices in the array it is a slice of
+                let indices = match path.last() {
+                    Some(Part::Slice(slice)) => {
+                        let mut mapped = vec![];
+                        for i in indices {
+                            match usize::try_from(i).ok().and_then(|i| slice.get(i)) {
+                                Some(i) => mapped.push(*i),
+                                None => {
+                                    return Err(error(
+                                        self.db,
+                                        access,
+                                        "index out of bounds",
+                                        format!(
+                                            "the length is {} but the index is {i}",
+                                            slice.len()
+                                        )
+                                        .as_str(),
+                                    ))
+                                }
+                            }
+                        }
+                        path.pop();
+                        mapped
+                    }
+                    _ => indices,
+                };
+
+                path.push(match access.select_node() {
+                    Some(_) => Part::Slice(indices),
+                    None => Part::Index(indices[0]),
+                });
+                Ok(Some((var, Some(path))))
+            }
+        }
+    }
+
+    /// Returns the value of a variable of the node, or `None` if it is not one
+    fn variable(&mut self, name: &str) -> Result<Option<Value>, Diagnostic> {
+        for equation in self.definitions.get(name).cloned().unwrap_or_default() {
+            self.equation(&equation)?;
+        }
+
+        Ok(self.values.get(name).cloned())
+    }
+
+    /// Evaluates an expression in the context of another clock
+    fn eval_on(&mut self, clock: Clock, expr: &ExpressionNode) -> Result<Vec<Value>, Diagnostic> {
+        let context = std::mem::replace(&mut self.context, clock);
+        let values = self.eval(expr);
+        self.context = context;
+        values
+    }
+
+    /// Returns the clock of an expression, and the number of values it has
+    fn clock_of(&self, expr: &ExpressionNode) -> (Clock, usize) {
+        let clocks = self.clocks.expr(expr);
+        let width = clocks.len();
+        let clock = clocks.into_iter().flatten().next();
+        (clock.unwrap_or_else(|| self.context.clone()), width)
+    }
+
+    /// Tells if a clock is active during the current cycle
+    fn on_clock(&mut self, clock: &Clock) -> Result<bool, Diagnostic> {
+        let Clock::On { parent, var, case } = clock else {
+            return Ok(true);
+        };
+
+        if !self.on_clock(parent)? {
+            return Ok(false);
+        }
+        let value = self.variable(var)?.flatten();
+        Ok(case_holds(&value, case).unwrap_or(false))
+    }
+
+    /// Evaluates a stateful expression if its clock is active, and records that its memory must be
+    /// updated at the end of the cycle
+    fn tick(
+        &mut self,
+        expr: &ExpressionNode,
+        f: impl FnOnce(&mut Self) -> Result<Vec<Value>, Diagnostic>,
+    ) -> Result<Vec<Value>, Diagnostic> {
+        let (clock, width) = self.clock_of(expr);
+        if !self.on_clock(&clock)? {
+            return Ok(vec![None; width]);
+        }
+
+        self.ticked.insert(expr.clone(), clock);
+        f(self)
+    }
+
+    fn scalar(&mut self, expr: &ExpressionNode) -> Result<Value, Diagnostic> {
+        let mut values = self.eval(expr)?;
+        if values.len() != 1 {
+            return Err(error(
+                self.db,
+                expr,
+                "unexpected tuple",
+                format!("expected a single value, got {}", values.len()).as_str(),
+            ));
+        }
+
+        Ok(values.remove(0))
+    }
+
+    fn operand(
+        &mut self,
+        operand: Option<ExpressionNode>,
+        parent: &impl AstNode,
+    ) -> Result<Value, Diagnostic> {
+        match operand {
+            Some(operand) => self.scalar(&operand),
+            None => Err(error(
+                self.db,
+                parent,
+                "incomplete expression",
+                "an operand is missing",
+            )),
+        }
+    }
+
+    fn unary<E>(
+        &mut self,
+        e: &E,
+        f: fn(ConstValue) -> Option<ConstValue>,
+    ) -> Result<Value, Diagnostic>
+    where
+        E: UnaryExpression + AstNode,
+    {
+        let Some(operand) = self.operand(e.operand(), e)? else {
+            return Ok(None);
+        };
+
+        match f(operand) {
+            Some(value) => Ok(Some(value)),
+            None => Err(error(
+                self.db,
+                e,
+                "type error",
+                "invalid operand for this operator",
+            )),
+        }
+    }
+
+    fn binary<E>(
+        &mut self,
+        e: &E,
+        f: impl Fn(ConstValue, ConstValue) -> Option<ConstValue>,
+    ) -> Result<Value, Diagnostic>
+    where
+        E: BinaryExpression + AstNode,
+    {
+        let left = self.operand(e.left(), e)?;
+        let right = self.operand(e.right(), e)?;
+        self.apply(e, left, right, f)
+    }
+
+    fn division<E>(
+        &mut self,
+        e: &E,
+        int: fn(i32, i32) -> i32,
+        real: fn(f32, f32) -> f32,
+    ) -> Result<Value, Diagnostic>
+    where
+        E: BinaryExpression + AstNode,
+    {
+        let left = self.operand(e.left(), e)?;
+        let right = self.operand(e.right(), e)?;
+        if is_zero(&right) {
+            return Err(error(self.db, e, "division by zero", "the divisor is zero"));
+        }
+
+        self.apply(e, left, right, |l, r| numeric(l, r, int, real))
+    }
+
+    fn apply(
+        &self,
+        e: &impl AstNode,
+        left: Value,
+        right: Value,
+        f: impl Fn(ConstValue, ConstValue) -> Option<ConstValue>,
+    ) -> Result<Value, Diagnostic> {
+        let (Some(left), Some(right)) = (left, right) else {
+            return Ok(None);
+        };
+
+        match f(left, right) {
+            Some(value) => Ok(Some(value)),
+            None => Err(error(
+                self.db,
+                e,
+                "type error",
+                "invalid operands for this operator",
+            )),
+        }
+    }
+
+    fn booleans(&mut self, e: &impl AstNode) -> Result<Option<Vec<bool>>, Diagnostic> {
+        let mut booleans = vec![];
+        for operand in e.syntax().children().filter_map(ExpressionNode::cast) {
+            for value in self.eval(&operand)? {
+                match value {
+                    Some(ConstValue::Boolean(b)) => booleans.push(b),
+                    None => return Ok(None),
+                    Some(_) => {
+                        return Err(error(self.db, &operand, "type error", "expected booleans"))
+                    }
+                }
+            }
+        }
+
+        Ok(Some(booleans))
+    }
+
+    /// Evaluates an expression, which may be a tuple
+    fn eval(&mut self, expr: &ExpressionNode) -> Result<Vec<Value>, Diagnostic> {
+        let value = match expr {
+            ExpressionNode::ConstantNode(_) => self.instance.eval(self.db, expr.clone()),
+            ExpressionNode::IdentExpressionNode(e) => {
+                let Some(ident) = e.id_node().and_then(|i| i.ident()) else {
+                    return Ok(vec![None]);
+                };
+
+                match self.variable(ident.text())? {
+                    Some(value) => value,
+                    None => {
+                        let value = self.instance.eval(self.db, expr.clone());
+                        if value.is_none() {
+                            return Err(error(
+                                self.db,
+                                e,
+                                "unknown value",
+                                "this is neither a variable nor a constant",
+                            ));
+                        }
+                        value
+                    }
+                }
+            }
+            ExpressionNode::NotExpressionNode(e) => self.unary(e, |v| match v {
+                ConstValue::Boolean(b) => Some(ConstValue::Boolean(!b)),
+                _ => None,
+            })?,
+            ExpressionNode::NegExpressionNode(e) => self.unary(e, |v| match v {
+                ConstValue::Integer(i) => Some(ConstValue::Integer(i.wrapping_neg())),
+                ConstValue::Real(r) => Some(ConstValue::Real(-r)),
+                _ => None,
+            })?,
+            ExpressionNode::IntExpressionNode(e) => self.unary(e, |v| match v {
+                ConstValue::Integer(i) => Some(ConstValue::Integer(i)),
+                ConstValue::Real(r) => Some(ConstValue::Integer(r as i32)),
+                _ => None,
+            })?,
+            ExpressionNode::RealExpressionNode(e) => self.unary(e, |v| match v {
+                ConstValue::Integer(i) => Some(ConstValue::Real(i as f32)),
+                ConstValue::Real(r) => Some(ConstValue::Real(r)),
+                _ => None,
+            })?,
+            ExpressionNode::PreExpressionNode(_) => {
+                return self.tick(expr, |this| match this.memory.get(expr) {
+                    Some(Memory::Pre(last)) if !last.is_empty() => Ok(last.clone()),
+                    _ => Ok(vec![None]),
+                });
+            }
+            ExpressionNode::FbyExpressionNode(e) => {
+                let Some(first) = e.left() else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "an operand is missing",
+                    ));
+                };
+
+                // The first operand is evaluated at every cycle of the clock, for its sub-instances to
+                // stay in sync
+                return self.tick(expr, |this| {
+                    let first = this.eval(&first)?;
+                    match this.memory.get(expr) {
+                        Some(Memory::Fby(Some(last))) => Ok(last.clone()),
+                        _ => Ok(first),
+                    }
+                });
+            }
+            ExpressionNode::ArrowExpressionNode(e) => {
+                let (Some(first), Some(then)) = (e.left(), e.right()) else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "an operand is missing",
+                    ));
+                };
+
+                return self.tick(expr, |this| {
+                    let first = this.eval(&first)?;
+                    let then = this.eval(&then)?;
+                    match this.memory.get(expr) {
+                        Some(Memory::Arrow(false)) => Ok(then),
+                        _ => Ok(first),
+                    }
+                });
+            }
+            ExpressionNode::CurrentExpressionNode(e) => {
+                let Some(operand) = e.operand() else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "an operand is missing",
+                    ));
+                };
+
+                let values = self.eval(&operand)?;
+                let Memory::Current(last) = self
+                    .memory
+                    .entry(expr.clone())
+                    .or_insert_with(|| Memory::Current(vec![None; values.len()]))
+                else {
+                    unreachable!()
+                };
+
+                last.resize(values.len(), None);
+                for (last, value) in last.iter_mut().zip(values) {
+                    if value.is_some() {
+                        *last = value;
+                    }
+                }
+                return Ok(last.clone());
+            }
+            ExpressionNode::WhenExpressionNode(e) => {
+                let Some(operand) = e.left() else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "an operand is missing",
+                    ));
+                };
+                let values = self.eval(&operand)?;
+
+                let clock = e
+                    .syntax()
+                    .children()
+                    .find_map(rustre_parser::ast::ClockExpressionNode::cast);
+                let Some((clock, case)) = clock.as_ref().and_then(clock_condition) else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "the clock is missing",
+                    ));
+                };
+
+                let value = self.variable(clock.text())?;
+                let Some(present) = value.and_then(|v| case_holds(&v, &case)) else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "invalid clock",
+                        "clocks must be boolean or enumerated variables",
+                    ));
+                };
+
+                return if present {
+                    Ok(values)
+                } else {
+                    Ok(vec![None; values.len()])
+                };
+            }
+            ExpressionNode::AndExpressionNode(e) => {
+                self.binary(e, |l, r| logic(l, r, |l, r| l && r))?
+            }
+            ExpressionNode::OrExpressionNode(e) => {
+                self.binary(e, |l, r| logic(l, r, |l, r| l || r))?
+            }
+            ExpressionNode::XorExpressionNode(e) => {
+                self.binary(e, |l, r| logic(l, r, |l, r| l ^ r))?
+            }
+            ExpressionNode::ImplExpressionNode(e) => {
+                self.binary(e, |l, r| logic(l, r, |l, r| !l || r))?
+            }
+            ExpressionNode::EqExpressionNode(e) => {
+                self.binary(e, |l, r| equal(l, r).map(ConstValue::Boolean))?
+            }
+            ExpressionNode::NeqExpressionNode(e) => {
+                self.binary(e, |l, r| equal(l, r).map(|eq| ConstValue::Boolean(!eq)))?
+            }
+            ExpressionNode::LtExpressionNode(e) => {
+                self.binary(e, |l, r| compare(l, r, Ordering::is_lt))?
+            }
+            ExpressionNode::LteExpressionNode(e) => {
+                self.binary(e, |l, r| compare(l, r, Ordering::is_le))?
+            }
+            ExpressionNode::GtExpressionNode(e) => {
+                self.binary(e, |l, r| compare(l, r, Ordering::is_gt))?
+            }
+            ExpressionNode::GteExpressionNode(e) => {
+                self.binary(e, |l, r| compare(l, r, Ordering::is_ge))?
+            }
+            ExpressionNode::AddExpressionNode(e) => {
+                self.binary(e, |l, r| numeric(l, r, i32::wrapping_add, |l, r| l + r))?
+            }
+            ExpressionNode::SubExpressionNode(e) => {
+                self.binary(e, |l, r| numeric(l, r, i32::wrapping_sub, |l, r| l - r))?
+            }
+            ExpressionNode::MulExpressionNode(e) => {
+                self.binary(e, |l, r| numeric(l, r, i32::wrapping_mul, |l, r| l * r))?
+            }
+            ExpressionNode::PowerExpressionNode(e) => self.binary(e, |l, r| {
+                numeric(l, r, |l, r| l.wrapping_pow(r as u32), f32::powf)
+            })?,
+            ExpressionNode::DivExpressionNode(e) => {
+                self.division(e, i32::wrapping_div, |l, r| l / r)?
+            }
+            ExpressionNode::ModExpressionNode(e) => {
+                self.division(e, i32::wrapping_rem, |l, r| l % r)?
+            }
+            ExpressionNode::FieldAccessExpressionNode(e) => {
+                let Some(field) = e.field() else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "the field is missing",
+                    ));
+                };
+
+                match self.operand(e.left(), e)? {
+                    None => None,
+                    Some(ConstValue::Struct(fields)) => {
+                        let value = fields.into_iter().find(|(f, _)| f == field.text());
+                        let Some((_, value)) = value else {
+                            return Err(error(self.db, e, "type error", "unknown field"));
+                        };
+                        Some(value)
+                    }
+                    Some(_) => return Err(error(self.db, e, "type error", "expected a structure")),
+                }
+            }
+            ExpressionNode::CallByNameExpressionNode(e) => return self.call_by_name(expr, e),
+            ExpressionNode::MergeExpressionNode(e) => {
+                let Some(clock) = e.id_node().and_then(|i| i.ident()) else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "the clock is missing",
+                    ));
+                };
+                let value = match self.variable(clock.text())? {
+                    Some(Some(ConstValue::Boolean(b))) => Some(ClockCase::Bool(b)),
+                    Some(Some(ConstValue::Enum(c))) => Some(ClockCase::Constructor(c)),
+                    Some(None) => None,
+                    _ => {
+                        return Err(error(
+                            self.db,
+                            e,
+                            "invalid clock",
+                            "clocks must be boolean or enumerated variables",
+                        ))
+                    }
+                };
+
+                let Some(value) = value else {
+                    return Ok(vec![None; self.clock_of(expr).1]);
+                };
+
+                // Only the selected branch is on an active clock
+                let selected = e
+                    .all_merge_case_node()
+                    .find(|case| merge_case(case).as_ref() == Some(&value))
+                    .and_then(|case| case.expression_node());
+                let Some(branch) = selected else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "non-exhaustive merge",
+                        "no branch is selected by the value of the clock",
+                    ));
+                };
+
+                let parent = self.clocks.variable(clock.text()).cloned();
+                let clock = Clock::On {
+                    parent: Box::new(parent.unwrap_or(Clock::Base)),
+                    var: clock.text().to_owned(),
+                    case: value,
+                };
+                return self.eval_on(clock, &branch);
+            }
+            ExpressionNode::IfExpressionNode(e) => {
+                let cond = self.operand(e.cond(), e)?;
+                let (Some(then), Some(otherwise)) = (e.if_body(), e.else_body()) else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "a branch is missing",
+                    ));
+                };
+
+                // Both branches are evaluated, for their sub-instances to stay in sync
+                let then = self.eval(&then)?;
+                let otherwise = self.eval(&otherwise)?;
+                return match cond {
+                    Some(ConstValue::Boolean(true)) => Ok(then),
+                    Some(ConstValue::Boolean(false)) => Ok(otherwise),
+                    None => Ok(vec![None; then.len()]),
+                    Some(_) => Err(error(
+                        self.db,
+                        e,
+                        "type error",
+                        "the condition must be a boolean",
+                    )),
+                };
+            }
+            ExpressionNode::WithExpressionNode(e) => {
+                let cond = e.cond().and_then(|cond| self.instance.eval(self.db, cond));
+                let branch = match cond {
+                    Some(ConstValue::Boolean(true)) => e.with_body(),
+                    Some(ConstValue::Boolean(false)) => e.else_body(),
+                    _ => {
+                        return Err(error(
+                            self.db,
+                            e,
+                            "invalid static condition",
+                            "the condition must be a constant boolean",
+                        ))
+                    }
+                };
+
+                return match branch {
+                    Some(branch) => self.eval(&branch),
+                    None => Err(error(
+                        self.db,
+                        e,
+                        "incomplete expression",
+                        "a branch is missing",
+                    )),
+                };
+            }
+            ExpressionNode::DieseExpressionNode(e) => self
+                .booleans(e)?
+                .map(|b| ConstValue::Boolean(b.into_iter().filter(|b| *b).count() <= 1)),
+            ExpressionNode::NorExpressionNode(e) => self
+                .booleans(e)?
+                .map(|b| ConstValue::Boolean(b.into_iter().all(|b| !b))),
+            ExpressionNode::ParExpressionNode(e) => {
+                let mut values = vec![];
+                for operand in e.syntax().children().filter_map(ExpressionNode::cast) {
+                    values.extend(self.eval(&operand)?);
+                }
+                return Ok(values);
+            }
+            ExpressionNode::HatExpressionNode(e) => {
+                let value = self.operand(e.left(), e)?;
+                let size = e.right().and_then(|size| self.instance.eval(self.db, size));
+                let Some(ConstValue::Integer(size)) = size else {
+                    return Err(error(
+                        self.db,
+                        e,
+                        "invalid array size",
+                        "the size must be a constant integer",
+                    ));
+                };
+
+                value.map(|v| ConstValue::Array(std::iter::repeat_n(v, size as usize).collect()))
+            }
+            ExpressionNode::ArrayAccessExpressionNode(e) => {
+                let Some(array) = self.operand(e.array(), e)? else {
+                    return Ok(vec![None]);
+                };
+                let ConstValue::Array(elements) = array else {
+                    return Err(error(self.db, e, "type error", "expected an array"));
+                };
+
+                let indices = match e.select_node() {
+                    Some(select) => self
+                        .instance
+                        .slice_bounds(self.db, &select)
+                        .and_then(|(first, last, step)| slice_indices(first, last, step))
+                        .ok_or_else(|| {
+                            error(
+                                self.db,
+                                &select,
+                                "invalid slice",
+                                "the bounds and the step must be valid constants",
+                            )
+                        })?,
+                    None => match self.operand(e.index(), e)? {
+                        Some(ConstValue::Integer(i)) => vec![i],
+                        None => return Ok(vec![None]),
+                        Some(_) => {
+                            return Err(error(
+                                self.db,
+                                e,
+                                "type error",
+                                "expected an integer index",
+                            ))
+                        }
+                    },
+                };
+
+                let mut selected = vec![];
+                for i in &indices {
+                    match usize::try_from(*i).ok().and_then(|i| elements.get(i)) {
+                        Some(element) => selected.push(element.clone()),
+                        None => {
+                            return Err(error(
+                                self.db,
+                                e,
+                                "index out of bounds",
+                                format!("the length is {} but the index is {i}", elements.len())
+                                    .as_str(),
+                            ))
+                        }
+                    }
+                }
+
+                match e.select_node() {
+                    Some(_) => Some(ConstValue::Array(selected)),
+                    None => selected.pop(),
+                }
+            }
+            ExpressionNode::ArrayLiteralExpressionNode(e) => {
+                let mut elements = vec![];
+                for element in e.elements() {
+                    match self.scalar(&element)? {
+                        Some(value) => elements.push(value),
+                        None => return Ok(vec![None]),
+                    }
+                }
+                Some(ConstValue::Array(elements))
+            }
+            ExpressionNode::ConcatExpressionNode(e) => self.binary(e, |l, r| match (l, r) {
+                (ConstValue::Array(mut l), ConstValue::Array(r)) => {
+                    l.extend(r);
+                    Some(ConstValue::Array(l))
+                }
+                _ => None,
+            })?,
+            ExpressionNode::CallByPosExpressionNode(e) => return self.call(expr, e),
+        };
+
+        Ok(vec![value])
+    }
+
+    fn call(
+        &mut self,
+        expr: &ExpressionNode,
+        call: &CallByPosExpressionNode,
+    ) -> Result<Vec<Value>, Diagnostic> {
+        // Nodes only run on the cycles of the clock of their arguments that are on their own base
+        // clock
+        let (_, width) = self.clock_of(expr);
+        let clock = self.clocks.call_clock(call);
+        let clock = clock.unwrap_or_else(|| self.context.clone());
+        if !self.on_clock(&clock)? {
+            return Ok(vec![None; width]);
+        }
+
+        let name = call.node_ref().and_then(|n| n.id_node());
+        if let Some(iterator) = name.as_ref().and_then(|n| resolve_iterator(self.db, n)) {
+            return self.iterate(expr, call, iterator);
+        }
+
+        let mut args = vec![];
+        for arg in call.args().skip(1) {
+            args.extend(self.eval(&arg)?);
+        }
+
+        let callee = name.and_then(|name| {
+            resolve_callee(self.db, &name, call.static_args_node(), &self.instance)
+        });
+        self.run(expr, call, callee.as_ref(), 0, args)
+    }
+
+    /// Evaluates a structure literal (`T { a = 1; b = 2 }`), the update of a structure
+    /// (`T { s with a = 1 }`), or a call with named arguments (`N { x = 1; y = 2 }`)
+    fn call_by_name(
+        &mut self,
+        expr: &ExpressionNode,
+        call: &CallByNameExpressionNode,
+    ) -> Result<Vec<Value>, Diagnostic> {
+        let Some(name) = call.name() else {
+            return Err(error(
+                self.db,
+                call,
+                "incomplete expression",
+                "the name is missing",
+            ));
+        };
+        let value_of = |name: &str| {
+            call.all_call_by_name_param_node()
+                .find(|p| {
+                    let ident = p.id_node().and_then(|i| i.ident());
+                    ident.is_some_and(|i| i.text() == name)
+                })
+                .and_then(|p| p.expression_node())
+        };
+        let missing = |name: &str| {
+            error(
+                self.db,
+                call,
+                "incomplete expression",
+                format!("no value is given to {name}").as_str(),
+            )
+        };
+
+        let Some(decl) = Option::clone(&resolve_type_decl(self.db, name.clone())) else {
+            let (clock, width) = self.clock_of(expr);
+            if !self.on_clock(&clock)? {
+                return Ok(vec![None; width]);
+            }
+
+            let callee = resolve_callee(self.db, &name, None, &self.instance);
+            let params = callee
+                .as_ref()
+                .map(|c| c.signature(self.db).params)
+                .unwrap_or_default();
+            let mut args = vec![];
+            for (param, _) in params {
+                match value_of(param.text()) {
+                    Some(value) => args.extend(self.eval(&value)?),
+                    None => return Err(missing(param.text())),
+                }
+            }
+            return self.run(expr, call, callee.as_ref(), 0, args);
+        };
+
+        let node = Some(self.instance.node.clone());
+        let ty = type_of_type_decl(self.db, node, decl);
+        let Type::Struct { fields, .. } = &*ty else {
+            return Err(error(self.db, call, "type error", "expected a structure"));
+        };
+
+        // Fields that are not given keep the value they have in the updated structure
+        let base = match call.base() {
+            Some(base) => {
+                let ident = base.ident();
+                let value = match &ident {
+                    Some(ident) => self.variable(ident.text())?,
+                    None => None,
+                };
+                match value {
+                    Some(value) => Some(value),
+                    None => match self.instance.eval_ident(self.db, &base) {
+                        Some(value) => Some(Some(value)),
+                        None => {
+                            return Err(error(
+                                self.db,
+                                &base,
+                                "unknown value",
+                                "this is neither a variable nor a constant",
+                            ))
+                        }
+                    },
+                }
+            }
+            None => None,
+        };
+
+        let mut values = vec![];
+        let mut is_nil = matches!(base, Some(None));
+        for (field, _) in fields {
+            let value = match (value_of(field), &base) {
+                (Some(value), _) => self.scalar(&value)?,
+                (None, Some(Some(ConstValue::Struct(base)))) => base
+                    .iter()
+                    .find_map(|(f, value)| (f == field).then(|| value.clone())),
+                (None, Some(_)) => None,
+                (None, None) => return Err(missing(field)),
+            };
+            match value {
+                Some(value) => values.push((field.clone(), value)),
+                None => is_nil = true,
+            }
+        }
+
+        match is_nil {
+            true => Ok(vec![None]),
+            false => Ok(vec![Some(ConstValue::Struct(values))]),
+        }
+    }
+
+    /// Runs the `k`-th instance of the node called by an expression, which is created the first
+    /// time it runs
+    fn run(
+        &mut self,
+        expr: &ExpressionNode,
+        call: &impl AstNode,
+        callee: Option<&Callee>,
+        k: usize,
+        args: Vec<Value>,
+    ) -> Result<Vec<Value>, Diagnostic> {
+        let instance = match callee {
+            Some(Callee::Node(instance)) => instance,
+            Some(Callee::Extern(_)) => {
+                return Err(error(
+                    self.db,
+                    call,
+                    "cannot run node",
+                    "extern nodes are implemented outside of the program",
+                ))
+            }
+            None => {
+                return Err(error(
+                    self.db,
+                    call,
+                    "cannot run node",
+                    "this node is unknown, or generic and not given static arguments",
+                ))
+            }
+        };
+
+        let db = self.db;
+        let slot = self
+            .memory
+            .entry(expr.clone())
+            .or_insert_with(|| Memory::Instances(vec![]));
+        let Memory::Instances(instances) = slot else {
+            unreachable!()
+        };
+
+        while instances.len() <= k {
+            instances.push(NodeInstance::of_instance(db, instance.clone()));
+        }
+        instances[k].step(args)
+    }
+
+    /// Applies an array iterator, each application of a node having its own instance
+    fn iterate(
+        &mut self,
+        expr: &ExpressionNode,
+        call: &CallByPosExpressionNode,
+        iterator: ArrayIterator,
+    ) -> Result<Vec<Value>, Diagnostic> {
+        let statics = call
+            .static_args_node()
+            .map(|args| args.all_static_arg_node().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let Some(node_arg) = statics.first() else {
+            return Err(error(
+                self.db,
+                call,
+                "incomplete expression",
+                "the static arguments are missing",
+            ));
+        };
+
+        // Sizes, and the bounds of `boolred`, are constant integers
+        let const_args = match iterator {
+            ArrayIterator::BoolRed => &statics[..],
+            _ => &statics[1..],
+        };
+        let mut consts = vec![];
+        for arg in const_args {
+            match arg
+                .expression_node()
+                .and_then(|e| self.instance.eval(self.db, e))
+            {
+                Some(ConstValue::Integer(i)) if i >= 0 => consts.push(i as usize),
+                _ => {
+                    return Err(error(
+                        self.db,
+                        arg,
+                        "invalid static argument",
+                        "expected a constant positive integer",
+                    ))
+                }
+            }
+        }
+        let Some(&size) = consts.last() else {
+            return Err(error(
+                self.db,
+                call,
+                "incomplete expression",
+                "the size of the arrays is missing",
+            ));
+        };
+        let mut operands = call.args().skip(1);
+
+        if iterator == ArrayIterator::BoolRed {
+            let array = self.operand(operands.next(), call)?;
+            let Some(elements) = self.elements(array, size, call)? else {
+                return Ok(vec![None]);
+            };
+            let count = elements
+                .iter()
+                .filter(|e| **e == ConstValue::Boolean(true))
+                .count();
+            let holds = consts[0] <= count && count <= consts[1];
+            return Ok(vec![Some(ConstValue::Boolean(holds))]);
+        }
+
+        let op = node_arg.predef_op();
+        let callee = match op {
+            Some(_) => None,
+            None => {
+                let args = node_arg
+                    .effective_node_node()
+                    .and_then(|e| e.static_args_node());
+                iterated_node_name(node_arg)
+                    .and_then(|name| resolve_callee(self.db, &name, args, &self.instance))
+            }
+        };
+
+        let mut acc = match iterator.accumulates() {
+            true => Some(self.operand(operands.next(), call)?),
+            false => None,
+        };
+        let mut arrays = vec![];
+        for operand in operands {
+            let array = self.scalar(&operand)?;
+            arrays.push(self.elements(array, size, &operand)?);
+        }
+
+        let mut outputs: Vec<Vec<Value>> = vec![];
+        for k in 0..size {
+            let mut inputs = acc.take().into_iter().collect::<Vec<_>>();
+            inputs.extend(
+                arrays
+                    .iter()
+                    .map(|array| array.as_ref().map(|elements| elements[k].clone())),
+            );
+            let mut results = match &op {
+                Some(op) => vec![self.predef_op(op, inputs, node_arg)?],
+                None => self.run(expr, call, callee.as_ref(), k, inputs)?,
+            };
+
+            if iterator.accumulates() && !results.is_empty() {
+                acc = Some(results.remove(0));
+            }
+            outputs.resize(results.len(), vec![]);
+            for (output, result) in outputs.iter_mut().zip(results) {
+                output.push(result);
+            }
+        }
+
+        // Arrays with a `nil` element are `nil`
+        let arrays = outputs.into_iter().map(|elements| {
+            let elements = elements.into_iter().collect::<Option<Vec<_>>>();
+            elements.map(ConstValue::Array)
+        });
+        Ok(acc.into_iter().chain(arrays).collect())
+    }
+
+    /// Returns the elements of an array given to an iterator, or `None` if it is `nil`
+    fn elements(
+        &self,
+        array: Value,
+        size: usize,
+        e: &impl AstNode,
+    ) -> Result<Option<Vec<ConstValue>>, Diagnostic> {
+        match array {
+            None => Ok(None),
+            Some(ConstValue::Array(elements)) if elements.len() == size => Ok(Some(elements)),
+            Some(ConstValue::Array(elements)) => Err(error(
+                self.db,
+                e,
+                "invalid array size",
+                format!("expected {size} elements, got {}", elements.len()).as_str(),
+            )),
+            Some(_) => Err(error(self.db, e, "type error", "expected an array")),
+        }
+    }
+
+    /// Applies a predefined operator given to an iterator (as `+` in `red<<+, 4>>`)
+    fn predef_op(
+        &self,
+        op: &PredefOp,
+        inputs: Vec<Value>,
+        arg: &StaticArgNode,
+    ) -> Result<Value, Diagnostic> {
+        type Operator = fn(ConstValue, ConstValue) -> Option<ConstValue>;
+        let binary: Option<Operator> = if op.and().is_some() {
+            Some(|l, r| logic(l, r, |l, r| l && r))
+        } else if op.or().is_some() {
+            Some(|l, r| logic(l, r, |l, r| l || r))
+        } else if op.xor().is_some() {
+            Some(|l, r| logic(l, r, |l, r| l ^ r))
+        } else if op.r#impl().is_some() {
+            Some(|l, r| logic(l, r, |l, r| !l || r))
+        } else if op.equal().is_some() {
+            Some(|l, r| equal(l, r).map(ConstValue::Boolean))
+        } else if op.neq().is_some() {
+            Some(|l, r| equal(l, r).map(|eq| ConstValue::Boolean(!eq)))
+        } else if op.lt().is_some() {
+            Some(|l, r| compare(l, r, Ordering::is_lt))
+        } else if op.lte().is_some() {
+            Some(|l, r| compare(l, r, Ordering::is_le))
+        } else if op.gt().is_some() {
+            Some(|l, r| compare(l, r, Ordering::is_gt))
+        } else if op.gte().is_some() {
+            Some(|l, r| compare(l, r, Ordering::is_ge))
+        } else if op.plus().is_some() {
+            Some(|l, r| numeric(l, r, i32::wrapping_add, |l, r| l + r))
+        } else if op.minus().is_some() {
+            Some(|l, r| numeric(l, r, i32::wrapping_sub, |l, r| l - r))
+        } else if op.star().is_some() {
+            Some(|l, r| numeric(l, r, i32::wrapping_mul, |l, r| l * r))
+        } else if op.slash().is_some() || op.div().is_some() {
+            Some(|l, r| numeric(l, r, i32::wrapping_div, |l, r| l / r))
+        } else if op.r#mod().is_some() {
+            Some(|l, r| numeric(l, r, i32::wrapping_rem, |l, r| l % r))
+        } else {
+            None
+        };
+
+        let mut inputs = inputs.into_iter();
+        let mut operand = || {
+            inputs.next().ok_or_else(|| {
+                error(
+                    self.db,
+                    arg,
+                    "incomplete expression",
+                    "an operand is missing",
+                )
+            })
+        };
+        if let Some(binary) = binary {
+            let (left, right) = (operand()?, operand()?);
+            let is_division = op.slash().is_some() || op.div().is_some() || op.r#mod().is_some();
+            if is_division && is_zero(&right) {
+                return Err(error(
+                    self.db,
+                    arg,
+                    "division by zero",
+                    "the divisor is zero",
+                ));
+            }
+            return self.apply(arg, left, right, binary);
+        }
+
+        if op.not().is_some() {
+            match operand()? {
+                Some(ConstValue::Boolean(b)) => Ok(Some(ConstValue::Boolean(!b))),
+                None => Ok(None),
+                Some(_) => Err(error(self.db, arg, "type error", "expected a boolean")),
+            }
+        } else if op.r#if().is_some() {
+            let (cond, then, otherwise) = (operand()?, operand()?, operand()?);
+            match cond {
+                Some(ConstValue::Boolean(true)) => Ok(then),
+                Some(ConstValue::Boolean(false)) => Ok(otherwise),
+                None => Ok(None),
+                Some(_) => Err(error(
+                    self.db,
+                    arg,
+                    "type error",
+                    "the condition must be a boolean",
+                )),
+            }
+        } else {
+            Err(error(
+                self.db,
+                arg,
+                "unsupported operator",
+                "temporal operators can't be given to iterators",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::name_resolution::find_node;
+
+    fn run(source: &str, node: &str, inputs: Vec<Vec<Value>>) -> Result<Vec<Vec<Value>>, String> {
+        let mut db = crate::driver();
+        crate::add_source_contents(&mut db, source.into());
+
+        let node = Option::clone(&find_node(&db, node.into())).unwrap();
+        let mut instance = NodeInstance::new(&db, node);
+        inputs
+            .into_iter()
+            .map(|inputs| instance.step(inputs).map_err(|d| d.message))
+            .collect()
+    }
+
+    fn int(i: i32) -> Value {
+        Some(ConstValue::Integer(i))
+    }
+
+    #[test]
+    fn counter() {
+        let outputs = run(
+            "node counter(x : int) returns (y : int);
+             let
+                 y = x + (0 -> pre y);
+             tel",
+            "counter",
+            vec![vec![int(1)], vec![int(2)], vec![int(3)]],
+        );
+
+        assert_eq!(outputs, Ok(vec![vec![int(1)], vec![int(3)], vec![int(6)]]));
+    }
+
+    #[test]
+    fn enum_merge() {
+        let mode = |m: &str| Some(ConstValue::Enum(m.into()));
+        let outputs = run(
+            "type mode = enum { Off, On };
+
+             node n(m : mode; x : int) returns (y : int; on : bool);
+             let
+                 y = merge m (On -> x when On(m)) (Off -> 0 when Off(m));
+                 on = m = On;
+             tel",
+            "n",
+            vec![vec![mode("On"), int(4)], vec![mode("Off"), int(5)]],
+        );
+
+        let boolean = |b| Some(ConstValue::Boolean(b));
+        assert_eq!(
+            outputs,
+            Ok(vec![
+                vec![int(4), boolean(true)],
+                vec![int(0), boolean(false)]
+            ])
+        );
+    }
+
+    #[test]
+    fn arrays() {
+        let array = |values: &[i32]| {
+            let values = values.iter().map(|v| ConstValue::Integer(*v)).collect();
+            Some(ConstValue::Array(values))
+        };
+        let source = "node n(x : int^3; i : int) returns (y : int; z : int^4);
+                      let
+                          y = x[i];
+                          z = x[2 .. 0] | [i];
+                      tel";
+
+        let outputs = run(source, "n", vec![vec![array(&[1, 2, 3]), int(1)]]);
+        assert_eq!(outputs, Ok(vec![vec![int(2), array(&[3, 2, 1, 1])]]));
+
+        let outputs = run(source, "n", vec![vec![array(&[1, 2, 3]), int(3)]]);
+        assert_eq!(outputs, Err("index out of bounds".into()));
+    }
+
+    #[test]
+    fn sub_node_instances() {
+        let outputs = run(
+            "node counter(x : int) returns (y : int);
+             var next : int;
+             let
+                 next = y + x;
+                 y = 0 fby next;
+             tel
+
+             node twice(x : int) returns (a, b : int);
+             let
+                 a = counter(x);
+                 b = counter(2 * x);
+             tel",
+            "twice",
+            vec![vec![int(1)], vec![int(1)], vec![int(1)]],
+        );
+
+        assert_eq!(
+            outputs,
+            Ok(vec![
+                vec![int(0), int(0)],
+                vec![int(1), int(2)],
+                vec![int(2), int(4)],
+            ])
+        );
+    }
+
+    #[test]
+    fn sub_clocks() {
+        let outputs = run(
+            "node counter(x : int) returns (y : int);
+             let
+                 y = x + (0 -> pre y);
+             tel
+
+             node n(c : bool; x : int) returns (y, z, w : int);
+             let
+                 y = current(counter(x when c));
+                 z = current((0 when c) -> pre (x when c));
+                 w = merge c (true -> counter(x when c)) (false -> 0);
+             tel",
+            "n",
+            [(true, 1), (false, 1), (false, 1), (true, 1), (true, 5)]
+                .into_iter()
+                .map(|(c, x)| vec![Some(ConstValue::Boolean(c)), int(x)])
+                .collect(),
+        );
+
+        assert_eq!(
+            outputs,
+            Ok(vec![
+                vec![int(1), int(0), int(1)],
+                vec![int(1), int(0), int(0)],
+                vec![int(1), int(0), int(0)],
+                vec![int(2), int(1), int(2)],
+                vec![int(7), int(1), int(7)],
+            ])
+        );
+    }
+
+    #[test]
+    fn causality_loop() {
+        let outputs = run(
+            "node loop(x : int) returns (y : int);
+             var z : int;
+             let
+                 y = z + x;
+                 z = y;
+             tel",
+            "loop",
+            vec![vec![int(1)]],
+        );
+
+        assert_eq!(outputs, Err("causality loop".into()));
+    }
+
+    #[test]
+    fn structures() {
+        let point = |x, y| {
+            Some(ConstValue::Struct(vec![
+                ("x".into(), ConstValue::Integer(x)),
+                ("y".into(), ConstValue::Integer(y)),
+            ]))
+        };
+        let outputs = run(
+            "type point = { x : int; y : int };
+
+             function sub(a, b : int) returns (c : int);
+             let
+                 c = a - b;
+             tel
+
+             node n(p : point) returns (q : point; x : int);
+             let
+                 q = point { p with y = sub { b = p.y; a = 0 } };
+                 x = q.x + q.y;
+             tel",
+            "n",
+            vec![vec![point(3, 4)]],
+        );
+
+        assert_eq!(outputs, Ok(vec![vec![point(3, -4), int(-1)]]));
+    }
+
+    #[test]
+    fn partial_definitions() {
+        let array = |values: &[i32]| {
+            let values = values.iter().map(|v| ConstValue::Integer(*v)).collect();
+            Some(ConstValue::Array(values))
+        };
+        let source = "type point = { x : int; y : int };
+
+                      node n(i : int; a : int^2) returns (t : int^4; u : int^2; p : point);
+                      let
+                          t[0] = 7;
+                          t[1 .. 3][0] = i + 1;
+                          t[2 .. 3] = a;
+                          u[i] = i;
+                          p.x = i;
+                          p.y = 0 -> pre p.x;
+                      tel";
+
+        let point = |x, y| {
+            Some(ConstValue::Struct(vec![
+                ("x".into(), ConstValue::Integer(x)),
+                ("y".into(), ConstValue::Integer(y)),
+            ]))
+        };
+        let outputs = run(
+            source,
+            "n",
+            vec![vec![int(0), array(&[5, 6])], vec![int(1), array(&[1, 2])]],
+        );
+        assert_eq!(
+            outputs,
+            Ok(vec![
+                vec![array(&[7, 1, 5, 6]), array(&[0, 0]), point(0, 0)],
+                vec![array(&[7, 2, 1, 2]), array(&[0, 1]), point(1, 0)],
+            ])
+        );
+
+        let outputs = run(source, "n", vec![vec![int(2), array(&[5, 6])]]);
+        assert_eq!(outputs, Err("index out of bounds".into()));
+    }
+
+    #[test]
+    fn generic_instances() {
+        let array = |values: &[i32]| {
+            let values = values.iter().map(|v| ConstValue::Integer(*v)).collect();
+            Some(ConstValue::Array(values))
+        };
+        let source = "node id<<type t>>(x : t) returns (y : t);
+                      let
+                          y = x;
+                      tel
+
+                      node repeat<<const n : int>>(x : int) returns (y : int^n);
+                      let
+                          y = x ^ n;
+                      tel
+
+                      node id_int = id<<int>>;
+
+                      node n(x : int) returns (a : int; b : int^3);
+                      let
+                          a = id_int(x);
+                          b = repeat<<3>>(a);
+                      tel";
+
+        let outputs = run(source, "n", vec![vec![int(2)]]);
+        assert_eq!(outputs, Ok(vec![vec![int(2), array(&[2, 2, 2])]]));
+
+        let outputs = run(source, "id_int", vec![vec![int(5)]]);
+        assert_eq!(outputs, Ok(vec![vec![int(5)]]));
+    }
+
+    #[test]
+    fn iterators() {
+        let array = |values: &[i32]| {
+            let values = values.iter().map(|v| ConstValue::Integer(*v)).collect();
+            Some(ConstValue::Array(values))
+        };
+        let source = "node counter(x : int) returns (y : int);
+                      let
+                          y = x + (0 -> pre y);
+                      tel
+
+                      node n(x : int^3) returns (sums : int^3; total : int; some : bool);
+                      let
+                          sums = map<<counter, 3>>(x);
+                          total = red<<+, 3>>(0, x);
+                          some = boolred<<1, 3, 3>>(map<<>, 3>>(x, 0^3));
+                      tel";
+
+        // Each element has its own counter
+        let outputs = run(
+            source,
+            "n",
+            vec![vec![array(&[1, 0, -1])], vec![array(&[1, 2, 0])]],
+        );
+        let boolean = |b| Some(ConstValue::Boolean(b));
+        assert_eq!(
+            outputs,
+            Ok(vec![
+                vec![array(&[1, 0, -1]), int(0), boolean(true)],
+                vec![array(&[2, 2, -1]), int(3), boolean(true)],
+            ])
+        );
+
+        let outputs = run(
+            "extern function sin(x : real) returns (y : real);
+
+             node n(x : real^2) returns (y : real^2);
+             let
+                 y = map<<sin, 2>>(x);
+             tel",
+            "n",
+            vec![vec![Some(ConstValue::Array(vec![
+                ConstValue::Real(0.0);
+                2
+            ]))]],
+        );
+        assert_eq!(outputs, Err("cannot run node".into()));
+    }
+}
@@ -0,0 +1,426 @@
+//! Array iterators
+//!
+//! Lustre v6 predefines higher-order nodes, that apply a node (or a predefined operator) to the
+//! elements of arrays of size `n`:
+//!
+//!   * `map<<f, n>>(a1, ..., ak)` applies `f` to the elements of the arrays `ai`, and returns the
+//!     arrays of its results.
+//!   * `red<<f, n>>(acc, a1, ..., ak)` reduces arrays, passing the accumulator returned by each
+//!     application of `f` to the next one. It returns the last accumulator.
+//!   * `fill<<f, n>>(acc)` builds arrays out of the values returned by `n` applications of `f`,
+//!     that also pass an accumulator to each other.
+//!   * `fillred<<f, n>>(acc, a1, ..., ak)` is the combination of `fill` and `red`.
+//!   * `boolred<<i, j, n>>(a)` is `true` if at least `i` and at most `j` elements of `a` are.
+//!
+//! Nodes of the program with one of these names hide the corresponding iterator.
+
+use crate::diagnostics::{Diagnostic, Level, Span};
+use crate::eval::eval_const_node;
+use crate::generics::{static_param_kind, StaticParamKind};
+use crate::name_resolution::{
+    resolve_extern_node, resolve_node, resolve_static_param, NameResolveQuery,
+};
+use crate::types::{ConstValue, Type};
+use crate::TypedSignature;
+use rustre_parser::ast::{
+    AstNode, AstToken, CallByPosExpressionNode, EffectiveNodeNode, ExpressionNode, IdNode,
+    NodeNode, PredefOp, StaticArgNode, StaticArgsNode,
+};
+use yeter::Database;
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq)]
+pub enum ArrayIterator {
+    Map,
+    Red,
+    Fill,
+    FillRed,
+    BoolRed,
+}
+
+impl ArrayIterator {
+    pub fn from_name(name: &str) -> Option<ArrayIterator> {
+        match name {
+            "map" => Some(ArrayIterator::Map),
+            "red" => Some(ArrayIterator::Red),
+            "fill" => Some(ArrayIterator::Fill),
+            "fillred" => Some(ArrayIterator::FillRed),
+            "boolred" => Some(ArrayIterator::BoolRed),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the first parameter and return value of the iterated node are an
+    /// accumulator, that is passed from an application to the next one
+    pub fn accumulates(self) -> bool {
+        matches!(
+            self,
+            ArrayIterator::Red | ArrayIterator::Fill | ArrayIterator::FillRed
+        )
+    }
+
+    fn static_params(self) -> &'static [&'static str] {
+        match self {
+            ArrayIterator::BoolRed => &["i", "j", "n"],
+            _ => &["f", "n"],
+        }
+    }
+}
+
+impl std::fmt::Display for ArrayIterator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArrayIterator::Map => write!(f, "map"),
+            ArrayIterator::Red => write!(f, "red"),
+            ArrayIterator::Fill => write!(f, "fill"),
+            ArrayIterator::FillRed => write!(f, "fillred"),
+            ArrayIterator::BoolRed => write!(f, "boolred"),
+        }
+    }
+}
+
+/// Returns the iterator to which a name refers, unless it is hidden by a node of the program
+pub fn resolve_iterator(db: &Database, name: &IdNode) -> Option<ArrayIterator> {
+    if name.package().is_some() {
+        return None;
+    }
+
+    let iterator = ArrayIterator::from_name(name.name()?.text())?;
+    let is_hidden =
+        resolve_node(db, name.clone()).is_some() || resolve_extern_node(db, name.clone()).is_some();
+    (!is_hidden).then_some(iterator)
+}
+
+/// Returns the iterator instantiated with some static arguments
+pub fn instantiated_iterator(db: &Database, args: &StaticArgsNode) -> Option<ArrayIterator> {
+    let parent = args.syntax().parent()?;
+    let name = if let Some(effective) = EffectiveNodeNode::cast(parent.clone()) {
+        effective.id_node()
+    } else {
+        CallByPosExpressionNode::cast(parent)?.node_ref()?.id_node()
+    };
+
+    resolve_iterator(db, &name?)
+}
+
+/// Name of the node given to an iterator, as `f` in `map<<f, 4>>`
+pub fn iterated_node_name(arg: &StaticArgNode) -> Option<IdNode> {
+    match (arg.effective_node_node(), arg.expression_node()) {
+        (Some(effective), _) => effective.id_node(),
+        (None, Some(ExpressionNode::IdentExpressionNode(ident))) => ident.id_node(),
+        _ => None,
+    }
+}
+
+/// Parameter and return types of a predefined operator, applied to operands of type `operand`
+fn predef_op_type(op: &PredefOp, operand: Type) -> (Vec<Type>, Vec<Type>) {
+    let t = operand;
+    if op.not().is_some() {
+        (vec![Type::Boolean], vec![Type::Boolean])
+    } else if op.and().is_some() || op.or().is_some() || op.xor().is_some() || op.r#impl().is_some()
+    {
+        (vec![Type::Boolean, Type::Boolean], vec![Type::Boolean])
+    } else if op.equal().is_some()
+        || op.neq().is_some()
+        || op.lt().is_some()
+        || op.lte().is_some()
+        || op.gt().is_some()
+        || op.gte().is_some()
+    {
+        (vec![t.clone(), t], vec![Type::Boolean])
+    } else if op.r#if().is_some() {
+        (vec![Type::Boolean, t.clone(), t.clone()], vec![t])
+    } else if op.pre().is_some() || op.current().is_some() {
+        (vec![t.clone()], vec![t])
+    } else {
+        // Arithmetic operators, `fby` and `->`
+        (vec![t.clone(), t.clone()], vec![t])
+    }
+}
+
+/// Resolves the parameter and return types of the node given to an iterator
+fn iterated_node_type(
+    db: &Database,
+    arg: &StaticArgNode,
+    in_node: &Option<NodeNode>,
+    operand: Type,
+) -> Option<(Vec<Type>, Vec<Type>)> {
+    if let Some(op) = arg.predef_op() {
+        return Some(predef_op_type(&op, operand));
+    }
+
+    let name = iterated_node_name(arg)?;
+    let types = |sig: &TypedSignature| {
+        let types = |params: &[(_, Type)]| params.iter().map(|(_, t)| t.clone()).collect();
+        (types(&sig.params), types(&sig.return_params))
+    };
+
+    let query = NameResolveQuery {
+        ident: name.name()?,
+        in_node: in_node.clone(),
+    };
+    let param = Option::clone(&resolve_static_param(db, query))
+        .filter(|p| static_param_kind(p) == StaticParamKind::Node);
+    if let Some(param) = param {
+        let sig = crate::Signature::of_profile(name.name(), param.node_profile_node())
+            .typed(db, in_node.clone());
+        return Some(types(&sig));
+    }
+
+    if let Some(node) = Option::clone(&resolve_node(db, name.clone())) {
+        let static_args = arg.effective_node_node().and_then(|e| e.static_args_node());
+        let sig = match static_args {
+            Some(args) => Option::clone(&crate::generics::instance_signature(
+                db,
+                args,
+                in_node.clone(),
+            ))?,
+            None => TypedSignature::clone(&crate::get_typed_signature(db, node)),
+        };
+        return Some(types(&sig));
+    }
+
+    let ext = Option::clone(&resolve_extern_node(db, name))?;
+    Some(types(&crate::get_extern_signature(db, ext)))
+}
+
+/// Returns `true` if two types can be the same, once their unknown parts are known
+pub fn compatible(a: &Type, b: &Type) -> bool {
+    match (a, b) {
+        (Type::Unknown, _) | (_, Type::Unknown) => true,
+        (
+            Type::Array { elem, size },
+            Type::Array {
+                elem: other_elem,
+                size: other_size,
+            },
+        ) => size == other_size && compatible(elem, other_elem),
+        _ => a == b,
+    }
+}
+
+/// **Query:** Computes the type of an instance of an iterator, as a [Type::Function]
+///
+/// The types of predefined operators (`red<<+, 4>>`) depend on their operands: `operand` is the
+/// type of the values they are applied to. Invalid static arguments are reported, and give an
+/// unknown type.
+#[yeter::query]
+pub fn iterator_type(
+    db: &Database,
+    args: StaticArgsNode,
+    in_node: Option<NodeNode>,
+    operand: Type,
+) -> Type {
+    let Some(iterator) = instantiated_iterator(db, &args) else {
+        return Type::Unknown;
+    };
+
+    let params = iterator.static_params();
+    let arg_nodes = args.all_static_arg_node().collect::<Vec<_>>();
+    if params.len() != arg_nodes.len() {
+        Diagnostic::new(Level::Error, "wrong number of static arguments")
+            .with_attachment(
+                Span::of_node(db, args.syntax()),
+                format!(
+                    "{:?} expects {} static arguments but {} were supplied",
+                    iterator.to_string(),
+                    params.len(),
+                    arg_nodes.len()
+                ),
+            )
+            .emit(db);
+        return Type::Unknown;
+    }
+
+    let invalid = |arg: &StaticArgNode, param: &str, kind: StaticParamKind| {
+        Diagnostic::new(Level::Error, "invalid static argument")
+            .with_attachment(
+                Span::of_node(db, arg.syntax()),
+                format!("expected a {kind} for the static parameter {param:?}"),
+            )
+            .emit(db);
+    };
+
+    // Sizes, and the bounds of `boolred`, are constant integers
+    let mut consts = vec![];
+    let const_args = match iterator {
+        ArrayIterator::BoolRed => &arg_nodes[..],
+        _ => &arg_nodes[1..],
+    };
+    let const_params = &params[params.len() - const_args.len()..];
+    for (arg, param) in const_args.iter().zip(const_params) {
+        let value = arg
+            .expression_node()
+            .filter(|_| arg.predef_op().is_none() && arg.type_node().is_none())
+            .and_then(|expr| Option::clone(&eval_const_node(db, expr, in_node.clone())));
+        match value {
+            Some(ConstValue::Integer(i)) if i >= 0 => consts.push(i as usize),
+            _ => {
+                invalid(arg, param, StaticParamKind::Const);
+                return Type::Unknown;
+            }
+        }
+    }
+    let size = consts[consts.len() - 1];
+    let array = |elem: Type| Type::Array {
+        elem: Box::new(elem),
+        size,
+    };
+
+    if iterator == ArrayIterator::BoolRed {
+        return Type::Function {
+            args: vec![array(Type::Boolean)],
+            ret: vec![Type::Boolean],
+        };
+    }
+
+    let Some((node_params, node_returns)) =
+        iterated_node_type(db, &arg_nodes[0], &in_node, operand)
+    else {
+        invalid(&arg_nodes[0], params[0], StaticParamKind::Node);
+        return Type::Unknown;
+    };
+
+    if iterator.accumulates() {
+        let shape_is_valid = match (node_params.first(), node_returns.first()) {
+            (Some(param), Some(ret)) => {
+                compatible(param, ret)
+                    && (iterator != ArrayIterator::Red || node_returns.len() == 1)
+                    && (iterator != ArrayIterator::Fill || node_params.len() == 1)
+            }
+            _ => false,
+        };
+
+        if !shape_is_valid {
+            Diagnostic::new(Level::Error, "invalid node for iterator")
+                .with_attachment(
+                    Span::of_node(db, arg_nodes[0].syntax()),
+                    format!(
+                        "{:?} expects a node whose first parameter and return value have the same type",
+                        iterator.to_string()
+                    ),
+                )
+                .emit(db);
+            return Type::Unknown;
+        }
+
+        let accumulated = |types: Vec<Type>| {
+            let mut types = types.into_iter();
+            types.next().into_iter().chain(types.map(array)).collect()
+        };
+        return Type::Function {
+            args: accumulated(node_params),
+            ret: accumulated(node_returns),
+        };
+    }
+
+    Type::Function {
+        args: node_params.into_iter().map(array).collect(),
+        ret: node_returns.into_iter().map(array).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::name_resolution::find_node;
+    use crate::node_state::is_node_stateful;
+    use crate::types::type_check_query;
+
+    #[test]
+    fn iterator_calls() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "function add(a, b : int) returns (c : int);
+             let
+               c = a + b;
+             tel
+
+             node count(acc : int; x : bool) returns (next : int; y : int);
+             let
+               next = acc + (if x then 1 else 0);
+               y = 0 -> pre acc;
+             tel
+
+             function f(a, b : int^4; bs : bool^4) returns (c : int^4; d : int; e : bool; g : int^4);
+             let
+               c = map<<add, 4>>(a, b);
+               d = red<<+, 4>>(0, a);
+               e = boolred<<1, 2, 4>>(bs);
+               g = map<<+; 4>>(a, bs);
+             tel
+
+             node h(bs : bool^4) returns (n : int; ys : int^4);
+             let
+               n, ys = fillred<<count, 4>>(0, bs);
+             tel
+
+             function wrong(a : int^4) returns (x : int);
+             let
+               x = red<<add, 3, 4>>(0, a);
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let f = Option::clone(&find_node(&db, "f".into())).unwrap();
+        assert!(!*is_node_stateful(&db, f.clone()));
+        let h = Option::clone(&find_node(&db, "h".into())).unwrap();
+        assert!(*is_node_stateful(&db, h.clone()));
+        assert!(type_check_query(&db, h).is_function());
+
+        let mut messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            [
+                "\"red\" expects 2 static arguments but 3 were supplied",
+                "expected int^4, found bool^4",
+            ]
+        );
+    }
+
+    #[test]
+    fn fillred_type() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "function stage(acc : int; x : bool) returns (next : int; y : real);
+             let
+               next = acc;
+               y = 0.0;
+             tel
+
+             function n(bs : bool^3) returns (k : int; ys : real^3);
+             let
+               k, ys = fillred<<stage, 3>>(0, bs);
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let root = crate::parsed_files(&db)[0].clone();
+        let args = AstNode::syntax(&*root)
+            .descendants()
+            .find_map(StaticArgsNode::cast)
+            .unwrap();
+        let n = Option::clone(&find_node(&db, "n".into()));
+        let array = |elem, size| Type::Array {
+            elem: Box::new(elem),
+            size,
+        };
+        assert_eq!(
+            *iterator_type(&db, args, n, Type::Unknown),
+            Type::Function {
+                args: vec![Type::Integer, array(Type::Boolean, 3)],
+                ret: vec![Type::Integer, array(Type::Real, 3)],
+            }
+        );
+    }
+}
@@ -2,19 +2,35 @@
 //!
 //! It is built around [yeter].
 
+pub mod causality;
 pub mod checks;
+pub mod clocks;
+pub mod codegen;
+pub mod dataflow;
 pub mod diagnostics;
 pub mod eval;
+pub mod format;
+pub mod generics;
+pub mod initialization;
+pub mod interpreter;
+pub mod iterators;
 pub mod name_resolution;
 pub mod node_state;
-mod types;
+pub mod rif;
+pub mod types;
 
 use crate::{
     diagnostics::{Diagnostic, Level, Span},
     types::type_check_query,
 };
-use rustre_parser::ast::{Ident, NodeNode, NodeProfileNode, ParamsNode, Root, TypedIdsNode};
-use std::path::PathBuf;
+use rustre_parser::ast::{
+    AstNode, AstToken, ExternalNodeDeclNode, Ident, IncludeStatement, NodeNode, NodeProfileNode,
+    OneTypeDeclNode, ParamsNode, Root, TypedIdsNode,
+};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use yeter::Database;
 
@@ -36,6 +52,23 @@ impl SourceFile {
     pub fn new(path: PathBuf, text: String) -> SourceFile {
         SourceFile { path, text }
     }
+
+    /// Identifies a version of the file, from its contents
+    pub fn revision(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.text.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// A file that was explicitly given to the compiler, as opposed to files that are loaded because
+/// they are `include`d by another one
+#[derive(Clone, Hash)]
+pub enum RootFile {
+    /// File that is read from the disk when the program is loaded
+    Path(PathBuf),
+    /// In-memory source file
+    Contents(SourceFile),
 }
 
 #[derive(Clone, Debug, Hash)]
@@ -45,6 +78,52 @@ pub struct Signature {
     pub return_params: Vec<TypedIdsNode>,
 }
 
+impl Signature {
+    /// Lists the parameters declared by a node profile
+    pub fn of_profile(name: Option<Ident>, profile: Option<NodeProfileNode>) -> Signature {
+        let get_params = |f: fn(&NodeProfileNode) -> Option<ParamsNode>| {
+            profile
+                .clone()
+                .and_then(|sig| f(&sig))
+                .iter()
+                .flat_map(|p| p.all_var_decl_node())
+                .flat_map(|v| v.all_typed_ids_node())
+                .collect::<Vec<_>>()
+        };
+
+        Signature {
+            name,
+            params: get_params(NodeProfileNode::params),
+            return_params: get_params(NodeProfileNode::return_params),
+        }
+    }
+
+    /// Resolves the types of the parameters, in the scope of the node where they are declared
+    pub fn typed(&self, db: &Database, node: Option<NodeNode>) -> TypedSignature {
+        let get_params = |params: &[TypedIdsNode]| {
+            params
+                .iter()
+                .flat_map(|group| {
+                    let ty = group
+                        .type_node()
+                        .map(|t| types::type_of_ast_type(db, node.clone(), t))
+                        .unwrap_or_default();
+
+                    group
+                        .all_ident()
+                        .zip(std::iter::repeat(ty.as_ref().clone()))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        TypedSignature {
+            name: self.name.clone(),
+            params: get_params(&self.params),
+            return_params: get_params(&self.return_params),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Hash)]
 pub struct TypedSignature {
     pub name: Option<Ident>,
@@ -55,12 +134,14 @@ pub struct TypedSignature {
 /// **Query**: Parses a given file
 #[yeter::query]
 pub fn parse_file(db: &Database, file: SourceFile) -> Root {
+    let revision = file.revision();
     let source = file.text;
 
     let (root, errors) = rustre_parser::parse(&source);
     for error in errors {
         let span = Span {
             file: file.path.clone(),
+            revision,
             start: error.span.start,
             end: error.span.end,
         };
@@ -73,9 +154,207 @@ pub fn parse_file(db: &Database, file: SourceFile) -> Root {
     root
 }
 
+/// Source files of the program, as given to the compiler
+#[derive(Clone, Default)]
+struct Sources {
+    /// Files that were explicitly given to the compiler
+    roots: Vec<RootFile>,
+    /// Contents of the root and included files that are read from the disk, by path, or the error
+    /// that prevented reading them
+    disk: BTreeMap<PathBuf, Result<String, String>>,
+}
+
+/// **Query**: Input query for the source files, set by [add_source_file], [add_source_contents] and
+/// [set_source_contents]
+#[yeter::query]
+fn sources_input(_db: &Database) -> Option<Sources>;
+
 /// **Query**: Returns a list of all directly and indirectly included files in the Lustre program
+///
+/// Included paths are resolved relatively to the directory of the including file. Each file is only
+/// loaded once, even if it is included several times, or if it is also a root file. Files that were
+/// given in memory are included with these contents rather than the ones on the disk. Unreadable
+/// files and include cycles are reported as diagnostics.
+///
+/// Files are read from the disk when they are added to the compiler, not by this query: it only
+/// depends on the contents of the files it loads, and is computed again as soon as one of them
+/// changes.
 #[yeter::query]
-pub fn files(_db: &Database) -> Option<Vec<SourceFile>>;
+pub fn files(db: &Database) -> Option<Vec<SourceFile>> {
+    let sources = sources(db);
+    if sources.roots.is_empty() {
+        return None;
+    }
+
+    let mut loader = IncludeLoader {
+        db,
+        files: Vec::new(),
+        loaded: HashSet::new(),
+        stack: Vec::new(),
+        contents: in_memory_files(&sources.roots),
+        disk: &sources.disk,
+    };
+
+    for root in sources.roots.iter() {
+        let path = match root {
+            RootFile::Path(path) => path,
+            RootFile::Contents(file) => &file.path,
+        };
+        if !path.as_os_str().is_empty() && loader.loaded.contains(&canonical(path)) {
+            continue;
+        }
+
+        match root {
+            RootFile::Path(path) => match &sources.disk[path] {
+                Ok(text) => loader.load(SourceFile::new(path.clone(), text.clone())),
+                Err(err) => {
+                    Diagnostic::new(
+                        Level::Error,
+                        format!("cannot read {}: {err}", path.display()),
+                    )
+                    .emit(db);
+                }
+            },
+            RootFile::Contents(file) => loader.load(file.clone()),
+        }
+    }
+
+    Some(loader.files)
+}
+
+/// **Query**: Returns the source files given to the compiler
+#[yeter::query]
+fn sources(db: &Database) -> Sources {
+    // Yéter may record spurious dependencies for a query that was just returned from its cache, and
+    // an input query is reset to `None` as soon as one of its dependencies changes. Only reading it
+    // as the very last step of another query ensures that it never gets any dependency (see the
+    // `root_files_after_check` test).
+    Option::clone(&sources_input(db)).unwrap_or_default()
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
+
+/// Files given in memory, by canonical path
+fn in_memory_files(roots: &[RootFile]) -> HashMap<PathBuf, SourceFile> {
+    let contents = roots.iter().filter_map(|root| match root {
+        RootFile::Path(_) => None,
+        RootFile::Contents(file) => Some((canonical(&file.path), file.clone())),
+    });
+    contents.collect()
+}
+
+/// Returns the path of an included file, relative to the directory of the including one
+fn include_path(from: &SourceFile, target: &str) -> PathBuf {
+    let dir = from.path.parent().unwrap_or(Path::new(""));
+    dir.join(target.trim_matches('"'))
+}
+
+/// Reads the root files and the files they include that are not given in memory from the disk
+///
+/// This is done outside of any query, as they can't tell when a file changes on the disk.
+fn read_from_disk(roots: &[RootFile]) -> BTreeMap<PathBuf, Result<String, String>> {
+    let contents = in_memory_files(roots);
+    let mut disk = BTreeMap::new();
+    let mut to_read = roots
+        .iter()
+        .filter_map(|root| match root {
+            RootFile::Path(path) => Some(path.clone()),
+            RootFile::Contents(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let mut to_parse = contents.values().cloned().collect::<Vec<_>>();
+
+    loop {
+        for path in to_read.drain(..) {
+            if disk.contains_key(&path) {
+                continue;
+            }
+
+            let text = std::fs::read_to_string(&path).map_err(|err| err.to_string());
+            if let Ok(text) = &text {
+                to_parse.push(SourceFile::new(path.clone(), text.clone()));
+            }
+            disk.insert(path, text);
+        }
+
+        let Some(file) = to_parse.pop() else {
+            return disk;
+        };
+        let (root, _) = rustre_parser::parse(&file.text);
+        let includes = root.all_include_statement().filter_map(|i| i.str());
+        to_read.extend(
+            includes
+                .map(|target| include_path(&file, target.text()))
+                .filter(|path| !contents.contains_key(&canonical(path))),
+        );
+    }
+}
+
+/// Recursively follows `include` statements, see [files][files()]
+struct IncludeLoader<'db> {
+    db: &'db Database,
+    files: Vec<SourceFile>,
+    /// Canonical paths of all the files that have been loaded so far
+    loaded: HashSet<PathBuf>,
+    /// Canonical paths of the files whose includes are being loaded, used to detect cycles
+    stack: Vec<PathBuf>,
+    /// Files given in memory, by canonical path
+    contents: HashMap<PathBuf, SourceFile>,
+    /// Files read from the disk, see [Sources::disk]
+    disk: &'db BTreeMap<PathBuf, Result<String, String>>,
+}
+
+impl<'db> IncludeLoader<'db> {
+    fn load(&mut self, file: SourceFile) {
+        let key = canonical(&file.path);
+        let root = parse_file(self.db, file.clone());
+
+        self.loaded.insert(key.clone());
+        self.files.push(file.clone());
+
+        self.stack.push(key);
+        for include in root.all_include_statement() {
+            self.include(&file, include);
+        }
+        self.stack.pop();
+    }
+
+    fn include(&mut self, from: &SourceFile, include: IncludeStatement) {
+        // A missing string is a syntax error, that has already been reported by the parser
+        let Some(target) = include.str() else {
+            return;
+        };
+
+        let path = include_path(from, target.text());
+        let target = target.text().trim_matches('"');
+        let key = canonical(&path);
+
+        if self.stack.contains(&key) {
+            let span = Span::in_file(from, include.syntax());
+
+            Diagnostic::new(Level::Error, format!("include cycle on {target:?}"))
+                .with_attachment(span, "this file is already being included")
+                .emit(self.db);
+        } else if self.loaded.contains(&key) {
+            // Each file is only loaded once
+        } else if let Some(file) = self.contents.get(&key).cloned() {
+            self.load(file);
+        } else {
+            match &self.disk[&path] {
+                Ok(text) => self.load(SourceFile::new(path, text.clone())),
+                Err(err) => {
+                    let span = Span::in_file(from, include.syntax());
+
+                    Diagnostic::new(Level::Error, format!("cannot include {target:?}"))
+                        .with_attachment(span, format!("{}: {err}", path.display()))
+                        .emit(self.db);
+                }
+            }
+        }
+    }
+}
 
 #[yeter::query]
 fn parsed_files(db: &Database) -> Vec<Rc<Root>> {
@@ -90,51 +369,64 @@ fn parsed_files(db: &Database) -> Vec<Rc<Root>> {
     }
 }
 
+/// **Query:** Lists the parameters of a node
+///
+/// Node aliases (`node a = b<<...>>;`) have the parameters of the node they refer to.
 #[yeter::query]
-pub fn get_signature(_db: &Database, node: NodeNode) -> Signature {
-    let sig = node.node_profile_node();
-
-    let get_params = |f: fn(&NodeProfileNode) -> Option<ParamsNode>| {
-        sig.clone()
-            .and_then(|sig| f(&sig))
-            .and_then(|p| p.all_var_decl_node().next())
-            .iter()
-            .flat_map(|v| v.all_typed_ids_node())
-            .collect::<Vec<_>>()
-    };
+pub fn get_signature(db: &Database, node: NodeNode) -> Signature {
+    let name = node.id_node().and_then(|id| id.ident());
 
-    Signature {
-        name: node.id_node().and_then(|id| id.ident()),
-        params: get_params(NodeProfileNode::params),
-        return_params: get_params(NodeProfileNode::return_params),
+    match generics::aliased_node(db, &node) {
+        Some(target) => Signature {
+            name,
+            ..Signature::clone(&get_signature(db, target))
+        },
+        None => Signature::of_profile(name, node.node_profile_node()),
     }
 }
 
+/// **Query:** Resolves the types of the parameters of a node
+///
+/// Instances of generic nodes have their static arguments substituted.
 #[yeter::query]
 pub fn get_typed_signature(db: &Database, node: NodeNode) -> TypedSignature {
-    let sig = get_signature(db, node);
+    let name = node.id_node().and_then(|id| id.ident());
+    let Some(target) = generics::aliased_node(db, &node) else {
+        return get_signature(db, node.clone()).typed(db, Some(node));
+    };
 
-    let get_params = |params: &[TypedIdsNode]| {
-        params
-            .iter()
-            .flat_map(|group| {
-                let ty = group
-                    .type_node()
-                    .map(|t| types::type_of_ast_type(db, None, t))
-                    .unwrap_or_default();
-
-                group
-                    .all_ident()
-                    .zip(std::iter::repeat(ty.as_ref().clone()))
-            })
-            .collect::<Vec<_>>()
+    let static_args = node
+        .effective_node_node()
+        .and_then(|e| e.static_args_node());
+    let instance = static_args.map(|args| generics::instance_signature(db, args, None));
+    let sig = match instance.as_ref().map(|i| i.as_ref().as_ref()) {
+        Some(Some(sig)) => sig.clone(),
+        // Invalid static arguments have been reported, the parameters of the instance are unknown
+        Some(None) => {
+            let sig = get_typed_signature(db, target);
+            let forget = |params: &[(Ident, types::Type)]| {
+                params
+                    .iter()
+                    .map(|(id, _)| (id.clone(), types::Type::Unknown))
+                    .collect()
+            };
+            TypedSignature {
+                name: None,
+                params: forget(&sig.params),
+                return_params: forget(&sig.return_params),
+            }
+        }
+        None => TypedSignature::clone(&get_typed_signature(db, target)),
     };
 
-    TypedSignature {
-        name: sig.name.clone(),
-        params: get_params(&sig.params),
-        return_params: get_params(&sig.return_params),
-    }
+    TypedSignature { name, ..sig }
+}
+
+/// **Query:** Resolves the types of the parameters of an extern node
+#[yeter::query]
+pub fn get_extern_signature(db: &Database, node: ExternalNodeDeclNode) -> TypedSignature {
+    let name = node.id_node().and_then(|id| id.ident());
+    Signature::of_profile(name, node.node_profile_node()).typed(db, None)
 }
 
 /// **Query:** Global program check
@@ -142,39 +434,145 @@ pub fn get_typed_signature(db: &Database, node: NodeNode) -> TypedSignature {
 pub fn check(db: &Database) {
     let files = parsed_files(db);
     for file in files.as_slice() {
-        for node in file.all_node_node() {
+        for package in file.all_package_decl_node() {
+            checks::check_package(db, package);
+        }
+
+        for model in file.all_model_decl_node() {
+            checks::check_model(db, model);
+        }
+
+        for instance in file.all_package_alias_node() {
+            generics::check_package_instance(db, instance);
+        }
+
+        for decl in program_type_decls(file) {
+            checks::check_type_decl(db, decl);
+        }
+
+        for node in program_extern_nodes(file) {
+            let _ = get_extern_signature(db, node);
+        }
+
+        for node in program_nodes(file) {
             let _ = get_typed_signature(db, node.clone());
 
             checks::check_arity(db, node.clone());
 
+            checks::check_definitions(db, node.clone());
+
+            causality::check_causality(db, node.clone());
+
             let _ = type_check_query(db, node.clone());
 
-            node_state::check_node_function_state(db, node);
+            clocks::check_clocks(db, node.clone());
+
+            node_state::check_node_function_state(db, node.clone());
+
+            node_state::check_unsafe_calls(db, node.clone());
+
+            initialization::check_initialization(db, node);
         }
     }
 }
 
+/// Lists the type declarations of a file, including the ones of packages and models
+pub fn program_type_decls(root: &Root) -> impl Iterator<Item = OneTypeDeclNode> {
+    let packages = root
+        .all_package_decl_node()
+        .filter_map(|p| p.package_decl_body());
+    let models = root
+        .all_model_decl_node()
+        .filter_map(|m| m.package_decl_body());
+    let bodies = packages.chain(models);
+    root.all_type_decl_node()
+        .chain(bodies.flat_map(|body| body.all_type_decl_node()))
+        .flat_map(|decl| decl.all_one_type_decl_node())
+}
+
+/// Lists the extern nodes of a file, including the ones declared in packages and models
+pub fn program_extern_nodes(root: &Root) -> impl Iterator<Item = ExternalNodeDeclNode> {
+    let packages = root
+        .all_package_decl_node()
+        .filter_map(|p| p.package_decl_body());
+    let models = root
+        .all_model_decl_node()
+        .filter_map(|m| m.package_decl_body());
+    let bodies = packages.chain(models);
+    root.all_external_node_decl_node()
+        .chain(bodies.flat_map(|body| body.all_external_node_decl_node()))
+}
+
+/// Lists the nodes of a file, including the ones declared in packages and models
+pub fn program_nodes(root: &Root) -> impl Iterator<Item = NodeNode> {
+    let packages = root
+        .all_package_decl_node()
+        .filter_map(|p| p.package_decl_body());
+    let models = root
+        .all_model_decl_node()
+        .filter_map(|m| m.package_decl_body());
+    let bodies = packages.chain(models);
+    root.all_node_node()
+        .chain(bodies.flat_map(|body| body.all_node_node()))
+}
+
 /// Adds a source file to the list of files that are known by the compiler
+///
+/// The file, and the ones it includes, are read from the disk right away: changes that are made to
+/// them afterwards are not seen by the compiler.
 pub fn add_source_file(db: &Database, path: PathBuf) {
-    let contents = std::fs::read_to_string(&path).unwrap(); // TODO: report the error
-    let file = SourceFile::new(path, contents);
-    let files = files(db);
-    let mut files = Option::clone(&files).unwrap_or_default();
-    files.push(file);
-    db.set::<files>((), Some(files));
+    add_root_file(db, RootFile::Path(path));
 }
 
 pub fn add_source_contents(db: &mut Database, contents: String) {
     let file = SourceFile::new(PathBuf::new(), contents);
-    let files = files(db);
-    let mut files = Option::clone(&files).unwrap_or_default();
+    add_root_file(db, RootFile::Contents(file));
+}
+
+/// Sets the contents of a source file, for instance while it is being edited
+///
+/// They replace the ones that were previously given for this path, or the file on the disk if it
+/// was added with [add_source_file]. Otherwise, the file is added to the program.
+pub fn set_source_contents(db: &Database, path: PathBuf, contents: String) {
+    let file = SourceFile::new(path, contents);
+    let mut files = sources(db).roots.clone();
+
+    let existing = files.iter_mut().find(|root| match root {
+        RootFile::Path(p) => *p == file.path,
+        RootFile::Contents(f) => f.path == file.path,
+    });
+    match existing {
+        Some(existing) => *existing = RootFile::Contents(file),
+        None => files.push(RootFile::Contents(file)),
+    }
+
+    set_root_files(db, files);
+}
+
+fn add_root_file(db: &Database, file: RootFile) {
+    let mut files = sources(db).roots.clone();
     files.push(file);
-    db.set::<files>((), Some(files));
+    set_root_files(db, files);
+}
+
+fn set_root_files(db: &Database, roots: Vec<RootFile>) {
+    let sources = Some(Sources {
+        disk: read_from_disk(&roots),
+        roots,
+    });
+
+    // Yéter gives a query that is computed again the version of its newest dependency plus one,
+    // while setting an input only increments its version: [sources] would still be considered up to
+    // date if the input was only set once after it has been computed again.
+    db.set::<sources_input>((), sources.clone());
+    db.set::<sources_input>((), sources);
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
+    use crate::diagnostics::{file_for_root, Diagnostic};
+    use rustre_parser::ast::AstNode;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn parse_query() {
@@ -182,9 +580,135 @@ mod tests {
         super::add_source_file(&driver, Path::new("../tests/stable.lus").to_owned());
         let files = super::files(&driver);
         let files = files.as_ref().as_deref().unwrap_or_default();
-        for file in files {
-            let ast = super::parse_file(&driver, file.clone());
-            assert_eq!(ast.all_include_statement().count(), 1);
+        let ast = super::parse_file(&driver, files[0].clone());
+        assert_eq!(ast.all_include_statement().count(), 1);
+    }
+
+    #[test]
+    fn transitive_includes() {
+        let driver = super::driver();
+        super::add_source_file(&driver, Path::new("../tests/include.lus").to_owned());
+        let files = super::files(&driver);
+        let files = files.as_ref().as_deref().unwrap_or_default();
+        let paths = files.iter().map(|f| f.path.as_path()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                Path::new("../tests/include.lus"),
+                Path::new("../tests/test.lus"),
+                Path::new("../tests/access.lus"),
+            ]
+        );
+        assert!(driver.effect::<Diagnostic>().is_empty());
+    }
+
+    #[test]
+    fn spans_in_included_file() {
+        let driver = super::driver();
+        super::add_source_file(&driver, Path::new("../tests/include.lus").to_owned());
+        super::check(&driver);
+
+        let test_lus = std::fs::read_to_string("../tests/test.lus").unwrap();
+        let spans = driver
+            .effect::<Diagnostic>()
+            .into_iter()
+            .flat_map(|d| d.attachments)
+            .map(|(span, _)| span)
+            .filter(|span| span.file == Path::new("../tests/test.lus"))
+            .collect::<Vec<_>>();
+
+        assert!(spans.iter().any(|s| &test_lus[s.start..s.end] == "12.6"));
+    }
+
+    #[test]
+    fn several_root_files() {
+        let driver = super::driver();
+        super::add_source_file(&driver, Path::new("../tests/stable.lus").to_owned());
+        super::add_source_file(&driver, Path::new("../tests/adder.lus").to_owned());
+
+        let files = super::files(&driver);
+        let files = files.as_ref().as_deref().unwrap_or_default();
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().any(|f| f.path.ends_with("adder.lus")));
+    }
+
+    #[test]
+    fn files_with_identical_text() {
+        let driver = super::driver();
+        let text = "node n(x: int) returns (y: int); let y = x; tel";
+        for name in ["first.lus", "second.lus"] {
+            super::set_source_contents(&driver, PathBuf::from(name), text.to_owned());
         }
+
+        let files = super::files(&driver);
+        let second = files.as_ref().as_ref().unwrap()[1].clone();
+        let root = super::parse_file(&driver, second.clone()).syntax().clone();
+        let bound = file_for_root(&driver, root);
+        assert_eq!(bound.as_ref().as_ref().map(|f| &f.path), Some(&second.path));
+
+        // A tree parsed from this text could come from either file
+        let outdated = rustre_parser::parse(text).0.syntax().clone();
+        assert!(file_for_root(&driver, outdated).is_none());
+    }
+
+    #[test]
+    fn root_files_after_check() {
+        let driver = super::driver();
+        super::add_source_file(&driver, Path::new("../tests/include.lus").to_owned());
+        super::check(&driver);
+
+        let files = super::files(&driver);
+        assert_eq!(files.as_ref().as_deref().unwrap_or_default().len(), 3);
+    }
+
+    #[test]
+    fn missing_include() {
+        let mut driver = super::driver();
+        super::add_source_contents(&mut driver, "include \"missing.lus\"\n".into());
+        let files = super::files(&driver);
+        assert_eq!(files.as_ref().as_deref().unwrap_or_default().len(), 1);
+
+        let diagnostics = driver.effect::<Diagnostic>();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "cannot include \"missing.lus\"");
+    }
+
+    #[test]
+    fn edited_contents() {
+        let driver = super::driver();
+        let path = Path::new("../tests/include.lus").to_owned();
+        super::add_source_file(&driver, path.clone());
+        super::set_source_contents(&driver, path.clone(), "include \"test.lus\"\n".into());
+
+        let files = super::files(&driver);
+        let files = files.as_ref().as_deref().unwrap_or_default();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].text, "include \"test.lus\"\n");
+        assert!(files[1].path.ends_with("test.lus"));
+    }
+
+    #[test]
+    fn included_files_are_read_when_added() {
+        let dir = std::env::temp_dir().join(format!("rustre-include-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("main.lus"), "include \"lib.lus\"\n").unwrap();
+        std::fs::write(dir.join("lib.lus"), "const a = 1;\n").unwrap();
+
+        let driver = super::driver();
+        super::add_source_file(&driver, dir.join("main.lus"));
+        std::fs::write(dir.join("lib.lus"), "const a = 2;\n").unwrap();
+
+        let files = super::files(&driver);
+        let files = files.as_ref().as_deref().unwrap_or_default();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[1].text, "const a = 1;\n");
+
+        // The new contents are only seen once they are given to the compiler
+        super::add_source_file(&driver, dir.join("lib.lus"));
+        let files = super::files(&driver);
+        let files = files.as_ref().as_deref().unwrap_or_default();
+        assert_eq!(files[1].text, "const a = 2;\n");
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
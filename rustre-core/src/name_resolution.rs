@@ -1,35 +1,345 @@
+//! Name resolution
+//!
+//! Declarations are either at the top level of a file, or in the body of a package. A name is
+//! looked up in the package where it is used, then among the items provided by the packages it
+//! `uses`, and finally at the top level of all the files. Qualified names (`P::x`) are only looked
+//! up among the items provided by `P`, unless they are used within `P` itself.
+//!
+//! Instances of models (`package P = M(...)`) expose the declarations of their model.
+
 use rustre_parser::ast::*;
+use rustre_parser::SyntaxNode;
 use yeter::Database;
 
-/// **Query** Resolves a type declaration by name
+/// Declarations of a package, or of the top level of a file
+struct Scope {
+    /// [Root] or [PackageDeclBody]
+    decls: SyntaxNode,
+    /// Items that can be seen from outside of the package, `None` if they all can
+    provides: Option<ProvidesListNode>,
+    /// Instance through which the declarations of a model are seen
+    instance: Option<PackageAliasNode>,
+}
+
+impl Scope {
+    fn decls<N: AstNode>(&self) -> impl Iterator<Item = N> {
+        self.decls.children().filter_map(N::cast)
+    }
+
+    fn exposes(&self, name: &str) -> bool {
+        self.provides.as_ref().is_none_or(|provides| {
+            provides
+                .all_provides_node()
+                .any(|item| item.name().is_some_and(|n| n.text() == name))
+        })
+    }
+
+    /// Returns `true` if a constant, a type or a node is declared with this name
+    fn declares(&self, name: &str) -> bool {
+        let consts = self
+            .decls::<ConstantDeclNode>()
+            .flat_map(|c| c.all_one_constant_decl_node())
+            .flat_map(|c| c.all_id_node())
+            .filter_map(|id| id.name());
+        let types = self
+            .decls::<TypeDeclNode>()
+            .flat_map(|t| t.all_one_type_decl_node())
+            .filter_map(|t| t.ident());
+        let nodes = self
+            .decls::<NodeNode>()
+            .filter_map(|n| n.id_node())
+            .chain(
+                self.decls::<ExternalNodeDeclNode>()
+                    .filter_map(|n| n.id_node()),
+            )
+            .filter_map(|id| id.name());
+
+        consts.chain(types).chain(nodes).any(|n| n.text() == name)
+    }
+}
+
+/// Package or model, in which declarations are grouped
+enum Container {
+    Package(PackageDeclNode),
+    Model(ModelDeclNode),
+}
+
+impl Container {
+    fn enclosing(syntax: &SyntaxNode) -> Option<Container> {
+        syntax
+            .ancestors()
+            .find_map(|s| match ModelDeclNode::cast(s.clone()) {
+                Some(model) => Some(Container::Model(model)),
+                None => PackageDeclNode::cast(s).map(Container::Package),
+            })
+    }
+
+    fn name(&self) -> Option<Ident> {
+        match self {
+            Container::Package(p) => p.id_node()?.name(),
+            Container::Model(m) => m.id_node()?.name(),
+        }
+    }
+
+    fn uses(&self) -> Option<UsesNode> {
+        match self {
+            Container::Package(p) => p.uses_node(),
+            Container::Model(m) => m.uses_node(),
+        }
+    }
+
+    fn scope(&self, public: bool, instance: Option<PackageAliasNode>) -> Option<Scope> {
+        let (body, provides) = match self {
+            Container::Package(p) => (p.package_decl_body()?, p.provides_list_node()),
+            Container::Model(m) => (m.package_decl_body()?, m.provides_list_node()),
+        };
+
+        Some(Scope {
+            decls: body.syntax().clone(),
+            provides: provides.filter(|_| public),
+            instance,
+        })
+    }
+}
+
+/// Returns the package in which a part of the program is declared, if any
+pub fn enclosing_package(syntax: &SyntaxNode) -> Option<PackageDeclNode> {
+    syntax.ancestors().find_map(PackageDeclNode::cast)
+}
+
+/// Returns the model in which a part of the program is declared, if any
+pub fn enclosing_model(syntax: &SyntaxNode) -> Option<ModelDeclNode> {
+    syntax.ancestors().find_map(ModelDeclNode::cast)
+}
+
+/// Package qualifier of an identifier, as `P` for `x` in `P::x`
+fn qualifier(ident: &Ident) -> Option<Ident> {
+    let id = IdNode::cast(ident.syntax().parent()?)?;
+    id.package().filter(|package| package != ident)
+}
+
+/// **Query:** Finds a package by name
 #[yeter::query]
-pub fn resolve_type_decl(db: &Database, name: IdNode) -> Option<OneTypeDeclNode> {
-    let name = name.ident()?;
-    let name = name.text();
+pub fn find_package(db: &Database, name: String) -> Option<PackageDeclNode> {
+    super::parsed_files(db)
+        .iter()
+        .flat_map(|root| root.all_package_decl_node())
+        .find(|package| {
+            package
+                .id_node()
+                .and_then(|id| id.name())
+                .is_some_and(|n| n.text() == name)
+        })
+}
+
+/// **Query:** Finds a model by name
+#[yeter::query]
+pub fn find_model(db: &Database, name: String) -> Option<ModelDeclNode> {
+    super::parsed_files(db)
+        .iter()
+        .flat_map(|root| root.all_model_decl_node())
+        .find(|model| {
+            model
+                .id_node()
+                .and_then(|id| id.name())
+                .is_some_and(|n| n.text() == name)
+        })
+}
+
+/// **Query:** Finds an instance of a model (`package P = M(...)`) by name
+#[yeter::query]
+pub fn find_package_instance(db: &Database, name: String) -> Option<PackageAliasNode> {
+    super::parsed_files(db)
+        .iter()
+        .flat_map(|root| root.all_package_alias_node())
+        .find(|instance| {
+            instance
+                .name()
+                .and_then(|id| id.name())
+                .is_some_and(|n| n.text() == name)
+        })
+}
+
+/// Returns the model of which a package is an instance
+pub fn instantiated_model(db: &Database, instance: &PackageAliasNode) -> Option<ModelDeclNode> {
+    let name = instance.model()?.name()?;
+    Option::clone(&find_model(db, name.text().into()))
+}
+
+/// Returns the items provided by a package or an instance of a model
+fn package_scope(db: &Database, name: &str) -> Option<Scope> {
+    if let Some(package) = Option::clone(&find_package(db, name.into())) {
+        return Container::Package(package).scope(true, None);
+    }
+
+    let instance = Option::clone(&find_package_instance(db, name.into()))?;
+    let model = instantiated_model(db, &instance)?;
+    Container::Model(model).scope(true, Some(instance))
+}
+
+/// Lists the scopes in which a name that is used in `from` is looked up, in order
+fn scopes(db: &Database, from: &SyntaxNode, package: Option<Ident>) -> Vec<Scope> {
+    let current = Container::enclosing(from);
+
+    if let Some(package) = package {
+        let is_current = current
+            .as_ref()
+            .and_then(Container::name)
+            .is_some_and(|n| n.text() == package.text());
+        let scope = match &current {
+            Some(current) if is_current => current.scope(false, None),
+            _ => package_scope(db, package.text()),
+        };
+        return scope.into_iter().collect();
+    }
+
+    let mut scopes = vec![];
+    if let Some(current) = &current {
+        scopes.extend(current.scope(false, None));
+
+        let uses = current.uses().into_iter().flat_map(|u| u.all_id_node());
+        for used in uses.filter_map(|id| id.name()) {
+            scopes.extend(package_scope(db, used.text()));
+        }
+    }
 
     let files = super::parsed_files(db);
+    scopes.extend(files.iter().map(|root| Scope {
+        decls: root.syntax().clone(),
+        provides: None,
+        instance: None,
+    }));
+    scopes
+}
+
+/// **Query:** Returns the instance of a model through which a name is resolved, if any
+///
+/// The declarations of a model are shared by all its instances, this tells which one of them is
+/// referred to, and thus which static arguments apply.
+#[yeter::query]
+pub fn resolve_instance(db: &Database, name: IdNode) -> Option<PackageAliasNode> {
+    let ident = name.name()?;
+    let ident = ident.text();
+
+    scopes(db, name.syntax(), name.package())
+        .into_iter()
+        .find(|scope| scope.exposes(ident) && scope.declares(ident))?
+        .instance
+}
+
+/// **Query** Resolves a type declaration by name
+#[yeter::query]
+pub fn resolve_type_decl(db: &Database, name: IdNode) -> Option<OneTypeDeclNode> {
+    let ident = name.name()?;
+    let ident = ident.text();
 
-    files
+    scopes(db, name.syntax(), name.package())
         .iter()
-        .flat_map(|root| root.all_type_decl_node())
+        .filter(|scope| scope.exposes(ident))
+        .flat_map(|scope| scope.decls::<TypeDeclNode>())
         .flat_map(|decl| decl.all_one_type_decl_node())
-        .find(|decl| matches!(decl.ident(), Some(n) if n.text() == name))
+        .find(|decl| matches!(decl.ident(), Some(n) if n.text() == ident))
 }
 
+/// **Query:** Resolves an enumeration constructor, returning the declaration of its type
+///
+/// Constructors are visible wherever their type is, and may be qualified by the package of their
+/// type (`Pack::Ctor`).
+#[yeter::query]
+pub fn resolve_enum_constructor(db: &Database, name: IdNode) -> Option<OneTypeDeclNode> {
+    let ident = name.name()?;
+    let ident = ident.text();
+
+    scopes(db, name.syntax(), name.package())
+        .iter()
+        .flat_map(|scope| {
+            scope
+                .decls::<TypeDeclNode>()
+                .flat_map(|decl| decl.all_one_type_decl_node())
+                .filter(|decl| decl.ident().is_some_and(|n| scope.exposes(n.text())))
+        })
+        .find(|decl| {
+            decl.enum_decl_node()
+                .is_some_and(|e| e.all_ident().any(|ctor| ctor.text() == ident))
+        })
+}
+
+/// **Query:** Finds a node by name, at the top level of the program or in a package (`P::n`)
 #[yeter::query]
 pub fn find_node(db: &Database, node_name: String) -> Option<NodeNode> {
-    for file in super::parsed_files(db).as_slice() {
-        for node in file.all_node_node() {
-            if node.id_node()?.ident()?.text() == node_name {
-                return Some(node);
-            }
-        }
+    if let Some((package, name)) = node_name.split_once("::") {
+        return package_scope(db, package)?
+            .decls::<NodeNode>()
+            .find(|node| has_name(node.id_node(), name));
+    }
+
+    let files = super::parsed_files(db);
+    let node = files
+        .iter()
+        .flat_map(|file| file.all_node_node())
+        .find(|node| has_name(node.id_node(), &node_name));
+    node
+}
+
+/// **Query:** Finds an extern node by name, at the top level of the program or in a package
+/// (`P::n`)
+#[yeter::query]
+pub fn find_extern_node(db: &Database, node_name: String) -> Option<ExternalNodeDeclNode> {
+    if let Some((package, name)) = node_name.split_once("::") {
+        return package_scope(db, package)?
+            .decls::<ExternalNodeDeclNode>()
+            .find(|node| has_name(node.id_node(), name));
+    }
+
+    let files = super::parsed_files(db);
+    let node = files
+        .iter()
+        .flat_map(|file| file.all_external_node_decl_node())
+        .find(|node| has_name(node.id_node(), &node_name));
+    node
+}
+
+fn has_name(id: Option<IdNode>, name: &str) -> bool {
+    matches!(id.and_then(|id| id.name()), Some(n) if n.text() == name)
+}
+
+/// **Query:** Resolves the node that is referred to by a name
+#[yeter::query]
+pub fn resolve_node(db: &Database, name: IdNode) -> Option<NodeNode> {
+    let ident = name.name()?;
+    let ident = ident.text();
+
+    scopes(db, name.syntax(), name.package())
+        .iter()
+        .filter(|scope| scope.exposes(ident))
+        .flat_map(|scope| scope.decls::<NodeNode>())
+        .find(|node| has_name(node.id_node(), ident))
+}
+
+/// **Query:** Resolves the extern node (`extern function f(...) returns (...);`) that is referred
+/// to by a name
+///
+/// Nodes declared with a body take precedence over extern nodes, see [resolve_node].
+#[yeter::query]
+pub fn resolve_extern_node(db: &Database, name: IdNode) -> Option<ExternalNodeDeclNode> {
+    if resolve_node(db, name.clone()).is_some() {
+        return None;
     }
 
-    None
+    let ident = name.name()?;
+    let ident = ident.text();
+
+    scopes(db, name.syntax(), name.package())
+        .iter()
+        .filter(|scope| scope.exposes(ident))
+        .flat_map(|scope| scope.decls::<ExternalNodeDeclNode>())
+        .find(|node| has_name(node.id_node(), ident))
 }
 
-// TODO handle packages correctly
+/// Name that is used in a node (or in a global declaration if `in_node` is `None`)
+///
+/// The scopes in which it is looked up depend on where `ident` is in the program. It may be the
+/// second part of a qualified name (`P::x`).
 #[derive(Clone, Debug, Hash)]
 pub struct NameResolveQuery {
     pub ident: Ident,
@@ -46,24 +356,58 @@ pub enum ResolvedRuntimeNode<Var, Input = Var> {
 /// **Query**
 #[yeter::query]
 pub fn resolve_const_node(db: &Database, query: NameResolveQuery) -> Option<OneConstantDeclNode> {
+    let qualifier = qualifier(&query.ident);
+    let is_local = qualifier.is_none();
     let local_scope = query
         .in_node
         .iter()
+        .filter(|_| is_local)
         .flat_map(|in_node| in_node.all_one_constant_decl_node());
 
     // TODO statics
 
-    let files = super::parsed_files(db);
-    let global_scope = files
+    let scopes = scopes(db, &query.ident.syntax().parent()?, qualifier);
+    let global_scope = scopes
         .iter()
-        .flat_map(|root| root.all_constant_decl_node())
+        .filter(|scope| scope.exposes(query.ident.text()))
+        .flat_map(|scope| scope.decls::<ConstantDeclNode>())
         .flat_map(|const_decl| const_decl.all_one_constant_decl_node());
 
-    local_scope.chain(global_scope).find(|one_const| {
+    let one_const = local_scope.chain(global_scope).find(|one_const| {
         one_const
             .all_id_node()
             .any(|i| i.ident().unwrap().text() == query.ident.text())
-    })
+    });
+    one_const
+}
+
+/// **Query:** Resolves a static parameter of the node in which a name is used
+#[yeter::query]
+pub fn resolve_static_param(_db: &Database, query: NameResolveQuery) -> Option<StaticParamNode> {
+    if qualifier(&query.ident).is_some() {
+        return None;
+    }
+
+    // The `needs` of a model are static parameters of all its declarations
+    let model = query
+        .ident
+        .syntax()
+        .parent()
+        .and_then(|p| enclosing_model(&p));
+    let needs = model.into_iter().flat_map(|m| m.all_static_param_node());
+
+    query
+        .in_node
+        .and_then(|n| n.static_params_node())
+        .into_iter()
+        .flat_map(|p| p.all_static_param_node())
+        .chain(needs)
+        .find(|param| {
+            param
+                .id_node()
+                .and_then(|i| i.ident())
+                .is_some_and(|i| i.text() == query.ident.text())
+        })
 }
 
 /// **Query**
@@ -81,7 +425,8 @@ pub fn resolve_runtime_node(
     db: &Database,
     query: NameResolveQuery,
 ) -> Option<ResolvedRuntimeNode<TypedIdsNode>> {
-    if let (ident, Some(in_node)) = (&query.ident, &query.in_node) {
+    let is_qualified = qualifier(&query.ident).is_some();
+    if let (ident, Some(in_node), false) = (&query.ident, &query.in_node, is_qualified) {
         let sig = super::get_signature(db, in_node.clone());
 
         let params_c = std::iter::repeat(ResolvedRuntimeNode::Param as fn(_) -> _);
@@ -94,7 +439,7 @@ pub fn resolve_runtime_node(
         let local_vars = query
             .in_node
             .iter()
-            .flat_map(|in_node| in_node.all_var_decl_node().next())
+            .flat_map(|in_node| in_node.all_var_decl_node())
             .flat_map(|var| var.all_typed_ids_node())
             .zip(local_vars_c);
 
@@ -114,3 +459,112 @@ pub fn resolve_runtime_node(
         .clone()
         .map(ResolvedRuntimeNode::Const)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::{Diagnostic, Level};
+
+    const PROGRAM: &str = "package Lib
+           provides
+             const zero : int;
+             type mode;
+             function incr(x : int) returns (y : int);
+         body
+           const zero : int = 0;
+           const secret : int = 42;
+           type mode = enum { On, Off };
+           function incr(x : int) returns (y : int);
+           let
+             y = bump(x);
+           tel
+           function bump(x : int) returns (y : int);
+           let
+             y = x + 1;
+           tel
+         end
+
+         package App
+           uses Lib;
+         body
+           function main(a : int) returns (b : int; m : mode; c : int; d : int);
+           let
+             b = incr(a) + zero;
+             m = Lib::On;
+             c = Lib::secret;
+             d = Lib::bump(a);
+           tel
+         end
+
+         function top(a : int) returns (b : int);
+         let
+           b = Lib::incr(a) + incr(a);
+         tel";
+
+    #[test]
+    fn qualified_names() {
+        let mut db = crate::driver();
+        crate::add_source_contents(&mut db, PROGRAM.into());
+
+        assert!(find_node(&db, "incr".into()).is_none());
+        let incr = Option::clone(&find_node(&db, "Lib::incr".into())).unwrap();
+        let package = enclosing_package(incr.syntax()).unwrap();
+        assert_eq!(package.id_node().unwrap().name().unwrap().text(), "Lib");
+        assert!(find_node(&db, "App::main".into()).is_some());
+    }
+
+    #[test]
+    fn provided_items() {
+        let mut db = crate::driver();
+        crate::add_source_contents(&mut db, PROGRAM.into());
+        crate::check(&db);
+
+        let mut errors = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        errors.sort();
+        assert_eq!(
+            errors,
+            [
+                "cannot find value \"Lib::secret\"",
+                "incompatible types",
+                "unknown node \"Lib::bump\"",
+                "unknown node \"incr\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn locals_of_several_sections() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node n(x : int) returns (y : int);
+             var a : int;
+             var b : int;
+             let
+               a = x;
+               b = a;
+               y = b;
+             tel"
+            .into(),
+        );
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let ident = node
+            .syntax()
+            .descendants_with_tokens()
+            .filter_map(|t| t.into_token().and_then(Ident::cast))
+            .find(|i| i.text() == "b")
+            .unwrap();
+
+        let query = NameResolveQuery {
+            ident,
+            in_node: Some(node),
+        };
+        let resolved = resolve_runtime_node(&db, query);
+        assert!(matches!(*resolved, Some(ResolvedRuntimeNode::Var(_))));
+    }
+}
@@ -14,7 +14,7 @@
 //!
 //! # Stateful expressions
 //!
-//! 4 kinds of Lustre expression require persisted memory between node invocations.
+//! 5 kinds of Lustre expression require persisted memory between node invocations.
 //!
 //!   * The `pre` unary operator: evaluates to the previous value it was applied to. It requires a
 //!     (nullable) "slot" of the same type as its operand.
@@ -25,14 +25,16 @@
 //!     then to the value of its second operand for the remaining ones. It only requires a boolean
 //!     value to be represented as it doesn't persist any Lustre data; it must just know if it is
 //!     in its first evaluation cycle.
+//!   * The `current` unary operator: evaluates to the last value its operand had when its clock
+//!     was active. It requires a (nullable) slot of the same type as its operand.
 //!   * Node call sites: each call site corresponds to an _instanciation_ of a node, with its own
 //!     memory. They have to be recursively accounted for.
 
 use crate::diagnostics::{Diagnostic, Level, Span};
 use rustre_parser::ast::expr_visitor::ExpressionWalker;
 use rustre_parser::ast::{
-    ArrowExpressionNode, AstToken, CallByPosExpressionNode, ExpressionNode, FbyExpressionNode,
-    NodeNode, PreExpressionNode,
+    ArrowExpressionNode, AstNode, AstToken, CallByPosExpressionNode, CurrentExpressionNode,
+    ExpressionNode, FbyExpressionNode, IdNode, NodeNode, PreExpressionNode,
 };
 use std::collections::HashSet;
 use yeter::Database;
@@ -66,24 +68,48 @@ impl<'db> ExpressionWalker for NodeStateWalker<'db> {
         self.push(ExpressionNode::ArrowExpressionNode(e));
     }
 
+    fn walk_current(&mut self, e: CurrentExpressionNode) {
+        self.push(ExpressionNode::CurrentExpressionNode(e));
+    }
+
     fn walk_call_by_pos(&mut self, e: CallByPosExpressionNode) {
-        if let Some(node_name) = e
-            .node_ref()
-            .and_then(|n| n.id_node())
-            .and_then(|i| i.ident())
-        {
-            let sub_node = Option::clone(&crate::name_resolution::find_node(
-                self.db,
-                node_name.text().into(),
-            ));
-
-            if matches!(sub_node, Some(sub_node) if *is_node_stateful(self.db, sub_node.clone())) {
-                self.push(ExpressionNode::CallByPosExpressionNode(e));
-            }
+        if is_call_stateful(self.db, &e) {
+            self.push(ExpressionNode::CallByPosExpressionNode(e));
         }
     }
 }
 
+/// Returns `true` if a call site instantiates a stateful node
+///
+/// Calls to iterators (`map<<f, 4>>(a)`) are stateful if the iterated node is.
+fn is_call_stateful(db: &Database, call: &CallByPosExpressionNode) -> bool {
+    let Some(node_name) = call.node_ref().and_then(|n| n.id_node()) else {
+        return false;
+    };
+
+    if crate::iterators::resolve_iterator(db, &node_name).is_some() {
+        let iterated = call
+            .static_args_node()
+            .and_then(|args| args.all_static_arg_node().next())
+            .and_then(|arg| crate::iterators::iterated_node_name(&arg));
+        return iterated.is_some_and(|name| is_callee_stateful(db, name));
+    }
+
+    is_callee_stateful(db, node_name)
+}
+
+fn is_callee_stateful(db: &Database, node_name: IdNode) -> bool {
+    let sub_node = Option::clone(&crate::name_resolution::resolve_node(db, node_name.clone()));
+    match sub_node {
+        Some(sub_node) => *is_node_stateful(db, sub_node),
+        // The body of extern nodes is unknown, they are trusted to be declared correctly
+        None => crate::name_resolution::resolve_extern_node(db, node_name)
+            .as_ref()
+            .as_ref()
+            .is_some_and(|ext| ext.is_node()),
+    }
+}
+
 /// **Query:** Returns a list of stateful expressions in a node
 #[yeter::query]
 pub fn stateful_expr_of_node(db: &Database, node: NodeNode) -> Vec<ExpressionNode> {
@@ -113,13 +139,84 @@ pub fn stateful_expr_of_node(db: &Database, node: NodeNode) -> Vec<ExpressionNod
 /// itself)
 #[yeter::query]
 pub fn is_node_stateful(db: &Database, node: NodeNode) -> bool {
+    if let Some(target) = crate::generics::aliased_node(db, &node) {
+        return *is_node_stateful(db, target);
+    }
+
     !stateful_expr_of_node(db, node).is_empty()
 }
 
+/// Collects the calls to `unsafe` nodes
+struct UnsafeCallWalker<'db> {
+    db: &'db Database,
+    collected: Vec<CallByPosExpressionNode>,
+}
+
+impl<'db> ExpressionWalker for UnsafeCallWalker<'db> {
+    fn walk_call_by_pos(&mut self, e: CallByPosExpressionNode) {
+        let Some(node_name) = e.node_ref().and_then(|n| n.id_node()) else {
+            return;
+        };
+
+        let sub_node = crate::name_resolution::resolve_node(self.db, node_name.clone());
+        let is_unsafe = match sub_node.as_ref() {
+            Some(sub_node) => sub_node.is_unsafe(),
+            None => crate::name_resolution::resolve_extern_node(self.db, node_name)
+                .as_ref()
+                .as_ref()
+                .is_some_and(|ext| ext.is_unsafe()),
+        };
+
+        if is_unsafe {
+            self.collected.push(e);
+        }
+    }
+}
+
+/// **Query:** Checks that `unsafe` nodes are only called by nodes that are `unsafe` themselves
+#[yeter::query]
+pub fn check_unsafe_calls(db: &Database, node: NodeNode) {
+    let (Some(body), false) = (node.body_node(), node.is_unsafe()) else {
+        return;
+    };
+
+    let mut walker = UnsafeCallWalker {
+        db,
+        collected: Default::default(),
+    };
+    body.all_equals_equation_node()
+        .flat_map(|e| e.expression_node())
+        .chain(
+            body.all_assert_equation_node()
+                .flat_map(|e| e.expression_node()),
+        )
+        .for_each(|e| {
+            walker.walk_expr(e);
+        });
+
+    for call in walker.collected {
+        let Some(callee) = call.node_ref() else {
+            continue;
+        };
+
+        Diagnostic::new(Level::Error, "call to an unsafe node")
+            .with_attachment(
+                Span::of_node(db, callee.syntax()),
+                "hint: declare the calling node as `unsafe`",
+            )
+            .emit(db);
+    }
+}
+
 /// **Query:** Checks the coherence between the use of the `function` or `node` keyword and the
 /// presence or absence of temporal state
 #[yeter::query]
 pub fn check_node_function_state(db: &Database, node: NodeNode) {
+    // The keyword of an alias is only checked on the node it refers to
+    if node.effective_node_node().is_some() {
+        return;
+    }
+
     let has_no_state = !*is_node_stateful(db, node.clone());
 
     if node.is_node() && has_no_state {
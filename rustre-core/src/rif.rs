@@ -0,0 +1,692 @@
+//! Reactive Input Format
+//!
+//! RIF is the textual format used by the Verimag tools (`lus2lic`, Lurette, rdbg, luciole...) to
+//! exchange simulation traces. This module reads and writes RIF streams for a given node, typing
+//! values according to its [TypedSignature].
+//!
+//! # Format
+//!
+//! A RIF stream is made of whitespace-separated values, and of directives which start with a `#`
+//! and go to the end of the line:
+//!
+//! ```text
+//! #inputs "x":int "reset":bool
+//! #outputs "y":real
+//! #step 1
+//! 12 f
+//! #outs 12.0
+//! #step 2
+//! 3 t #outs 0.0
+//! ```
+//!
+//! Arrays and structures are flattened: each of their elements (or fields) is written as a
+//! separate value, in order, and their type is declared as in Lustre (`"a":int^3`). Values of
+//! enumerated types are written as the names of their constructors, and their type is declared with
+//! its name.
+//!
+//! The values of the inputs of a cycle may span several lines, or share a line with the `#outs`
+//! directive. The `#inputs` and `#outputs` headers are optional when reading a stream, but they
+//! must match the signature of the node when present. Directives that aren't meaningful to a
+//! simulator are considered as comments.
+
+use crate::interpreter::Value;
+use crate::types::{ConstValue, Type};
+use crate::TypedSignature;
+use rustre_parser::ast::AstToken;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+
+/// Item of a RIF stream that is relevant to a simulator
+#[derive(Clone, Debug, PartialEq)]
+pub enum RifItem {
+    /// Values of the inputs for a new cycle
+    Inputs(Vec<ConstValue>),
+    /// Values of the outputs for the last cycle, as computed by the tool that produced the stream
+    Outputs(Vec<Value>),
+    /// The node should be put back in its initial state
+    Reset,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RifError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for RifError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for RifError {}
+
+/// Returns the name of a type in RIF headers
+pub fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Boolean | Type::Integer | Type::Real | Type::Enum { .. } | Type::Struct { .. } => {
+            Some(ty.to_string())
+        }
+        Type::Array { elem, size } => Some(format!("{}^{size}", type_name(elem)?)),
+        _ => None,
+    }
+}
+
+/// Number of values that a value of a given type is flattened to
+fn flat_len(ty: &Type) -> usize {
+    match ty {
+        Type::Array { elem, size } => size * flat_len(elem),
+        Type::Struct { fields, .. } => fields.iter().map(|(_, ty)| flat_len(ty)).sum(),
+        _ => 1,
+    }
+}
+
+/// Formats a value as a sequence of RIF values, `nil` being written for each of the values it is
+/// flattened to
+fn flatten(value: &Value, ty: &Type, values: &mut Vec<String>) {
+    match (value, ty) {
+        (None, _) => values.extend(std::iter::repeat_n("nil".into(), flat_len(ty))),
+        (Some(ConstValue::Array(elements)), Type::Array { elem, .. }) => {
+            for element in elements {
+                flatten(&Some(element.clone()), elem, values);
+            }
+        }
+        (Some(ConstValue::Struct(fields)), Type::Struct { fields: types, .. }) => {
+            for ((_, field), (_, ty)) in fields.iter().zip(types) {
+                flatten(&Some(field.clone()), ty, values);
+            }
+        }
+        (value, _) => values.push(format_value(value)),
+    }
+}
+
+/// Builds a value of a given type out of flattened RIF values
+fn unflatten<'a>(tokens: &mut impl Iterator<Item = &'a str>, ty: &Type) -> Option<ConstValue> {
+    match ty {
+        Type::Array { elem, size } => (0..*size)
+            .map(|_| unflatten(tokens, elem))
+            .collect::<Option<_>>()
+            .map(ConstValue::Array),
+        Type::Struct { fields, .. } => fields
+            .iter()
+            .map(|(name, ty)| Some((name.clone(), unflatten(tokens, ty)?)))
+            .collect::<Option<_>>()
+            .map(ConstValue::Struct),
+        _ => parse_value(tokens.next()?, ty),
+    }
+}
+
+/// Parses a value of a given type, as written in a RIF stream
+pub fn parse_value(text: &str, ty: &Type) -> Option<ConstValue> {
+    match ty {
+        Type::Boolean => match text {
+            "t" | "T" | "true" | "1" => Some(ConstValue::Boolean(true)),
+            "f" | "F" | "false" | "0" => Some(ConstValue::Boolean(false)),
+            _ => None,
+        },
+        Type::Integer => text.parse().ok().map(ConstValue::Integer),
+        Type::Real => text.parse().ok().map(ConstValue::Real),
+        Type::Enum { constructors, .. } => constructors
+            .iter()
+            .find(|c| *c == text)
+            .map(|c| ConstValue::Enum(c.clone())),
+        Type::Array { elem, size } => {
+            let elements = literal_items(text, '[', ']', ',')?;
+            if elements.len() != *size {
+                return None;
+            }
+
+            let elements = elements.into_iter().map(|e| parse_value(e, elem));
+            elements.collect::<Option<_>>().map(ConstValue::Array)
+        }
+        Type::Struct { fields, .. } => {
+            let items = literal_items(text, '{', '}', ';')?;
+            if items.len() != fields.len() {
+                return None;
+            }
+
+            let mut values = Vec::with_capacity(fields.len());
+            for (item, (field, ty)) in items.into_iter().zip(fields) {
+                let (name, value) = item.split_once('=')?;
+                if name.trim() != field {
+                    return None;
+                }
+                values.push((field.clone(), parse_value(value.trim(), ty)?));
+            }
+            Some(ConstValue::Struct(values))
+        }
+        _ => None,
+    }
+}
+
+/// Splits a text at the given separators, except inside of array and structure literals
+fn split_top_level(text: &str, is_separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut items = vec![];
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (idx, c) in text.char_indices() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth = depth.saturating_sub(1),
+            c if depth == 0 && is_separator(c) => {
+                items.push(text[start..idx].trim());
+                start = idx + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+
+    items.push(text[start..].trim());
+    items
+}
+
+/// Returns the items of an array (`[1, 2]`) or structure (`{x = 1; y = 2}`) literal
+fn literal_items(text: &str, open: char, close: char, separator: char) -> Option<Vec<&str>> {
+    let inner = text.trim().strip_prefix(open)?.strip_suffix(close)?;
+    match inner.trim().is_empty() {
+        true => Some(vec![]),
+        false => Some(split_top_level(inner, |c| c == separator)),
+    }
+}
+
+/// Splits whitespace-separated values, array and structure literals possibly containing spaces
+/// (as in `[1, 2, 3]`)
+pub fn split_values(text: &str) -> Vec<&str> {
+    split_top_level(text, char::is_whitespace)
+        .into_iter()
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Formats a value as it should be written in a RIF stream
+pub fn format_value(value: &Value) -> String {
+    match value {
+        None => "nil".into(),
+        Some(ConstValue::Boolean(b)) => if *b { "t" } else { "f" }.into(),
+        Some(ConstValue::Integer(i)) => i.to_string(),
+        Some(ConstValue::Real(r)) => format!("{r:?}"),
+        Some(ConstValue::Enum(name)) => name.clone(),
+        Some(ConstValue::Array(values)) => {
+            let values = values
+                .iter()
+                .map(|v| format_value(&Some(v.clone())))
+                .collect::<Vec<_>>();
+            format!("[{}]", values.join(", "))
+        }
+        Some(ConstValue::Struct(fields)) => {
+            let fields = fields
+                .iter()
+                .map(|(field, v)| format!("{field} = {}", format_value(&Some(v.clone()))))
+                .collect::<Vec<_>>();
+            format!("{{{}}}", fields.join("; "))
+        }
+    }
+}
+
+/// Compares the outputs of a cycle with the ones read from a RIF stream
+///
+/// Reals are rounded when they are written by other tools, so they only have to be close enough
+/// to each other: the difference must be within an absolute tolerance of `1e-6`, or a relative
+/// tolerance of `1e-5`.
+pub fn outputs_match(expected: &[Value], actual: &[Value]) -> bool {
+    fn values_match(expected: &ConstValue, actual: &ConstValue) -> bool {
+        match (expected, actual) {
+            (ConstValue::Real(e), ConstValue::Real(a)) => {
+                let tolerance = f32::max(1e-6, 1e-5 * f32::max(e.abs(), a.abs()));
+                e == a || (e - a).abs() <= tolerance
+            }
+            (ConstValue::Array(e), ConstValue::Array(a)) => {
+                e.len() == a.len() && e.iter().zip(a).all(|(e, a)| values_match(e, a))
+            }
+            (ConstValue::Struct(e), ConstValue::Struct(a)) => {
+                e.len() == a.len()
+                    && e.iter()
+                        .zip(a)
+                        .all(|((ef, e), (af, a))| ef == af && values_match(e, a))
+            }
+            _ => expected == actual,
+        }
+    }
+
+    expected.len() == actual.len()
+        && expected.iter().zip(actual).all(|(e, a)| match (e, a) {
+            (Some(e), Some(a)) => values_match(e, a),
+            _ => e == a,
+        })
+}
+
+fn variables(params: &[(rustre_parser::ast::Ident, Type)]) -> Vec<(String, Type)> {
+    params
+        .iter()
+        .map(|(ident, ty)| (ident.text().to_owned(), ty.clone()))
+        .collect()
+}
+
+/// Streaming reader of RIF inputs
+///
+/// Items are returned as soon as they are complete, so that the reader can be used to drive a
+/// simulation interactively.
+pub struct RifReader<R> {
+    input: R,
+    inputs: Vec<(String, Type)>,
+    outputs: Vec<(String, Type)>,
+    line: usize,
+    values: Vec<ConstValue>,
+    /// Flattened values of the input being read
+    tokens: Vec<String>,
+    items: VecDeque<RifItem>,
+    done: bool,
+}
+
+impl<R: BufRead> RifReader<R> {
+    pub fn new(input: R, sig: &TypedSignature) -> Self {
+        RifReader {
+            input,
+            inputs: variables(&sig.params),
+            outputs: variables(&sig.return_params),
+            line: 0,
+            values: vec![],
+            tokens: vec![],
+            items: Default::default(),
+            done: false,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> RifError {
+        RifError {
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn parse_values(&mut self, text: &str) -> Result<(), RifError> {
+        for token in text.split_whitespace() {
+            if self.inputs.is_empty() {
+                return Err(self.error("the node has no inputs"));
+            }
+
+            // Complete cycles are flushed right away, so there is always an input to read
+            let (name, ty) = &self.inputs[self.values.len()];
+            self.tokens.push(token.to_owned());
+            if self.tokens.len() < flat_len(ty) {
+                continue;
+            }
+
+            let tokens = std::mem::take(&mut self.tokens);
+            let value = unflatten(&mut tokens.iter().map(String::as_str), ty).ok_or_else(|| {
+                let text = tokens.join(" ");
+                self.error(format!("invalid value for input {name}: {text}"))
+            })?;
+            self.values.push(value);
+
+            if self.values.len() == self.inputs.len() {
+                self.items
+                    .push_back(RifItem::Inputs(std::mem::take(&mut self.values)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_outputs(&self, text: &str) -> Result<RifItem, RifError> {
+        let tokens = text.split_whitespace().collect::<Vec<_>>();
+        let expected = self
+            .outputs
+            .iter()
+            .map(|(_, ty)| flat_len(ty))
+            .sum::<usize>();
+        if tokens.len() != expected {
+            return Err(self.error(format!(
+                "expected {expected} output values, got {}",
+                tokens.len()
+            )));
+        }
+
+        let mut values = Vec::with_capacity(self.outputs.len());
+        let mut tokens = tokens.into_iter();
+        for (name, ty) in &self.outputs {
+            let flat = tokens.by_ref().take(flat_len(ty)).collect::<Vec<_>>();
+            if flat.iter().all(|token| *token == "nil") {
+                values.push(None);
+                continue;
+            }
+
+            match unflatten(&mut flat.iter().copied(), ty) {
+                Some(value) => values.push(Some(value)),
+                None => {
+                    let text = flat.join(" ");
+                    return Err(self.error(format!("invalid value for output {name}: {text}")));
+                }
+            }
+        }
+
+        Ok(RifItem::Outputs(values))
+    }
+
+    /// Checks that an `#inputs` or `#outputs` header matches the signature of the node
+    fn check_header(&self, text: &str, expected: &[(String, Type)]) -> Result<(), RifError> {
+        let declared = text.split_whitespace().collect::<Vec<_>>();
+        if declared.len() != expected.len() {
+            return Err(self.error(format!(
+                "expected {} variables in header, got {}",
+                expected.len(),
+                declared.len()
+            )));
+        }
+
+        for (declaration, (name, ty)) in declared.into_iter().zip(expected) {
+            let (declared_name, declared_type) = declaration
+                .rsplit_once(':')
+                .ok_or_else(|| self.error(format!("invalid declaration: {declaration}")))?;
+            let declared_name = declared_name.trim_matches('"');
+
+            if declared_name != name || Some(declared_type) != type_name(ty).as_deref() {
+                return Err(self.error(format!(
+                    "declaration {declaration} doesn't match variable {name}"
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), RifError> {
+        let (values, directive) = match line.split_once('#') {
+            Some((values, directive)) => (values, Some(directive)),
+            None => (line, None),
+        };
+
+        self.parse_values(values)?;
+
+        let Some(directive) = directive else {
+            return Ok(());
+        };
+
+        let (name, rest) = directive
+            .split_once(char::is_whitespace)
+            .unwrap_or((directive, ""));
+        match name {
+            "inputs" => self.check_header(rest, &self.inputs)?,
+            "outputs" => self.check_header(rest, &self.outputs)?,
+            "outs" => {
+                let outputs = self.parse_outputs(rest)?;
+                self.items.push_back(outputs);
+            }
+            "step" if self.inputs.is_empty() => self.items.push_back(RifItem::Inputs(vec![])),
+            "reset" => self.items.push_back(RifItem::Reset),
+            "q" | "quit" => self.done = true,
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for RifReader<R> {
+    type Item = Result<RifItem, RifError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.pop_front() {
+                return Some(Ok(item));
+            }
+
+            if self.done {
+                return None;
+            }
+
+            let mut line = String::new();
+            self.line += 1;
+            match self.input.read_line(&mut line) {
+                Ok(0) => {
+                    self.done = true;
+                    if !self.values.is_empty() || !self.tokens.is_empty() {
+                        return Some(Err(self.error("incomplete input values")));
+                    }
+                }
+                Ok(_) => {
+                    if let Err(err) = self.parse_line(&line) {
+                        self.done = true;
+                        return Some(Err(err));
+                    }
+                }
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(self.error(err.to_string())));
+                }
+            }
+        }
+    }
+}
+
+/// Writer of RIF traces
+pub struct RifWriter<W> {
+    output: W,
+    inputs: Vec<Type>,
+    outputs: Vec<Type>,
+    step: usize,
+}
+
+impl<W: Write> RifWriter<W> {
+    /// Creates a writer, and writes the headers of the stream
+    pub fn new(mut output: W, sig: &TypedSignature) -> std::io::Result<Self> {
+        let header = |directive: &str, params: &[(rustre_parser::ast::Ident, Type)]| {
+            params
+                .iter()
+                .fold(String::from(directive), |header, (ident, ty)| {
+                    let ty = type_name(ty).unwrap_or_else(|| "?".into());
+                    format!("{header} \"{}\":{ty}", ident.text())
+                })
+        };
+
+        writeln!(output, "{}", header("#inputs", &sig.params))?;
+        writeln!(output, "{}", header("#outputs", &sig.return_params))?;
+        let types = |params: &[(rustre_parser::ast::Ident, Type)]| {
+            params.iter().map(|(_, ty)| ty.clone()).collect()
+        };
+        Ok(RifWriter {
+            output,
+            inputs: types(&sig.params),
+            outputs: types(&sig.return_params),
+            step: 0,
+        })
+    }
+
+    /// Writes the inputs and outputs of a cycle
+    pub fn write_step(&mut self, inputs: &[Value], outputs: &[Value]) -> std::io::Result<()> {
+        let join = |values: &[Value], types: &[Type]| {
+            let mut flat = vec![];
+            for (value, ty) in values.iter().zip(types) {
+                flatten(value, ty, &mut flat);
+            }
+            flat.join(" ")
+        };
+
+        self.step += 1;
+        writeln!(self.output, "#step {}", self.step)?;
+        if !inputs.is_empty() {
+            writeln!(self.output, "{}", join(inputs, &self.inputs))?;
+        }
+        writeln!(self.output, "#outs {}", join(outputs, &self.outputs))?;
+        self.output.flush()
+    }
+
+    /// Writes a comment, which is ignored by other tools
+    pub fn write_comment(&mut self, comment: &str) -> std::io::Result<()> {
+        writeln!(self.output, "# {comment}")
+    }
+
+    pub fn write_reset(&mut self) -> std::io::Result<()> {
+        writeln!(self.output, "#reset")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(db: &mut yeter::Database) -> std::rc::Rc<TypedSignature> {
+        crate::add_source_contents(
+            db,
+            "node n(x : int; r : bool) returns (y : real); let y = 0.0; tel".into(),
+        );
+        let node = crate::name_resolution::find_node(db, "n".into());
+        crate::get_typed_signature(db, Option::clone(&node).unwrap())
+    }
+
+    #[test]
+    fn read_lus2lic_trace() {
+        let mut db = crate::driver();
+        let sig = signature(&mut db);
+        let trace = "# generated by lus2lic\n\
+                     #inputs \"x\":int \"r\":bool\n\
+                     #outputs \"y\":real\n\
+                     #step 1\n\
+                     12\n\
+                     f\n\
+                     #outs 12.0\n\
+                     #step 2\n\
+                     3 t #outs nil\n\
+                     #reset\n";
+
+        let items = RifReader::new(trace.as_bytes(), &sig).collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            items,
+            Ok(vec![
+                RifItem::Inputs(vec![ConstValue::Integer(12), ConstValue::Boolean(false)]),
+                RifItem::Outputs(vec![Some(ConstValue::Real(12.0))]),
+                RifItem::Inputs(vec![ConstValue::Integer(3), ConstValue::Boolean(true)]),
+                RifItem::Outputs(vec![None]),
+                RifItem::Reset,
+            ])
+        );
+    }
+
+    #[test]
+    fn mismatched_header() {
+        let mut db = crate::driver();
+        let sig = signature(&mut db);
+        let trace = "#inputs \"x\":real \"r\":bool\n";
+
+        let error = RifReader::new(trace.as_bytes(), &sig).next();
+        assert!(matches!(error, Some(Err(RifError { line: 1, .. }))));
+    }
+
+    #[test]
+    fn write_then_read() {
+        let mut db = crate::driver();
+        let sig = signature(&mut db);
+
+        let mut trace = vec![];
+        let mut writer = RifWriter::new(&mut trace, &sig).unwrap();
+        let inputs = [
+            Some(ConstValue::Integer(-4)),
+            Some(ConstValue::Boolean(true)),
+        ];
+        writer
+            .write_step(&inputs, &[Some(ConstValue::Real(1.5))])
+            .unwrap();
+
+        let items = RifReader::new(trace.as_slice(), &sig).collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            items,
+            Ok(vec![
+                RifItem::Inputs(vec![ConstValue::Integer(-4), ConstValue::Boolean(true)]),
+                RifItem::Outputs(vec![Some(ConstValue::Real(1.5))]),
+            ])
+        );
+    }
+
+    #[test]
+    fn write_then_read_flattened() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type mode = enum { Off, On };
+             type point = struct { x : int; y : real };
+             node n(m : mode; a : int^2) returns (p : point^2; b : bool);
+             let p = [point { x = 0; y = 0.0 }]^2; b = true; tel"
+                .into(),
+        );
+        let node = crate::name_resolution::find_node(&db, "n".into());
+        let sig = crate::get_typed_signature(&db, Option::clone(&node).unwrap());
+
+        let mut trace = vec![];
+        let mut writer = RifWriter::new(&mut trace, &sig).unwrap();
+        let inputs = [
+            Some(ConstValue::Enum("On".into())),
+            Some(ConstValue::Array(vec![
+                ConstValue::Integer(1),
+                ConstValue::Integer(2),
+            ])),
+        ];
+        let point = |x, y| {
+            ConstValue::Struct(vec![
+                ("x".into(), ConstValue::Integer(x)),
+                ("y".into(), ConstValue::Real(y)),
+            ])
+        };
+        let points = ConstValue::Array(vec![point(1, 0.5), point(2, 1.5)]);
+        writer
+            .write_step(&inputs, &[Some(points.clone()), None])
+            .unwrap();
+
+        let trace = String::from_utf8(trace).unwrap();
+        assert_eq!(
+            trace,
+            "#inputs \"m\":mode \"a\":int^2\n\
+             #outputs \"p\":point^2 \"b\":bool\n\
+             #step 1\n\
+             On 1 2\n\
+             #outs 1 0.5 2 1.5 nil\n"
+        );
+
+        let items = RifReader::new(trace.as_bytes(), &sig).collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            items,
+            Ok(vec![
+                RifItem::Inputs(inputs.into_iter().flatten().collect()),
+                RifItem::Outputs(vec![Some(points), None]),
+            ])
+        );
+    }
+
+    #[test]
+    fn array_and_struct_values() {
+        let point = Type::Struct {
+            name: "point".into(),
+            fields: vec![("x".into(), Type::Integer), ("y".into(), Type::Real)],
+        };
+        let ty = Type::Array {
+            elem: Box::new(point),
+            size: 2,
+        };
+        let text = "[{x = 1; y = 0.5}, {x = -2; y = 3.0}]";
+
+        let point = |x, y| {
+            ConstValue::Struct(vec![
+                ("x".into(), ConstValue::Integer(x)),
+                ("y".into(), ConstValue::Real(y)),
+            ])
+        };
+        let value = ConstValue::Array(vec![point(1, 0.5), point(-2, 3.0)]);
+        assert_eq!(parse_value(text, &ty), Some(value.clone()));
+        assert_eq!(
+            parse_value(&format_value(&Some(value)), &ty),
+            parse_value(text, &ty)
+        );
+        assert_eq!(parse_value("[{x = 1; y = 0.5}]", &ty), None);
+
+        assert_eq!(split_values("1 [1, 2,3]  t"), vec!["1", "[1, 2,3]", "t"]);
+    }
+
+    #[test]
+    fn rounded_reals() {
+        let third = |r| vec![Some(ConstValue::Real(r)), Some(ConstValue::Integer(3))];
+        assert!(outputs_match(&third(0.333333), &third(1.0 / 3.0)));
+        assert!(outputs_match(&third(333333.3), &third(1e6 / 3.0)));
+        assert!(!outputs_match(&third(0.3333), &third(1.0 / 3.0)));
+        assert!(!outputs_match(&third(0.333333), &[None, None]));
+    }
+}
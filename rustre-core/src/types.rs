@@ -1,10 +1,23 @@
+use crate::clocks::{clock_condition, merge_case, ClockCase};
 use crate::diagnostics::{Diagnostic, Level, Span};
-use crate::eval::eval_const_node;
-use crate::name_resolution::{resolve_runtime_node, NameResolveQuery, ResolvedRuntimeNode};
+use crate::eval::{eval_const_node, eval_slice_bounds, eval_with_statics, slice_indices};
+use crate::generics::{
+    in_model, package_instance_bindings, package_instance_signature, static_param_kind,
+    StaticBindings, StaticParamKind,
+};
+use crate::iterators::{compatible, iterator_type, resolve_iterator, ArrayIterator};
+use crate::name_resolution::{
+    resolve_extern_node, resolve_instance, resolve_node, resolve_runtime_node,
+    resolve_static_param, NameResolveQuery, ResolvedRuntimeNode,
+};
 use crate::TypedSignature;
 use rustre_parser::ast::{
-    AstNode, AstToken, CallByPosExpressionNode, ExpressionNode, LeftItemNode, NodeNode, TypeNode,
+    ArrayLiteralExpressionNode, AstNode, AstToken, CallByNameExpressionNode, CallByNameParamNode,
+    CallByPosExpressionNode, ClockExpressionNode, ConcatExpressionNode, ExpressionNode, IdNode,
+    Ident, LeftItemNode, MergeCaseNode, MergeExpressionNode, NodeNode, OneTypeDeclNode, SelectNode,
+    StaticArgsNode, TypeNode,
 };
+use std::collections::HashSet;
 use yeter::Database;
 
 #[derive(Clone, Debug, Default, Hash, PartialEq)]
@@ -32,6 +45,23 @@ pub enum Type {
     /// function returns exactly one value, it **mustn't** be typed as a `ReturnTuple` as this would
     /// prevent it from being used as an operand to pretty much all operators.
     Tuple(Vec<Type>),
+
+    /// Structure, with the types of its fields in declaration order
+    ///
+    /// Structure types are nominal: they are only equal if they have the same name.
+    Struct {
+        name: String,
+        fields: Vec<(String, Type)>,
+    },
+
+    /// Enumerated type, with its constructors in declaration order
+    Enum {
+        name: String,
+        constructors: Vec<String>,
+    },
+
+    /// Static type parameter of a generic node, that is only known once it is instantiated
+    Abstract(String),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -40,6 +70,10 @@ pub enum ConstValue {
     Integer(i32),
     Real(f32),
     Array(Vec<ConstValue>),
+    /// Constructor of an enumerated type
+    Enum(String),
+    /// Structure, with the values of its fields in declaration order
+    Struct(Vec<(String, ConstValue)>),
 }
 
 impl Type {
@@ -54,6 +88,25 @@ impl Type {
     pub fn is_unknown(&self) -> bool {
         matches!(self, Self::Unknown)
     }
+
+    /// Returns `true` if this type is, or contains, the [abstract][Type::Abstract] type of a static
+    /// parameter
+    pub fn is_abstract(&self) -> bool {
+        match self {
+            Type::Abstract(_) => true,
+            Type::Array { elem, .. } => elem.is_abstract(),
+            Type::Tuple(types) => types.iter().any(Type::is_abstract),
+            _ => false,
+        }
+    }
+
+    /// Returns the type of a field, if this is a structure that has it
+    pub fn field(&self, name: &str) -> Option<&Type> {
+        match self {
+            Type::Struct { fields, .. } => fields.iter().find(|(f, _)| f == name).map(|(_, t)| t),
+            _ => None,
+        }
+    }
 }
 
 // This is not great for complex types. For instance, type aliases should remain as-is instead of
@@ -78,6 +131,9 @@ impl std::fmt::Display for Type {
                 }
                 write!(f, ")")
             }
+            Type::Struct { name, .. } | Type::Enum { name, .. } | Type::Abstract(name) => {
+                write!(f, "{name}")
+            }
         }
     }
 }
@@ -85,10 +141,22 @@ impl std::fmt::Display for Type {
 /// **Query**: Type-checks a given node
 #[yeter::query]
 pub fn type_check_query(db: &yeter::Database, node_node: NodeNode) -> Type {
+    // Node aliases (`node a = b<<...>>;`) have no body, and the type of the node they refer to
+    if node_node.effective_node_node().is_some() {
+        let sig = crate::get_typed_signature(db, node_node);
+        return Type::Function {
+            args: sig.params.iter().map(|(_, ty)| ty.clone()).collect(),
+            ret: sig.return_params.iter().map(|(_, ty)| ty.clone()).collect(),
+        };
+    }
+
     let body_node = node_node.body_node();
     let in_node = Some(node_node.clone());
 
-    for node in body_node.as_ref().unwrap().all_equals_equation_node() {
+    let equations = body_node.iter().flat_map(|b| b.all_equals_equation_node());
+    let assertions = body_node.iter().flat_map(|b| b.all_assert_equation_node());
+
+    for node in equations {
         if let (Some(left_node), Some(expr_node)) = (node.left_node(), node.expression_node()) {
             let lefts = left_node.all_left_item_node();
             let mut left_types = Vec::new();
@@ -105,7 +173,9 @@ pub fn type_check_query(db: &yeter::Database, node_node: NodeNode) -> Type {
             let right_types =
                 type_check_expression(db, &expr_node, &in_node, Some(left_types.clone()));
 
-            if left_types != right_types {
+            // Types of static parameters are only known once the node is instantiated
+            let is_abstract = left_types.is_abstract() || right_types.is_abstract();
+            if !is_abstract && left_types != right_types {
                 Diagnostic::new(Level::Error, "incompatible types")
                     .with_attachment(
                         Span::of_node(db, node.left_node().unwrap().syntax()),
@@ -120,7 +190,7 @@ pub fn type_check_query(db: &yeter::Database, node_node: NodeNode) -> Type {
         }
     }
 
-    for node in body_node.as_ref().unwrap().all_assert_equation_node() {
+    for node in assertions {
         let right_types = type_check_expression(
             db,
             &node.expression_node().unwrap(),
@@ -143,12 +213,9 @@ pub fn type_check_query(db: &yeter::Database, node_node: NodeNode) -> Type {
     let mut ret = Vec::new();
 
     let params = node_profile_node
-        .as_ref()
-        .as_ref()
-        .unwrap()
-        .params()
-        .unwrap()
-        .all_var_decl_node();
+        .iter()
+        .flat_map(|p| p.params())
+        .flat_map(|p| p.all_var_decl_node());
     for param in params {
         let typed_id_nodes = param.all_typed_ids_node();
         for type_nodes in typed_id_nodes {
@@ -161,12 +228,9 @@ pub fn type_check_query(db: &yeter::Database, node_node: NodeNode) -> Type {
     }
 
     let return_params = node_profile_node
-        .as_ref()
-        .as_ref()
-        .unwrap()
-        .return_params()
-        .unwrap()
-        .all_var_decl_node();
+        .iter()
+        .flat_map(|p| p.return_params())
+        .flat_map(|p| p.all_var_decl_node());
     for return_param in return_params {
         let typed_id_nodes = return_param.all_typed_ids_node();
         for type_nodes in typed_id_nodes {
@@ -182,22 +246,67 @@ pub fn type_check_query(db: &yeter::Database, node_node: NodeNode) -> Type {
 
 #[yeter::query]
 pub fn type_of_ast_type(db: &Database, node: Option<NodeNode>, type_node: TypeNode) -> Type {
-    let scalar = if type_node.bool().is_some() {
+    let scalar = scalar_type(db, &node, &type_node);
+
+    if let Some(power) = type_node.power() {
+        let is_generic = node
+            .as_ref()
+            .is_some_and(|n| n.static_params_node().is_some())
+            || in_model(type_node.syntax());
+        let size = match *eval_const_node(db, power.clone(), node) {
+            Some(ConstValue::Integer(i)) => i as usize,
+            // Sizes may depend on static constants, that are only known in instances
+            _ if is_generic => return Type::Unknown,
+            _ => {
+                Diagnostic::new(Level::Debug, "cannot evaluate type")
+                    .with_attachment(
+                        Span::of_node(db, power.syntax()),
+                        "this expression is not constant",
+                    )
+                    .emit(db);
+                return Type::Unknown;
+            }
+        };
+        Type::Array {
+            elem: Box::new(scalar),
+            size,
+        }
+    } else {
+        scalar
+    }
+}
+
+/// Resolves a type, ignoring its size if it is an array
+fn scalar_type(db: &Database, node: &Option<NodeNode>, type_node: &TypeNode) -> Type {
+    if type_node.bool().is_some() {
         Type::Boolean
     } else if type_node.int().is_some() {
         Type::Integer
     } else if type_node.real().is_some() {
         Type::Real
     } else if let Some(id) = type_node.id_node() {
-        let decl = crate::name_resolution::resolve_type_decl(db, id.clone());
+        let query = id.name().map(|ident| NameResolveQuery {
+            ident,
+            in_node: node.clone(),
+        });
+        let param = query.and_then(|q| Option::clone(&resolve_static_param(db, q)));
+        if let Some(param) = param.filter(|p| static_param_kind(p) == StaticParamKind::Type) {
+            let name = param.id_node().and_then(|i| i.ident());
+            return Type::Abstract(name.map(|n| n.text().to_owned()).unwrap_or_default());
+        }
+
+        let decl = Option::clone(&crate::name_resolution::resolve_type_decl(db, id.clone()));
+
+        // Types of a model are given their static arguments when seen through an instance
+        let instance = Option::clone(&resolve_instance(db, id.clone()));
+        let instance_type = decl.as_ref().and_then(|d| d.type_node()).zip(instance);
+        if let Some((type_node, instance)) = instance_type {
+            let bindings = Option::clone(&package_instance_bindings(db, instance));
+            return type_with_statics(db, None, type_node, &bindings.unwrap_or_default());
+        }
 
         match decl.as_ref() {
-            Some(decl) => Type::clone(
-                &decl
-                    .type_node()
-                    .map(|t| type_of_ast_type(db, node.clone(), t))
-                    .unwrap_or_default(),
-            ),
+            Some(decl) => Type::clone(&type_of_type_decl(db, node.clone(), decl.clone())),
             None => {
                 let span = Span::of_node(db, id.syntax());
                 let ident = id.ident().unwrap();
@@ -212,27 +321,104 @@ pub fn type_of_ast_type(db: &Database, node: Option<NodeNode>, type_node: TypeNo
         }
     } else {
         Type::Unknown
-    };
+    }
+}
 
-    if let Some(power) = type_node.power() {
-        let size = match *eval_const_node(db, power.clone(), node) {
-            Some(ConstValue::Integer(i)) => i as usize,
-            _ => {
-                Diagnostic::new(Level::Debug, "cannot evaluate type")
-                    .with_attachment(
-                        Span::of_node(db, power.syntax()),
-                        "this expression is not constant",
-                    )
-                    .emit(db);
-                return Type::Unknown;
-            }
-        };
-        Type::Array {
+/// Resolves a type that is written in a generic node, for one of its instances
+///
+/// The sizes of arrays are not reported when they cannot be evaluated: the static arguments have
+/// already been checked when they were bound.
+pub fn type_with_statics(
+    db: &Database,
+    node: Option<NodeNode>,
+    type_node: TypeNode,
+    statics: &StaticBindings,
+) -> Type {
+    let bound = type_node
+        .id_node()
+        .and_then(|i| i.ident())
+        .and_then(|i| statics.types.get(i.text()).cloned());
+    let scalar = bound.unwrap_or_else(|| scalar_type(db, &node, &type_node));
+
+    let Some(power) = type_node.power() else {
+        return scalar;
+    };
+    match eval_with_statics(db, power, node, statics) {
+        Some(ConstValue::Integer(size)) if size >= 0 => Type::Array {
             elem: Box::new(scalar),
-            size,
+            size: size as usize,
+        },
+        _ => Type::Unknown,
+    }
+}
+
+/// Returns the declarations of the types named in a type declaration
+fn referenced_type_decls(db: &Database, decl: &OneTypeDeclNode) -> Vec<OneTypeDeclNode> {
+    let fields = decl.struct_decl_node().into_iter().flat_map(|s| s.fields());
+    decl.type_node()
+        .into_iter()
+        .chain(fields.filter_map(|(_, ty)| ty))
+        .filter_map(|ty| ty.id_node())
+        .filter_map(|id| Option::clone(&crate::name_resolution::resolve_type_decl(db, id)))
+        .collect()
+}
+
+/// Tells whether a type declaration refers to itself, directly or through other declarations
+///
+/// Such a type would have infinite values, and it is typed as [Type::Unknown].
+pub fn is_recursive_type_decl(db: &Database, decl: &OneTypeDeclNode) -> bool {
+    let mut seen = HashSet::new();
+    let mut to_visit = referenced_type_decls(db, decl);
+    while let Some(next) = to_visit.pop() {
+        if next == *decl {
+            return true;
         }
-    } else {
-        scalar
+        if seen.insert(next.clone()) {
+            to_visit.extend(referenced_type_decls(db, &next));
+        }
+    }
+    false
+}
+
+/// **Query:** Resolves the type defined by a type declaration
+///
+/// Structures and enumerations are nominal: two declarations with the same fields or constructors
+/// define different types, but an alias (`type u = t;`) is the same type as the one it names.
+#[yeter::query]
+pub fn type_of_type_decl(db: &Database, node: Option<NodeNode>, decl: OneTypeDeclNode) -> Type {
+    if is_recursive_type_decl(db, &decl) {
+        return Type::Unknown;
+    }
+
+    if let Some(type_node) = decl.type_node() {
+        return Type::clone(&type_of_ast_type(db, node, type_node));
+    }
+
+    let Some(name) = decl.ident() else {
+        return Type::Unknown;
+    };
+
+    if let Some(enum_decl) = decl.enum_decl_node() {
+        return Type::Enum {
+            name: name.text().to_owned(),
+            constructors: enum_decl.all_ident().map(|c| c.text().to_owned()).collect(),
+        };
+    }
+
+    let Some(struct_decl) = decl.struct_decl_node() else {
+        return Type::Unknown;
+    };
+    let fields = struct_decl
+        .fields()
+        .map(|(field, ty)| {
+            let ty = ty.map(|t| Type::clone(&type_of_ast_type(db, node.clone(), t)));
+            (field.text().to_owned(), ty.unwrap_or_default())
+        })
+        .collect();
+
+    Type::Struct {
+        name: name.text().to_owned(),
+        fields,
     }
 }
 
@@ -249,14 +435,25 @@ macro_rules! some_or_unknown {
 #[yeter::query]
 pub fn declared_type_of_ident(db: &Database, query: NameResolveQuery) -> Option<Type> {
     let in_node = query.in_node.clone();
-    let resolved_node = crate::name_resolution::resolve_runtime_node(db, query);
-    let resolved_node = resolved_node.as_ref().as_ref()?;
+    let resolved_node = crate::name_resolution::resolve_runtime_node(db, query.clone());
+    let Some(resolved_node) = resolved_node.as_ref() else {
+        // Static constants are typed by their declaration, their value is only known in instances
+        let param = Option::clone(&resolve_static_param(db, query))?;
+        let ty = (static_param_kind(&param) == StaticParamKind::Const).then_some(param.type_node());
+        return Some(Type::clone(&type_of_ast_type(db, in_node, ty??)));
+    };
 
     Some(match resolved_node {
         ResolvedRuntimeNode::Const(const_decl_node) => {
-            type_of_ast_type(db, in_node, const_decl_node.type_node()?)
-                .as_ref()
-                .clone()
+            let type_node = const_decl_node.type_node()?;
+            let id = IdNode::cast(query.ident.syntax().parent()?);
+            match id.and_then(|id| Option::clone(&resolve_instance(db, id))) {
+                Some(instance) => {
+                    let bindings = Option::clone(&package_instance_bindings(db, instance));
+                    type_with_statics(db, None, type_node, &bindings.unwrap_or_default())
+                }
+                None => type_of_ast_type(db, in_node, type_node).as_ref().clone(),
+            }
         }
         ResolvedRuntimeNode::Param(var_decl_node)
         | ResolvedRuntimeNode::ReturnParam(var_decl_node)
@@ -511,7 +708,23 @@ pub fn type_check_expression(
                 }
             }
         }
-        ExpressionNode::WhenExpressionNode(_) => todo!(),
+        ExpressionNode::WhenExpressionNode(node) => {
+            let clock = node.syntax().children().find_map(ClockExpressionNode::cast);
+            let condition = clock.as_ref().and_then(|c| Some((c, clock_condition(c)?)));
+            if let Some((clock, (ident, case))) = condition {
+                let cases = clock_cases(db, &ident, in_node);
+                if cases.is_some_and(|cases| !cases.contains(&case)) {
+                    Diagnostic::new(Level::Error, "incorrect clock")
+                        .with_attachment(
+                            Span::of_node(db, clock.syntax()),
+                            format!("`{case}` is not a possible value of {:?}", ident.text()),
+                        )
+                        .emit(db);
+                }
+            }
+
+            type_check_expression(db, &some_or_unknown!(node.left()), in_node, expected_type)
+        }
         ExpressionNode::FbyExpressionNode(node) => {
             ty_check_expr!(binary_any, db, node, in_node, expected_type)
         }
@@ -531,10 +744,12 @@ pub fn type_check_expression(
             ty_check_expr!(binary, db, node, in_node, Type::Boolean)
         } // TODO, is that true?
         ExpressionNode::EqExpressionNode(node) => {
-            ty_check_expr!(binary_any, db, node, in_node, expected_type)
+            ty_check_expr!(binary_any, db, node, in_node, None);
+            Type::Boolean
         }
         ExpressionNode::NeqExpressionNode(node) => {
-            ty_check_expr!(binary_any, db, node, in_node, expected_type)
+            ty_check_expr!(binary_any, db, node, in_node, None);
+            Type::Boolean
         }
         ExpressionNode::LtExpressionNode(node) => ty_check_expr!(comparator, db, node, in_node),
         ExpressionNode::LteExpressionNode(node) => ty_check_expr!(comparator, db, node, in_node),
@@ -670,10 +885,11 @@ pub fn type_check_expression(
             with_body_type
         }
         ExpressionNode::DieseExpressionNode(node) => {
-            let node_list = node.list().unwrap().all_expression_node();
+            // The parser doesn't wrap the operands in an ExpressionListNode
+            let node_list = node.syntax().children().filter_map(ExpressionNode::cast);
             for element in node_list {
                 let el_type = type_check_expression(db, &element, in_node, Some(Type::Boolean));
-                if el_type != Type::Boolean || el_type != Type::Unknown {
+                if el_type != Type::Boolean && el_type != Type::Unknown {
                     Diagnostic::new(Level::Error, "Incorrect type")
                         .with_attachment(
                             Span::of_node(db, element.syntax()),
@@ -686,10 +902,11 @@ pub fn type_check_expression(
             Type::Boolean
         }
         ExpressionNode::NorExpressionNode(node) => {
-            let node_list = node.list().unwrap().all_expression_node();
+            // The parser doesn't wrap the operands in an ExpressionListNode
+            let node_list = node.syntax().children().filter_map(ExpressionNode::cast);
             for element in node_list {
                 let el_type = type_check_expression(db, &element, in_node, Some(Type::Boolean));
-                if el_type != Type::Boolean || el_type != Type::Unknown {
+                if el_type != Type::Boolean && el_type != Type::Unknown {
                     Diagnostic::new(Level::Error, "Incorrect type")
                         .with_attachment(
                             Span::of_node(db, element.syntax()),
@@ -702,20 +919,25 @@ pub fn type_check_expression(
             Type::Boolean
         }
         ExpressionNode::IdentExpressionNode(node) => {
-            let ident = some_or_unknown!(some_or_unknown!(node.id_node()).ident());
+            let id_node = some_or_unknown!(node.id_node());
+            let ident = some_or_unknown!(id_node.name());
             let query = NameResolveQuery {
                 ident: ident.clone(),
                 in_node: in_node.clone(),
             };
 
-            let resolved = declared_type_of_ident(db, query);
-            match resolved.as_ref().as_ref() {
-                Some(ty) => ty.clone(),
+            if let Some(ty) = declared_type_of_ident(db, query).as_ref() {
+                return ty.clone();
+            }
+
+            let constructor = crate::name_resolution::resolve_enum_constructor(db, id_node);
+            match Option::clone(&constructor) {
+                Some(decl) => Type::clone(&type_of_type_decl(db, in_node.clone(), decl)),
                 None => {
-                    let name = ident.text();
-                    let span = Span::of_token(db, ident.syntax());
+                    let name = node.syntax().text().to_string();
+                    let span = Span::of_node(db, node.syntax());
 
-                    Diagnostic::new(Level::Error, format!("cannot find value {name:?}"))
+                    Diagnostic::new(Level::Error, format!("cannot find value {:?}", name.trim()))
                         .with_attachment(span, "not found in this scope")
                         .emit(db);
 
@@ -730,21 +952,62 @@ pub fn type_check_expression(
             expected_type,
         ),
         ExpressionNode::CallByPosExpressionNode(expr) => {
-            let name = expr
-                .node_ref()
-                .and_then(|r| r.id_node())
-                .and_then(|i| i.ident());
+            let id_node = expr.node_ref().and_then(|r| r.id_node());
+            let name = id_node.as_ref().and_then(|i| i.name());
+
+            if let (Some(id_node), Some(name)) = (id_node, name) {
+                // Static node parameters are called through their declared profile
+                let query = NameResolveQuery {
+                    ident: name.clone(),
+                    in_node: in_node.clone(),
+                };
+                let param = Option::clone(&resolve_static_param(db, query))
+                    .filter(|p| static_param_kind(p) == StaticParamKind::Node);
+                if let Some(param) = param {
+                    let sig = crate::Signature::of_profile(Some(name), param.node_profile_node())
+                        .typed(db, in_node.clone());
+                    return check_call_expression(db, expr, &sig, in_node);
+                }
+
+                if let Some(iterator) = resolve_iterator(db, &id_node) {
+                    let Some(args) = expr.static_args_node() else {
+                        missing_static_arguments(db, &name);
+                        return expected_type.unwrap_or_default();
+                    };
+                    return check_iterator_call(db, expr, iterator, args, in_node, expected_type);
+                }
 
-            if let Some(name) = name {
-                let node_node = crate::name_resolution::find_node(db, name.text().into());
+                let node_node = resolve_node(db, id_node.clone());
 
                 if let Some(node_node) = Option::clone(&node_node) {
+                    if let Some(args) = expr.static_args_node() {
+                        let sig = crate::generics::instance_signature(db, args, in_node.clone());
+                        return match Option::clone(&sig) {
+                            Some(sig) => check_call_expression(db, expr, &sig, in_node),
+                            None => expected_type.unwrap_or_default(),
+                        };
+                    }
+
+                    if let Some(instance) = Option::clone(&resolve_instance(db, id_node.clone())) {
+                        let sig = package_instance_signature(db, instance, node_node);
+                        return check_call_expression(db, expr, &sig, in_node);
+                    }
+
+                    if node_node.static_params_node().is_some() {
+                        missing_static_arguments(db, &name);
+                        return expected_type.unwrap_or_default();
+                    }
+
                     let sig = crate::get_typed_signature(db, node_node.clone());
-                    return check_call_expression(db, expr, &sig, &Some(node_node));
+                    return check_call_expression(db, expr, &sig, in_node);
+                } else if let Some(ext) = Option::clone(&resolve_extern_node(db, id_node.clone())) {
+                    let sig = crate::get_extern_signature(db, ext);
+                    return check_call_expression(db, expr, &sig, in_node);
                 } else {
-                    let span = Span::of_token(db, name.syntax());
+                    let span = Span::of_node(db, id_node.syntax());
+                    let name = id_node.syntax().text().to_string();
 
-                    Diagnostic::new(Level::Error, format!("unknown node {:?}", name.text()))
+                    Diagnostic::new(Level::Error, format!("unknown node {:?}", name.trim()))
                         .with_attachment(span, "not found in this scope")
                         .emit(db);
                 }
@@ -752,121 +1015,765 @@ pub fn type_check_expression(
 
             expected_type.unwrap_or_default()
         }
+        ExpressionNode::MergeExpressionNode(node) => {
+            check_merge_expression(db, node, in_node, expected_type)
+        }
+        ExpressionNode::FieldAccessExpressionNode(node) => {
+            let left = some_or_unknown!(node.left());
+            let field = some_or_unknown!(node.field());
+            let left_type = type_check_expression(db, &left, in_node, None);
+            field_type(db, &left_type, &field)
+        }
+        ExpressionNode::CallByNameExpressionNode(node) => check_call_by_name(db, node, in_node),
+        ExpressionNode::ArrayAccessExpressionNode(node) => {
+            let array = some_or_unknown!(node.array());
+            let array_type = type_check_expression(db, &array, in_node, None);
+            check_array_access(
+                db,
+                &array_type,
+                Span::of_node(db, array.syntax()),
+                node.index(),
+                node.select_node(),
+                in_node,
+            )
+        }
+        ExpressionNode::ArrayLiteralExpressionNode(node) => {
+            check_array_literal(db, node, in_node, expected_type)
+        }
+        ExpressionNode::ConcatExpressionNode(node) => check_concat(db, node, in_node),
     }
 }
 
-fn check_call_expression(
+/// Type-checks an index, and reports it if it is statically known to be out of bounds
+///
+/// Returns the value of the index, if it is constant.
+fn check_index(
     db: &Database,
-    expr: &CallByPosExpressionNode,
-    sig: &TypedSignature,
+    index: &ExpressionNode,
+    size: Option<usize>,
     in_node: &Option<NodeNode>,
-) -> Type {
-    // Check input parameters
-    let expected = sig.params.iter().map(Some).chain(std::iter::repeat(None));
-    let found = expr.args().skip(1).map(Some).chain(std::iter::repeat(None));
-    for (expected, found) in expected.zip(found) {
-        match (expected, found) {
-            (None, None) => break,
-            (Some((_, expected_ty)), Some(found)) => {
-                if !expected_ty.is_unknown() {
-                    let found_ty =
-                        type_check_expression(db, &found, in_node, Some(expected_ty.clone()));
-                    if !found_ty.is_unknown() && expected_ty != &found_ty {
-                        let span = Span::of_node(db, found.syntax());
-                        Diagnostic::new(Level::Error, "invalid type for argument")
-                            .with_attachment(
-                                span,
-                                format!("expected {expected_ty}, found {found_ty}"),
-                            )
-                            .emit(db);
-                    }
-                }
-            }
-            (Some((expected_ident, _expected_type)), None) => {
-                let error_span = expr
-                    .args()
-                    .last()
-                    .map(|s| Span::of_node(db, s.syntax()))
-                    .or_else(|| expr.open_par().map(|p| Span::of_token(db, p.syntax())))
-                    .or_else(|| expr.node_ref().map(|i| Span::of_node(db, i.syntax())))
-                    .unwrap_or_else(|| Span::of_node(db, expr.syntax()))
-                    .after();
+) -> Option<i32> {
+    let span = Span::of_node(db, index.syntax());
+    let index_type = type_check_expression(db, index, in_node, Some(Type::Integer));
+    if !index_type.is_unknown() && index_type != Type::Integer {
+        Diagnostic::new(Level::Error, "incorrect type")
+            .with_attachment(span, format!("expected int, found {index_type}"))
+            .emit(db);
+        return None;
+    }
 
-                let name_span = Span::of_node(db, expr.node_ref().unwrap().syntax());
-                let found_count = expr.args().skip(1).count();
-                let expected_count = sig.params.len();
-                let expected_ident = expected_ident.text();
+    let value = match *eval_const_node(db, index.clone(), in_node.clone()) {
+        Some(ConstValue::Integer(i)) => i,
+        _ => return None,
+    };
 
-                Diagnostic::new(Level::Error, format!("missing argument {expected_ident:?}"))
-                    .with_attachment(name_span, format!("this function expects {expected_count} arguments but {found_count} were supplied"))
-                    .with_attachment(error_span, "hint: add the missing arguments(s)")
-                    .emit(db);
-            }
-            (None, Some(found)) => {
-                let arg_span = Span::of_node(db, found.syntax());
-                let name_span = Span::of_node(db, expr.node_ref().unwrap().syntax());
-                let found_count = expr.args().skip(1).count();
-                let expected_count = sig.params.len();
+    if let Some(size) = size {
+        if usize::try_from(value).map_or(true, |i| i >= size) {
+            Diagnostic::new(Level::Error, "index out of bounds")
+                .with_attachment(
+                    span,
+                    format!("the length is {size} but the index is {value}"),
+                )
+                .emit(db);
+        }
+    }
 
-                Diagnostic::new(Level::Error, "unexpected argument")
-                    .with_attachment(arg_span, "hint: remove this argument")
-                    .with_attachment(name_span, format!("this function expects {expected_count} arguments but {found_count} were supplied"))
-                    .emit(db);
-            }
+    Some(value)
+}
+
+/// Type-checks an access to an element (`a[i]`) or a slice (`a[i .. j step k]`) of an array
+fn check_array_access(
+    db: &Database,
+    array_type: &Type,
+    array_span: Span,
+    index: Option<ExpressionNode>,
+    select: Option<SelectNode>,
+    in_node: &Option<NodeNode>,
+) -> Type {
+    let (elem, size) = match array_type {
+        Type::Array { elem, size } => (Type::clone(elem), Some(*size)),
+        Type::Unknown => (Type::Unknown, None),
+        _ => {
+            Diagnostic::new(Level::Error, "incorrect type")
+                .with_attachment(array_span, format!("expected an array, found {array_type}"))
+                .emit(db);
+            (Type::Unknown, None)
         }
+    };
+
+    let Some(select) = select else {
+        check_index(db, &some_or_unknown!(index), size, in_node);
+        return elem;
+    };
+
+    let first = some_or_unknown!(select.left());
+    let last = some_or_unknown!(select.right());
+    check_index(db, &first, size, in_node);
+    check_index(db, &last, size, in_node);
+    if let Some(step) = select.step_node().and_then(|s| s.expression_node()) {
+        check_index(db, &step, None, in_node);
     }
 
-    // Find out returned value(s)
-    if sig.return_params.len() == 1 {
-        sig.return_params[0].1.clone()
-    } else {
-        let cloned = sig.return_params.iter().map(|(_, t)| t).cloned().collect();
-        Type::Tuple(cloned)
+    let span = Span::of_node(db, select.syntax());
+    let Some((first, last, step)) = eval_slice_bounds(db, &select, in_node.clone()) else {
+        Diagnostic::new(Level::Error, "invalid slice")
+            .with_attachment(span, "the bounds and the step of a slice must be constant")
+            .emit(db);
+        return Type::Unknown;
+    };
+
+    let Some(indices) = slice_indices(first, last, step) else {
+        Diagnostic::new(Level::Error, "invalid slice")
+            .with_attachment(
+                span,
+                format!("cannot go from {first} to {last} with this step"),
+            )
+            .emit(db);
+        return Type::Unknown;
+    };
+
+    if elem.is_unknown() {
+        return Type::Unknown;
+    }
+
+    Type::Array {
+        elem: Box::new(elem),
+        size: indices.len(),
     }
 }
 
-fn type_check_left(db: &yeter::Database, expr: &LeftItemNode, in_node: &Option<NodeNode>) -> Type {
-    match expr {
-        LeftItemNode::IdNode(ident) => {
-            let query = NameResolveQuery {
-                ident: ident.ident().unwrap(),
-                in_node: in_node.clone(),
-            };
-            let resolved_node = resolve_runtime_node(db, query);
-            match *resolved_node {
-                Some(ResolvedRuntimeNode::Const(ref const_decl_node)) => type_of_ast_type(
-                    db,
-                    in_node.clone(),
-                    some_or_unknown!(const_decl_node.type_node()),
-                )
-                .as_ref()
-                .clone(),
-                Some(ResolvedRuntimeNode::Param(ref var_decl_node))
-                | Some(ResolvedRuntimeNode::ReturnParam(ref var_decl_node))
-                | Some(ResolvedRuntimeNode::Var(ref var_decl_node)) => type_of_ast_type(
-                    db,
-                    in_node.clone(),
-                    some_or_unknown!(var_decl_node.type_node()),
-                )
-                .as_ref()
-                .clone(),
-                None => Type::Unknown,
+/// Type-checks an array literal, as in `[1, 2, 3]`
+fn check_array_literal(
+    db: &Database,
+    expr: &ArrayLiteralExpressionNode,
+    in_node: &Option<NodeNode>,
+    expected_type: Option<Type>,
+) -> Type {
+    let mut elem = match expected_type {
+        Some(Type::Array { elem, .. }) => Some(*elem),
+        _ => None,
+    };
+
+    let mut size = 0;
+    for element in expr.elements() {
+        size += 1;
+        let found = type_check_expression(db, &element, in_node, elem.clone());
+        match &elem {
+            None => elem = Some(found),
+            Some(expected) => {
+                if !expected.is_unknown() && !found.is_unknown() && found != *expected {
+                    Diagnostic::new(Level::Error, "incorrect type")
+                        .with_attachment(
+                            Span::of_node(db, element.syntax()),
+                            format!("expected {expected}, found {found}"),
+                        )
+                        .emit(db);
+                }
             }
         }
-        LeftItemNode::LeftTableAccessNode(table_item) => {
-            type_check_left(db, &table_item.left_item_node().unwrap(), in_node)
-        }
-        LeftItemNode::LeftFieldAccessNode(_) => {
-            todo!("Structures are not supported yet.")
-        }
+    }
+
+    match elem {
+        Some(elem) if !elem.is_unknown() && size > 0 => Type::Array {
+            elem: Box::new(elem),
+            size,
+        },
+        _ => Type::Unknown,
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::name_resolution::find_node;
+/// Type-checks the concatenation of two arrays, as in `a | b`
+fn check_concat(db: &Database, expr: &ConcatExpressionNode, in_node: &Option<NodeNode>) -> Type {
+    let left = some_or_unknown!(expr.left());
+    let right = some_or_unknown!(expr.right());
+    let left_type = type_check_expression(db, &left, in_node, None);
+    let right_type = type_check_expression(db, &right, in_node, None);
 
-    use super::*;
+    for (operand, ty) in [(&left, &left_type), (&right, &right_type)] {
+        if !ty.is_unknown() && !ty.is_array() {
+            Diagnostic::new(Level::Error, "incorrect type")
+                .with_attachment(
+                    Span::of_node(db, operand.syntax()),
+                    format!("expected an array, found {ty}"),
+                )
+                .emit(db);
+        }
+    }
+
+    match (left_type, right_type) {
+        (
+            Type::Array {
+                elem: left_elem,
+                size: left_size,
+            },
+            Type::Array {
+                elem: right_elem,
+                size: right_size,
+            },
+        ) => {
+            if left_elem == right_elem {
+                Type::Array {
+                    elem: left_elem,
+                    size: left_size + right_size,
+                }
+            } else {
+                Diagnostic::new(Level::Error, "incompatible types")
+                    .with_attachment(
+                        Span::of_node(db, left.syntax()),
+                        format!("this is an array of {left_elem}"),
+                    )
+                    .with_attachment(
+                        Span::of_node(db, right.syntax()),
+                        format!("while this is an array of {right_elem}"),
+                    )
+                    .emit(db);
+                Type::Unknown
+            }
+        }
+        _ => Type::Unknown,
+    }
+}
+
+/// Returns the type of a field of a structure, reporting it if there is no such field
+fn field_type(db: &Database, ty: &Type, field: &Ident) -> Type {
+    let span = Span::of_token(db, field.syntax());
+    match ty {
+        Type::Unknown => Type::Unknown,
+        Type::Struct { name, .. } => match ty.field(field.text()) {
+            Some(field_ty) => field_ty.clone(),
+            None => {
+                Diagnostic::new(
+                    Level::Error,
+                    format!("no field {:?} in type {name}", field.text()),
+                )
+                .with_attachment(span, "unknown field")
+                .emit(db);
+                Type::Unknown
+            }
+        },
+        _ => {
+            Diagnostic::new(Level::Error, "incorrect type")
+                .with_attachment(
+                    span,
+                    format!(
+                        "{ty} is not a structure, it has no field {:?}",
+                        field.text()
+                    ),
+                )
+                .emit(db);
+            Type::Unknown
+        }
+    }
+}
+
+/// Type-checks a call by name
+///
+/// It is either a structure literal (`T { a = 1; b = 2.0 }`), that may update another structure
+/// (`T { s with a = 1 }`), or a call to a node with named arguments (`N { x = 1; y = 2 }`).
+fn check_call_by_name(
+    db: &Database,
+    expr: &CallByNameExpressionNode,
+    in_node: &Option<NodeNode>,
+) -> Type {
+    let name = some_or_unknown!(expr.name());
+    let ident = some_or_unknown!(name.name());
+    let span = Span::of_node(db, name.syntax());
+
+    let Some(decl) = Option::clone(&crate::name_resolution::resolve_type_decl(db, name.clone()))
+    else {
+        let node = Option::clone(&resolve_node(db, name.clone()));
+        let sig = match node {
+            Some(node) => Some(TypedSignature::clone(&crate::get_typed_signature(db, node))),
+            None => Option::clone(&resolve_extern_node(db, name.clone()))
+                .map(|ext| TypedSignature::clone(&crate::get_extern_signature(db, ext))),
+        };
+
+        let Some(sig) = sig else {
+            Diagnostic::new(
+                Level::Error,
+                format!("cannot resolve type {:?}", ident.text()),
+            )
+            .with_attachment(span, "not found in this scope")
+            .emit(db);
+            return Type::Unknown;
+        };
+        return check_named_call(db, expr, &sig, in_node);
+    };
+
+    let ty = type_of_type_decl(db, in_node.clone(), decl);
+    let Type::Struct { fields, .. } = &*ty else {
+        Diagnostic::new(Level::Error, "incorrect type")
+            .with_attachment(span, format!("{} is not a structure", ident.text()))
+            .emit(db);
+        return Type::Unknown;
+    };
+
+    if let Some(base) = expr.base().and_then(|b| b.ident()) {
+        let query = NameResolveQuery {
+            ident: base.clone(),
+            in_node: in_node.clone(),
+        };
+        let base_span = Span::of_token(db, base.syntax());
+        match Option::clone(&declared_type_of_ident(db, query)) {
+            Some(base_ty) if !base_ty.is_unknown() && base_ty != *ty => {
+                Diagnostic::new(Level::Error, "incorrect type")
+                    .with_attachment(base_span, format!("expected {ty}, found {base_ty}"))
+                    .emit(db);
+            }
+            Some(_) => (),
+            None => {
+                Diagnostic::new(Level::Error, format!("cannot find value {:?}", base.text()))
+                    .with_attachment(base_span, "not found in this scope")
+                    .emit(db);
+            }
+        }
+    }
+
+    let given = check_named_values(db, expr, in_node, "field", |field| {
+        field_type(db, &ty, field)
+    });
+
+    // Fields that are not given keep the value they have in the updated structure
+    if expr.base().is_none() {
+        let missing = fields
+            .iter()
+            .filter(|(field, _)| !given.contains(field))
+            .map(|(field, _)| format!("{field:?}"))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            Diagnostic::new(Level::Error, "missing fields")
+                .with_attachment(
+                    Span::of_node(db, expr.syntax()),
+                    format!("no value is given for {}", missing.join(", ")),
+                )
+                .emit(db);
+        }
+    }
+
+    Type::clone(&ty)
+}
+
+/// Type-checks a call to a node with named arguments (`N { x = 1; y = 2 }`)
+fn check_named_call(
+    db: &Database,
+    expr: &CallByNameExpressionNode,
+    sig: &TypedSignature,
+    in_node: &Option<NodeNode>,
+) -> Type {
+    if let Some(base) = expr.base() {
+        Diagnostic::new(Level::Error, "invalid update")
+            .with_attachment(
+                Span::of_node(db, base.syntax()),
+                "only structures can be updated with `with`",
+            )
+            .emit(db);
+    }
+
+    let node_name = sig.name.as_ref().map(|n| n.text().to_owned());
+    let given = check_named_values(db, expr, in_node, "argument", |param| {
+        let ty = sig.params.iter().find(|(p, _)| p.text() == param.text());
+        match ty {
+            Some((_, ty)) => ty.clone(),
+            None => {
+                Diagnostic::new(
+                    Level::Error,
+                    format!(
+                        "no parameter {:?} in node {}",
+                        param.text(),
+                        node_name.as_deref().unwrap_or_default()
+                    ),
+                )
+                .with_attachment(Span::of_token(db, param.syntax()), "unknown parameter")
+                .emit(db);
+                Type::Unknown
+            }
+        }
+    });
+
+    let missing = sig
+        .params
+        .iter()
+        .filter(|(param, _)| !given.iter().any(|g| g == param.text()))
+        .map(|(param, _)| format!("{:?}", param.text()))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        Diagnostic::new(Level::Error, "missing arguments")
+            .with_attachment(
+                Span::of_node(db, expr.syntax()),
+                format!("no value is given for {}", missing.join(", ")),
+            )
+            .emit(db);
+    }
+
+    if sig.return_params.len() == 1 {
+        sig.return_params[0].1.clone()
+    } else {
+        let cloned = sig.return_params.iter().map(|(_, t)| t).cloned().collect();
+        Type::Tuple(cloned)
+    }
+}
+
+/// Type-checks the values given by name in a call by name, and returns the names that are given
+///
+/// `expected` resolves the type of a name, reporting it if it is unknown. Names that are given
+/// twice are reported.
+fn check_named_values(
+    db: &Database,
+    expr: &CallByNameExpressionNode,
+    in_node: &Option<NodeNode>,
+    what: &str,
+    expected: impl Fn(&Ident) -> Type,
+) -> Vec<String> {
+    let mut given = Vec::<(String, CallByNameParamNode)>::new();
+    for param in expr.all_call_by_name_param_node() {
+        let name = param.id_node().and_then(|i| i.ident());
+        let (Some(name), Some(value)) = (name, param.expression_node()) else {
+            continue;
+        };
+
+        if let Some((_, first)) = given.iter().find(|(n, _)| n == name.text()) {
+            Diagnostic::new(
+                Level::Error,
+                format!("{what} {:?} is given twice", name.text()),
+            )
+            .with_attachment(Span::of_node(db, first.syntax()), "first given here")
+            .with_attachment(Span::of_node(db, param.syntax()), "given again here")
+            .emit(db);
+            continue;
+        }
+        given.push((name.text().to_owned(), param.clone()));
+
+        let expected = expected(&name);
+        let found = type_check_expression(db, &value, in_node, Some(expected.clone()));
+        if !expected.is_unknown() && !found.is_unknown() && found != expected {
+            Diagnostic::new(Level::Error, "incorrect type")
+                .with_attachment(
+                    Span::of_node(db, value.syntax()),
+                    format!("expected {expected}, found {found}"),
+                )
+                .emit(db);
+        }
+    }
+
+    given.into_iter().map(|(name, _)| name).collect()
+}
+
+/// Returns the values a clock variable can take, reporting it if it is not a valid clock
+///
+/// `None` is returned when these values cannot be known.
+fn clock_cases(db: &Database, ident: &Ident, in_node: &Option<NodeNode>) -> Option<Vec<ClockCase>> {
+    let query = NameResolveQuery {
+        ident: ident.clone(),
+        in_node: in_node.clone(),
+    };
+    let span = Span::of_token(db, ident.syntax());
+
+    match declared_type_of_ident(db, query.clone()).as_ref() {
+        Some(Type::Boolean) => Some(vec![ClockCase::Bool(true), ClockCase::Bool(false)]),
+        Some(Type::Enum { constructors, .. }) => Some(
+            constructors
+                .iter()
+                .map(|c| ClockCase::Constructor(c.clone()))
+                .collect(),
+        ),
+        Some(Type::Unknown) => None,
+        Some(ty) => {
+            Diagnostic::new(Level::Error, "incorrect type")
+                .with_attachment(
+                    span,
+                    format!("expected a boolean or enumerated clock, found {ty}"),
+                )
+                .emit(db);
+            None
+        }
+        None => {
+            Diagnostic::new(
+                Level::Error,
+                format!("cannot find value {:?}", ident.text()),
+            )
+            .with_attachment(span, "not found in this scope")
+            .emit(db);
+            None
+        }
+    }
+}
+
+/// Checks that the branches of a `merge` have the same type, and that there is exactly one for
+/// each value of the clock
+fn check_merge_expression(
+    db: &Database,
+    expr: &MergeExpressionNode,
+    in_node: &Option<NodeNode>,
+    expected_type: Option<Type>,
+) -> Type {
+    let clock = some_or_unknown!(expr.id_node().and_then(|i| i.ident()));
+    let cases = clock_cases(db, &clock, in_node);
+
+    let mut ty: Option<(Type, ExpressionNode)> = None;
+    let mut handled = Vec::<(ClockCase, MergeCaseNode)>::new();
+    for case in expr.all_merge_case_node() {
+        if let Some(value) = merge_case(&case) {
+            if let Some((_, first)) = handled.iter().find(|(v, _)| v == &value) {
+                Diagnostic::new(Level::Error, format!("`{value}` is handled twice"))
+                    .with_attachment(Span::of_node(db, first.syntax()), "first handled here")
+                    .with_attachment(Span::of_node(db, case.syntax()), "handled again here")
+                    .emit(db);
+            } else if cases.as_ref().is_some_and(|cases| !cases.contains(&value)) {
+                Diagnostic::new(Level::Error, "invalid merge case")
+                    .with_attachment(
+                        Span::of_node(db, case.syntax()),
+                        format!("`{value}` is not a possible value of {:?}", clock.text()),
+                    )
+                    .emit(db);
+            }
+            handled.push((value, case.clone()));
+        }
+
+        let Some(branch) = case.expression_node() else {
+            continue;
+        };
+        let branch_ty = type_check_expression(db, &branch, in_node, expected_type.clone());
+        match &ty {
+            Some((first_ty, first)) if first_ty != &branch_ty && !branch_ty.is_unknown() => {
+                Diagnostic::new(Level::Error, "incompatible types")
+                    .with_attachment(
+                        Span::of_node(db, first.syntax()),
+                        format!("this is of type {first_ty}"),
+                    )
+                    .with_attachment(
+                        Span::of_node(db, branch.syntax()),
+                        format!("while this is of type {branch_ty}"),
+                    )
+                    .emit(db);
+            }
+            Some(_) => (),
+            None if branch_ty.is_unknown() => (),
+            None => ty = Some((branch_ty, branch)),
+        }
+    }
+
+    let missing = cases
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|value| handled.iter().all(|(v, _)| v != value))
+        .map(|value| format!("`{value}`"))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        Diagnostic::new(Level::Error, "non-exhaustive merge")
+            .with_attachment(
+                Span::of_node(db, expr.syntax()),
+                format!("missing cases for {}", missing.join(", ")),
+            )
+            .emit(db);
+    }
+
+    ty.map(|(ty, _)| ty).unwrap_or_default()
+}
+
+fn check_call_expression(
+    db: &Database,
+    expr: &CallByPosExpressionNode,
+    sig: &TypedSignature,
+    in_node: &Option<NodeNode>,
+) -> Type {
+    // Check input parameters
+    let expected = sig.params.iter().map(Some).chain(std::iter::repeat(None));
+    let found = expr.args().skip(1).map(Some).chain(std::iter::repeat(None));
+    for (expected, found) in expected.zip(found) {
+        match (expected, found) {
+            (None, None) => break,
+            (Some((_, expected_ty)), Some(found)) => {
+                if !expected_ty.is_unknown() {
+                    let found_ty =
+                        type_check_expression(db, &found, in_node, Some(expected_ty.clone()));
+                    if !found_ty.is_unknown() && expected_ty != &found_ty {
+                        let span = Span::of_node(db, found.syntax());
+                        Diagnostic::new(Level::Error, "invalid type for argument")
+                            .with_attachment(
+                                span,
+                                format!("expected {expected_ty}, found {found_ty}"),
+                            )
+                            .emit(db);
+                    }
+                }
+            }
+            (Some((expected_ident, _expected_type)), None) => {
+                let error_span = expr
+                    .args()
+                    .last()
+                    .map(|s| Span::of_node(db, s.syntax()))
+                    .or_else(|| expr.open_par().map(|p| Span::of_token(db, p.syntax())))
+                    .or_else(|| expr.node_ref().map(|i| Span::of_node(db, i.syntax())))
+                    .unwrap_or_else(|| Span::of_node(db, expr.syntax()))
+                    .after();
+
+                let name_span = Span::of_node(db, expr.node_ref().unwrap().syntax());
+                let found_count = expr.args().skip(1).count();
+                let expected_count = sig.params.len();
+                let expected_ident = expected_ident.text();
+
+                Diagnostic::new(Level::Error, format!("missing argument {expected_ident:?}"))
+                    .with_attachment(name_span, format!("this function expects {expected_count} arguments but {found_count} were supplied"))
+                    .with_attachment(error_span, "hint: add the missing arguments(s)")
+                    .emit(db);
+            }
+            (None, Some(found)) => {
+                let arg_span = Span::of_node(db, found.syntax());
+                let name_span = Span::of_node(db, expr.node_ref().unwrap().syntax());
+                let found_count = expr.args().skip(1).count();
+                let expected_count = sig.params.len();
+
+                Diagnostic::new(Level::Error, "unexpected argument")
+                    .with_attachment(arg_span, "hint: remove this argument")
+                    .with_attachment(name_span, format!("this function expects {expected_count} arguments but {found_count} were supplied"))
+                    .emit(db);
+            }
+        }
+    }
+
+    // Find out returned value(s)
+    if sig.return_params.len() == 1 {
+        sig.return_params[0].1.clone()
+    } else {
+        let cloned = sig.return_params.iter().map(|(_, t)| t).cloned().collect();
+        Type::Tuple(cloned)
+    }
+}
+
+fn missing_static_arguments(db: &Database, name: &Ident) {
+    Diagnostic::new(Level::Error, "missing static arguments")
+        .with_attachment(
+            Span::of_token(db, name.syntax()),
+            format!(
+                "{:?} is generic and must be instantiated with `{}<<...>>`",
+                name.text(),
+                name.text()
+            ),
+        )
+        .emit(db);
+}
+
+/// Type-checks a call to an array iterator (`map<<f, 4>>(a)`)
+///
+/// Each argument is only typed once, as predefined operators get their types from the arguments.
+fn check_iterator_call(
+    db: &Database,
+    expr: &CallByPosExpressionNode,
+    iterator: ArrayIterator,
+    static_args: StaticArgsNode,
+    in_node: &Option<NodeNode>,
+    expected_type: Option<Type>,
+) -> Type {
+    let found = expr
+        .args()
+        .skip(1)
+        .map(|arg| (type_check_expression(db, &arg, in_node, None), arg))
+        .collect::<Vec<_>>();
+
+    let operand = match found.first() {
+        Some((Type::Array { elem, .. }, _)) if iterator == ArrayIterator::Map => *elem.clone(),
+        Some((ty, _)) if iterator.accumulates() => ty.clone(),
+        _ => Type::Unknown,
+    };
+    let ty = iterator_type(db, static_args, in_node.clone(), operand);
+    // Invalid static arguments have been reported
+    let Type::Function { args, ret } = Type::clone(&ty) else {
+        return expected_type.unwrap_or_default();
+    };
+
+    if args.len() != found.len() {
+        let name_span = Span::of_node(db, expr.node_ref().unwrap().syntax());
+        Diagnostic::new(Level::Error, "wrong number of arguments")
+            .with_attachment(
+                name_span,
+                format!(
+                    "this iterator expects {} arguments but {} were supplied",
+                    args.len(),
+                    found.len()
+                ),
+            )
+            .emit(db);
+    }
+
+    for ((found_ty, arg), expected_ty) in found.iter().zip(&args) {
+        if !compatible(expected_ty, found_ty) {
+            Diagnostic::new(Level::Error, "invalid type for argument")
+                .with_attachment(
+                    Span::of_node(db, arg.syntax()),
+                    format!("expected {expected_ty}, found {found_ty}"),
+                )
+                .emit(db);
+        }
+    }
+
+    match <[Type; 1]>::try_from(ret) {
+        Ok([ret]) => ret,
+        Err(ret) => Type::Tuple(ret),
+    }
+}
+
+fn type_check_left(db: &yeter::Database, expr: &LeftItemNode, in_node: &Option<NodeNode>) -> Type {
+    match expr {
+        LeftItemNode::IdNode(ident) => {
+            let query = NameResolveQuery {
+                ident: ident.ident().unwrap(),
+                in_node: in_node.clone(),
+            };
+            let resolved_node = resolve_runtime_node(db, query);
+            match *resolved_node {
+                Some(ResolvedRuntimeNode::Const(ref const_decl_node)) => type_of_ast_type(
+                    db,
+                    in_node.clone(),
+                    some_or_unknown!(const_decl_node.type_node()),
+                )
+                .as_ref()
+                .clone(),
+                Some(ResolvedRuntimeNode::Param(ref var_decl_node))
+                | Some(ResolvedRuntimeNode::ReturnParam(ref var_decl_node))
+                | Some(ResolvedRuntimeNode::Var(ref var_decl_node)) => type_of_ast_type(
+                    db,
+                    in_node.clone(),
+                    some_or_unknown!(var_decl_node.type_node()),
+                )
+                .as_ref()
+                .clone(),
+                None => {
+                    let name = ident.ident().unwrap();
+                    Diagnostic::new(Level::Error, format!("cannot find value {:?}", name.text()))
+                        .with_attachment(
+                            Span::of_token(db, name.syntax()),
+                            "not found in this scope",
+                        )
+                        .emit(db);
+                    Type::Unknown
+                }
+            }
+        }
+        LeftItemNode::LeftTableAccessNode(table_item) => {
+            let array = some_or_unknown!(table_item.left_item_node());
+            let array_type = type_check_left(db, &array, in_node);
+            check_array_access(
+                db,
+                &array_type,
+                Span::of_node(db, array.syntax()),
+                table_item.index(),
+                table_item.select_node(),
+                in_node,
+            )
+        }
+        LeftItemNode::LeftFieldAccessNode(field_item) => {
+            let ty = type_check_left(db, &some_or_unknown!(field_item.left_item_node()), in_node);
+            let field = some_or_unknown!(field_item.field().and_then(|f| f.ident()));
+            field_type(db, &ty, &field)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::name_resolution::find_node;
+
+    use super::*;
 
     #[test]
     fn node_type_check() {
@@ -922,4 +1829,644 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn node_without_body() {
+        let mut db = crate::driver();
+        crate::add_source_contents(&mut db, "node f(x: int) returns (y: int);".into());
+        let node = find_node(&db, "f".into()).as_ref().clone().unwrap();
+
+        assert_eq!(
+            type_check_query(&db, node).as_ref(),
+            &Type::Function {
+                args: vec![Type::Integer],
+                ret: vec![Type::Integer]
+            }
+        );
+    }
+
+    #[test]
+    fn merge_cases() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type color = enum { Red, Green, Blue };
+
+             node n(c : bool; col : color; x : int) returns (y, z, w : int);
+             let
+                 y = merge c (true -> x when c) (true -> 0 when c);
+                 z = merge col (Red -> x when Red(col)) (Green -> 1.0 when Green(col));
+                 w = merge c (true -> 0) (Red -> 1) (false -> 2);
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        for expected in [
+            "`true` is handled twice",
+            "incompatible types",
+            "invalid merge case",
+        ] {
+            assert!(messages.contains(&expected.to_owned()), "{messages:?}");
+        }
+        assert_eq!(
+            messages
+                .iter()
+                .filter(|m| *m == "non-exhaustive merge")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn struct_types() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type point = struct { x, y : real };
+             type segment = { a : point; b : point };
+
+             node n(s : segment) returns (d : real; p : point; ok : bool; e : int);
+             let
+                 d = s.b.x - s.a.x;
+                 p = point { x = d; y = 0 };
+                 ok = s.a = p;
+                 e = s.c + d.x;
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let point = Type::Struct {
+            name: "point".into(),
+            fields: vec![("x".into(), Type::Real), ("y".into(), Type::Real)],
+        };
+        let segment = Type::Struct {
+            name: "segment".into(),
+            fields: vec![("a".into(), point.clone()), ("b".into(), point.clone())],
+        };
+        assert_eq!(
+            *type_check_query(&db, node),
+            Type::Function {
+                args: vec![segment],
+                ret: vec![Type::Real, point, Type::Boolean, Type::Integer]
+            }
+        );
+
+        let messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        for expected in [
+            "expected real, found int",
+            "unknown field",
+            "real is not a structure, it has no field \"x\"",
+        ] {
+            assert!(messages.contains(&expected.to_owned()), "{messages:?}");
+        }
+    }
+
+    #[test]
+    fn array_types() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "const c : int^4 = [1, 2, 3] | [4];
+
+             node n(x : bool^4; i : int) returns (a : bool; b : bool^3; c2 : int^6; d : bool^3);
+             let
+                 a = x[i] or x[5];
+                 b = x[3 .. 1];
+                 c2 = c | [5, true];
+                 d[0 .. 1] = x[0 .. 3 step 2];
+                 d[2] = x[1];
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let array = |elem: Type, size| Type::Array {
+            elem: Box::new(elem),
+            size,
+        };
+        assert_eq!(
+            *type_check_query(&db, node),
+            Type::Function {
+                args: vec![array(Type::Boolean, 4), Type::Integer],
+                ret: vec![
+                    Type::Boolean,
+                    array(Type::Boolean, 3),
+                    array(Type::Integer, 6),
+                    array(Type::Boolean, 3),
+                ]
+            }
+        );
+
+        let mut messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        // Diagnostics emitted by different queries come in no particular order
+        messages.sort();
+        assert_eq!(
+            messages,
+            [
+                "expected int, found bool",
+                "the length is 4 but the index is 5",
+            ]
+        );
+    }
+
+    #[test]
+    fn enum_types() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type mode = enum { Off, Idle, Run };
+             const start : mode = Idle;
+
+             node n(m : mode) returns (a : bool; b : bool; c : mode);
+             let
+                 a = m = Run;
+                 b = m = 1;
+                 c = if m <> start then Off else m;
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let node = Option::clone(&find_node(&db, "n".into())).unwrap();
+        let mode = Type::Enum {
+            name: "mode".into(),
+            constructors: vec!["Off".into(), "Idle".into(), "Run".into()],
+        };
+        assert_eq!(
+            *type_check_query(&db, node),
+            Type::Function {
+                args: vec![mode.clone()],
+                ret: vec![Type::Boolean, Type::Boolean, mode]
+            }
+        );
+
+        let value = crate::eval::eval_const_node(&db, start_expr(&db), None);
+        assert_eq!(*value, Some(ConstValue::Enum("Idle".into())));
+
+        let errors = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            ["this is of type mode", "while this is of type int"]
+        );
+    }
+
+    fn start_expr(db: &Database) -> ExpressionNode {
+        crate::parsed_files(db)
+            .iter()
+            .flat_map(|f| f.all_constant_decl_node())
+            .flat_map(|c| c.all_one_constant_decl_node())
+            .find_map(|c| c.expression_node())
+            .unwrap()
+    }
+
+    #[test]
+    fn generic_nodes() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "node fold<<type t; const n : int; node f(a, b : t) returns (c : t)>>(x : t^n; init : t)
+             returns (s : t);
+             let
+                 s = f(x[0], init);
+             tel
+
+             function add(a, b : int) returns (c : int);
+             let
+                 c = a + b;
+             tel
+
+             node fold8 = fold<<int, 8, add>>;
+
+             function n(a : int^8) returns (r : int; q : int; z : int);
+             let
+                 r = fold8(a, 1);
+                 q = fold<<int, 2 * 4, add>>(a, 2);
+                 z = fold<<int, 4, add>>(a, 3);
+             tel
+
+             node wrong_arity = fold<<int, 8>>;
+             node wrong_kind = fold<<4, int, add>>;"
+                .into(),
+        );
+        crate::check(&db);
+
+        let fold8 = Option::clone(&find_node(&db, "fold8".into())).unwrap();
+        let array = |elem: Type, size| Type::Array {
+            elem: Box::new(elem),
+            size,
+        };
+        assert_eq!(
+            *type_check_query(&db, fold8),
+            Type::Function {
+                args: vec![array(Type::Integer, 8), Type::Integer],
+                ret: vec![Type::Integer],
+            }
+        );
+
+        let fold = Option::clone(&find_node(&db, "fold".into())).unwrap();
+        assert_eq!(
+            *type_check_query(&db, fold),
+            Type::Function {
+                args: vec![Type::Unknown, Type::Abstract("t".into())],
+                ret: vec![Type::Abstract("t".into())],
+            }
+        );
+
+        let mut messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            [
+                "\"fold\" expects 3 static arguments but 2 were supplied",
+                "expected a constant for the static parameter \"n\"",
+                "expected a type for the static parameter \"t\"",
+                "expected int^4, found int^8",
+            ]
+        );
+    }
+
+    #[test]
+    fn package_instances() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "model Fifo
+               needs type elem; const size : int;
+               provides node push(x : elem) returns (y : elem^size); const cap : int;
+             body
+               const cap : int = size * 2;
+               function push(x : elem) returns (y : elem^size);
+               let
+                 y = x^size;
+               tel
+             end
+
+             package IntFifo = Fifo(elem = int; size = 4);
+             package Bad = Fifo(elem = 3; other = 1);
+
+             function main(x : int; b : bool) returns (y : int^4; c : int^IntFifo::cap; z : int^4);
+             let
+               y = IntFifo::push(x);
+               c = 0^IntFifo::cap;
+               z = IntFifo::push(b);
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let push = Option::clone(&find_node(&db, "IntFifo::push".into())).unwrap();
+        let instance = Option::clone(&crate::name_resolution::find_package_instance(
+            &db,
+            "IntFifo".into(),
+        ))
+        .unwrap();
+        let sig = package_instance_signature(&db, instance, push.clone());
+        assert_eq!(sig.params[0].1, Type::Integer);
+        assert_eq!(
+            sig.return_params[0].1,
+            Type::Array {
+                elem: Box::new(Type::Integer),
+                size: 4,
+            }
+        );
+
+        // The model itself is checked with its needs left abstract
+        assert_eq!(
+            *type_check_query(&db, push),
+            Type::Function {
+                args: vec![Type::Abstract("elem".into())],
+                ret: vec![Type::Unknown],
+            }
+        );
+
+        let mut messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            [
+                "\"Fifo\" has no static parameter \"other\"",
+                "\"Fifo\" needs a constant \"size\"",
+                "expected a type for the static parameter \"elem\"",
+                "expected int, found bool",
+            ]
+        );
+    }
+
+    #[test]
+    fn extern_nodes() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "extern function sqrt(x : real) returns (y : real);
+             unsafe extern node rand(seed : int) returns (r : int);
+
+             function f(x : real; i : int) returns (y : real; z : real);
+             let
+               y = sqrt(x);
+               z = sqrt(i);
+             tel
+
+             node g(x : int) returns (y : int);
+             let
+               y = rand(x);
+             tel
+
+             unsafe node h(x : int) returns (y : int);
+             let
+               y = rand(x);
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let sqrt = Option::clone(&crate::name_resolution::find_extern_node(
+            &db,
+            "sqrt".into(),
+        ));
+        let sig = crate::get_extern_signature(&db, sqrt.unwrap());
+        assert_eq!(sig.params[0].1, Type::Real);
+        assert_eq!(sig.return_params[0].1, Type::Real);
+
+        // Calls to extern nodes declared as `node` are stateful
+        let g = Option::clone(&find_node(&db, "g".into())).unwrap();
+        assert!(*crate::node_state::is_node_stateful(&db, g));
+        let f = Option::clone(&find_node(&db, "f".into())).unwrap();
+        assert!(!*crate::node_state::is_node_stateful(&db, f));
+
+        let mut messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| !matches!(d.level, Level::Debug))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            [
+                "expected real, found int",
+                "hint: declare the calling node as `unsafe`",
+            ]
+        );
+    }
+
+    #[test]
+    fn call_by_name() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type point = struct { x : int; y : int };
+
+             function add(a, b : int) returns (c : int);
+             let
+               c = a + b;
+             tel
+
+             function f(p : point; b : bool) returns (q : point; r : point; s : point; u : int; v : int);
+             let
+               q = point { p with y = 1 };
+               r = point { x = 1 };
+               s = point { b with x = 1; y = 2; x = 3; z = 4 };
+               u = add { b = 2; a = 1 };
+               v = add { a = 1; c = true };
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let mut messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        messages.sort();
+        assert_eq!(
+            messages,
+            [
+                "expected point, found bool",
+                "first given here",
+                "given again here",
+                "no value is given for \"b\"",
+                "no value is given for \"y\"",
+                "unknown field",
+                "unknown parameter",
+            ]
+        );
+    }
+
+    fn error_messages(db: &Database) -> Vec<String> {
+        let mut messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .flat_map(|d| d.attachments)
+            .map(|(_, message)| message)
+            .collect::<Vec<_>>();
+        messages.sort();
+        messages
+    }
+
+    #[test]
+    fn equality_is_boolean() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "function f(a : int; b : int; x : real; y : real) returns (c : bool; d : bool);
+             let
+               c = a = b;
+               d = x <> y;
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let node = Option::clone(&find_node(&db, "f".into())).unwrap();
+        assert_eq!(
+            *type_check_query(&db, node),
+            Type::Function {
+                args: vec![Type::Integer, Type::Integer, Type::Real, Type::Real],
+                ret: vec![Type::Boolean, Type::Boolean]
+            }
+        );
+        assert!(error_messages(&db).is_empty());
+    }
+
+    #[test]
+    fn diese_and_nor_operands() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "function f(a : bool; b : bool; x : int) returns (c : bool; d : bool);
+             let
+               c = #(a, b);
+               d = nor(a, x);
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        assert_eq!(error_messages(&db), ["expected boolean, found int"]);
+    }
+
+    #[test]
+    fn call_arguments_in_caller_scope() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "function g(x : int) returns (y : int);
+             let
+               y = x;
+             tel
+
+             function f(a : int) returns (b : int);
+             var t : int;
+             let
+               t = a;
+               b = g(t);
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        assert!(error_messages(&db).is_empty());
+    }
+
+    #[test]
+    fn non_constant_indices() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "function f(i : int) returns (j : int);
+             let
+               j = i;
+             tel
+
+             function g(a : int^3; i : int; c : bool) returns (x : int; y : int);
+             let
+               x = a[f(i)];
+               y = a[if c then 0 else 1];
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        assert!(error_messages(&db).is_empty());
+    }
+
+    #[test]
+    fn nominal_structs() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type A = { x : int };
+             type B = { x : int };
+             type C = A;
+
+             function f(a : A) returns (b : B; c : C);
+             let
+               b = a;
+               c = a;
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        assert_eq!(
+            error_messages(&db),
+            [
+                "the left term is of type B",
+                "while the right term is of type A"
+            ]
+        );
+    }
+
+    #[test]
+    fn recursive_types() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "type T = { next : T };
+             type U = V^2;
+             type V = { u : U };
+
+             function f(t : T; v : V) returns (u : T);
+             let
+               u = t;
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        for name in ["T", "U", "V"] {
+            assert!(messages.contains(&format!("type {name:?} is recursive")));
+        }
+    }
+
+    #[test]
+    fn undeclared_left() {
+        let mut db = crate::driver();
+        crate::add_source_contents(
+            &mut db,
+            "function f(a : int) returns (b : int);
+             let
+               b = a;
+               q = 3;
+             tel"
+            .into(),
+        );
+        crate::check(&db);
+
+        let messages = db
+            .effect::<Diagnostic>()
+            .into_iter()
+            .filter(|d| matches!(d.level, Level::Error))
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert!(messages.contains(&"cannot find value \"q\"".to_string()));
+    }
 }
@@ -0,0 +1,100 @@
+//! Conversion of the diagnostics of the compiler to LSP diagnostics
+
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::line_index::LineIndex;
+use lsp_types::{DiagnosticRelatedInformation, DiagnosticSeverity, Location};
+use rustre_core::diagnostics::{Diagnostic, Level, Span};
+use rustre_core::SourceFile;
+use yeter::Database;
+
+/// Lists the diagnostics of the current version of the program, by file
+///
+/// If files were edited, the effects of queries computed on their former versions are still in the
+/// database: they are left out, as well as duplicates (the same expression may be checked by several
+/// queries).
+/// Diagnostics that aren't attached to any source code can't be shown in an editor.
+pub fn current_diagnostics(db: &Database) -> HashMap<PathBuf, Vec<Diagnostic>> {
+    let mut seen = HashSet::new();
+    let mut by_file = HashMap::<_, Vec<_>>::new();
+
+    for diagnostic in db.effect::<Diagnostic>() {
+        let Some((path, _)) = diagnostic.file_context() else {
+            continue;
+        };
+        if diagnostic.is_outdated(db) || !seen.insert(format!("{diagnostic:?}")) {
+            continue;
+        }
+
+        by_file.entry(path.to_owned()).or_default().push(diagnostic);
+    }
+
+    by_file
+}
+
+/// Converts a diagnostic whose first attachment is in `file`
+///
+/// The message of the first attachment is appended to the one of the diagnostic, the other ones are
+/// given as related information.
+pub fn to_lsp(
+    diagnostic: &Diagnostic,
+    file: &SourceFile,
+    location: impl Fn(&Span) -> Option<Location>,
+) -> lsp_types::Diagnostic {
+    let severity = match diagnostic.level {
+        Level::Debug => DiagnosticSeverity::HINT,
+        Level::Info => DiagnosticSeverity::INFORMATION,
+        Level::Warning => DiagnosticSeverity::WARNING,
+        Level::Error => DiagnosticSeverity::ERROR,
+    };
+
+    let (span, label) = &diagnostic.attachments[0];
+    let message = match label.as_str() {
+        "" => diagnostic.message.clone(),
+        label => format!("{}: {label}", diagnostic.message),
+    };
+
+    let related = diagnostic.attachments[1..]
+        .iter()
+        .filter_map(|(span, message)| {
+            Some(DiagnosticRelatedInformation {
+                location: location(span)?,
+                message: message.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    lsp_types::Diagnostic {
+        range: LineIndex::new(&file.text).range(span.start, span.end),
+        severity: Some(severity),
+        source: Some("rustre".into()),
+        message,
+        related_information: Some(related).filter(|r| !r.is_empty()),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostics_by_file() {
+        let (db, file) =
+            crate::test_file("function n(x : int) returns (y : bool);\nlet y = x; tel\n");
+        rustre_core::check(&db);
+
+        let diagnostics = current_diagnostics(&db);
+        let lsp = diagnostics[&file.path]
+            .iter()
+            .map(|d| to_lsp(d, &file, |_| None))
+            .collect::<Vec<_>>();
+        assert_eq!(lsp.len(), 1);
+        assert_eq!(lsp[0].range.start.line, 1);
+
+        let (db, _) = crate::test_file("function n(x : int) returns (y : int);\nlet y = x; tel\n");
+        rustre_core::check(&db);
+        assert!(current_diagnostics(&db).is_empty());
+    }
+}
@@ -0,0 +1,70 @@
+//! Conversions between byte offsets and LSP positions
+//!
+//! Positions are given as lines and UTF-16 code units, which is the only encoding all clients
+//! support.
+
+use lsp_types::{Position, Range};
+
+/// Start offsets of the lines of a text
+pub struct LineIndex<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let lines = text.match_indices('\n').map(|(i, _)| i + 1);
+        LineIndex {
+            text,
+            starts: std::iter::once(0).chain(lines).collect(),
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.starts[line];
+        let character = self.text[start..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    pub fn range(&self, start: usize, end: usize) -> Range {
+        Range::new(self.position(start), self.position(end))
+    }
+
+    /// Returns the byte offset of a position, clamped to the end of its line
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.starts.get(position.line as usize) else {
+            return self.text.len();
+        };
+
+        let mut units = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if c == '\n' || units >= position.character as usize {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        self.text.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let index = LineIndex::new("node é()\n  x = 1;\n");
+
+        assert_eq!(index.position(0), Position::new(0, 0));
+        assert_eq!(index.position(7), Position::new(0, 6));
+        assert_eq!(index.position(12), Position::new(1, 2));
+        assert_eq!(index.position(100), Position::new(2, 0));
+
+        assert_eq!(index.offset(Position::new(0, 6)), 7);
+        assert_eq!(index.offset(Position::new(1, 2)), 12);
+        assert_eq!(index.offset(Position::new(1, 40)), 18);
+        assert_eq!(index.offset(Position::new(5, 0)), 19);
+    }
+}
@@ -0,0 +1,409 @@
+//! Language server for Lustre, speaking LSP over stdio
+//!
+//! Every open document is a root file of a compiler [driver][rustre_core::driver], that is created
+//! again on each change. Yéter only invalidates the queries that directly read an edited input, so
+//! the ones that depend on it through other queries would keep outdated results in a long-lived
+//! driver.
+
+mod diagnostics;
+mod line_index;
+mod navigation;
+mod symbols;
+
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::line_index::LineIndex;
+use lsp_server::{Connection, ExtractError, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{DocumentSymbolRequest, GotoDefinition, HoverRequest};
+use lsp_types::{
+    DocumentSymbolResponse, GotoDefinitionResponse, Hover, HoverContents, HoverProviderCapability,
+    InitializeParams, Location, MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+use rustre_core::diagnostics::Span;
+use rustre_core::SourceFile;
+use yeter::Database;
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+    let _params: InitializeParams = serde_json::from_value(params)?;
+
+    let mut server = Server::new(connection);
+    server.run()?;
+
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Server {
+    db: Database,
+    connection: Connection,
+    /// Contents of the open documents, by path
+    documents: BTreeMap<PathBuf, String>,
+    /// Documents for which diagnostics have been published, that have to be cleared once fixed
+    published: HashSet<Url>,
+}
+
+impl Server {
+    fn new(connection: Connection) -> Self {
+        Server {
+            db: rustre_core::driver(),
+            connection,
+            documents: BTreeMap::new(),
+            published: HashSet::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.handle_request(request)?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let id = request.id.clone();
+
+        let request = match cast_request::<HoverRequest>(request) {
+            Ok((id, params)) => {
+                let position = params.text_document_position_params;
+                let hover = self.file(&position.text_document.uri).and_then(|file| {
+                    let index = LineIndex::new(&file.text);
+                    let offset = index.offset(position.position);
+                    let (text, span) = navigation::hover(&self.db, &file, offset)?;
+
+                    Some(Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: text,
+                        }),
+                        range: Some(index.range(span.start, span.end)),
+                    })
+                });
+                return self.respond(id, hover);
+            }
+            Err(ExtractError::MethodMismatch(request)) => request,
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_params(id, &method, error)
+            }
+        };
+
+        let request = match cast_request::<GotoDefinition>(request) {
+            Ok((id, params)) => {
+                let position = params.text_document_position_params;
+                let definition = self.file(&position.text_document.uri).and_then(|file| {
+                    let offset = LineIndex::new(&file.text).offset(position.position);
+                    let span = navigation::definition(&self.db, &file, offset)?;
+                    self.location(&span).map(GotoDefinitionResponse::Scalar)
+                });
+                return self.respond(id, definition);
+            }
+            Err(ExtractError::MethodMismatch(request)) => request,
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_params(id, &method, error)
+            }
+        };
+
+        let request = match cast_request::<DocumentSymbolRequest>(request) {
+            Ok((id, params)) => {
+                let symbols = self.file(&params.text_document.uri).map(|file| {
+                    DocumentSymbolResponse::Nested(symbols::document_symbols(&self.db, &file))
+                });
+                return self.respond(id, symbols);
+            }
+            Err(ExtractError::MethodMismatch(request)) => request,
+            Err(ExtractError::JsonError { method, error }) => {
+                return self.invalid_params(id, &method, error)
+            }
+        };
+
+        let response = Response::new_err(
+            request.id,
+            lsp_server::ErrorCode::MethodNotFound as i32,
+            format!("unsupported request {}", request.method),
+        );
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    fn handle_notification(
+        &mut self,
+        notification: Notification,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let document = params.text_document;
+                if let Ok(path) = document.uri.to_file_path() {
+                    self.documents.insert(path, document.text);
+                    self.reload();
+                    self.publish_diagnostics()?;
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // Documents are synchronized in full, the last change is the whole new text
+                let text = params.content_changes.into_iter().last().map(|c| c.text);
+                if let (Ok(path), Some(text)) = (params.text_document.uri.to_file_path(), text) {
+                    self.documents.insert(path, text);
+                    self.reload();
+                    self.publish_diagnostics()?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // A closed document is read from the disk again if it is still included by another one
+                if let Ok(path) = params.text_document.uri.to_file_path() {
+                    self.documents.remove(&path);
+                    self.reload();
+                    self.publish_diagnostics()?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Creates a new driver, whose root files are the open documents
+    fn reload(&mut self) {
+        self.db = rustre_core::driver();
+        for (path, text) in &self.documents {
+            rustre_core::set_source_contents(&self.db, path.clone(), text.clone());
+        }
+    }
+
+    /// Checks the whole program again, and publishes the diagnostics of each of its files
+    fn publish_diagnostics(&mut self) -> Result<(), Box<dyn Error + Sync + Send>> {
+        rustre_core::check(&self.db);
+
+        let files = rustre_core::files(&self.db);
+        let files = files.as_ref().as_deref().unwrap_or_default();
+        let mut by_file = diagnostics::current_diagnostics(&self.db);
+
+        let uris = files
+            .iter()
+            .filter_map(|file| Some((Url::from_file_path(&file.path).ok()?, file)))
+            .collect::<Vec<_>>();
+        let mut published = HashSet::new();
+        for (uri, file) in &uris {
+            let diagnostics = by_file.remove(&file.path).unwrap_or_default();
+            let diagnostics = diagnostics
+                .iter()
+                .map(|d| diagnostics::to_lsp(d, file, |span| self.location(span)))
+                .collect();
+            self.notify::<PublishDiagnostics>(PublishDiagnosticsParams::new(
+                uri.clone(),
+                diagnostics,
+                None,
+            ))?;
+            published.insert(uri.clone());
+        }
+
+        // Files that are not part of the program anymore have no diagnostics
+        for uri in self.published.difference(&published) {
+            self.notify::<PublishDiagnostics>(PublishDiagnosticsParams::new(
+                uri.clone(),
+                Vec::new(),
+                None,
+            ))?;
+        }
+        self.published = published;
+
+        Ok(())
+    }
+
+    /// Returns the loaded file a document corresponds to
+    fn file(&self, uri: &Url) -> Option<SourceFile> {
+        let path = uri.to_file_path().ok()?;
+        source_file(&self.db, &path)
+    }
+
+    /// Converts a span to a location in the editor
+    fn location(&self, span: &Span) -> Option<Location> {
+        let file = source_file(&self.db, &span.file)?;
+        let uri = Url::from_file_path(&span.file).ok()?;
+        let range = LineIndex::new(&file.text).range(span.start, span.end);
+        Some(Location::new(uri, range))
+    }
+
+    fn respond<T: serde::Serialize>(
+        &self,
+        id: RequestId,
+        result: T,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let response = Response::new_ok(id, result);
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    /// Answers a request whose parameters couldn't be read
+    fn invalid_params(
+        &self,
+        id: RequestId,
+        method: &str,
+        error: serde_json::Error,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let response = Response::new_err(
+            id,
+            lsp_server::ErrorCode::InvalidParams as i32,
+            format!("invalid parameters for {method}: {error}"),
+        );
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    fn notify<N: lsp_types::notification::Notification>(
+        &self,
+        params: N::Params,
+    ) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let notification = Notification::new(N::METHOD.to_owned(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+}
+
+/// Finds a file of the program by path
+pub fn source_file(db: &Database, path: &Path) -> Option<SourceFile> {
+    let files = rustre_core::files(db);
+    let files = files.as_ref().as_deref().unwrap_or_default();
+    files.iter().find(|file| file.path == path).cloned()
+}
+
+fn cast_request<R>(request: Request) -> Result<(RequestId, R::Params), ExtractError<Request>>
+where
+    R: lsp_types::request::Request,
+    R::Params: serde::de::DeserializeOwned,
+{
+    request.extract(R::METHOD)
+}
+
+#[cfg(test)]
+pub(crate) fn test_file(text: &str) -> (Database, SourceFile) {
+    let db = rustre_core::driver();
+    let path = std::path::PathBuf::from("/lsp-test/main.lus");
+    rustre_core::set_source_contents(&db, path.clone(), text.to_owned());
+    let file = source_file(&db, &path).unwrap();
+    (db, file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const URI: &str = "file:///lsp-test/main.lus";
+
+    fn server() -> (Server, Connection) {
+        let (server, client) = Connection::memory();
+        (Server::new(server), client)
+    }
+
+    /// Sends a notification about the test document to the server
+    fn notify(server: &mut Server, method: &str, params: serde_json::Value) {
+        let notification = Notification::new(method.to_owned(), params);
+        server.handle_notification(notification).unwrap();
+    }
+
+    /// Returns the number of diagnostics of each document in the last diagnostics published for it
+    fn published(client: &Connection) -> Vec<(String, usize)> {
+        client
+            .receiver
+            .try_iter()
+            .filter_map(|message| match message {
+                Message::Notification(n) if n.method == PublishDiagnostics::METHOD => {
+                    let params: PublishDiagnosticsParams = serde_json::from_value(n.params).ok()?;
+                    Some((params.uri.to_string(), params.diagnostics.len()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn edited_document() {
+        let (mut server, client) = server();
+        let text = "function n(x : int) returns (y : bool);\nlet y = x; tel\n";
+        notify(
+            &mut server,
+            DidOpenTextDocument::METHOD,
+            json!({ "textDocument": { "uri": URI, "languageId": "lustre", "version": 0, "text": text } }),
+        );
+        assert_eq!(published(&client), [(URI.to_owned(), 1)]);
+
+        let fixed = "function n(x : int) returns (y : int);\nlet y = x; tel\n";
+        notify(
+            &mut server,
+            DidChangeTextDocument::METHOD,
+            json!({
+                "textDocument": { "uri": URI, "version": 1 },
+                "contentChanges": [{ "text": fixed }],
+            }),
+        );
+        assert_eq!(published(&client), [(URI.to_owned(), 0)]);
+    }
+
+    #[test]
+    fn closed_document() {
+        let (mut server, client) = server();
+        let text = "function n(x : int) returns (y : bool);\nlet y = x; tel\n";
+        notify(
+            &mut server,
+            DidOpenTextDocument::METHOD,
+            json!({ "textDocument": { "uri": URI, "languageId": "lustre", "version": 0, "text": text } }),
+        );
+        assert_eq!(published(&client), [(URI.to_owned(), 1)]);
+
+        notify(
+            &mut server,
+            DidCloseTextDocument::METHOD,
+            json!({ "textDocument": { "uri": URI } }),
+        );
+        assert_eq!(published(&client), [(URI.to_owned(), 0)]);
+        assert!(server.file(&Url::parse(URI).unwrap()).is_none());
+    }
+
+    #[test]
+    fn invalid_params() {
+        let (server, client) = server();
+        let request = Request::new(RequestId::from(1), "textDocument/hover".into(), json!({}));
+        server.handle_request(request).unwrap();
+
+        let Ok(Message::Response(response)) = client.receiver.try_recv() else {
+            panic!("expected a response");
+        };
+        assert_eq!(response.id, RequestId::from(1));
+        assert_eq!(
+            response.error.map(|e| e.code),
+            Some(lsp_server::ErrorCode::InvalidParams as i32)
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Hover and go-to-definition
+
+use rustre_core::diagnostics::Span;
+use rustre_core::name_resolution::{self, NameResolveQuery, ResolvedRuntimeNode};
+use rustre_core::types::type_check_expression;
+use rustre_core::{SourceFile, TypedSignature};
+use rustre_parser::ast::{
+    AstNode, AstToken, CallByPosExpressionNode, ExpressionNode, FieldAccessExpressionNode, IdNode,
+    Ident, NodeNode, TypeNode,
+};
+use yeter::Database;
+
+/// Returns the identifier at an offset of a file, if any
+fn ident_at(db: &Database, file: &SourceFile, offset: usize) -> Option<Ident> {
+    let root = rustre_core::parse_file(db, file.clone());
+    let offset = u32::try_from(offset).ok()?;
+    root.syntax()
+        .token_at_offset(offset.into())
+        .find_map(Ident::cast)
+}
+
+/// Returns `true` if an expression is the name of the node in a call
+fn is_node_ref(expr: &ExpressionNode) -> bool {
+    let call = expr
+        .syntax()
+        .parent()
+        .and_then(CallByPosExpressionNode::cast);
+    call.and_then(|c| c.node_ref())
+        .is_some_and(|r| r.syntax() == expr.syntax())
+}
+
+/// Returns `true` if an expression is the name of the field in a field access
+fn is_field_name(expr: &ExpressionNode) -> bool {
+    let access = expr
+        .syntax()
+        .parent()
+        .and_then(FieldAccessExpressionNode::cast);
+    access
+        .and_then(|a| a.right())
+        .is_some_and(|r| r.syntax() == expr.syntax())
+}
+
+fn format_signature(keyword: &str, sig: &TypedSignature) -> String {
+    let params = |params: &[(Ident, rustre_core::types::Type)]| {
+        params
+            .iter()
+            .map(|(id, ty)| format!("{} : {ty}", id.text()))
+            .collect::<Vec<_>>()
+            .join("; ")
+    };
+    let name = sig.name.as_ref().map(|n| n.text()).unwrap_or_default();
+
+    format!(
+        "{keyword} {name}({}) returns ({})",
+        params(&sig.params),
+        params(&sig.return_params)
+    )
+}
+
+/// Describes what is at an offset of a file: the type of an expression, or the signature of a
+/// called node
+///
+/// The returned span is the part of the source that is described.
+pub fn hover(db: &Database, file: &SourceFile, offset: usize) -> Option<(String, Span)> {
+    let root = rustre_core::parse_file(db, file.clone());
+    let offset = u32::try_from(offset).ok()?;
+    let token = root
+        .syntax()
+        .token_at_offset(offset.into())
+        .right_biased()?;
+
+    let mut exprs = token.parent_ancestors().filter_map(ExpressionNode::cast);
+    let mut expr = exprs.next()?;
+    let in_node = token.parent_ancestors().find_map(NodeNode::cast);
+
+    if is_node_ref(&expr) {
+        let id = expr.syntax().children().find_map(IdNode::cast)?;
+        let sig = match Option::clone(&name_resolution::resolve_node(db, id.clone())) {
+            Some(node) => {
+                let keyword = if node.is_function() {
+                    "function"
+                } else {
+                    "node"
+                };
+                format_signature(keyword, &rustre_core::get_typed_signature(db, node))
+            }
+            None => {
+                let node = Option::clone(&name_resolution::resolve_extern_node(db, id.clone()))?;
+                let keyword = if node.is_function() {
+                    "function"
+                } else {
+                    "node"
+                };
+                let sig = rustre_core::get_extern_signature(db, node);
+                format!("extern {}", format_signature(keyword, &sig))
+            }
+        };
+        return Some((
+            format!("```lustre\n{sig}\n```"),
+            Span::of_node(db, id.syntax()),
+        ));
+    }
+
+    // The name of a field has no type on its own
+    if is_field_name(&expr) {
+        expr = exprs.next()?;
+    }
+
+    let ty = type_check_expression(db, &expr, &in_node, None);
+    Some((
+        format!("```lustre\n{ty}\n```"),
+        Span::of_node(db, expr.syntax()),
+    ))
+}
+
+/// Finds where the name at an offset of a file is declared
+pub fn definition(db: &Database, file: &SourceFile, offset: usize) -> Option<Span> {
+    let ident = ident_at(db, file, offset)?;
+    let id = IdNode::cast(ident.syntax().parent()?)?;
+    let name = id.name()?;
+
+    // Package qualifier, as `P` in `P::x`
+    if id.package().as_ref() == Some(&ident) {
+        let package = name_resolution::find_package(db, ident.text().to_owned());
+        if let Some(package) = Option::clone(&package) {
+            return Some(Span::of_node(db, package.id_node()?.syntax()));
+        }
+        let instance = name_resolution::find_package_instance(db, ident.text().to_owned());
+        return Some(Span::of_node(
+            db,
+            Option::clone(&instance)?.name()?.syntax(),
+        ));
+    }
+
+    let of_id = |id: Option<IdNode>| Some(Span::of_node(db, id?.syntax()));
+    let is_type = id.syntax().parent().and_then(TypeNode::cast).is_some();
+
+    if !is_type {
+        let in_node = id.syntax().ancestors().find_map(NodeNode::cast);
+        let query = NameResolveQuery {
+            ident: name.clone(),
+            in_node,
+        };
+        match &*name_resolution::resolve_runtime_node(db, query) {
+            Some(ResolvedRuntimeNode::Const(decl)) => {
+                let id = decl
+                    .all_id_node()
+                    .find(|i| i.name().as_ref() == Some(&name));
+                return of_id(id.or_else(|| decl.all_id_node().next()));
+            }
+            Some(
+                ResolvedRuntimeNode::Param(ids)
+                | ResolvedRuntimeNode::ReturnParam(ids)
+                | ResolvedRuntimeNode::Var(ids),
+            ) => {
+                let decl = ids.all_ident().find(|i| i.text() == name.text())?;
+                return Some(Span::of_token(db, decl.syntax()));
+            }
+            None => (),
+        }
+
+        if let Some(node) = Option::clone(&name_resolution::resolve_node(db, id.clone())) {
+            return of_id(node.id_node());
+        }
+        if let Some(node) = Option::clone(&name_resolution::resolve_extern_node(db, id.clone())) {
+            return of_id(node.id_node());
+        }
+    }
+
+    if let Some(decl) = Option::clone(&name_resolution::resolve_type_decl(db, id.clone())) {
+        return Some(Span::of_token(db, decl.ident()?.syntax()));
+    }
+
+    let decl = Option::clone(&name_resolution::resolve_enum_constructor(db, id))?;
+    let constructor = decl
+        .enum_decl_node()?
+        .all_ident()
+        .find(|c| c.text() == name.text())?;
+    Some(Span::of_token(db, constructor.syntax()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: &str = "type mode = enum { On, Off };
+const limit : int = 10;
+node incr(x : int) returns (y : int);
+let
+  y = x + 1;
+tel
+node main(a : int) returns (b : bool; m : mode);
+var c : int;
+let
+  c = incr(a);
+  b = c > limit;
+  m = On;
+tel
+";
+
+    fn text_at(file: &SourceFile, span: &Span) -> String {
+        file.text[span.start..span.end].to_owned()
+    }
+
+    #[test]
+    fn hover_types() {
+        let (db, file) = crate::test_file(PROGRAM);
+
+        let at = |needle: &str| {
+            let offset = PROGRAM.rfind(needle).unwrap();
+            hover(&db, &file, offset).unwrap()
+        };
+
+        let (text, span) = at("c > limit");
+        assert_eq!(text, "```lustre\nint\n```");
+        assert_eq!(text_at(&file, &span), "c");
+
+        let (text, span) = at("> limit");
+        assert_eq!(text, "```lustre\nbool\n```");
+        assert_eq!(text_at(&file, &span), "c > limit");
+
+        let (text, _) = at("incr(a)");
+        assert_eq!(text, "```lustre\nnode incr(x : int) returns (y : int)\n```");
+    }
+
+    #[test]
+    fn definitions() {
+        let (db, file) = crate::test_file(PROGRAM);
+
+        let at = |needle: &str| {
+            let offset = PROGRAM.rfind(needle).unwrap();
+            let span = definition(&db, &file, offset).unwrap();
+            (span.start, text_at(&file, &span))
+        };
+
+        assert_eq!(
+            at("incr(a)"),
+            (PROGRAM.find("incr").unwrap(), "incr".into())
+        );
+        assert_eq!(
+            at("limit;"),
+            (PROGRAM.find("limit").unwrap(), "limit".into())
+        );
+        assert_eq!(at("c > "), (PROGRAM.find("c : int").unwrap(), "c".into()));
+        assert_eq!(at("On;"), (PROGRAM.find("On").unwrap(), "On".into()));
+        assert_eq!(at("mode);"), (PROGRAM.find("mode").unwrap(), "mode".into()));
+    }
+}
@@ -0,0 +1,197 @@
+//! Outline of a file: its nodes, types and constants, grouped by package
+
+use crate::line_index::LineIndex;
+use lsp_types::{DocumentSymbol, SymbolKind};
+use rustre_core::diagnostics::Span;
+use rustre_core::SourceFile;
+use rustre_parser::ast::{
+    AstNode, AstToken, ConstantDeclNode, ExternalNodeDeclNode, IdNode, NodeNode, TypeDeclNode,
+};
+use rustre_parser::{SyntaxNode, SyntaxToken};
+use yeter::Database;
+
+struct SymbolBuilder<'a> {
+    file: &'a SourceFile,
+    index: LineIndex<'a>,
+}
+
+impl SymbolBuilder<'_> {
+    /// Builds a symbol declared by `decl`, and named by `name`
+    fn symbol(
+        &self,
+        decl: &SyntaxNode,
+        name: &SyntaxToken,
+        kind: SymbolKind,
+        children: Vec<DocumentSymbol>,
+    ) -> DocumentSymbol {
+        let span = Span::in_file(self.file, decl);
+        let name_range = name.text_range();
+
+        #[allow(deprecated)]
+        DocumentSymbol {
+            name: name.text().to_owned(),
+            detail: None,
+            kind,
+            tags: None,
+            deprecated: None,
+            range: self.index.range(span.start, span.end),
+            selection_range: self
+                .index
+                .range(name_range.start().into(), name_range.end().into()),
+            children: Some(children).filter(|c| !c.is_empty()),
+        }
+    }
+
+    fn named(
+        &self,
+        decl: &SyntaxNode,
+        id: Option<IdNode>,
+        kind: SymbolKind,
+    ) -> Option<DocumentSymbol> {
+        let name = id?.name()?;
+        Some(self.symbol(decl, name.syntax(), kind, Vec::new()))
+    }
+
+    /// Lists the declarations of a file, or of the body of a package
+    fn declarations(&self, decls: &SyntaxNode) -> Vec<DocumentSymbol> {
+        let mut symbols = Vec::new();
+
+        for decl in decls.children().filter_map(ConstantDeclNode::cast) {
+            for one in decl.all_one_constant_decl_node() {
+                let consts = one
+                    .all_id_node()
+                    .filter_map(|id| self.named(one.syntax(), Some(id), SymbolKind::CONSTANT));
+                symbols.extend(consts);
+            }
+        }
+
+        for decl in decls.children().filter_map(TypeDeclNode::cast) {
+            for one in decl.all_one_type_decl_node() {
+                let Some(name) = one.ident() else {
+                    continue;
+                };
+
+                let (kind, children) = if let Some(e) = one.enum_decl_node() {
+                    let constructors = e.all_ident().map(|ctor| {
+                        let decl = ctor.syntax().parent().unwrap();
+                        self.symbol(&decl, ctor.syntax(), SymbolKind::ENUM_MEMBER, Vec::new())
+                    });
+                    (SymbolKind::ENUM, constructors.collect())
+                } else if let Some(s) = one.struct_decl_node() {
+                    let fields = s.fields().map(|(field, _)| {
+                        let decl = field.syntax().parent().unwrap();
+                        self.symbol(&decl, field.syntax(), SymbolKind::FIELD, Vec::new())
+                    });
+                    (SymbolKind::STRUCT, fields.collect())
+                } else {
+                    (SymbolKind::TYPE_PARAMETER, Vec::new())
+                };
+                symbols.push(self.symbol(one.syntax(), name.syntax(), kind, children));
+            }
+        }
+
+        let externs = decls
+            .children()
+            .filter_map(ExternalNodeDeclNode::cast)
+            .filter_map(|n| self.named(n.syntax(), n.id_node(), SymbolKind::FUNCTION));
+        symbols.extend(externs);
+
+        let nodes = decls
+            .children()
+            .filter_map(NodeNode::cast)
+            .filter_map(|n| self.named(n.syntax(), n.id_node(), SymbolKind::FUNCTION));
+        symbols.extend(nodes);
+
+        symbols
+    }
+}
+
+/// Lists the symbols declared in a file
+///
+/// Packages and models are modules, with their declarations as children. Enumerated types and
+/// structures have their constructors and fields as children.
+pub fn document_symbols(db: &Database, file: &SourceFile) -> Vec<DocumentSymbol> {
+    let root = rustre_core::parse_file(db, file.clone());
+    let builder = SymbolBuilder {
+        file,
+        index: LineIndex::new(&file.text),
+    };
+
+    let mut symbols = builder.declarations(root.syntax());
+
+    let packages = root
+        .all_package_decl_node()
+        .map(|p| (p.syntax().clone(), p.id_node(), p.package_decl_body()));
+    let models = root
+        .all_model_decl_node()
+        .map(|m| (m.syntax().clone(), m.id_node(), m.package_decl_body()));
+    for (decl, id, body) in packages.chain(models) {
+        let Some(name) = id.and_then(|id| id.name()) else {
+            continue;
+        };
+        let children = body
+            .map(|b| builder.declarations(b.syntax()))
+            .unwrap_or_default();
+        symbols.push(builder.symbol(&decl, name.syntax(), SymbolKind::MODULE, children));
+    }
+
+    let instances = root
+        .all_package_alias_node()
+        .filter_map(|i| builder.named(i.syntax(), i.name(), SymbolKind::MODULE));
+    symbols.extend(instances);
+
+    symbols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outline() {
+        let (db, file) = crate::test_file(
+            "type color = enum { Red, Green };
+package Lib
+  body
+    const zero : int = 0;
+    function id(x : int) returns (y : int);
+    let y = x; tel
+  end
+node main(a : int) returns (b : int);
+let b = Lib::id(a); tel
+",
+        );
+
+        let symbols = document_symbols(&db, &file);
+        let names = |symbols: &[DocumentSymbol]| {
+            symbols
+                .iter()
+                .map(|s| (s.name.clone(), s.kind))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            names(&symbols),
+            [
+                ("color".into(), SymbolKind::ENUM),
+                ("main".into(), SymbolKind::FUNCTION),
+                ("Lib".into(), SymbolKind::MODULE),
+            ]
+        );
+        assert_eq!(
+            names(symbols[0].children.as_deref().unwrap()),
+            [
+                ("Red".into(), SymbolKind::ENUM_MEMBER),
+                ("Green".into(), SymbolKind::ENUM_MEMBER),
+            ]
+        );
+        assert_eq!(
+            names(symbols[2].children.as_deref().unwrap()),
+            [
+                ("zero".into(), SymbolKind::CONSTANT),
+                ("id".into(), SymbolKind::FUNCTION),
+            ]
+        );
+        assert_eq!(symbols[1].selection_range.start.line, 7);
+    }
+}
@@ -98,6 +98,76 @@ impl NodeProfileNode {
     }
 }
 
+impl IdNode {
+    /// Name of the identified item, without its package qualifier
+    pub fn name(&self) -> Option<Ident> {
+        self.idents().last()
+    }
+
+    /// Package qualifier of the identifier, as `P` in `P::x`
+    pub fn package(&self) -> Option<Ident> {
+        let mut idents = self.idents();
+        let first = idents.next()?;
+        idents.next().map(|_| first)
+    }
+
+    fn idents(&self) -> impl Iterator<Item = Ident> {
+        self.syntax
+            .children_with_tokens()
+            .filter_map(|t| t.into_token())
+            .filter_map(Ident::cast)
+    }
+}
+
+impl ProvidesNode {
+    /// Name of the item that is exported by a package
+    pub fn name(&self) -> Option<Ident> {
+        match self.one_type_decl_node() {
+            Some(decl) => decl.ident(),
+            None => self.id_node()?.name(),
+        }
+    }
+}
+
+impl StructDeclNode {
+    /// Fields of the structure, with their declared types
+    ///
+    /// Fields declared together, as in `struct { x, y : real }`, share the same type node.
+    pub fn fields(&self) -> impl Iterator<Item = (Ident, Option<TypeNode>)> {
+        self.syntax
+            .children()
+            .filter(|s| s.kind() == Token::TypedValuedLv6IdNode)
+            .flat_map(|field| {
+                let ty = field.children().find_map(TypeNode::cast);
+                field
+                    .children_with_tokens()
+                    .filter_map(|t| t.into_token())
+                    .filter_map(Ident::cast)
+                    .map(move |name| (name, ty.clone()))
+            })
+    }
+}
+
+impl FieldAccessExpressionNode {
+    /// Name of the accessed field
+    pub fn field(&self) -> Option<Ident> {
+        match self.right()? {
+            ExpressionNode::IdentExpressionNode(e) => e.id_node()?.ident(),
+            _ => None,
+        }
+    }
+}
+
+impl LeftFieldAccessNode {
+    /// Name of the assigned field
+    pub fn field(&self) -> Option<IdNode> {
+        self.syntax
+            .children_with_tokens()
+            .skip_while(|s| s.kind() != Token::Dot)
+            .find_map(|s| IdNode::cast(s.into_node()?))
+    }
+}
+
 pub trait BinaryExpression {
     fn left(&self) -> Option<ExpressionNode>;
     fn right(&self) -> Option<ExpressionNode>;
@@ -145,6 +215,7 @@ impl_bin_expr!(AddExpressionNode);
 impl_bin_expr!(MulExpressionNode);
 impl_bin_expr!(PowerExpressionNode);
 impl_bin_expr!(HatExpressionNode);
+impl_bin_expr!(ConcatExpressionNode);
 
 macro_rules! impl_un_expr {
     ($name:ident) => {
@@ -38,6 +38,12 @@ pub trait ExpressionVisitor<O> {
     fn visit_par(&mut self, e: ParExpressionNode) -> O;
     fn visit_call_by_pos(&mut self, e: CallByPosExpressionNode) -> O;
     fn visit_hat(&mut self, e: HatExpressionNode) -> O;
+    fn visit_merge(&mut self, e: MergeExpressionNode) -> O;
+    fn visit_field_access(&mut self, e: FieldAccessExpressionNode) -> O;
+    fn visit_call_by_name(&mut self, e: CallByNameExpressionNode) -> O;
+    fn visit_array_access(&mut self, e: ArrayAccessExpressionNode) -> O;
+    fn visit_array_literal(&mut self, e: ArrayLiteralExpressionNode) -> O;
+    fn visit_concat(&mut self, e: ConcatExpressionNode) -> O;
 }
 
 macro_rules! walk_rec1 {
@@ -102,6 +108,12 @@ pub trait ExpressionWalker: ExpressionVisitor<()> {
     fn walk_par(&mut self, _e: ParExpressionNode) {}
     fn walk_call_by_pos(&mut self, _e: CallByPosExpressionNode) {}
     fn walk_hat(&mut self, _e: HatExpressionNode) {}
+    fn walk_merge(&mut self, _e: MergeExpressionNode) {}
+    fn walk_field_access(&mut self, _e: FieldAccessExpressionNode) {}
+    fn walk_call_by_name(&mut self, _e: CallByNameExpressionNode) {}
+    fn walk_array_access(&mut self, _e: ArrayAccessExpressionNode) {}
+    fn walk_array_literal(&mut self, _e: ArrayLiteralExpressionNode) {}
+    fn walk_concat(&mut self, _e: ConcatExpressionNode) {}
 
     /// Recursively walk over an expression and its sub-expression, calling `walk_*` methods
     #[deny(unused_variables)] // We don't want to miss a recursion case
@@ -120,7 +132,9 @@ pub trait ExpressionWalker: ExpressionVisitor<()> {
             ExpressionNode::IntExpressionNode(e) => walk_rec1!(self.walk_int(e)),
             ExpressionNode::RealExpressionNode(e) => walk_rec1!(self.walk_real(e)),
             ExpressionNode::WhenExpressionNode(e) => {
+                let op = e.left();
                 self.walk_when(e);
+                self.walk_expr_opt(op);
             }
             ExpressionNode::FbyExpressionNode(e) => walk_rec2!(self.walk_fby(e)),
             ExpressionNode::ArrowExpressionNode(e) => walk_rec2!(self.walk_arrow(e)),
@@ -159,32 +173,82 @@ pub trait ExpressionWalker: ExpressionVisitor<()> {
                 self.walk_expr_opt(op3);
             }
             ExpressionNode::DieseExpressionNode(e) => {
-                let list = e.list();
+                // The parser puts the operands directly in the node, not in an ExpressionListNode
+                let operands = e.syntax().children().filter_map(ExpressionNode::cast);
+                let operands = operands.collect::<Vec<_>>();
                 self.walk_diese(e);
-                for e in list.iter().flat_map(|el| el.all_expression_node()) {
+                for e in operands {
                     self.walk_expr(e);
                 }
             }
             ExpressionNode::NorExpressionNode(e) => {
-                let list = e.list();
+                // The parser puts the operands directly in the node, not in an ExpressionListNode
+                let operands = e.syntax().children().filter_map(ExpressionNode::cast);
+                let operands = operands.collect::<Vec<_>>();
                 self.walk_nor(e);
-                for e in list.iter().flat_map(|el| el.all_expression_node()) {
+                for e in operands {
                     self.walk_expr(e);
                 }
             }
             ExpressionNode::ParExpressionNode(e) => {
-                let expr = e.expression_node();
+                // Tuples are parenthesized expressions with multiple children
+                let exprs = e.syntax().children().filter_map(ExpressionNode::cast);
+                let exprs = exprs.collect::<Vec<_>>();
                 self.walk_par(e);
-                self.walk_expr_opt(expr);
+                for e in exprs {
+                    self.walk_expr(e);
+                }
             }
             ExpressionNode::CallByPosExpressionNode(e) => {
-                let args = e.args();
+                // The first "argument" is the name of the called node
+                let args = e.args().skip(1);
                 self.walk_call_by_pos(e);
                 for arg in args {
                     self.walk_expr(arg);
                 }
             }
             ExpressionNode::HatExpressionNode(e) => walk_rec2!(self.walk_hat(e)),
+            ExpressionNode::MergeExpressionNode(e) => {
+                let cases = e.all_merge_case_node().collect::<Vec<_>>();
+                self.walk_merge(e);
+                for case in cases {
+                    self.walk_expr_opt(case.expression_node());
+                }
+            }
+            ExpressionNode::FieldAccessExpressionNode(e) => {
+                // The right operand is the name of the field, not a value
+                let op = e.left();
+                self.walk_field_access(e);
+                self.walk_expr_opt(op);
+            }
+            ExpressionNode::CallByNameExpressionNode(e) => {
+                let params = e.all_call_by_name_param_node().collect::<Vec<_>>();
+                self.walk_call_by_name(e);
+                for param in params {
+                    self.walk_expr_opt(param.expression_node());
+                }
+            }
+            ExpressionNode::ArrayAccessExpressionNode(e) => {
+                let array = e.array();
+                let index = e.index();
+                let select = e.select_node();
+                self.walk_array_access(e);
+                self.walk_expr_opt(array);
+                self.walk_expr_opt(index);
+                if let Some(select) = select {
+                    self.walk_expr_opt(select.left());
+                    self.walk_expr_opt(select.right());
+                    self.walk_expr_opt(select.step_node().and_then(|s| s.expression_node()));
+                }
+            }
+            ExpressionNode::ArrayLiteralExpressionNode(e) => {
+                let elements = e.elements().collect::<Vec<_>>();
+                self.walk_array_literal(e);
+                for e in elements {
+                    self.walk_expr(e);
+                }
+            }
+            ExpressionNode::ConcatExpressionNode(e) => walk_rec2!(self.walk_concat(e)),
         }
     }
 
@@ -333,4 +397,28 @@ impl<T: ExpressionWalker> ExpressionVisitor<()> for T {
     fn visit_hat(&mut self, e: HatExpressionNode) {
         self.walk_hat(e);
     }
+
+    fn visit_merge(&mut self, e: MergeExpressionNode) {
+        self.walk_merge(e);
+    }
+
+    fn visit_field_access(&mut self, e: FieldAccessExpressionNode) {
+        self.walk_field_access(e);
+    }
+
+    fn visit_call_by_name(&mut self, e: CallByNameExpressionNode) {
+        self.walk_call_by_name(e);
+    }
+
+    fn visit_array_access(&mut self, e: ArrayAccessExpressionNode) {
+        self.walk_array_access(e);
+    }
+
+    fn visit_array_literal(&mut self, e: ArrayLiteralExpressionNode) {
+        self.walk_array_literal(e);
+    }
+
+    fn visit_concat(&mut self, e: ConcatExpressionNode) {
+        self.walk_concat(e);
+    }
 }
@@ -1,11 +1,21 @@
 #![cfg(test)]
 
-use crate::ast::{AstToken, Root};
+use crate::ast::{
+    AstNode, AstToken, CallByNameExpressionNode, ExpressionNode, Root, StaticArgNode,
+};
 
 fn parse(source: &str) -> Root {
     crate::parse(source).0
 }
 
+/// Returns the right-hand side of the first equation of a program
+fn equation_rhs(source: &str) -> ExpressionNode {
+    let root = parse(source);
+    let node = root.all_node_node().next().unwrap();
+    let eq = node.body_node().unwrap().all_equals_equation_node().next();
+    eq.unwrap().expression_node().unwrap()
+}
+
 #[test]
 fn enumerate_nodes_invalid_syntax() {
     let root = parse(
@@ -65,3 +75,149 @@ fn labeled_expression_are_different() {
     let right = addition.right().unwrap();
     assert!(left != right);
 }
+
+#[test]
+fn struct_fields() {
+    let root = parse("type point = struct { x, y : real; valid : bool };");
+
+    let decl = root
+        .all_type_decl_node()
+        .flat_map(|t| t.all_one_type_decl_node())
+        .next()
+        .unwrap();
+    let fields = decl
+        .struct_decl_node()
+        .unwrap()
+        .fields()
+        .map(|(name, ty)| (name.text().to_owned(), ty.unwrap().bool().is_some()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        fields,
+        [
+            ("x".to_owned(), false),
+            ("y".to_owned(), false),
+            ("valid".to_owned(), true)
+        ]
+    );
+}
+
+#[test]
+fn typed_array_constant() {
+    let root = parse("const c : int^2 = [1, 2];");
+
+    let decl = root
+        .all_constant_decl_node()
+        .flat_map(|c| c.all_one_constant_decl_node())
+        .next()
+        .unwrap();
+    let size = decl.type_node().unwrap().power().unwrap();
+    assert!(matches!(size, crate::ast::ExpressionNode::ConstantNode(_)));
+    let value = decl.expression_node().unwrap();
+    assert!(matches!(
+        value,
+        crate::ast::ExpressionNode::ArrayLiteralExpressionNode(_)
+    ));
+}
+
+#[test]
+fn package_body() {
+    let root = parse(
+        "package P uses Q; provides const k : int; type t; body const k : int = 1; type t = int; end",
+    );
+
+    let package = root.all_package_decl_node().next().unwrap();
+    let uses = package.uses_node().unwrap().all_id_node().count();
+    assert_eq!(uses, 1);
+    let provided = package
+        .provides_list_node()
+        .unwrap()
+        .all_provides_node()
+        .map(|p| p.name().unwrap().text().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(provided, ["k", "t"]);
+    let body = package.package_decl_body().unwrap();
+    assert_eq!(body.all_constant_decl_node().count(), 1);
+    assert_eq!(body.all_type_decl_node().count(), 1);
+}
+
+#[test]
+fn model_instance() {
+    let root = parse(
+        "model M needs type t; const n : int; body const k : int = n; end
+         package P = M(t = int; n = 3);",
+    );
+
+    let model = root.all_model_decl_node().next().unwrap();
+    assert_eq!(model.id_node().unwrap().name().unwrap().text(), "M");
+    assert_eq!(model.all_static_param_node().count(), 2);
+    assert!(model.package_decl_body().is_some());
+
+    let instance = root.all_package_alias_node().next().unwrap();
+    assert_eq!(instance.name().unwrap().name().unwrap().text(), "P");
+    assert_eq!(instance.model().unwrap().name().unwrap().text(), "M");
+    let args = instance
+        .named_static_args_node()
+        .unwrap()
+        .all_named_static_arg_node()
+        .map(|a| a.id_node().unwrap().name().unwrap().text().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(args, ["t", "n"]);
+}
+
+#[test]
+fn predef_op_static_arg() {
+    let root = parse("node n(a : int^4) returns (s : int); let s = red<<+, 4>>(0, a); tel");
+
+    let arg = AstNode::syntax(&root)
+        .descendants()
+        .find_map(StaticArgNode::cast)
+        .unwrap();
+    assert!(arg.predef_op().unwrap().plus().is_some());
+}
+
+#[test]
+fn struct_update() {
+    let root = parse("const q = point { p with y = 1 };");
+
+    let update = AstNode::syntax(&root)
+        .descendants()
+        .find_map(CallByNameExpressionNode::cast)
+        .unwrap();
+    assert_eq!(update.name().unwrap().name().unwrap().text(), "point");
+    assert_eq!(update.base().unwrap().name().unwrap().text(), "p");
+    assert!(update.with().is_some());
+    assert_eq!(update.all_call_by_name_param_node().count(), 1);
+}
+
+#[test]
+fn non_strict_comparisons() {
+    let lte = equation_rhs("node n(a, b : int) returns (c : bool); let c = a <= b; tel");
+    assert!(matches!(lte, ExpressionNode::LteExpressionNode(_)));
+
+    let gte = equation_rhs("node n(a, b : int) returns (c : bool); let c = a >= b; tel");
+    assert!(matches!(gte, ExpressionNode::GteExpressionNode(_)));
+}
+
+#[test]
+fn right_associative_chains() {
+    let source = "node n(a, b, c : int) returns (d : int); let d = a -> b -> c; tel";
+    let ExpressionNode::ArrowExpressionNode(arrow) = equation_rhs(source) else {
+        panic!("expected an arrow");
+    };
+    assert_eq!(arrow.left().unwrap().syntax().to_string().trim(), "a");
+    assert!(matches!(
+        arrow.right(),
+        Some(ExpressionNode::ArrowExpressionNode(_))
+    ));
+
+    let source = "node n(a, b, c : int) returns (d : int); let d = a fby b fby c; tel";
+    let ExpressionNode::FbyExpressionNode(fby) = equation_rhs(source) else {
+        panic!("expected a fby");
+    };
+    assert_eq!(fby.left().unwrap().syntax().to_string().trim(), "a");
+    let Some(ExpressionNode::FbyExpressionNode(inner)) = fby.right() else {
+        panic!("expected a nested fby");
+    };
+    assert_eq!(inner.left().unwrap().syntax().to_string().trim(), "b");
+    assert_eq!(inner.right().unwrap().syntax().to_string().trim(), "c");
+}
@@ -109,7 +109,7 @@ pub type SyntaxElement = rowan::SyntaxElement<LustreLang>;
 ///     Token::Semicolon,
 /// ]);
 /// ```
-pub fn lex(source: &str) -> Lexer {
+pub fn lex(source: &str) -> Lexer<'_> {
     Lexer::from_source(source)
 }
 
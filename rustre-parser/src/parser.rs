@@ -164,9 +164,10 @@ pub fn parse_type<'slice, 'src>(input: Input<'slice, 'src>) -> IResult<'slice, '
 }
 
 fn parse_type_hat<'slice, 'src>(input: Input<'slice, 'src>) -> IResult<'slice, 'src> {
+    // The size stops before comparisons, so that `const c : int^2 = ...` isn't read as `2 = ...`
     opt(join((
         t(Hat),
-        expect(expression::parse_expression, "expected expression"),
+        expect(expression::parse_expression_10, "expected expression"),
     )))(input)
 }
 
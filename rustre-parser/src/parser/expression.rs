@@ -59,7 +59,8 @@ fn parse_expression_right<'slice, 'src: 'slice>(
         fold_many1_right_expr(
             next,
             |input| parse_operator.parse(input),
-            |a, (b, n)| (a + b).into_node(n),
+            // The right operand is folded first, and must be placed after the left one
+            |right, (left, n)| (left + right).into_node(n),
         )(input)
     }
 }
@@ -259,10 +260,10 @@ pub fn parse_expression_12<'slice, 'src>(input: Input<'slice, 'src>) -> IResult<
     parse_expression_no_assoc(
         parse_ops! {
             Lt => LtExpressionNode,
-            Lte => LtExpressionNode,
+            Lte => LteExpressionNode,
             Equal => EqExpressionNode,
             Gt => GtExpressionNode,
-            Gte => GtExpressionNode,
+            Gte => GteExpressionNode,
             Neq => NeqExpressionNode,
         },
         parse_expression_11,
@@ -291,7 +292,7 @@ pub use parse_expression_15 as parse_expression_16;
 pub use parse_expression_16 as parse_expression_17;
 
 pub fn parse_expression_18<'slice, 'src>(input: Input<'slice, 'src>) -> IResult<'slice, 'src> {
-    parse_expression_left(
+    parse_expression_right(
         parse_ops!(Arrow => ArrowExpressionNode),
         parse_expression_17,
     )(input)